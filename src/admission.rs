@@ -0,0 +1,240 @@
+use crate::error::EstimateError;
+use crate::sample::Sample;
+
+/// Parameters of [`AdmissionControl`]. Rates and widths are in timestamp units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdmissionConfig {
+    /// Largest sustained rate of admitted samples per timestamp unit. Unlimited by default.
+    pub max_rate: f64,
+    /// Samples admitted at once after a quiet period, the depth of the token bucket that enforces
+    /// `max_rate`.
+    pub burst: f64,
+    /// A sample within this time of the last admitted one and within `dedup_tolerance` of its
+    /// value is a duplicate. Both samples need a timestamp: equal delays alone are common with
+    /// coarse timers and tell nothing of a repeated probe. Zero by default, so that only
+    /// samples repeated with their timestamp are.
+    pub dedup_window: f64,
+    /// See `dedup_window`.
+    pub dedup_tolerance: f64,
+    /// Width of the time strata, each of which admits at most `stratum_quota` samples, so that
+    /// a window is not dominated by one congestion episode. Zero disables stratification.
+    pub stratum_width: f64,
+    /// See `stratum_width`.
+    pub stratum_quota: usize,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        AdmissionConfig {
+            max_rate: f64::INFINITY,
+            burst: 1.0,
+            dedup_window: 0.0,
+            dedup_tolerance: 0.0,
+            stratum_width: 0.0,
+            stratum_quota: usize::MAX,
+        }
+    }
+}
+
+/// Outcome of [`AdmissionControl::admit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Admitted,
+    /// Arrived faster than [`AdmissionConfig::max_rate`] allows.
+    RateLimited,
+    /// Repeats the last admitted sample.
+    Duplicate,
+    /// Its stratum already holds [`AdmissionConfig::stratum_quota`] samples.
+    StratumFull,
+}
+
+/// Ingestion front-end deciding which samples reach the estimator, protecting it from a
+/// misconfigured prober flooding the window and from bursts biasing it toward one episode.
+///
+/// Samples are checked in order for being duplicates, for their stratum being full and for the
+/// rate limit, and only admitted samples count toward the later checks. The checks go by the
+/// sample timestamps, which are expected in time order; samples without a finite timestamp are
+/// only checked for being duplicates.
+///
+/// ```
+/// use gamlr::{Admission, AdmissionConfig, AdmissionControl, Sample};
+///
+/// // At most 10 samples per second, in bursts of up to 5.
+/// let mut admission = AdmissionControl::new(AdmissionConfig {
+///     max_rate: 10.0,
+///     burst: 5.0,
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let flood = (0..100).map(|i| Sample::new(0.35).at(i as f64 * 1e-3));
+/// assert_eq!(admission.filter(flood).count(), 5);
+/// assert_eq!(admission.admit(Sample::new(0.35).at(0.2)), Admission::Admitted);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AdmissionControl {
+    config: AdmissionConfig,
+    /// Tokens in the bucket as of `refilled`.
+    tokens: f64,
+    refilled: Option<f64>,
+    last: Option<Sample>,
+    /// Index of the current stratum and the samples admitted into it.
+    stratum: Option<(f64, usize)>,
+}
+
+impl AdmissionControl {
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `max_rate` is not
+    /// positive, `burst` is below one, or a window, tolerance or width is negative or NaN.
+    pub fn new(config: AdmissionConfig) -> Result<Self, EstimateError> {
+        let valid = [
+            ("max_rate", config.max_rate > 0.0),
+            ("burst", config.burst >= 1.0),
+            ("dedup_window", config.dedup_window >= 0.0),
+            ("dedup_tolerance", config.dedup_tolerance >= 0.0),
+            ("stratum_width", config.stratum_width >= 0.0),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(AdmissionControl {
+            tokens: config.burst,
+            config,
+            refilled: None,
+            last: None,
+            stratum: None,
+        })
+    }
+
+    pub fn config(&self) -> &AdmissionConfig {
+        &self.config
+    }
+
+    /// Decides whether `sample` is admitted, updating the state for the samples that follow.
+    pub fn admit(&mut self, sample: Sample) -> Admission {
+        let time = sample.timestamp.filter(|t| t.is_finite());
+        if self
+            .last
+            .is_some_and(|last| self.duplicates(&last, &sample))
+        {
+            return Admission::Duplicate;
+        }
+        let Some(time) = time else {
+            self.last = Some(sample);
+            return Admission::Admitted;
+        };
+
+        let stratum = match self.config.stratum_width > 0.0 {
+            true => {
+                let index = libm::floor(time / self.config.stratum_width);
+                let count = match self.stratum {
+                    Some((current, count)) if current == index => count,
+                    _ => 0,
+                };
+                if count >= self.config.stratum_quota {
+                    return Admission::StratumFull;
+                }
+                Some((index, count + 1))
+            }
+            false => None,
+        };
+
+        // Out of order samples refill nothing.
+        let elapsed = self.refilled.map_or(0.0, |at| (time - at).max(0.0));
+        let tokens = (self.tokens + elapsed * self.config.max_rate).min(self.config.burst);
+        self.refilled = Some(self.refilled.map_or(time, |at| at.max(time)));
+        if tokens < 1.0 {
+            self.tokens = tokens;
+            return Admission::RateLimited;
+        }
+        self.tokens = tokens - 1.0;
+        if stratum.is_some() {
+            self.stratum = stratum;
+        }
+        self.last = Some(sample);
+        Admission::Admitted
+    }
+
+    /// The admitted samples of `samples`.
+    pub fn filter<'a, I>(&'a mut self, samples: I) -> impl Iterator<Item = Sample> + 'a
+    where
+        I: IntoIterator<Item = Sample>,
+        I::IntoIter: 'a,
+    {
+        samples
+            .into_iter()
+            .filter(move |&sample| self.admit(sample) == Admission::Admitted)
+    }
+
+    fn duplicates(&self, last: &Sample, sample: &Sample) -> bool {
+        let close_in_time = match (last.timestamp, sample.timestamp) {
+            (Some(a), Some(b)) => libm::fabs(b - a) <= self.config.dedup_window,
+            _ => false,
+        };
+        close_in_time
+            && last.censored == sample.censored
+            && libm::fabs(sample.value - last.value) <= self.config.dedup_tolerance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admission_control() {
+        let mut admission = AdmissionControl::new(AdmissionConfig {
+            max_rate: 2.0,
+            burst: 2.0,
+            dedup_window: 0.05,
+            dedup_tolerance: 1e-3,
+            stratum_width: 10.0,
+            stratum_quota: 3,
+        })
+        .unwrap();
+        let outcomes: [Admission; 7] = [
+            Sample::new(1.0).at(0.0),
+            // The same probe reported twice.
+            Sample::new(1.0005).at(0.01),
+            Sample::new(2.0).at(0.02),
+            // Two samples empty the bucket, which refills at two per unit.
+            Sample::new(3.0).at(0.3),
+            Sample::new(4.0).at(0.6),
+            // The first stratum holds three samples.
+            Sample::new(5.0).at(5.0),
+            Sample::new(6.0).at(10.0),
+        ]
+        .map(|s| admission.admit(s));
+        assert_eq!(
+            outcomes,
+            [
+                Admission::Admitted,
+                Admission::Duplicate,
+                Admission::Admitted,
+                Admission::RateLimited,
+                Admission::Admitted,
+                Admission::StratumFull,
+                Admission::Admitted,
+            ]
+        );
+        assert_eq!(admission.admit(Sample::new(7.0)), Admission::Admitted);
+        assert_eq!(
+            admission.admit(Sample::new(7.0).at(20.0)),
+            Admission::Admitted
+        );
+        assert_eq!(
+            admission.admit(Sample::new(7.0).at(20.0)),
+            Admission::Duplicate
+        );
+        // Quantized delays repeat without being duplicates.
+        let mut default = AdmissionControl::new(AdmissionConfig::default()).unwrap();
+        assert!((0..3).all(|_| default.admit(Sample::new(7.0)) == Admission::Admitted));
+
+        assert_eq!(
+            AdmissionControl::new(AdmissionConfig {
+                max_rate: f64::NAN,
+                ..Default::default()
+            })
+            .err(),
+            Some(EstimateError::InvalidConfig { field: "max_rate" })
+        );
+    }
+}
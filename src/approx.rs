@@ -0,0 +1,58 @@
+use crate::float;
+
+/// Natural logarithm on the sampler's hot path.
+#[cfg(not(feature = "micromath"))]
+pub(crate) fn ln(x: f64) -> f64 {
+    float::ln(x)
+}
+
+/// Square root on the sampler's hot path.
+#[cfg(not(feature = "micromath"))]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    float::sqrt(x)
+}
+
+/// Natural logarithm by `micromath`, in single precision with an absolute error below 1e-4.
+///
+/// Arguments below one are inverted first: `micromath` approximates their reciprocal as well,
+/// which costs two orders of magnitude of accuracy.
+#[cfg(feature = "micromath")]
+pub(crate) fn ln(x: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 || x.is_infinite() {
+        return float::ln(x);
+    }
+    let x = x as f32;
+    let ln = if x < 1.0 {
+        -micromath::F32(1.0 / x).ln().0
+    } else {
+        micromath::F32(x).ln().0
+    };
+    ln as f64
+}
+
+/// Square root from the `micromath` estimate, within a few percent, refined by two Newton steps
+/// to a relative error below 1e-5.
+#[cfg(feature = "micromath")]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    if x.is_nan() || x <= 0.0 || x.is_infinite() {
+        return float::sqrt(x);
+    }
+    let mut y = micromath::F32(x as f32).sqrt().0 as f64;
+    for _ in 0..2 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accuracy() {
+        for x in [1e-6, 0.01, 0.3, 0.5, 0.99, 1.0, 2.0, 10.0, 1e4] {
+            assert!((ln(x) - float::ln(x)).abs() < 1e-4, "ln({x})");
+            assert!((sqrt(x) / float::sqrt(x) - 1.0).abs() < 1e-5, "sqrt({x})");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use core::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::error::EstimateError;
+use crate::offset_estimator::Estimate;
+use crate::online::OnlineEstimator;
+use crate::sample::Sample;
+
+/// Latest result published by a background estimator, `None` until the first estimate.
+pub type LatestEstimate = Option<Result<Estimate, EstimateError>>;
+
+/// Handles to an estimator running in a background task, see [`spawn_estimator`].
+#[derive(Debug)]
+pub struct EstimatorHandle {
+    /// Feeds samples to the task. Dropping every sender makes the task publish a final estimate
+    /// and exit.
+    pub samples: mpsc::Sender<Sample>,
+    /// Receives the latest estimate.
+    pub estimates: watch::Receiver<LatestEstimate>,
+    /// The background task itself.
+    pub task: JoinHandle<()>,
+}
+
+/// Spawns a tokio task owning `estimator`.
+///
+/// Samples sent through [`EstimatorHandle::samples`] are pushed into the estimator's window, and
+/// every `period` a fresh estimate is published on [`EstimatorHandle::estimates`] if new samples
+/// arrived since the previous one. At most `buffer` samples queue up in the channel. A zero
+/// `period` or `buffer` is treated as the smallest allowed value.
+///
+/// Must be called from within a tokio runtime with the time driver enabled.
+pub fn spawn_estimator(
+    mut estimator: OnlineEstimator,
+    period: Duration,
+    buffer: usize,
+) -> EstimatorHandle {
+    let (sample_tx, mut sample_rx) = mpsc::channel(buffer.max(1));
+    let (estimate_tx, estimate_rx) = watch::channel(None);
+
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period.max(Duration::from_nanos(1)));
+        let mut fresh = false;
+        loop {
+            tokio::select! {
+                sample = sample_rx.recv() => match sample {
+                    Some(sample) => {
+                        estimator.push(sample);
+                        fresh = true;
+                    }
+                    None => break,
+                },
+                _ = interval.tick(), if fresh => {
+                    fresh = false;
+                    estimate_tx.send_replace(Some(estimator.estimate()));
+                }
+            }
+        }
+        if fresh {
+            estimate_tx.send_replace(Some(estimator.estimate()));
+        }
+    });
+
+    EstimatorHandle {
+        samples: sample_tx,
+        estimates: estimate_rx,
+        task,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EstimatorConfig;
+
+    #[test]
+    fn test_spawn_estimator_publishes_final_estimate() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let estimator = OnlineEstimator::new(EstimatorConfig::default(), 100);
+            let handle = spawn_estimator(estimator, Duration::from_millis(5), 8);
+            for i in 0..30 {
+                let owd = 100.0 + f64::from(i % 6) * 10.0;
+                handle.samples.send(owd.into()).await.unwrap();
+            }
+            let EstimatorHandle {
+                samples,
+                estimates,
+                task,
+            } = handle;
+            drop(samples);
+            task.await.unwrap();
+
+            let latest = estimates.borrow().clone();
+            assert_eq!(latest.unwrap().unwrap().samples, 30);
+        });
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::cancel;
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::offset_estimator::{run, seed, Estimate, LcgRng};
+use crate::sample::Sample;
+
+/// Estimator over many links at once, e.g. every peer of a telemetry collector.
+///
+/// The traces of a batch form a ragged matrix, one row of [`Sample`]s per link. They are fitted
+/// one after the other through the same sample and synthetic buffers, which grow to the longest
+/// trace and are kept across batches, so that a steady stream of batches stops allocating. Each
+/// link draws its synthetic sample from its own stream, seeded in turn from
+/// [`EstimatorConfig::seed`], so links are independent of each other yet the batch is
+/// reproducible.
+///
+/// ```
+/// use gamlr::{BatchEstimator, EstimatorConfig, Sample};
+///
+/// let mut batch = BatchEstimator::new(EstimatorConfig::default());
+/// let links: Vec<Vec<Sample>> = (0..10)
+///     .map(|link| (0..32).map(|i| Sample::new(link as f64 + (i % 7) as f64)).collect())
+///     .collect();
+/// let estimates = batch.estimate(&links);
+/// assert_eq!(estimates.len(), 10);
+/// assert!(estimates.offset.iter().all(|offset| offset.is_finite()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct BatchEstimator {
+    config: EstimatorConfig,
+    samples: Vec<Sample>,
+    synthetic: Vec<f64>,
+}
+
+/// Estimates of a batch of links as a struct of arrays, one entry per link in each column.
+///
+/// Links whose estimation failed hold NaN offsets, uncertainties, shapes and scales, zero
+/// counts, and their error in [`error`](Self::error).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchEstimates {
+    /// See [`Estimate::offset`].
+    pub offset: Vec<f64>,
+    /// See [`Estimate::uncertainty`].
+    pub uncertainty: Vec<f64>,
+    /// See [`Estimate::shape`].
+    pub shape: Vec<f64>,
+    /// See [`Estimate::scale`].
+    pub scale: Vec<f64>,
+    /// See [`Estimate::samples`].
+    pub samples: Vec<usize>,
+    /// See [`Estimate::censored`].
+    pub censored: Vec<usize>,
+    /// Why the estimation of a link failed, `None` for the links that were estimated.
+    pub error: Vec<Option<EstimateError>>,
+}
+
+impl BatchEstimator {
+    pub fn new(config: EstimatorConfig) -> Self {
+        BatchEstimator {
+            config,
+            samples: Vec::new(),
+            synthetic: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &EstimatorConfig {
+        &self.config
+    }
+
+    /// Estimates the offset of every trace in `traces`.
+    pub fn estimate<T: AsRef<[Sample]>>(&mut self, traces: &[T]) -> BatchEstimates {
+        let mut estimates = BatchEstimates::default();
+        self.estimate_into(traces, &mut estimates);
+        estimates
+    }
+
+    /// [`estimate`](Self::estimate) into the columns of `out`, which are cleared first and keep
+    /// their capacity. When [`EstimatorConfig::cancel`] is cancelled, `out` holds the links done
+    /// before.
+    pub fn estimate_into<T: AsRef<[Sample]>>(&mut self, traces: &[T], out: &mut BatchEstimates) {
+        let _ = self.estimate_with_progress(traces, out, |_, _| ControlFlow::Continue(()));
+    }
+
+    /// [`estimate_into`](Self::estimate_into), calling `progress` with the links done and the
+    /// total after each link, e.g. to display the progress of a long job. When `progress` breaks
+    /// or [`EstimatorConfig::cancel`] is cancelled, the job stops with
+    /// [`EstimateError::Cancelled`] and `out` holds the links done so far.
+    pub fn estimate_with_progress<T, F>(
+        &mut self,
+        traces: &[T],
+        out: &mut BatchEstimates,
+        mut progress: F,
+    ) -> Result<(), EstimateError>
+    where
+        T: AsRef<[Sample]>,
+        F: FnMut(usize, usize) -> ControlFlow<()>,
+    {
+        out.clear();
+        out.reserve(traces.len());
+        let mut seeds = LcgRng::new(seed(&self.config));
+        let base_seed = self.config.seed;
+        let mut cancelled = false;
+        for (done, trace) in traces.iter().enumerate() {
+            if cancel::check(&self.config.cancel).is_err() {
+                cancelled = true;
+                break;
+            }
+            let trace = trace.as_ref();
+            self.samples.clear();
+            self.samples.extend_from_slice(trace);
+            self.synthetic
+                .resize(self.synthetic.len().max(trace.len()), 0.0);
+            self.config.seed = Some(seeds.next_u64());
+            out.push(run(&mut self.samples, &mut self.synthetic, &self.config));
+            if progress(done + 1, traces.len()).is_break() {
+                cancelled = true;
+                break;
+            }
+        }
+        self.config.seed = base_seed;
+        match cancelled {
+            true => Err(EstimateError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+impl BatchEstimates {
+    pub fn len(&self) -> usize {
+        self.offset.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.offset.clear();
+        self.uncertainty.clear();
+        self.shape.clear();
+        self.scale.clear();
+        self.samples.clear();
+        self.censored.clear();
+        self.error.clear();
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.offset.reserve(additional);
+        self.uncertainty.reserve(additional);
+        self.shape.reserve(additional);
+        self.scale.reserve(additional);
+        self.samples.reserve(additional);
+        self.censored.reserve(additional);
+        self.error.reserve(additional);
+    }
+
+    fn push(&mut self, result: Result<Estimate, EstimateError>) {
+        let (estimate, error) = match result {
+            Ok(estimate) => (estimate, None),
+            Err(error) => (Estimate::from_offset(f64::NAN, f64::NAN), Some(error)),
+        };
+        self.offset.push(estimate.offset);
+        self.uncertainty.push(estimate.uncertainty);
+        self.shape.push(estimate.shape);
+        self.scale.push(estimate.scale);
+        self.samples.push(estimate.samples);
+        self.censored.push(estimate.censored);
+        self.error.push(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::offset_estimator::estimate_samples;
+
+    #[test]
+    fn test_batch_matches_single_estimates() {
+        let config = EstimatorConfig {
+            seed: Some(7),
+            ..Default::default()
+        };
+        let traces: Vec<Vec<Sample>> = [40, 3, 120, 25]
+            .into_iter()
+            .map(|len| {
+                (0..len)
+                    .map(|i| Sample::new(len as f64 + (i * 13 % 17) as f64))
+                    .collect()
+            })
+            .collect();
+        let mut batch = BatchEstimator::new(config.clone());
+        let estimates = batch.estimate(&traces);
+        assert_eq!(estimates.len(), traces.len());
+
+        let mut seeds = LcgRng::new(7);
+        for (i, trace) in traces.iter().enumerate() {
+            let link_config = EstimatorConfig {
+                seed: Some(seeds.next_u64()),
+                ..config.clone()
+            };
+            match estimate_samples(trace.iter().copied(), &link_config) {
+                Ok(single) => {
+                    assert_eq!(estimates.offset[i], single.offset);
+                    assert_eq!(estimates.uncertainty[i], single.uncertainty);
+                    assert_eq!(estimates.samples[i], single.samples);
+                    assert_eq!(estimates.error[i], None);
+                }
+                Err(error) => {
+                    assert!(estimates.offset[i].is_nan());
+                    assert_eq!(estimates.error[i], Some(error));
+                }
+            }
+        }
+        assert_eq!(
+            estimates.error[1],
+            Some(EstimateError::InsufficientSamples { got: 3, need: 10 })
+        );
+
+        // The buffers carry nothing over from one batch to the next.
+        let mut again = BatchEstimates::default();
+        batch.estimate_into(&traces, &mut again);
+        let bits = |column: &[f64]| column.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&again.offset), bits(&estimates.offset));
+        assert_eq!(again.error, estimates.error);
+        assert_eq!(batch.config().seed, Some(7));
+    }
+
+    #[test]
+    fn test_progress_cancels() {
+        let traces: Vec<Vec<Sample>> = (0..5)
+            .map(|link| {
+                (0..20)
+                    .map(|i| Sample::new((link + i % 3) as f64))
+                    .collect()
+            })
+            .collect();
+        let mut batch = BatchEstimator::new(EstimatorConfig::default());
+        let mut out = BatchEstimates::default();
+        let mut calls = Vec::new();
+        let result = batch.estimate_with_progress(&traces, &mut out, |done, total| {
+            calls.push((done, total));
+            match done {
+                3 => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(()),
+            }
+        });
+        assert_eq!(result, Err(EstimateError::Cancelled));
+        assert_eq!(calls, [(1, 5), (2, 5), (3, 5)]);
+        assert_eq!(out.len(), 3);
+        assert_eq!(out.offset[..], batch.estimate(&traces).offset[..3]);
+
+        let token = CancelToken::new();
+        let mut batch = BatchEstimator::new(EstimatorConfig {
+            cancel: Some(token.clone()),
+            ..Default::default()
+        });
+        token.cancel();
+        assert!(batch.estimate(&traces).is_empty());
+        token.reset();
+        assert_eq!(batch.estimate(&traces).len(), 5);
+    }
+}
@@ -0,0 +1,274 @@
+use alloc::vec::Vec;
+
+use crate::config::{DelayPrior, EstimatorConfig};
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+use crate::offset_estimator::prepare;
+use crate::sample::Sample;
+
+/// Number of offset grid points the posterior is evaluated on.
+const OFFSET_GRID: usize = 256;
+/// Number of shape grid points the posterior is integrated over.
+const SHAPE_GRID: usize = 32;
+
+/// Posterior distribution of the offset under the shifted Gamma delay model, see
+/// [`estimate_bayesian`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posterior {
+    /// Posterior mean of the offset.
+    pub offset: f64,
+    /// Posterior mean of the Gamma shape α, usable as the shape of the next prior.
+    pub shape: f64,
+    /// Posterior mean of the Gamma scale β, usable as the scale of the next prior.
+    pub scale: f64,
+    /// Number of samples that entered the fit.
+    pub samples: usize,
+    /// Offset grid in ascending order with the posterior probability of each cell.
+    grid: Vec<(f64, f64)>,
+    /// Width of a grid cell.
+    step: f64,
+}
+
+impl Posterior {
+    /// Central credible interval of the offset holding the posterior probability `level`, e.g.
+    /// `0.95`. The level is clamped to `[0, 1]`.
+    pub fn credible_interval(&self, level: f64) -> (f64, f64) {
+        if level.is_nan() {
+            return (f64::NAN, f64::NAN);
+        }
+        let level = level.clamp(0.0, 1.0);
+        (
+            self.quantile(0.5 * (1.0 - level)),
+            self.quantile(0.5 * (1.0 + level)),
+        )
+    }
+
+    /// Offset below which the posterior probability is `q`, spreading each cell's probability
+    /// uniformly over the cell.
+    fn quantile(&self, q: f64) -> f64 {
+        let mut below = 0.0;
+        for &(offset, probability) in &self.grid {
+            if probability > 0.0 && below + probability >= q {
+                return offset - 0.5 * self.step + self.step * (q - below) / probability;
+            }
+            below += probability;
+        }
+        self.grid
+            .last()
+            .map_or(f64::NAN, |&(offset, _)| offset + 0.5 * self.step)
+    }
+}
+
+/// Estimates the offset under the model `sample = offset + delay`, `delay ~ Gamma(α, β)`, by
+/// combining the likelihood of `samples` with `prior`.
+///
+/// The scale is integrated out analytically, the shape numerically over a grid of prior
+/// quantiles, and the offset posterior is evaluated on a grid below the smallest sample. On
+/// small batches the prior keeps the shape and scale plausible where the method of moments
+/// behind [`estimate_samples`](crate::estimate_samples) is erratic.
+///
+/// Censored samples enter through the probability of a delay beyond their timeout, evaluated at
+/// the posterior mean of the scale given the offset, the shape and the uncensored samples, since
+/// the survival function does not integrate the scale out in closed form. The offset is net of
+/// [`EstimatorConfig::path_delay`].
+///
+/// `samples` are preprocessed as configured in `config`. Fails like
+/// [`estimate_samples`](crate::estimate_samples), and with [`EstimateError::InvalidConfig`]
+/// naming `prior` unless every prior parameter is finite and positive.
+pub fn estimate_bayesian<I>(
+    samples: I,
+    prior: &DelayPrior,
+    config: &EstimatorConfig,
+) -> Result<Posterior, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    if !prior.is_valid() {
+        return Err(EstimateError::InvalidConfig { field: "prior" });
+    }
+    let DelayPrior {
+        shape,
+        scale,
+        strength,
+    } = *prior;
+    let mut samples: Vec<Sample> = samples.into_iter().collect();
+    let prepared = prepare(&mut samples, config)?;
+
+    // Shape grid at equally likely prior quantiles, so every point carries the same prior mass.
+    let shapes: Vec<f64> = (0..SHAPE_GRID)
+        .map(|k| {
+            let p = (k as f64 + 0.5) / SHAPE_GRID as f64;
+            shape / strength * math::gamma_quantile(strength, p)
+        })
+        .collect();
+    // Conjugate Gamma(a0, b0) prior on the rate 1 / β.
+    let a0 = strength * shape;
+    let b0 = strength * shape * scale;
+
+    let (censored, observed): (Vec<Sample>, Vec<Sample>) = samples.iter().partition(|s| s.censored);
+    let w_sum = math::sum(observed.iter().map(|s| s.weight), config.precise);
+    let min = observed
+        .iter()
+        .map(|s| s.value)
+        .fold(f64::INFINITY, f64::min);
+    let mean = math::sum(observed.iter().map(|s| s.weight * s.value), config.precise) / w_sum;
+    // The offset lies below the smallest sample, by about the smallest delay.
+    let span = 2.0 * (mean - min).max(shape * scale);
+    let step = span / OFFSET_GRID as f64;
+
+    let mut grid = Vec::with_capacity(OFFSET_GRID);
+    let mut cells = Vec::with_capacity(OFFSET_GRID * SHAPE_GRID);
+    for j in 0..OFFSET_GRID {
+        let offset = min - span + (j as f64 + 0.5) * step;
+        let log_delays = math::sum(
+            observed
+                .iter()
+                .map(|s| s.weight * float::ln(s.value - offset)),
+            config.precise,
+        );
+        let delays = math::sum(
+            observed.iter().map(|s| s.weight * (s.value - offset)),
+            config.precise,
+        );
+        for &alpha in &shapes {
+            let a = a0 + w_sum * alpha;
+            let b = b0 + delays;
+            // Posterior mean of β given the offset and shape, that of an inverse Gamma.
+            let beta = b / (a - 1.0);
+            let log_survival = math::sum(
+                censored.iter().map(|s| {
+                    let exceed = math::gamma_sf(alpha, beta, (s.value - offset).max(0.0));
+                    s.weight * float::ln(exceed)
+                }),
+                config.precise,
+            );
+            let log_posterior = (alpha - 1.0) * log_delays - w_sum * libm::lgamma(alpha)
+                + libm::lgamma(a)
+                - a * float::ln(b)
+                + log_survival;
+            cells.push((log_posterior, alpha, beta));
+        }
+        grid.push((offset, 0.0));
+    }
+
+    // Normalize in log space to avoid underflow.
+    let peak = cells.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max);
+    let mut total = 0.0;
+    let (mut offset_mean, mut shape_mean, mut scale_mean) = (0.0, 0.0, 0.0);
+    for (j, row) in cells.chunks(SHAPE_GRID).enumerate() {
+        for &(log_posterior, alpha, beta) in row {
+            let weight = float::exp(log_posterior - peak);
+            grid[j].1 += weight;
+            shape_mean += weight * alpha;
+            scale_mean += weight * beta;
+        }
+        offset_mean += grid[j].1 * grid[j].0;
+        total += grid[j].1;
+    }
+    for cell in grid.iter_mut() {
+        cell.0 -= prepared.shift + config.path_delay;
+        cell.1 /= total;
+    }
+
+    Ok(Posterior {
+        offset: offset_mean / total - prepared.shift - config.path_delay,
+        shape: shape_mean / total,
+        scale: scale_mean / total,
+        samples: samples.len(),
+        grid,
+        step,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset_estimator::LcgRng;
+
+    /// `n` samples of `offset` plus an Erlang(2) delay with the given scale.
+    fn shifted_erlang(n: usize, offset: f64, scale: f64, seed: u64) -> Vec<Sample> {
+        let mut rng = LcgRng::new(seed);
+        let mut exponential = move || -scale * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        (0..n)
+            .map(|_| Sample::new(offset + exponential() + exponential()))
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_bayesian_small_batch() {
+        let prior = DelayPrior {
+            shape: 2.0,
+            scale: 10.0,
+            strength: 20.0,
+        };
+        let samples = shifted_erlang(10, 50.0, 10.0, 4);
+        let posterior = estimate_bayesian(samples, &prior, &EstimatorConfig::default()).unwrap();
+
+        let (lower, upper) = posterior.credible_interval(0.95);
+        assert!(lower < posterior.offset && posterior.offset < upper);
+        assert!(lower < 50.0 && 50.0 < upper, "Interval {lower}..{upper}");
+        assert!(
+            (posterior.offset - 50.0).abs() < 10.0,
+            "Offset {}",
+            posterior.offset
+        );
+        assert!((posterior.shape - 2.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_estimate_bayesian_censored_and_path_delay() {
+        let prior = DelayPrior {
+            shape: 2.0,
+            scale: 10.0,
+            strength: 5.0,
+        };
+        let config = EstimatorConfig::default();
+        let samples = shifted_erlang(30, 50.0, 10.0, 6);
+        let clean = estimate_bayesian(samples.clone(), &prior, &config).unwrap();
+        // The same probes with a timeout at 90, beyond which they are lost.
+        let timed_out = samples.iter().map(|s| match s.value > 90.0 {
+            true => Sample::timed_out(90.0),
+            false => *s,
+        });
+        let posterior = estimate_bayesian(timed_out, &prior, &config).unwrap();
+        assert!(
+            (posterior.offset - clean.offset).abs() < 1.0,
+            "{} against {}",
+            posterior.offset,
+            clean.offset
+        );
+        assert_eq!(posterior.samples, 30);
+
+        let config = EstimatorConfig {
+            path_delay: 3.0,
+            ..Default::default()
+        };
+        let shifted = estimate_bayesian(samples, &prior, &config).unwrap();
+        assert!((clean.offset - shifted.offset - 3.0).abs() < 1e-9);
+        let (lower, _) = shifted.credible_interval(1.0);
+        assert!((clean.credible_interval(1.0).0 - lower - 3.0).abs() < 1e-9);
+        assert_eq!(
+            estimate_bayesian(
+                [Sample::timed_out(1.0); 12],
+                &prior,
+                &EstimatorConfig::default()
+            ),
+            Err(EstimateError::InsufficientSamples { got: 0, need: 10 })
+        );
+    }
+
+    #[test]
+    fn test_estimate_bayesian_invalid_prior() {
+        let prior = DelayPrior {
+            shape: 2.0,
+            scale: f64::NAN,
+            strength: 1.0,
+        };
+        let samples = shifted_erlang(20, 0.0, 1.0, 1);
+        assert_eq!(
+            estimate_bayesian(samples, &prior, &EstimatorConfig::default()),
+            Err(EstimateError::InvalidConfig { field: "prior" })
+        );
+    }
+}
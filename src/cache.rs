@@ -0,0 +1,192 @@
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+use crate::error::EstimateError;
+use crate::holdover::Holdover;
+use crate::offset_estimator::Estimate;
+use crate::online::{OnlineEstimator, SampleStore};
+use crate::sample::Sample;
+
+/// Staleness policy of [`CachedEstimator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    /// New samples after which the cached estimate is recomputed.
+    pub refresh_after: usize,
+    /// Age after which the cached estimate is recomputed.
+    pub max_age: Duration,
+    /// Duration of one timestamp unit of the samples, over which drift and wander are expressed.
+    pub unit: Duration,
+    /// Frequency random walk of the clock, see [`Holdover::wander`]. `1e-8` by default, about a
+    /// free-running quartz oscillator with samples in seconds and a unit of one second, so that
+    /// cached estimates age without detrending too; scale it with the units of the samples.
+    pub wander: f64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            refresh_after: 16,
+            max_age: Duration::from_secs(60),
+            unit: Duration::from_secs(1),
+            wander: 1e-8,
+        }
+    }
+}
+
+/// [`OnlineEstimator`] that serves its last estimate until it goes stale, for request-driven
+/// servers that would otherwise refit the window on every query.
+///
+/// The estimate is recomputed once [`CacheConfig::refresh_after`] samples arrived since, or once
+/// it is [`CacheConfig::max_age`] old. In between it is carried forward like a [`Holdover`] over
+/// its age, so its offset follows the drift and its uncertainty grows with the drift uncertainty
+/// and the wander.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::{CacheConfig, CachedEstimator, EstimatorConfig, OnlineEstimator};
+///
+/// let online = OnlineEstimator::new(EstimatorConfig::default(), 1000);
+/// let mut cached = CachedEstimator::new(online, CacheConfig::default());
+/// for owd in [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36] {
+///     cached.push(owd);
+/// }
+/// let first = cached.estimate(Duration::from_secs(100)).unwrap();
+/// cached.push(0.30);
+/// // One new sample and a second later, the estimate is served from the cache.
+/// assert_eq!(cached.estimate(Duration::from_secs(101)).unwrap().offset, first.offset);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedEstimator<S = VecDeque<Sample>> {
+    online: OnlineEstimator<S>,
+    config: CacheConfig,
+    /// Last result and the time it was computed.
+    cached: Option<(Result<Estimate, EstimateError>, Duration)>,
+    /// Samples pushed since the last result.
+    pending: usize,
+}
+
+impl<S: SampleStore> CachedEstimator<S> {
+    pub fn new(online: OnlineEstimator<S>, config: CacheConfig) -> Self {
+        CachedEstimator {
+            online,
+            config,
+            cached: None,
+            pending: 0,
+        }
+    }
+
+    /// Adds a sample to the window of the underlying estimator.
+    pub fn push(&mut self, sample: impl Into<Sample>) {
+        self.online.push(sample);
+        self.pending = self.pending.saturating_add(1);
+    }
+
+    /// The estimate at time `now`, recomputed if the cached one is stale. Errors are cached like
+    /// estimates, until the next refresh.
+    pub fn estimate(&mut self, now: Duration) -> Result<Estimate, EstimateError> {
+        let (result, computed) = match &self.cached {
+            Some(cached) if !self.is_stale(now) => cached.clone(),
+            _ => {
+                let cached = (self.online.estimate(), now);
+                self.cached = Some(cached.clone());
+                self.pending = 0;
+                cached
+            }
+        };
+        // A clock stepped back makes the cached estimate fresh rather than negatively aged.
+        let age = now.saturating_sub(computed);
+        let estimate = result?;
+        Ok(Holdover::from_estimate(estimate, self.config.unit)
+            .with_wander(self.config.wander)
+            .predict(age))
+    }
+
+    /// Whether [`estimate`](Self::estimate) at `now` recomputes the estimate.
+    pub fn is_stale(&self, now: Duration) -> bool {
+        match &self.cached {
+            Some((_, computed)) => {
+                self.pending >= self.config.refresh_after
+                    || now.saturating_sub(*computed) >= self.config.max_age
+            }
+            None => true,
+        }
+    }
+
+    /// Drops the cached estimate, e.g. after a step of the local clock.
+    pub fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    /// Samples pushed since the cached estimate was computed.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn online(&self) -> &OnlineEstimator<S> {
+        &self.online
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EstimatorConfig;
+
+    #[test]
+    fn test_cached_estimator_staleness() {
+        let online = OnlineEstimator::new(EstimatorConfig::default(), 100);
+        let mut cached = CachedEstimator::new(
+            online,
+            CacheConfig {
+                refresh_after: 5,
+                max_age: Duration::from_secs(10),
+                wander: 0.1,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            cached.estimate(Duration::ZERO),
+            Err(EstimateError::InsufficientSamples { got: 0, need: 10 })
+        );
+        for i in 0..20 {
+            cached.push(f64::from(i % 7));
+        }
+        let at = Duration::from_secs(1);
+        assert!(cached.is_stale(at));
+        let first = cached.estimate(at).unwrap();
+        assert_eq!(cached.pending(), 0);
+
+        // Cached: aged by the wander, without a new fit.
+        for _ in 0..4 {
+            cached.push(100.0);
+        }
+        let later = cached.estimate(Duration::from_secs(4)).unwrap();
+        assert_eq!(later.offset, first.offset);
+        assert!(later.uncertainty > first.uncertainty);
+
+        // The fifth sample forces a refresh, as does the age.
+        cached.push(100.0);
+        assert!(cached.is_stale(Duration::from_secs(4)));
+        let refreshed = cached.estimate(Duration::from_secs(4)).unwrap();
+        assert_eq!(refreshed.samples, 25);
+        assert!(!cached.is_stale(Duration::from_secs(13)));
+        assert!(cached.is_stale(Duration::from_secs(14)));
+    }
+
+    #[test]
+    fn test_cached_estimator_ages_by_default() {
+        let online = OnlineEstimator::new(EstimatorConfig::default(), 100);
+        let mut cached = CachedEstimator::new(online, CacheConfig::default());
+        for i in 0..20 {
+            cached.push(0.3 + 0.01 * f64::from(i % 7));
+        }
+        let first = cached.estimate(Duration::ZERO).unwrap();
+        let later = cached.estimate(Duration::from_secs(59)).unwrap();
+        assert_eq!(later.offset, first.offset);
+        assert!(later.uncertainty > first.uncertainty);
+    }
+}
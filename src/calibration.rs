@@ -0,0 +1,331 @@
+use alloc::vec::Vec;
+
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::float;
+use crate::offset_estimator::{estimate_samples, Estimate};
+use crate::sample::Sample;
+
+/// One peer-delay exchange in the manner of gPTP: the request leaves at `t1` and arrives at
+/// `t2`, the response leaves at `t3` and arrives at `t4`. `t1` and `t4` are local times, `t2` and
+/// `t3` those of the peer, all in the unit of the samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerDelayExchange {
+    pub t1: f64,
+    pub t2: f64,
+    pub t3: f64,
+    pub t4: f64,
+}
+
+/// What is known about the calibration link, see [`calibrate_asymmetry`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationReference {
+    /// The offset of the peer clock from the local one, local minus peer time; zero for a
+    /// loopback, where both ends run on the same clock.
+    Offset(f64),
+    /// The forward delay of the link, e.g. from a cable of known length and the known latencies
+    /// of the ports.
+    ForwardDelay(f64),
+}
+
+/// Static path asymmetry found by [`calibrate_asymmetry`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AsymmetryCalibration {
+    /// Half the difference between the forward and the reverse delay floors.
+    pub asymmetry: f64,
+    /// Standard error of `asymmetry`.
+    pub uncertainty: f64,
+    /// Mean of the forward and reverse delay floors over the calibration link.
+    pub mean_path_delay: f64,
+}
+
+impl AsymmetryCalibration {
+    /// Forward delay of a path of `mean_path_delay` with the calibrated asymmetry, the
+    /// [`EstimatorConfig::path_delay`] that corrects subsequent estimates.
+    pub fn path_delay(&self, mean_path_delay: f64) -> f64 {
+        mean_path_delay + self.asymmetry
+    }
+}
+
+/// Solves for the static asymmetry of a path from bidirectional peer-delay exchanges over a link
+/// whose offset or forward delay is known, e.g. a loopback or a cable of known length.
+///
+/// The forward one-way delays `t2 - t1` and the reverse ones, measured the other way round as
+/// `t4 - t3`, are estimated separately. Their offsets are the delay floors of each direction
+/// shifted by the clock offset in opposite directions, so their mean is the mean path delay
+/// whatever the clocks do, and their half difference the asymmetry once the offset of the
+/// `reference` is taken out. Delays follow the sign convention of the estimator: local receive
+/// time minus the send time of the other side.
+///
+/// Fails like [`estimate_samples`](crate::estimate_samples) on either direction.
+pub fn calibrate_asymmetry(
+    exchanges: &[PeerDelayExchange],
+    reference: CalibrationReference,
+    config: &EstimatorConfig,
+) -> Result<AsymmetryCalibration, EstimateError> {
+    let config = EstimatorConfig {
+        path_delay: 0.0,
+        ..config.clone()
+    };
+    // As seen from the peer, `t2 - t1` carries the offset of the local clock with the
+    // opposite sign of `t4 - t3`.
+    let forward: Vec<Sample> = exchanges.iter().map(|e| Sample::new(e.t2 - e.t1)).collect();
+    let reverse: Vec<Sample> = exchanges.iter().map(|e| Sample::new(e.t4 - e.t3)).collect();
+    let forward = estimate_samples(forward, &config)?;
+    let reverse = estimate_samples(reverse, &config)?;
+    // forward = d_f - θ and reverse = d_r + θ, with θ the local minus peer time.
+    let mean_path_delay = (forward.offset + reverse.offset) / 2.0;
+    let forward_delay = match reference {
+        CalibrationReference::Offset(offset) => forward.offset + offset,
+        CalibrationReference::ForwardDelay(delay) => delay,
+    };
+    Ok(AsymmetryCalibration {
+        asymmetry: forward_delay - mean_path_delay,
+        uncertainty: float::sqrt(
+            forward.uncertainty * forward.uncertainty + reverse.uncertainty * reverse.uncertainty,
+        ) / 2.0,
+        mean_path_delay,
+    })
+}
+
+/// Offset found by [`estimate_bidirectional`] from the delays in both directions of a path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidirectionalEstimate {
+    /// Offset of the clock receiving the forward delays from the one sending them, the receiver
+    /// minus the sender time, like the offsets of [`estimate_samples`](crate::estimate_samples)
+    /// with the forward delay as [`EstimatorConfig::path_delay`].
+    pub offset: f64,
+    /// Standard error of `offset`.
+    pub uncertainty: f64,
+    /// Mean of the forward and reverse delay floors, see [`AsymmetryCalibration`].
+    pub mean_path_delay: f64,
+    /// Estimate of the forward delays alone.
+    pub forward: Estimate,
+    /// Estimate of the reverse delays alone.
+    pub reverse: Estimate,
+}
+
+/// Estimates the offset between two clocks from the one-way delays in both directions of the
+/// path between them, e.g. of probes sent both ways.
+///
+/// Each direction is estimated like [`estimate_samples`](crate::estimate_samples) does, without
+/// the [path delay](EstimatorConfig::path_delay). Their offsets are the delay floors of the
+/// directions shifted by the clock offset in opposite directions, so half their difference is
+/// the clock offset, less the `asymmetry` of the path, half the difference between its forward
+/// and reverse floors: zero for a symmetric path, or as found by [`calibrate_asymmetry`] with
+/// its forward direction the same. Unlike the offset of a single direction, it does not need
+/// the delay floor to be known, and it averages two fits, so its uncertainty is that of either
+/// over `√2` when they are alike.
+///
+/// The forward delays are measured at the receiver as its receive time minus the send time of
+/// the sender, and the reverse delays the other way round, at the sender.
+///
+/// Fails like [`estimate_samples`](crate::estimate_samples) on either direction.
+pub fn estimate_bidirectional<F, R>(
+    forward: F,
+    reverse: R,
+    asymmetry: f64,
+    config: &EstimatorConfig,
+) -> Result<BidirectionalEstimate, EstimateError>
+where
+    F: IntoIterator<Item = Sample>,
+    R: IntoIterator<Item = Sample>,
+{
+    let config = EstimatorConfig {
+        path_delay: 0.0,
+        ..config.clone()
+    };
+    let forward = estimate_samples(forward, &config)?;
+    let reverse = estimate_samples(reverse, &config)?;
+    // forward = d_f + θ and reverse = d_r - θ, with θ the receiver minus the sender time and
+    // d_f - d_r = 2 · asymmetry.
+    Ok(BidirectionalEstimate {
+        offset: (forward.offset - reverse.offset) / 2.0 - asymmetry,
+        uncertainty: float::sqrt(
+            forward.uncertainty * forward.uncertainty + reverse.uncertainty * reverse.uncertainty,
+        ) / 2.0,
+        mean_path_delay: (forward.offset + reverse.offset) / 2.0,
+        forward,
+        reverse,
+    })
+}
+
+/// Round trips split into their directions by [`decompose_round_trips`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RttDecomposition {
+    /// Offset of the peer clock from the local one, local minus peer time, for delay floors
+    /// that are the same both ways.
+    pub offset: f64,
+    /// Standard error of `offset` from the fits alone.
+    pub uncertainty: f64,
+    /// Standard deviation of the error of `offset` from the unknown asymmetry of the floors,
+    /// `R / (2√3)` for the round-trip floor `R` split anywhere between the directions. A
+    /// [calibration](calibrate_asymmetry) of the path removes it.
+    pub asymmetry_uncertainty: f64,
+    /// Mean forward delay, half the round-trip floor and the mean queueing of the forward fit.
+    pub forward_delay: f64,
+    /// Mean reverse delay, half the round-trip floor and the mean queueing of the reverse fit.
+    pub reverse_delay: f64,
+    /// Estimate of the round trips net of the turnaround `t3 - t2`, whose offset is the
+    /// round-trip floor.
+    pub round_trip: Estimate,
+    /// Estimate of the forward delays `t2 - t1`.
+    pub forward: Estimate,
+    /// Estimate of the reverse delays `t4 - t3`.
+    pub reverse: Estimate,
+}
+
+/// Decomposes round trips, `t4 - t1` less the turnaround `t3 - t2` of the peer, into their
+/// forward and reverse delays under the gamma model, for an offset where only round trips were
+/// measured.
+///
+/// Each direction is the delay floor plus gamma-distributed queueing. The clock offset only
+/// moves the location of the one-way delays, so the [shape](Estimate::shape) and
+/// [scale](Estimate::scale) of their fits describe the queueing of each direction exactly,
+/// whatever the clocks do. The floors, however, are only known through their sum, the floor of
+/// the round trips: the offset assumes they are equal, as NTP does, and reports the spread of
+/// its error if they are not as `asymmetry_uncertainty`.
+///
+/// Fails like [`estimate_samples`](crate::estimate_samples) on the round trips or either
+/// direction.
+pub fn decompose_round_trips(
+    exchanges: &[PeerDelayExchange],
+    config: &EstimatorConfig,
+) -> Result<RttDecomposition, EstimateError> {
+    let config = EstimatorConfig {
+        path_delay: 0.0,
+        ..config.clone()
+    };
+    let round_trips: Vec<Sample> = exchanges
+        .iter()
+        .map(|e| Sample::new((e.t4 - e.t1) - (e.t3 - e.t2)))
+        .collect();
+    let forward: Vec<Sample> = exchanges.iter().map(|e| Sample::new(e.t2 - e.t1)).collect();
+    let reverse: Vec<Sample> = exchanges.iter().map(|e| Sample::new(e.t4 - e.t3)).collect();
+    let round_trip = estimate_samples(round_trips, &config)?;
+    let forward = estimate_samples(forward, &config)?;
+    let reverse = estimate_samples(reverse, &config)?;
+    // forward = d_f - θ and reverse = d_r + θ, with θ the local minus peer time, and
+    // d_f = d_r = R / 2.
+    let floor = round_trip.offset;
+    Ok(RttDecomposition {
+        offset: (reverse.offset - forward.offset) / 2.0,
+        uncertainty: float::sqrt(
+            forward.uncertainty * forward.uncertainty + reverse.uncertainty * reverse.uncertainty,
+        ) / 2.0,
+        asymmetry_uncertainty: libm::fabs(floor) / (2.0 * float::sqrt(3.0)),
+        forward_delay: floor / 2.0 + forward.shape * forward.scale,
+        reverse_delay: floor / 2.0 + reverse.shape * reverse.scale,
+        round_trip,
+        forward,
+        reverse,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset_estimator::LcgRng;
+
+    #[test]
+    fn test_calibrate_asymmetry() {
+        // The forward path is 200 slower than the reverse one, around a mean of 900.
+        let mut rng = LcgRng::new(9);
+        let mut delay = move || -2.0 * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        let exchanges = |offset: f64, delay: &mut dyn FnMut() -> f64| -> Vec<PeerDelayExchange> {
+            (0..500)
+                .map(|i| {
+                    let t1 = 1e4 * i as f64;
+                    let t2 = t1 + 1000.0 + delay() - offset;
+                    let t3 = t2 + 50.0;
+                    let t4 = t3 + 800.0 + delay() + offset;
+                    PeerDelayExchange { t1, t2, t3, t4 }
+                })
+                .collect()
+        };
+        let config = EstimatorConfig::default();
+        let loopback = exchanges(0.0, &mut delay);
+        let calibration =
+            calibrate_asymmetry(&loopback, CalibrationReference::Offset(0.0), &config).unwrap();
+        assert!(
+            (calibration.asymmetry - 100.0).abs() < 1.0,
+            "{calibration:?}"
+        );
+        assert!(
+            (calibration.mean_path_delay - 900.0).abs() < 3.0,
+            "{calibration:?}"
+        );
+        assert!(calibration.uncertainty > 0.0);
+
+        // A known cable gives the same answer on clocks with an unknown offset, up to the bias
+        // of the delay floors that the loopback cancels.
+        let cable = exchanges(250.0, &mut delay);
+        let by_delay =
+            calibrate_asymmetry(&cable, CalibrationReference::ForwardDelay(1000.0), &config)
+                .unwrap();
+        assert!((by_delay.asymmetry - 100.0).abs() < 3.0, "{by_delay:?}");
+
+        // Corrected with the calibrated forward delay, the offset is that of the clocks.
+        let corrected = EstimatorConfig {
+            path_delay: calibration.path_delay(by_delay.mean_path_delay),
+            ..Default::default()
+        };
+        let owd = cable.iter().map(|e| Sample::new(e.t2 - e.t1));
+        let estimate = estimate_samples(owd, &corrected).unwrap();
+        assert!((estimate.offset + 250.0).abs() < 2.0, "{estimate:?}");
+    }
+
+    #[test]
+    fn test_estimate_bidirectional() {
+        let mut rng = LcgRng::new(5);
+        let mut delay = move || -5.0 * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        // The receiver is 40 ahead, over a path 1000 forward and 800 back.
+        let forward: Vec<Sample> = (0..300)
+            .map(|_| Sample::new(1000.0 + delay() + delay() + 40.0))
+            .collect();
+        let reverse: Vec<Sample> = (0..300)
+            .map(|_| Sample::new(800.0 + delay() + delay() - 40.0))
+            .collect();
+        let config = EstimatorConfig::default();
+        let symmetric =
+            estimate_bidirectional(forward.clone(), reverse.clone(), 0.0, &config).unwrap();
+        assert!((symmetric.offset - 140.0).abs() < 3.0, "{symmetric:?}");
+        assert!(
+            (symmetric.mean_path_delay - 900.0).abs() < 3.0,
+            "{symmetric:?}"
+        );
+
+        let calibrated = estimate_bidirectional(forward, reverse, 100.0, &config).unwrap();
+        assert!((calibrated.offset - 40.0).abs() < 3.0, "{calibrated:?}");
+        let single = calibrated
+            .forward
+            .uncertainty
+            .min(calibrated.reverse.uncertainty);
+        assert!(calibrated.uncertainty < single);
+    }
+
+    #[test]
+    fn test_decompose_round_trips() {
+        // A symmetric floor of 1000, with three times the queueing forward.
+        let mut rng = LcgRng::new(3);
+        let mut delay = move || -2.0 * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        let exchanges: Vec<PeerDelayExchange> = (0..500)
+            .map(|i| {
+                let t1 = 1e4 * i as f64;
+                let t2 = t1 + 1000.0 + delay() + delay() + delay() - 250.0;
+                let t3 = t2 + 50.0 + delay();
+                let t4 = t3 + 1000.0 + delay() + 250.0;
+                PeerDelayExchange { t1, t2, t3, t4 }
+            })
+            .collect();
+        let decomposition = decompose_round_trips(&exchanges, &EstimatorConfig::default()).unwrap();
+        assert!(
+            (decomposition.offset - 250.0).abs() < 2.0,
+            "{decomposition:?}"
+        );
+        assert!((decomposition.round_trip.offset - 2000.0).abs() < 3.0);
+        let queueing = decomposition.forward_delay - decomposition.reverse_delay;
+        assert!((queueing - 4.0).abs() < 1.5, "{decomposition:?}");
+        assert!((decomposition.asymmetry_uncertainty - 577.4).abs() < 1.0);
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::EstimateError;
+
+/// Flag for abandoning a long-running computation, e.g. when fresher data has arrived.
+///
+/// The [`BatchEstimator`](crate::BatchEstimator), [`sample_posterior`](crate::sample_posterior)
+/// and [`fit_mixture`](crate::fit_mixture) check the token of their configuration between chunks
+/// of work, before each link, every few dozen iterations of the chain and at each EM iteration,
+/// and stop with [`EstimateError::Cancelled`] once it is cancelled. Clones share the flag, so
+/// one clone goes into the configuration and another stays with whoever cancels.
+///
+/// Without an allocator, the flag is a `static`:
+///
+/// ```
+/// use core::sync::atomic::AtomicBool;
+/// use gamlr::{CancelToken, McmcConfig};
+///
+/// static STALE: AtomicBool = AtomicBool::new(false);
+///
+/// let config = McmcConfig { cancel: Some(CancelToken::from_static(&STALE)), ..Default::default() };
+/// // On fresher data, from another thread or an interrupt handler:
+/// CancelToken::from_static(&STALE).cancel();
+/// # let _ = config;
+/// ```
+#[derive(Clone)]
+pub struct CancelToken(Flag);
+
+#[derive(Clone)]
+enum Flag {
+    Static(&'static AtomicBool),
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    Shared(Arc<AtomicBool>),
+}
+
+impl CancelToken {
+    /// A token on a fresh flag, not cancelled.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub fn new() -> Self {
+        CancelToken(Flag::Shared(Arc::new(AtomicBool::new(false))))
+    }
+
+    /// A token on `flag`, for targets without an allocator.
+    pub const fn from_static(flag: &'static AtomicBool) -> Self {
+        CancelToken(Flag::Static(flag))
+    }
+
+    fn flag(&self) -> &AtomicBool {
+        match &self.0 {
+            Flag::Static(flag) => flag,
+            #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+            Flag::Shared(flag) => flag,
+        }
+    }
+
+    /// Cancels the computations holding this token or a clone of it.
+    pub fn cancel(&self) {
+        self.flag().store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag().load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag, so that the token can be handed to the next computation.
+    pub fn reset(&self) {
+        self.flag().store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Tokens are equal when they share their flag.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self.flag(), other.flag())
+    }
+}
+
+/// Fails with [`EstimateError::Cancelled`] when `token` is cancelled.
+pub(crate) fn check(token: &Option<CancelToken>) -> Result<(), EstimateError> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(EstimateError::Cancelled),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,537 @@
+use crate::cancel::CancelToken;
+use crate::event::Event;
+
+/// How samples that are NaN or infinite are treated before estimation.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum NonFinitePolicy {
+    /// Fail with [`EstimateError::NonFiniteSample`](crate::EstimateError::NonFiniteSample).
+    #[default]
+    Reject,
+    /// Discard non-finite samples and estimate from the remaining ones.
+    Drop,
+    /// Substitute every non-finite sample with the given value.
+    Replace(f64),
+}
+
+/// How negative samples, which fall outside the support of the Gamma model, are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum NegativePolicy {
+    /// Fit the samples as they are.
+    #[default]
+    Allow,
+    /// Fail with [`EstimateError::NegativeSample`](crate::EstimateError::NegativeSample).
+    Reject,
+    /// Translate the samples so the smallest one sits at zero, fit, and translate the offset back.
+    Shift,
+}
+
+/// Treatment of the distribution tails before fitting, as a defense against congestion outliers.
+///
+/// Fractions are of the uncensored sample count, e.g. `upper: 0.05` affects the largest 5% of the
+/// samples received, censored ones left aside, and each in `[0, 0.5)`, failing with
+/// [`EstimateError::InvalidConfig`](crate::EstimateError) naming `fraction` otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum TailPolicy {
+    /// Use all samples.
+    #[default]
+    Keep,
+    /// Discard the `lower` fraction of smallest and the `upper` fraction of largest samples.
+    Trim { lower: f64, upper: f64 },
+    /// Clamp the `lower` fraction of smallest and the `upper` fraction of largest samples to the
+    /// nearest retained value, preserving the sample count for small batches.
+    Winsorize { lower: f64, upper: f64 },
+    /// Discard outliers one at a time by the two-sided Grubbs test at the `significance` level,
+    /// e.g. 0.05, until the most extreme sample is no longer one. A statistical criterion
+    /// rather than a fixed fraction, so clean batches lose nothing; but the test assumes
+    /// normally distributed samples, and may take the long right tail of a strongly skewed
+    /// delay distribution for outliers.
+    Grubbs { significance: f64 },
+    /// Discard up to `max_outliers` outliers at once by the generalized extreme studentized
+    /// deviate test at the `significance` level. Unlike [`Grubbs`](Self::Grubbs), it is not
+    /// masked by several congestion spikes of similar height, and the same normality caveat
+    /// applies.
+    GeneralizedEsd {
+        max_outliers: usize,
+        significance: f64,
+    },
+}
+
+/// How the quantization noise of a coarse timer is removed before fitting, see
+/// [`EstimatorConfig::resolution`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum QuantizationNoise {
+    /// Place the samples sharing a reading at their quantiles within its interval under the
+    /// fitted model, refitting until the offset settles. The most faithful, at the cost of a
+    /// fit per iteration.
+    #[default]
+    Model,
+    /// Add uniform noise of one resolution, seeded by [`EstimatorConfig::seed`], to every
+    /// reading, which breaks up the staircase in a single fit. The dithered samples fill the
+    /// interval around the delay floor evenly, below the floor too, so the offset keeps a bias
+    /// of up to half a resolution towards smaller values.
+    Dither,
+}
+
+/// Leap seconds of the UTC timescale the sample timestamps are in, see [`LeapFilter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+// Callbacks compare by address, like the hook.
+#[allow(unpredictable_function_pointer_comparisons)]
+pub enum LeapSeconds {
+    /// UTC instants at which leap seconds took or will take effect, in the unit of the
+    /// timestamps, e.g. from the IERS `leap-seconds.list`.
+    Table(&'static [f64]),
+    /// TAI − UTC at a UTC timestamp, e.g. from the system's time zone database; a leap second
+    /// is wherever it changes.
+    Offset(fn(f64) -> f64),
+}
+
+/// Exclusion of the samples timestamped near a leap second, which the two clocks may not apply
+/// at the same instant, see [`EstimatorConfig::leap`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeapFilter {
+    pub leaps: LeapSeconds,
+    /// Half width of the exclusion zone around every leap second, in the unit of the
+    /// timestamps: at least the longest delay, so that no probe spans the leap, and as long as
+    /// a clock may take to apply or smear it.
+    pub window: f64,
+}
+
+impl LeapFilter {
+    /// Whether the sample timestamped `timestamp` falls within [`window`](Self::window) of a
+    /// leap second, e.g. to flag samples at ingestion.
+    pub fn spans_leap(&self, timestamp: f64) -> bool {
+        let window = self.window;
+        match self.leaps {
+            LeapSeconds::Table(leaps) => leaps
+                .iter()
+                .any(|&leap| timestamp - window < leap && leap <= timestamp + window),
+            LeapSeconds::Offset(tai_utc) => {
+                tai_utc(timestamp - window) != tai_utc(timestamp + window)
+            }
+        }
+    }
+}
+
+/// Source of the synthetic Gamma sample the measured samples are regressed against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SyntheticSample {
+    /// Draw a random sample from the fitted Gamma distribution, as in the original method.
+    /// The offset depends on [`EstimatorConfig::seed`].
+    #[default]
+    Random,
+    /// Use the theoretical quantiles of the fitted Gamma distribution at the plotting positions.
+    /// Fully deterministic and free of sampling noise; the seed is ignored.
+    Quantiles,
+}
+
+/// Estimator of the Gamma parameters from the samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum GammaFit {
+    /// Method of moments on the sample mean and variance, as in the original method.
+    #[default]
+    Moments,
+    /// Probability-weighted moments `b0` and `b1` of the sorted samples. Linear in the samples,
+    /// hence less affected by the long right tail of small skewed batches than the variance.
+    ProbabilityWeighted,
+    /// L-moments `λ2` and `τ3`, matching the shape and scale of the delays without assuming
+    /// the offset: nearly unbiased on small batches and far less sensitive to outliers than the
+    /// ordinary moments.
+    LMoments,
+}
+
+/// Line fitted to the quantile–quantile points, the samples shifted by their plotting positions
+/// against the synthetic values.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Regression {
+    /// Ordinary least squares of the synthetic values on the samples, as in the original method.
+    /// All the noise is attributed to the synthetic values, so noise in the samples attenuates
+    /// the slope.
+    #[default]
+    LeastSquares,
+    /// Deming regression, attributing noise to both axes. `variance_ratio` is the error variance
+    /// of the synthetic values over that of the samples, both in sample units; one gives the
+    /// orthogonal regression.
+    Deming { variance_ratio: f64 },
+    /// Passing–Bablok regression on the median of the pairwise slopes: robust to outliers and
+    /// free of assumptions on the errors, but quadratic in the number of samples, see
+    /// [`linfit::fit_passing_bablok`](crate::linfit::fit_passing_bablok). Pairs well with
+    /// [`EstimatorConfig::fast`] on large batches.
+    PassingBablok,
+    /// Siegel's repeated-median regression, the most robust option for hostile traces: the line
+    /// stays with the majority of the samples while up to half of them are outliers. Quadratic
+    /// in the number of samples too, see
+    /// [`linfit::fit_repeated_median`](crate::linfit::fit_repeated_median).
+    RepeatedMedian,
+    /// Least trimmed squares over the `coverage` fraction of the samples, from 0.5 to 1, of
+    /// smallest residuals: robust to the remaining fraction of outliers while staying a
+    /// least-squares fit, see [`linfit::fit_trimmed_squares`](crate::linfit::fit_trimmed_squares).
+    /// A `coverage` of 0.75 is a common compromise with the efficiency of the fit.
+    TrimmedSquares { coverage: f64 },
+}
+
+/// Bounds on the iterative numerical solvers, so that the worst-case execution time of a fit is
+/// known in advance, e.g. on firmware with a deadline.
+///
+/// Each solver stops once its relative change falls below `tolerance` and fails with
+/// [`EstimateError::NotConverged`](crate::EstimateError::NotConverged) naming it if that takes
+/// more than `max_iterations` iterations. The rejection sampler of the random synthetic sample
+/// only honors `max_iterations`, as the number of candidates it may reject per value before it
+/// falls back to the inverse distribution function, see
+/// [`Estimate::sampler_fallbacks`](crate::Estimate::sampler_fallbacks).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct SolverOptions {
+    /// Maximum number of iterations of a single solver run.
+    pub max_iterations: usize,
+    /// Relative change between two iterations below which a solver has converged.
+    pub tolerance: f64,
+}
+
+impl Default for SolverOptions {
+    fn default() -> Self {
+        SolverOptions {
+            max_iterations: 500,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+/// Subsampling of large batches, bounding the work of a fit for real-time loops that prefer a
+/// slightly noisier estimate now to an exact one later. The synthetic sample shrinks with the
+/// samples, so the cost of a fit is that of a batch of `max_samples`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct FastMode {
+    /// Largest number of samples entering the fit, at least 2.
+    pub max_samples: usize,
+    /// How the retained samples are chosen.
+    #[cfg_attr(feature = "toml", serde(default))]
+    pub subsampling: Subsampling,
+}
+
+/// Choice of the samples retained by [`FastMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Subsampling {
+    /// Every k-th sample in input order, with the smallest k that meets the budget.
+    /// Deterministic, and keeps the time structure of the batch.
+    #[default]
+    Stride,
+    /// A uniformly random subset, chosen by reservoir sampling with [`EstimatorConfig::seed`].
+    /// Unaffected by periodic patterns in the input.
+    Reservoir,
+}
+
+/// Coarse-to-fine estimation: a cheap first estimate locates the bulk of the samples, and only
+/// the samples within `width` interquartile ranges of it enter the Gamma regression. Gross
+/// outliers, e.g. probes delayed by a retransmission, are rejected before they can distort the
+/// moment fit, and the refinement works on fewer samples.
+///
+/// Censored samples are always kept. A batch whose interquartile range is zero is kept whole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct CoarseWindow {
+    /// Coarse estimate the window is centered on.
+    #[cfg_attr(feature = "toml", serde(default))]
+    pub center: CoarseCenter,
+    /// Half-width of the window, in interquartile ranges of the uncensored samples. Must be
+    /// finite and positive.
+    pub width: f64,
+}
+
+/// Coarse estimate of a [`CoarseWindow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CoarseCenter {
+    /// The median sample; the window then discards outliers on both sides.
+    #[default]
+    Median,
+    /// The smallest sample, the classic minimum-delay estimate; the window then only reaches
+    /// upwards, keeping the samples closest to the propagation delay.
+    Minimum,
+}
+
+/// Plotting positions, the probabilities at which the sorted samples are paired with the
+/// synthetic Gamma sample.
+///
+/// All are of the form `(i - a) / (n + 1 - 2a)` for the i-th of `n` sorted samples. Which one
+/// is least biased depends on the distribution and on the sample size, mostly for small batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum PlottingPosition {
+    /// `i / (n + 1)`, the mean of the i-th order statistic of a uniform sample.
+    Weibull,
+    /// `(i - 0.375) / (n + 0.25)`, nearly unbiased for normal quantiles.
+    Blom,
+    /// `(i - 0.5) / n`, the midpoint of each sample's probability cell.
+    #[default]
+    Hazen,
+}
+
+impl PlottingPosition {
+    /// Position of the sample of (possibly fractional) rank `rank`, counted from one, among
+    /// `count` samples.
+    pub(crate) fn position(self, rank: f64, count: f64) -> f64 {
+        let a = match self {
+            PlottingPosition::Weibull => 0.0,
+            PlottingPosition::Blom => 0.375,
+            PlottingPosition::Hazen => 0.5,
+        };
+        (rank - a) / (count + 1.0 - 2.0 * a)
+    }
+}
+
+/// Prior belief about the Gamma delay distribution of a path, e.g. from past measurements on it.
+///
+/// The shape gets a Gamma prior and the scale an inverse Gamma prior, both centered on the given
+/// values and as informative as `strength` samples: a strength of 20 weighs like 20 past
+/// measurements. The offset prior is flat. Used by [`estimate_bayesian`](crate::estimate_bayesian) and
+/// [`sample_posterior`](crate::sample_posterior).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayPrior {
+    /// Prior mean of the Gamma shape α.
+    pub shape: f64,
+    /// Prior center of the Gamma scale β, in the unit of the samples.
+    pub scale: f64,
+    /// Weight of the prior in equivalent samples.
+    pub strength: f64,
+}
+
+impl DelayPrior {
+    /// Whether every parameter is finite and positive.
+    pub(crate) fn is_valid(&self) -> bool {
+        [self.shape, self.scale, self.strength]
+            .iter()
+            .all(|p| p.is_finite() && *p > 0.0)
+    }
+}
+
+/// Quality tier of the reference a sample set was measured against, in the manner of the NTP
+/// stratum and root dispersion. Fusion and selection use it to prefer better sources.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct SourceQuality {
+    /// Distance of the reference from a primary time source; lower is better.
+    pub stratum: u8,
+    /// Error the reference itself accumulates relative to the primary source, in the unit of the
+    /// samples. It adds to the statistical uncertainty of the estimate.
+    pub root_dispersion: f64,
+}
+
+/// Configuration for [`estimate_with`](crate::estimate_with) and its variants.
+///
+/// All fields are public; start from [`EstimatorConfig::default`] and override what you need:
+///
+/// ```
+/// use gamlr::{EstimatorConfig, NonFinitePolicy};
+///
+/// let config = EstimatorConfig {
+///     seed: Some(42),
+///     non_finite: NonFinitePolicy::Drop,
+///     ..Default::default()
+/// };
+/// # let _ = config;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "toml",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+// Hooks compare by address: the same function may compare unequal across codegen units.
+#[allow(unpredictable_function_pointer_comparisons)]
+pub struct EstimatorConfig {
+    /// Seed for the synthetic Gamma sample generator. `None` uses a fixed internal seed.
+    pub seed: Option<u64>,
+    /// Whether the synthetic Gamma sample is random or made of theoretical quantiles.
+    pub synthetic: SyntheticSample,
+    /// Plotting positions pairing the sorted samples with the synthetic sample.
+    pub plotting: PlottingPosition,
+    /// Estimator of the Gamma parameters of the synthetic sample.
+    pub fit: GammaFit,
+    /// Line fitted to the samples against the synthetic sample.
+    pub regression: Regression,
+    /// Bounds on the rejection sampler, the quantile solver and the censored-sample EM.
+    pub solver: SolverOptions,
+    /// Number of synthetic samples drawn to measure the seed-dependent spread of the offset,
+    /// reported as [`Estimate::monte_carlo_error`](crate::Estimate::monte_carlo_error). The
+    /// offset itself still comes from the first one. Values below 2 skip the measurement, which
+    /// costs one regression per repetition.
+    pub repetitions: usize,
+    /// Compute the leave-one-out jackknife variance of the offset, reported as
+    /// [`Estimate::jackknife_variance`](crate::Estimate::jackknife_variance). Refits the model
+    /// once per sample, so it is meant for small batches.
+    pub jackknife: bool,
+    /// Treatment of NaN and infinite samples.
+    pub non_finite: NonFinitePolicy,
+    /// Treatment of negative samples, e.g. when the remote clock is ahead.
+    pub negative: NegativePolicy,
+    /// Treatment of the smallest and largest samples.
+    pub tails: TailPolicy,
+    /// Minimum number of samples required after filtering. Values below 2 are treated as 2,
+    /// since the variance estimate is undefined for a single sample.
+    pub min_samples: usize,
+    /// Half-life of the recency decay applied to timestamped samples, in the unit of their
+    /// timestamps. A sample one half-life older than the newest one counts half as much.
+    /// `None` disables the decay; samples without timestamp are never decayed.
+    pub half_life: Option<f64>,
+    /// Remove the linear trend of the timestamped samples before fitting, e.g. the drift of a
+    /// clock running at a slightly different rate during the measurement window, which would
+    /// otherwise bias the fit. The slope is reported as [`Estimate::drift`](crate::Estimate::drift)
+    /// and the offset then refers to the newest timestamp. Samples without timestamp are not
+    /// detrended.
+    pub detrend: bool,
+    /// Drop the timestamped samples around leap seconds, counted in
+    /// [`Estimate::leap_spanning`](crate::Estimate::leap_spanning), before they can make the
+    /// offset jump by a second when one clock applies the leap before the other. `None` keeps
+    /// them; samples without timestamp are always kept.
+    #[cfg_attr(feature = "toml", serde(skip))]
+    pub leap: Option<LeapFilter>,
+    /// Restrict the fit to a window around a coarse estimate. `None` fits every sample.
+    pub coarse: Option<CoarseWindow>,
+    /// Subsample batches above a budget to bound the work of a fit. `None` fits every sample.
+    pub fast: Option<FastMode>,
+    /// Resolution of the timer the delays were read from, e.g. its tick period, in the unit of
+    /// the samples. The readings are then taken as multiples of the resolution apart, each
+    /// standing for the interval of one resolution around it, as for the nearest tick or the
+    /// difference of two truncated clocks. Rather than piling up at the reading, where the
+    /// staircase biases the offset once the resolution is comparable to it, the samples are
+    /// spread over its interval as set by [`quantization`](Self::quantization). `None` takes
+    /// the delays as exact, see also [`estimate_ticks`](crate::estimate_ticks).
+    pub resolution: Option<f64>,
+    /// Treatment of the quantization noise of the [`resolution`](Self::resolution). The default
+    /// [`QuantizationNoise::Model`] spreads the samples sharing a reading over its interval and
+    /// refits them at their quantiles within it until the offset settles within
+    /// [`solver`](Self::solver), failing with
+    /// [`EstimateError::NotConverged`](crate::EstimateError::NotConverged) naming `resolution`
+    /// otherwise; detrended samples keep the even spread.
+    pub quantization: QuantizationNoise,
+    /// Static forward delay of the path, subtracted from the offset. The Gamma model places the
+    /// offset at the floor of the delays, which includes the propagation delay; with the mean
+    /// path delay and the asymmetry found by
+    /// [`calibrate_asymmetry`](crate::calibrate_asymmetry) known, the offset is that of the
+    /// clocks alone. Zero by default.
+    pub path_delay: f64,
+    /// Quality of the reference the samples were measured against, copied into the [`Estimate`](crate::Estimate).
+    pub source: SourceQuality,
+    /// Use compensated (Kahan-Neumaier) summation for the moment estimates and the regression.
+    /// Slightly slower, but keeps the rounding error independent of the batch size, which helps
+    /// with large batches and large-magnitude samples such as epoch-relative delays.
+    pub precise: bool,
+    /// Callback invoked with the diagnostic [`Event`](crate::Event)s of every run, e.g. to log
+    /// outliers dropped or clamping applied on firmware. `None` by default.
+    #[cfg_attr(feature = "toml", serde(skip))]
+    pub hook: Option<fn(&Event)>,
+    /// Token the [`BatchEstimator`](crate::BatchEstimator) checks before each link, and the fits
+    /// of censored samples and of [`estimate_fast_path`](crate::estimate_fast_path) at each EM
+    /// iteration; once cancelled, the batch or the fit stops with
+    /// [`EstimateError::Cancelled`](crate::EstimateError::Cancelled).
+    #[cfg_attr(feature = "toml", serde(skip))]
+    pub cancel: Option<CancelToken>,
+}
+
+/// Default for [`EstimatorConfig::min_samples`].
+pub const DEFAULT_MIN_SAMPLES: usize = 10;
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        EstimatorConfig {
+            seed: None,
+            synthetic: SyntheticSample::default(),
+            plotting: PlottingPosition::default(),
+            fit: GammaFit::default(),
+            regression: Regression::default(),
+            solver: SolverOptions::default(),
+            repetitions: 0,
+            jackknife: false,
+            non_finite: NonFinitePolicy::default(),
+            negative: NegativePolicy::default(),
+            tails: TailPolicy::default(),
+            min_samples: DEFAULT_MIN_SAMPLES,
+            half_life: None,
+            detrend: false,
+            leap: None,
+            coarse: None,
+            fast: None,
+            resolution: None,
+            quantization: QuantizationNoise::default(),
+            path_delay: 0.0,
+            source: SourceQuality::default(),
+            precise: false,
+            hook: None,
+            cancel: None,
+        }
+    }
+}
+
+impl EstimatorConfig {
+    /// Passes `event` to the [`hook`](Self::hook).
+    pub(crate) fn emit(&self, event: Event) {
+        if let Some(hook) = self.hook {
+            hook(&event);
+        }
+    }
+}
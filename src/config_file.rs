@@ -0,0 +1,233 @@
+//! Declarative configuration of daemons and command-line tools built on the estimator, loaded
+//! from TOML files and reloadable at runtime.
+//!
+//! ```
+//! use core::time::Duration;
+//! use gamlr::config_file::DaemonConfig;
+//! use gamlr::{NonFinitePolicy, TailPolicy};
+//!
+//! let config = DaemonConfig::from_toml(
+//!     r#"
+//!     [estimator]
+//!     seed = 42
+//!     non_finite = "drop"
+//!     tails = { trim = { lower = 0.0, upper = 0.05 } }
+//!
+//!     [probe]
+//!     interval = 0.5
+//!     window = 256
+//!
+//!     [[servers]]
+//!     address = "ntp1.example.net:123"
+//!
+//!     [[servers]]
+//!     address = "ntp2.example.net:123"
+//!     source = { stratum = 2, root_dispersion = 1e-4 }
+//!     "#,
+//! )
+//! .unwrap();
+//! assert_eq!(config.estimator.non_finite, NonFinitePolicy::Drop);
+//! assert_eq!(config.estimator.tails, TailPolicy::Trim { lower: 0.0, upper: 0.05 });
+//! assert_eq!(config.probe.interval, Duration::from_millis(500));
+//! assert_eq!(config.servers[1].source.stratum, 2);
+//! ```
+
+use core::fmt;
+use core::time::Duration;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::config::{EstimatorConfig, SourceQuality};
+
+/// Errors of loading a configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not valid TOML or does not match the configuration.
+    Parse(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "cannot read the configuration: {error}"),
+            ConfigError::Parse(error) => write!(f, "invalid configuration: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+        }
+    }
+}
+
+/// When and how the servers are probed. Durations are given in seconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProbeSchedule {
+    /// Time between two probes of a server.
+    #[serde(deserialize_with = "seconds")]
+    pub interval: Duration,
+    /// Time after which an unanswered probe counts as a
+    /// [timeout](crate::Sample::timed_out).
+    #[serde(deserialize_with = "seconds")]
+    pub timeout: Duration,
+    /// Most recent samples of a server kept for its estimate.
+    pub window: usize,
+}
+
+impl Default for ProbeSchedule {
+    fn default() -> Self {
+        ProbeSchedule {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            window: 64,
+        }
+    }
+}
+
+/// A reference server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address of the server, e.g. `host:port`.
+    pub address: String,
+    /// Quality of the server, copied into its estimates.
+    #[serde(default)]
+    pub source: SourceQuality,
+}
+
+/// Configuration of a daemon: the estimator, the probe schedule and the servers. Every table is
+/// optional and falls back to its defaults.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub estimator: EstimatorConfig,
+    pub probe: ProbeSchedule,
+    pub servers: Vec<ServerConfig>,
+}
+
+impl DaemonConfig {
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(ConfigError::Parse)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::from_toml(&text)
+    }
+}
+
+/// A [`DaemonConfig`] together with the file it was loaded from, for reloading it when the file
+/// changes.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    config: DaemonConfig,
+}
+
+impl ConfigFile {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        let path = path.into();
+        let modified = modified(&path)?;
+        let config = DaemonConfig::load(&path)?;
+        Ok(ConfigFile {
+            path,
+            modified,
+            config,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn config(&self) -> &DaemonConfig {
+        &self.config
+    }
+
+    /// Reloads the configuration if the file was modified since it was last loaded, e.g. on a
+    /// timer or on `SIGHUP`. Returns whether it was reloaded. On error the previous configuration
+    /// stays in effect, so that a half-written file does not take the daemon down.
+    pub fn reload(&mut self) -> Result<bool, ConfigError> {
+        let modified = modified(&self.path)?;
+        if modified.is_some() && modified == self.modified {
+            return Ok(false);
+        }
+        self.config = DaemonConfig::load(&self.path)?;
+        self.modified = modified;
+        Ok(true)
+    }
+}
+
+/// Modification time of `path`, `None` where the platform does not record it.
+fn modified(path: &Path) -> Result<Option<SystemTime>, ConfigError> {
+    let metadata = std::fs::metadata(path).map_err(ConfigError::Io)?;
+    Ok(metadata.modified().ok())
+}
+
+/// A duration given in seconds.
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FastMode, Subsampling};
+
+    #[test]
+    fn test_load_and_reload() {
+        let path = std::env::temp_dir().join(std::format!("gamlr-{}.toml", std::process::id()));
+        std::fs::write(&path, "[estimator]\nfast = { max_samples = 100 }\n").unwrap();
+        let mut file = ConfigFile::open(&path).unwrap();
+        assert_eq!(
+            file.config().estimator.fast,
+            Some(FastMode {
+                max_samples: 100,
+                subsampling: Subsampling::Stride
+            })
+        );
+        assert_eq!(file.config().probe, ProbeSchedule::default());
+        assert!(!file.reload().unwrap());
+
+        // A broken edit keeps the previous configuration.
+        std::fs::write(&path, "[estimator]\nseed = \"x\"\n").unwrap();
+        let modified = SystemTime::now() + Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(modified))
+            .unwrap();
+        assert!(matches!(file.reload(), Err(ConfigError::Parse(_))));
+        assert_eq!(file.config().estimator.fast.unwrap().max_samples, 100);
+
+        std::fs::write(&path, "[probe]\ninterval = 2.5\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(modified + Duration::from_secs(1)))
+            .unwrap();
+        assert!(file.reload().unwrap());
+        assert_eq!(file.config().estimator.fast, None);
+        assert_eq!(file.config().probe.interval, Duration::from_millis(2500));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            DaemonConfig::from_toml("[probe]\nperiod = 1.0\n"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+}
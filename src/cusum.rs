@@ -0,0 +1,266 @@
+use crate::error::EstimateError;
+use crate::float;
+use crate::offset_estimator::Estimate;
+
+/// Parameters of [`DriftMonitor`], in standard deviations of the monitored residuals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CusumConfig {
+    /// Slack subtracted from every residual before it accumulates, half the change to detect
+    /// fastest. 0.5 by default, for a change of one standard deviation.
+    pub allowance: f64,
+    /// Cumulative sum at which a change is flagged. 5 by default, an in-control average run
+    /// length of about 470 residuals at the default allowance.
+    pub threshold: f64,
+}
+
+impl Default for CusumConfig {
+    fn default() -> Self {
+        CusumConfig {
+            allowance: 0.5,
+            threshold: 5.0,
+        }
+    }
+}
+
+impl CusumConfig {
+    /// The configuration with `allowance` whose false alarms come on average every
+    /// `run_length` residuals while nothing changes, by Siegmund's approximation of the run
+    /// length. Fails with [`EstimateError::InvalidConfig`] naming the field when `allowance` is
+    /// not positive and finite or `run_length` is not above one.
+    pub fn with_run_length(allowance: f64, run_length: f64) -> Result<Self, EstimateError> {
+        if !(allowance.is_finite() && allowance > 0.0) {
+            return Err(EstimateError::InvalidConfig { field: "allowance" });
+        }
+        if run_length.is_nan() || run_length <= 1.0 {
+            return Err(EstimateError::InvalidConfig {
+                field: "run_length",
+            });
+        }
+        // The run length grows with the threshold: bracket it by doubling, then bisect.
+        let (mut low, mut high) = (0.0, 1.0);
+        let at = |threshold| CusumConfig {
+            allowance,
+            threshold,
+        };
+        while at(high).run_length() < run_length {
+            high *= 2.0;
+        }
+        for _ in 0..64 {
+            let middle = 0.5 * (low + high);
+            match at(middle).run_length() < run_length {
+                true => low = middle,
+                false => high = middle,
+            }
+        }
+        Ok(at(high))
+    }
+
+    /// Average number of residuals between false alarms while nothing changes, for normal
+    /// residuals by Siegmund's approximation, both directions together.
+    ///
+    /// D. Siegmund. "Sequential Analysis: Tests and Confidence Intervals". Springer, 1985,
+    /// p. 27.
+    pub fn run_length(&self) -> f64 {
+        let exponent = 2.0 * self.allowance * (self.threshold + 1.166);
+        let one_sided =
+            (float::exp(exponent) - exponent - 1.0) / (2.0 * self.allowance * self.allowance);
+        0.5 * one_sided
+    }
+}
+
+/// Change of the drift rate flagged by [`DriftMonitor::update`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftChange {
+    /// Drift rate before the change, the reference of the sums.
+    pub reference: f64,
+    /// Drift rate of the estimate that flagged the change, the new reference.
+    pub drift: f64,
+    /// Number of estimates since the flagged sum last left zero, the onset of the change.
+    pub estimates: usize,
+}
+
+/// One-sided cumulative sum and the residuals since it last left zero.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Sum {
+    value: f64,
+    residuals: usize,
+}
+
+impl Sum {
+    fn add(&mut self, excess: f64) {
+        self.value += excess;
+        self.residuals += 1;
+        if self.value <= 0.0 {
+            *self = Sum::default();
+        }
+    }
+}
+
+/// Page's two-sided CUSUM detector of gradual changes, complementing the
+/// [`ShiftMonitor`](crate::ShiftMonitor) for abrupt ones.
+///
+/// Small persistent changes accumulate in the sums of the standardized residuals in excess of
+/// the [allowance](CusumConfig::allowance), and are flagged once a sum reaches the
+/// [threshold](CusumConfig::threshold). [`update`](Self::update) monitors the
+/// [drift](Estimate::drift) of detrended estimates against the first one and then the one that
+/// flagged the last change, e.g. a remote clock whose frequency wanders as it warms up;
+/// [`update_residual`](Self::update_residual) takes any other standardized residual, such as the
+/// distance of the delay floor from its model.
+///
+/// ```
+/// use gamlr::{CusumConfig, DriftMonitor};
+///
+/// let mut monitor = DriftMonitor::new(CusumConfig::with_run_length(0.5, 1000.0).unwrap()).unwrap();
+/// for _ in 0..100 {
+///     assert!(!monitor.update_residual(0.3));
+/// }
+/// // A persistent change of one standard deviation is flagged within a dozen residuals.
+/// assert!((0..12).any(|_| monitor.update_residual(1.3)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftMonitor {
+    config: CusumConfig,
+    /// Drift rate of the reference and its variance.
+    reference: Option<(f64, f64)>,
+    up: Sum,
+    down: Sum,
+}
+
+impl DriftMonitor {
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `allowance` is negative
+    /// or `threshold` is not positive, or either is not finite.
+    pub fn new(config: CusumConfig) -> Result<Self, EstimateError> {
+        let valid = [
+            (
+                "allowance",
+                config.allowance.is_finite() && config.allowance >= 0.0,
+            ),
+            (
+                "threshold",
+                config.threshold.is_finite() && config.threshold > 0.0,
+            ),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(DriftMonitor {
+            config,
+            reference: None,
+            up: Sum::default(),
+            down: Sum::default(),
+        })
+    }
+
+    pub fn config(&self) -> &CusumConfig {
+        &self.config
+    }
+
+    /// Drift rate the estimates are compared with, `None` before the first one.
+    pub fn reference(&self) -> Option<f64> {
+        self.reference.map(|(drift, _)| drift)
+    }
+
+    /// Forgets the reference and the sums.
+    pub fn reset(&mut self) {
+        self.reference = None;
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.up = Sum::default();
+        self.down = Sum::default();
+    }
+
+    /// Adds `residual`, in standard deviations, to the sums. Returns whether a change is
+    /// flagged, which clears them; NaN residuals are ignored.
+    pub fn update_residual(&mut self, residual: f64) -> bool {
+        if residual.is_nan() {
+            return false;
+        }
+        let allowance = self.config.allowance;
+        self.up.add(residual - allowance);
+        self.down.add(-residual - allowance);
+        let flagged =
+            self.up.value >= self.config.threshold || self.down.value >= self.config.threshold;
+        if flagged {
+            self.clear();
+        }
+        flagged
+    }
+
+    /// Feeds the drift of `estimate` to the sums, returning the change it flagged, if any.
+    /// Estimates without a finite drift and a positive, finite drift uncertainty are ignored.
+    pub fn update(&mut self, estimate: &Estimate) -> Option<DriftChange> {
+        let (Some(drift), Some(uncertainty)) = (estimate.drift, estimate.drift_uncertainty) else {
+            return None;
+        };
+        if !(drift.is_finite() && uncertainty.is_finite() && uncertainty > 0.0) {
+            return None;
+        }
+        let variance = uncertainty * uncertainty;
+        let Some((reference, reference_variance)) = self.reference else {
+            self.reference = Some((drift, variance));
+            return None;
+        };
+        let (up, down) = (self.up, self.down);
+        let residual = (drift - reference) / float::sqrt(variance + reference_variance);
+        if !self.update_residual(residual) {
+            return None;
+        }
+        self.reference = Some((drift, variance));
+        let onset = match residual > 0.0 {
+            true => up,
+            false => down,
+        };
+        Some(DriftChange {
+            reference,
+            drift,
+            estimates: onset.residuals + 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_length() {
+        // Siegmund's one-sided run lengths at k = 0.5 are 938.2 for h = 5 and 338.0 for h = 4.
+        let config = CusumConfig::default();
+        assert!((2.0 * config.run_length() - 938.2).abs() < 0.1);
+        let config = CusumConfig::with_run_length(0.5, 338.0 / 2.0).unwrap();
+        assert!(
+            (config.threshold - 4.0).abs() < 0.05,
+            "{}",
+            config.threshold
+        );
+        assert_eq!(
+            CusumConfig::with_run_length(0.0, 100.0),
+            Err(EstimateError::InvalidConfig { field: "allowance" })
+        );
+    }
+
+    #[test]
+    fn test_drift_monitor() {
+        let mut monitor = DriftMonitor::new(CusumConfig::default()).unwrap();
+        let estimate = |drift: f64| {
+            let mut estimate = Estimate::from_offset(0.0, 1.0);
+            estimate.drift = Some(drift);
+            estimate.drift_uncertainty = Some(1.0);
+            estimate
+        };
+        assert_eq!(monitor.update(&Estimate::from_offset(0.0, 1.0)), None);
+        assert_eq!(monitor.update(&estimate(1.0)), None);
+        for i in 0..300 {
+            let noise = [0.5, -0.3, 0.1, -0.6, 0.2][i % 5];
+            assert_eq!(monitor.update(&estimate(1.0 + noise)), None);
+        }
+        // A gradual change of the drift rate.
+        let change = (1..100)
+            .find_map(|i| monitor.update(&estimate(1.0 + 0.05 * f64::from(i))))
+            .unwrap();
+        assert!(change.drift > 1.5 && change.reference == 1.0, "{change:?}");
+        assert_eq!(monitor.reference(), Some(change.drift));
+    }
+}
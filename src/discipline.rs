@@ -0,0 +1,249 @@
+use core::time::Duration;
+
+use crate::offset_estimator::Estimate;
+
+/// Scale of `timex.freq`: parts per million with 16 fractional bits.
+const SCALED_PPM: f64 = 65536.0;
+
+/// Parameters of [`Discipline`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisciplineConfig {
+    /// Duration of one unit of the samples, e.g. `Duration::from_nanos(1)` for delays in
+    /// nanoseconds.
+    pub unit: Duration,
+    /// Duration of one unit of the sample timestamps, for [`Estimate::drift`].
+    pub timestamp_unit: Duration,
+    /// Offsets beyond this are stepped rather than slewed, 128 ms by default like the step
+    /// threshold of ntpd.
+    pub step_threshold: Duration,
+    /// Time over which a slewed offset is worked off, unless the frequency limit needs longer.
+    pub time_constant: Duration,
+    /// Largest frequency adjustment in parts per million, 500 by default like the `MAXFREQ` of
+    /// the Linux kernel.
+    pub max_frequency: f64,
+}
+
+impl Default for DisciplineConfig {
+    fn default() -> Self {
+        DisciplineConfig {
+            unit: Duration::from_secs(1),
+            timestamp_unit: Duration::from_secs(1),
+            step_threshold: Duration::from_millis(128),
+            time_constant: Duration::from_secs(16),
+            max_frequency: 500.0,
+        }
+    }
+}
+
+/// Correction of the local clock decided by [`Discipline::correct`]. Offsets are in seconds and
+/// are to be added to the local clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Correction {
+    /// Leave the clock alone: the offset is within its uncertainty, or not finite.
+    Hold,
+    /// Step the clock by `offset` at once, as `clock_adjtime` with `ADJ_SETOFFSET`.
+    Step { offset: f64 },
+    /// Slew the clock by `offset` over `duration` while it runs `frequency` parts per million
+    /// fast, as `ADJ_OFFSET` and `ADJ_FREQUENCY`. The frequency stays in effect after the slew.
+    Slew {
+        offset: f64,
+        duration: Duration,
+        frequency: f64,
+    },
+}
+
+impl Correction {
+    /// `timex.time` of an `ADJ_SETOFFSET | ADJ_NANO` step: whole seconds and the nanoseconds in
+    /// `0..1_000_000_000` to add to them, as the kernel expects for negative offsets too.
+    pub fn set_offset(&self) -> Option<(i64, i64)> {
+        let Correction::Step { offset } = *self else {
+            return None;
+        };
+        let nanos = libm::round(offset * 1e9) as i64;
+        Some((
+            nanos.div_euclid(1_000_000_000),
+            nanos.rem_euclid(1_000_000_000),
+        ))
+    }
+
+    /// `timex.offset` of an `ADJ_OFFSET | ADJ_NANO` slew, in nanoseconds.
+    pub fn offset_nanos(&self) -> Option<i64> {
+        match *self {
+            Correction::Slew { offset, .. } => Some(libm::round(offset * 1e9) as i64),
+            _ => None,
+        }
+    }
+
+    /// `timex.freq` of an `ADJ_FREQUENCY` adjustment, in parts per million with 16 fractional
+    /// bits.
+    pub fn scaled_frequency(&self) -> Option<i64> {
+        match *self {
+            Correction::Slew { frequency, .. } => Some(libm::round(frequency * SCALED_PPM) as i64),
+            _ => None,
+        }
+    }
+}
+
+/// Clock discipline turning a stream of estimates into concrete corrections of the local clock,
+/// with the step-or-slew policy of NTP implementations.
+///
+/// Offsets beyond [`DisciplineConfig::step_threshold`] are stepped. Smaller ones are slewed over
+/// the [time constant](DisciplineConfig::time_constant), or longer when that would take the
+/// clock beyond [`DisciplineConfig::max_frequency`]. The [drift](Estimate::drift) of detrended
+/// estimates, the frequency error remaining under the current correction, accumulates into a
+/// frequency correction, as in a frequency-locked loop. A step discards it, since its estimate
+/// straddles the step.
+///
+/// The offsets of the estimates are local minus remote time, like the one-way delays they come
+/// from, so the corrections carry the opposite sign.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::{Correction, Discipline, DisciplineConfig};
+///
+/// let mut discipline = Discipline::new(DisciplineConfig {
+///     unit: Duration::from_micros(1),
+///     ..Default::default()
+/// });
+/// # let estimate = |offset: f64| {
+/// #     let mut samples = [gamlr::Sample::new(0.0); 20];
+/// #     for (i, s) in samples.iter_mut().enumerate() {
+/// #         *s = gamlr::Sample::new(offset + (i % 5) as f64);
+/// #     }
+/// #     gamlr::estimate_samples_checked(&mut samples, &mut [0.0; 20], &Default::default()).unwrap()
+/// # };
+/// // Two seconds ahead: step back.
+/// assert!(matches!(discipline.correct(&estimate(2e6)), Correction::Step { .. }));
+/// // Then 300 µs ahead: slew back.
+/// assert!(matches!(discipline.correct(&estimate(300.0)), Correction::Slew { .. }));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discipline {
+    config: DisciplineConfig,
+    frequency: f64,
+}
+
+impl Discipline {
+    pub fn new(config: DisciplineConfig) -> Self {
+        Discipline {
+            config,
+            frequency: 0.0,
+        }
+    }
+
+    pub fn config(&self) -> &DisciplineConfig {
+        &self.config
+    }
+
+    /// Accumulated frequency correction, in parts per million.
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Decides how to correct the local clock after `estimate`.
+    pub fn correct(&mut self, estimate: &Estimate) -> Correction {
+        let DisciplineConfig {
+            unit,
+            timestamp_unit,
+            step_threshold,
+            time_constant,
+            max_frequency,
+        } = self.config;
+        if !estimate.offset.is_finite() {
+            return Correction::Hold;
+        }
+        let offset = -estimate.offset * unit.as_secs_f64();
+        if libm::fabs(offset) > step_threshold.as_secs_f64() {
+            self.frequency = 0.0;
+            return Correction::Step { offset };
+        }
+        let drift = estimate.drift.filter(|drift| drift.is_finite());
+        if let Some(drift) = drift {
+            let ppm = drift * unit.as_secs_f64() / timestamp_unit.as_secs_f64() * 1e6;
+            // `max`/`min` rather than `clamp`: a NaN limit must not panic.
+            #[allow(clippy::manual_clamp)]
+            let frequency = (self.frequency - ppm)
+                .max(-max_frequency)
+                .min(max_frequency);
+            self.frequency = frequency;
+        }
+        if libm::fabs(estimate.offset) <= estimate.uncertainty && drift.is_none() {
+            return Correction::Hold;
+        }
+        // The slew rides on top of the frequency correction, within the same limit.
+        let headroom = (max_frequency - libm::fabs(self.frequency)) * 1e-6;
+        let shortest = match headroom > 0.0 {
+            true => libm::fabs(offset) / headroom,
+            false => f64::INFINITY,
+        };
+        let duration = match shortest > time_constant.as_secs_f64() {
+            true => Duration::try_from_secs_f64(shortest).unwrap_or(Duration::MAX),
+            false => time_constant,
+        };
+        Correction::Slew {
+            offset,
+            duration,
+            frequency: self.frequency,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discipline() {
+        let mut discipline = Discipline::new(DisciplineConfig {
+            unit: Duration::from_micros(1),
+            ..Default::default()
+        });
+        let step = discipline.correct(&Estimate::from_offset(-1.25e6, 10.0));
+        assert_eq!(step, Correction::Step { offset: 1.25 });
+        assert_eq!(step.set_offset(), Some((1, 250_000_000)));
+        let back = Correction::Step { offset: -1.25 };
+        assert_eq!(back.set_offset(), Some((-2, 750_000_000)));
+
+        assert_eq!(
+            discipline.correct(&Estimate::from_offset(5.0, 10.0)),
+            Correction::Hold
+        );
+
+        // 1 ms ahead is worked off over the time constant; 100 ms needs 200 s at 500 ppm.
+        let slew = discipline.correct(&Estimate::from_offset(1000.0, 10.0));
+        assert_eq!(
+            slew,
+            Correction::Slew {
+                offset: -1e-3,
+                duration: Duration::from_secs(16),
+                frequency: 0.0
+            }
+        );
+        assert_eq!(slew.offset_nanos(), Some(-1_000_000));
+        let Correction::Slew { duration, .. } =
+            discipline.correct(&Estimate::from_offset(1e5, 10.0))
+        else {
+            panic!("expected a slew");
+        };
+        assert!((duration.as_secs_f64() - 200.0).abs() < 1e-6);
+
+        // The local clock gains 20 µs per second: slow it down by 20 ppm, then 25 in total.
+        let drifting = Estimate {
+            drift: Some(20.0),
+            ..Estimate::from_offset(0.0, 10.0)
+        };
+        let slew = discipline.correct(&drifting);
+        assert_eq!(slew.scaled_frequency(), Some(-20 * 65536));
+        discipline.correct(&Estimate {
+            drift: Some(5.0),
+            ..drifting
+        });
+        assert_eq!(discipline.frequency(), -25.0);
+
+        discipline.correct(&Estimate {
+            drift: Some(1e6),
+            ..Estimate::from_offset(0.0, 10.0)
+        });
+        assert_eq!(discipline.frequency(), -500.0);
+    }
+}
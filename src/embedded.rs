@@ -0,0 +1,80 @@
+use embedded_time::clock::{Clock, Error};
+use embedded_time::Instant;
+
+use crate::sample::Sample;
+
+/// Nanoseconds elapsed between the epoch of clock `C` and `instant`.
+pub fn instant_nanos<C>(instant: &Instant<C>) -> f64
+where
+    C: Clock,
+    C::T: Into<u64>,
+{
+    let since_epoch = instant.duration_since_epoch();
+    let ticks: u64 = since_epoch.integer().into();
+    let scaling = since_epoch.scaling_factor();
+    ticks as f64 * f64::from(*scaling.numerator()) / f64::from(*scaling.denominator()) * 1e9
+}
+
+/// Builds one-way delay samples on-device from an [`embedded_time::Clock`], such as a
+/// free-running timer of a microcontroller, without `std`.
+///
+/// Timestamps are nanoseconds since the clock epoch. The remote side stamps each probe with its
+/// own clock when sending; the local clock stamps it on reception, so each sample carries the
+/// one-way delay plus the clock offset the estimator recovers.
+#[derive(Debug)]
+pub struct ClockSampler<'a, C> {
+    clock: &'a C,
+}
+
+impl<'a, C> ClockSampler<'a, C>
+where
+    C: Clock,
+    C::T: Into<u64>,
+{
+    pub fn new(clock: &'a C) -> Self {
+        ClockSampler { clock }
+    }
+
+    /// Current local time in nanoseconds since the clock epoch.
+    pub fn now(&self) -> Result<f64, Error> {
+        Ok(instant_nanos(&self.clock.try_now()?))
+    }
+
+    /// Builds the sample of a probe stamped `send` nanoseconds by the remote clock and received
+    /// now. The sample is timestamped with the local receive time.
+    pub fn receive(&self, send: f64) -> Result<Sample, Error> {
+        let received = self.now()?;
+        Ok(Sample::new(received - send).at(received))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use embedded_time::fraction::Fraction;
+
+    /// A 1 MHz clock advancing by 250 ticks on every read.
+    struct MockClock(Cell<u32>);
+
+    impl Clock for MockClock {
+        type T = u32;
+        const SCALING_FACTOR: Fraction = Fraction::new(1, 1_000_000);
+
+        fn try_now(&self) -> Result<Instant<Self>, Error> {
+            self.0.set(self.0.get() + 250);
+            Ok(Instant::new(self.0.get()))
+        }
+    }
+
+    #[test]
+    fn test_clock_sampler() {
+        let clock = MockClock(Cell::new(0));
+        let sampler = ClockSampler::new(&clock);
+
+        assert_eq!(sampler.now(), Ok(250_000.0));
+        let sample = sampler.receive(400_000.0).unwrap();
+        assert_eq!(sample.value, 100_000.0);
+        assert_eq!(sample.timestamp, Some(500_000.0));
+    }
+}
@@ -0,0 +1,127 @@
+/// Errors returned by the fallible estimation API.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EstimateError {
+    /// A NaN or infinite sample was found while the non-finite policy is
+    /// [`NonFinitePolicy::Reject`](crate::NonFinitePolicy::Reject).
+    NonFiniteSample {
+        /// Position of the offending sample in the input.
+        index: usize,
+    },
+    /// A negative sample was found while the negative policy is
+    /// [`NegativePolicy::Reject`](crate::NegativePolicy::Reject).
+    NegativeSample {
+        /// Position of the offending sample after non-finite filtering.
+        index: usize,
+    },
+    /// A sample weight was negative or non-finite.
+    InvalidWeight {
+        /// Position of the offending sample in the input.
+        index: usize,
+    },
+    /// A sample timestamp was non-finite while recency decay is enabled.
+    InvalidTimestamp {
+        /// Position of the offending sample in the input.
+        index: usize,
+    },
+    /// Too few samples remained after filtering to produce a meaningful fit.
+    InsufficientSamples {
+        /// Number of usable samples.
+        got: usize,
+        /// Number of samples required.
+        need: usize,
+    },
+    /// A configuration parameter is outside its valid range.
+    InvalidConfig {
+        /// Name of the offending [`EstimatorConfig`](crate::EstimatorConfig) field or parameter.
+        field: &'static str,
+    },
+    /// An iterative solver did not converge within
+    /// [`SolverOptions::max_iterations`](crate::SolverOptions::max_iterations).
+    NotConverged {
+        /// Name of the solver, e.g. `quantile` or `censored`.
+        solver: &'static str,
+    },
+    /// The input holds more samples than a fixed-capacity buffer can store, or an output does not
+    /// fit its buffer.
+    CapacityExceeded {
+        /// Capacity of the buffer.
+        capacity: usize,
+    },
+    /// No GPU adapter able to run compute shaders was found, or the device failed.
+    GpuUnavailable,
+    /// An allocation needed to buffer the input failed.
+    OutOfMemory {
+        /// Number of samples the failed allocation was to hold.
+        samples: usize,
+    },
+    /// Encoded data is truncated or malformed.
+    MalformedEncoding {
+        /// Byte offset at which decoding failed.
+        offset: usize,
+    },
+    /// The quantile regression is degenerate: the samples or the synthetic sample do not spread,
+    /// e.g. when every sample has the same value, so the offset is undefined.
+    DegenerateRegression,
+    /// A progress callback or a [`CancelToken`](crate::CancelToken) cancelled the job.
+    Cancelled,
+}
+
+impl core::fmt::Display for EstimateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EstimateError::NonFiniteSample { index } => {
+                write!(f, "sample {index} is not finite")
+            }
+            EstimateError::NegativeSample { index } => write!(f, "sample {index} is negative"),
+            EstimateError::InvalidWeight { index } => {
+                write!(f, "sample {index} has a negative or non-finite weight")
+            }
+            EstimateError::InvalidTimestamp { index } => {
+                write!(f, "sample {index} has an invalid timestamp")
+            }
+            EstimateError::InsufficientSamples { got, need } => {
+                write!(f, "{got} usable samples, at least {need} needed")
+            }
+            EstimateError::InvalidConfig { field } => {
+                write!(f, "configuration parameter `{field}` is out of range")
+            }
+            EstimateError::NotConverged { solver } => {
+                write!(f, "the {solver} solver did not converge")
+            }
+            EstimateError::CapacityExceeded { capacity } => {
+                write!(f, "more samples than the capacity of {capacity}")
+            }
+            EstimateError::GpuUnavailable => write!(f, "no usable GPU"),
+            EstimateError::OutOfMemory { samples } => {
+                write!(f, "out of memory buffering {samples} samples")
+            }
+            EstimateError::MalformedEncoding { offset } => {
+                write!(f, "malformed encoding at byte {offset}")
+            }
+            EstimateError::DegenerateRegression => {
+                write!(f, "degenerate quantile regression")
+            }
+            EstimateError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl core::error::Error for EstimateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_display() {
+        let error = EstimateError::InsufficientSamples { got: 3, need: 10 };
+        assert_eq!(error.to_string(), "3 usable samples, at least 10 needed");
+        let boxed: alloc::boxed::Box<dyn core::error::Error> =
+            EstimateError::InvalidConfig { field: "fraction" }.into();
+        assert_eq!(
+            boxed.to_string(),
+            "configuration parameter `fraction` is out of range"
+        );
+    }
+}
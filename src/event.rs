@@ -0,0 +1,110 @@
+use crate::error::EstimateError;
+
+/// Diagnostic event of an estimation run, passed to [`EstimatorConfig::hook`](crate::EstimatorConfig::hook)
+/// so that firmware can route the diagnostics of the estimator to its own logging, without a
+/// logging crate.
+///
+/// ```
+/// use gamlr::{estimate_samples_checked, EstimatorConfig, Event, Sample};
+///
+/// fn log(event: &Event) {
+///     if let Event::ShapeClamped { shape, .. } = event {
+///         // e.g. defmt::warn!("gamma shape {} clamped", shape);
+///         let _ = shape;
+///     }
+/// }
+///
+/// let config = EstimatorConfig { hook: Some(log), ..Default::default() };
+/// let mut samples = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36].map(Sample::new);
+/// estimate_samples_checked(&mut samples, &mut [0.0; 10], &config).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// Non-finite samples were dropped or replaced by the
+    /// [`NonFinitePolicy`](crate::NonFinitePolicy).
+    NonFinite { count: usize },
+    /// Samples around a leap second were dropped by
+    /// [`EstimatorConfig::leap`](crate::EstimatorConfig::leap).
+    LeapSpanning { count: usize },
+    /// Samples were left out by [`EstimatorConfig::fast`](crate::EstimatorConfig::fast).
+    Subsampled { count: usize },
+    /// Outliers outside the [`EstimatorConfig::coarse`](crate::EstimatorConfig::coarse) window
+    /// were dropped.
+    OutsideWindow { count: usize },
+    /// Samples were discarded or clamped by the [`TailPolicy`](crate::TailPolicy).
+    Tails { trimmed: usize, winsorized: usize },
+    /// The samples were translated by
+    /// [`NegativePolicy::Shift`](crate::NegativePolicy::Shift).
+    Shifted { shift: f64 },
+    /// The fitted Gamma shape, NaN for a degenerate fit, was clamped to `clamped`, the range the
+    /// synthetic sample handles.
+    ShapeClamped { shape: f64, clamped: f64 },
+    /// The rejection sampler gave up on `count` values of the synthetic sample, see
+    /// [`Estimate::sampler_fallbacks`](crate::Estimate::sampler_fallbacks).
+    SamplerFallback { count: usize },
+    /// A fit completed.
+    FitCompleted {
+        offset: f64,
+        uncertainty: f64,
+        samples: usize,
+    },
+    /// The run failed.
+    Failed(EstimateError),
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::config::{EstimatorConfig, NonFinitePolicy, TailPolicy};
+    use crate::sample::Sample;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+
+    static EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+
+    fn record(event: &Event) {
+        EVENTS.lock().unwrap().push(*event);
+    }
+
+    #[test]
+    fn test_hook_events() {
+        let config = EstimatorConfig {
+            non_finite: NonFinitePolicy::Drop,
+            tails: TailPolicy::Trim {
+                lower: 0.0,
+                upper: 0.1,
+            },
+            hook: Some(record),
+            ..Default::default()
+        };
+        let samples = (0..20)
+            .map(|i| Sample::new(1.0 + f64::from(i % 5)))
+            .chain([Sample::new(f64::NAN)]);
+        let estimate = crate::estimate_samples(samples, &config).unwrap();
+        assert!(crate::estimate_samples([Sample::new(1.0)], &config).is_err());
+
+        let events = EVENTS.lock().unwrap();
+        assert_eq!(
+            events[..2],
+            [
+                Event::NonFinite { count: 1 },
+                Event::Tails {
+                    trimmed: 2,
+                    winsorized: 0
+                },
+            ]
+        );
+        assert!(events.contains(&Event::FitCompleted {
+            offset: estimate.offset,
+            uncertainty: estimate.uncertainty,
+            samples: 18,
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&Event::Failed(crate::EstimateError::InsufficientSamples {
+                got: 1,
+                need: 10
+            }))
+        );
+    }
+}
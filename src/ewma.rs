@@ -0,0 +1,147 @@
+use crate::error::EstimateError;
+use crate::float;
+
+/// Parameters of [`EwmaChart`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EwmaConfig {
+    /// Weight of the newest residual in the moving average, from 0 excluded to 1. Small weights
+    /// detect small persistent deviations, large ones react to sudden ones. 0.2 by default.
+    pub smoothing: f64,
+    /// Width of the control limits, in standard deviations of the moving average. 3 by default.
+    pub width: f64,
+}
+
+impl Default for EwmaConfig {
+    fn default() -> Self {
+        EwmaConfig {
+            smoothing: 0.2,
+            width: 3.0,
+        }
+    }
+}
+
+/// Health of a model according to its [`EwmaChart`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Health {
+    /// No residual charted yet.
+    Unknown,
+    /// The moving average of the residuals is within the control limits `±limit`.
+    InControl { statistic: f64, limit: f64 },
+    /// The moving average is beyond the control limits: the model no longer describes the data,
+    /// e.g. after a path change or because of a probing artifact.
+    OutOfControl { statistic: f64, limit: f64 },
+}
+
+/// Exponentially weighted moving average control chart of standardized residuals.
+///
+/// The residuals of a model that describes the data have mean zero and unit variance, and their
+/// moving average stays within limits that widen to `width · √(λ / (2 - λ))` for the smoothing
+/// `λ`; a drift of the residuals takes it out.
+///
+/// S. W. Roberts. "Control Chart Tests Based on Geometric Moving Averages". Technometrics,
+/// Vol. 1, No. 3 (1959), pp. 239-250.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EwmaChart {
+    config: EwmaConfig,
+    statistic: f64,
+    /// `(1 - λ)^(2t)` after `t` residuals, for the limits of the first ones.
+    decay: f64,
+    residuals: usize,
+}
+
+impl EwmaChart {
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `smoothing` is not in
+    /// `(0, 1]` or `width` is not positive and finite.
+    pub fn new(config: EwmaConfig) -> Result<Self, EstimateError> {
+        let valid = [
+            (
+                "smoothing",
+                config.smoothing > 0.0 && config.smoothing <= 1.0,
+            ),
+            ("width", config.width.is_finite() && config.width > 0.0),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(EwmaChart {
+            config,
+            statistic: 0.0,
+            decay: 1.0,
+            residuals: 0,
+        })
+    }
+
+    pub fn config(&self) -> &EwmaConfig {
+        &self.config
+    }
+
+    /// Number of residuals charted.
+    pub fn residuals(&self) -> usize {
+        self.residuals
+    }
+
+    /// Charts `residual`, in standard deviations, returning the health after it. NaN residuals
+    /// are ignored.
+    pub fn update(&mut self, residual: f64) -> Health {
+        if !residual.is_nan() {
+            let smoothing = self.config.smoothing;
+            self.statistic += smoothing * (residual - self.statistic);
+            self.decay *= float::pow(1.0 - smoothing, 2.0);
+            self.residuals += 1;
+        }
+        self.health()
+    }
+
+    pub fn health(&self) -> Health {
+        if self.residuals == 0 {
+            return Health::Unknown;
+        }
+        let EwmaConfig { smoothing, width } = self.config;
+        let limit = width * float::sqrt(smoothing / (2.0 - smoothing) * (1.0 - self.decay));
+        let statistic = self.statistic;
+        match libm::fabs(statistic) > limit {
+            true => Health::OutOfControl { statistic, limit },
+            false => Health::InControl { statistic, limit },
+        }
+    }
+
+    /// Forgets the residuals charted.
+    pub fn reset(&mut self) {
+        self.statistic = 0.0;
+        self.decay = 1.0;
+        self.residuals = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ewma_chart() {
+        let mut chart = EwmaChart::new(EwmaConfig::default()).unwrap();
+        assert_eq!(chart.health(), Health::Unknown);
+        // The first limit is `width · λ`, widening to `width · √(λ / (2 - λ))`.
+        let Health::InControl { statistic, limit } = chart.update(0.5) else {
+            panic!("{:?}", chart.health());
+        };
+        assert!((statistic - 0.1).abs() < 1e-15 && (limit - 0.6).abs() < 1e-15);
+        for _ in 0..100 {
+            chart.update(f64::NAN);
+            chart.update(0.0);
+        }
+        assert_eq!(chart.residuals(), 101);
+        let Health::InControl { limit, .. } = chart.health() else {
+            panic!("{:?}", chart.health());
+        };
+        assert!((limit - 1.0).abs() < 1e-12);
+        assert!((0..10).any(|_| matches!(chart.update(2.0), Health::OutOfControl { .. })));
+        assert_eq!(
+            EwmaChart::new(EwmaConfig {
+                smoothing: 0.0,
+                ..Default::default()
+            }),
+            Err(EstimateError::InvalidConfig { field: "smoothing" })
+        );
+    }
+}
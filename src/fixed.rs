@@ -0,0 +1,92 @@
+//! Allocation-free variants of the estimation API, backed by `heapless::Vec` with a capacity of
+//! `N` samples chosen at compile time.
+//!
+//! ```
+//! use gamlr::{fixed, EstimatorConfig};
+//!
+//! let owds = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+//! let estimate = fixed::estimate_with::<64, _>(owds, &EstimatorConfig::default());
+//! assert!(estimate.is_ok());
+//! ```
+
+use heapless::Vec;
+
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::offset_estimator::{run, Estimate};
+use crate::sample::Sample;
+
+/// Allocation-free [`estimate_with`](crate::estimate_with) for at most `N` samples.
+pub fn estimate_with<const N: usize, I>(
+    time_values: I,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = f64>,
+{
+    estimate_samples::<N, _>(time_values.into_iter().map(Sample::new), config)
+}
+
+/// Allocation-free [`estimate_weighted`](crate::estimate_weighted) for at most `N` samples.
+pub fn estimate_weighted<const N: usize, I>(
+    samples: I,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64)>,
+{
+    let samples = samples
+        .into_iter()
+        .map(|(value, weight)| Sample::weighted(value, weight));
+    estimate_samples::<N, _>(samples, config)
+}
+
+/// Allocation-free [`estimate_samples`](crate::estimate_samples) for at most `N` samples.
+///
+/// Fails with [`EstimateError::CapacityExceeded`] if the input holds more than `N` samples.
+pub fn estimate_samples<const N: usize, I>(
+    samples: I,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let mut buffer: Vec<Sample, N> = Vec::new();
+    for sample in samples {
+        buffer
+            .push(sample)
+            .map_err(|_| EstimateError::CapacityExceeded { capacity: N })?;
+    }
+    let mut synthetic = [0.0; N];
+    run(&mut buffer, &mut synthetic, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_estimate_capacity() {
+        let owds = (0..20).map(|i| 100.0 + f64::from(i % 7) * 10.0);
+        let config = EstimatorConfig::default();
+        assert_eq!(
+            estimate_with::<16, _>(owds.clone(), &config),
+            Err(EstimateError::CapacityExceeded { capacity: 16 })
+        );
+        assert!(estimate_with::<32, _>(owds, &config).is_ok());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_fixed_estimate_matches_alloc() {
+        let owds = (0..200).map(|i| 100.0 + f64::from((i * 37) % 101));
+        let config = EstimatorConfig {
+            seed: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            estimate_with::<256, _>(owds.clone(), &config),
+            crate::estimate_with(owds, &config)
+        );
+    }
+}
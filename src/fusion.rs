@@ -0,0 +1,112 @@
+use crate::float;
+use crate::offset_estimator::Estimate;
+
+/// Combines offsets measured against several reference servers into a single offset.
+///
+/// Each estimate is weighted by the inverse square of its root [`distance`](Estimate::distance),
+/// so precise servers close to a primary reference dominate, in the spirit of the NTP combining
+/// algorithm. The fused uncertainty is `1 / sqrt(Σ 1 / distance²)`. Estimates with a non-finite
+/// offset or distance are ignored; if any estimate reports zero distance, only those estimates
+/// are averaged.
+///
+/// Sample counts are summed across the combined estimates and the fused source takes the best
+/// contributing stratum, its root dispersion being folded into the uncertainty. When nothing can
+/// be combined the returned offset is NaN and the uncertainty infinite.
+pub fn fuse(estimates: &[Estimate]) -> Estimate {
+    let usable = || {
+        estimates
+            .iter()
+            .filter(|e| e.offset.is_finite() && e.distance().is_finite() && e.distance() >= 0.0)
+    };
+    let exact = usable().filter(|e| e.distance() == 0.0).count();
+
+    let mut fused = Estimate::from_offset(f64::NAN, f64::INFINITY);
+    let mut weighted_sum = 0.0;
+    let mut weight_sum = 0.0;
+    let mut best_stratum = None;
+    for estimate in usable() {
+        let weight = if exact > 0 {
+            if estimate.distance() > 0.0 {
+                continue;
+            }
+            1.0
+        } else {
+            1.0 / (estimate.distance() * estimate.distance())
+        };
+        weighted_sum += weight * estimate.offset;
+        weight_sum += weight;
+        fused.samples = fused.samples.saturating_add(estimate.samples);
+        fused.censored = fused.censored.saturating_add(estimate.censored);
+        fused.non_finite = fused.non_finite.saturating_add(estimate.non_finite);
+        fused.trimmed = fused.trimmed.saturating_add(estimate.trimmed);
+        fused.winsorized = fused.winsorized.saturating_add(estimate.winsorized);
+        fused.subsampled = fused.subsampled.saturating_add(estimate.subsampled);
+        fused.leap_spanning = fused.leap_spanning.saturating_add(estimate.leap_spanning);
+        fused.outside_window = fused.outside_window.saturating_add(estimate.outside_window);
+        let stratum = estimate.source.stratum;
+        best_stratum = Some(best_stratum.map_or(stratum, |best: u8| best.min(stratum)));
+    }
+    fused.source.stratum = best_stratum.unwrap_or_default();
+
+    if weight_sum > 0.0 {
+        fused.offset = weighted_sum / weight_sum;
+        fused.uncertainty = if exact > 0 {
+            0.0
+        } else {
+            1.0 / float::sqrt(weight_sum)
+        };
+    }
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SourceQuality;
+
+    fn estimate(offset: f64, uncertainty: f64) -> Estimate {
+        Estimate {
+            samples: 100,
+            ..Estimate::from_offset(offset, uncertainty)
+        }
+    }
+
+    #[test]
+    fn test_fuse_inverse_variance() {
+        let fused = fuse(&[estimate(10.0, 1.0), estimate(20.0, 2.0)]);
+        assert!((fused.offset - 12.0).abs() < 1e-12);
+        assert!((fused.uncertainty - float::sqrt(0.8)).abs() < 1e-12);
+        assert_eq!(fused.samples, 200);
+    }
+
+    #[test]
+    fn test_fuse_degenerate_inputs() {
+        let fused = fuse(&[]);
+        assert!(fused.offset.is_nan());
+        assert_eq!(fused.uncertainty, f64::INFINITY);
+
+        let fused = fuse(&[
+            estimate(f64::NAN, 1.0),
+            estimate(5.0, 0.0),
+            estimate(9.0, 1.0),
+        ]);
+        assert_eq!(fused.offset, 5.0);
+        assert_eq!(fused.uncertainty, 0.0);
+        assert_eq!(fused.samples, 100);
+    }
+
+    #[test]
+    fn test_fuse_prefers_low_dispersion_sources() {
+        let mut far = estimate(20.0, 1.0);
+        far.source = SourceQuality {
+            stratum: 3,
+            root_dispersion: 9.0,
+        };
+        let mut near = estimate(10.0, 1.0);
+        near.source.stratum = 1;
+
+        let fused = fuse(&[near, far]);
+        assert!((fused.offset - (10.0 + 10.0 / 101.0)).abs() < 1e-12);
+        assert_eq!(fused.source.stratum, 1);
+    }
+}
@@ -0,0 +1,505 @@
+//! Batch estimation on the GPU, for telemetry platforms estimating the offsets of many links at
+//! once.
+//!
+//! [`GpuEstimator`] runs the moment fits, the quantile matching and the regressions of a whole
+//! batch in a `wgpu` compute shader, one workgroup per link, in single precision. The
+//! preprocessing, the sorting and the plotting positions stay on the CPU.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::ops::ControlFlow;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use wgpu::util::DeviceExt;
+
+use crate::config::{EstimatorConfig, GammaFit, QuantizationNoise, Regression, SyntheticSample};
+use crate::error::EstimateError;
+use crate::event::Event;
+use crate::math;
+use crate::offset_estimator::{
+    fit_prepared, plotting_positions, prepare, synthetic_index, Estimate, Prepared,
+};
+use crate::sample::{sort_samples, Sample};
+
+/// Values the shader writes per link, see `gpu.wgsl`.
+const FIT_LEN: usize = 5;
+/// Bytes per sample in the shader's sample buffer.
+const SAMPLE_SIZE: usize = 16;
+/// Largest workgroup count along one dispatch dimension.
+const MAX_WORKGROUPS: usize = 65535;
+
+/// Estimates the offsets of many links at once with a compute shader.
+///
+/// ```no_run
+/// use gamlr::{EstimatorConfig, GpuEstimator, Sample};
+///
+/// let gpu = GpuEstimator::new().unwrap();
+/// let links: Vec<Vec<Sample>> = (0..1000)
+///     .map(|link| (0..64).map(|i| Sample::new(link as f64 + (i % 7) as f64)).collect())
+///     .collect();
+/// let estimates = gpu.estimate_batch(&links, &EstimatorConfig::default());
+/// assert_eq!(estimates.len(), 1000);
+/// ```
+#[derive(Debug)]
+pub struct GpuEstimator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    max_samples: usize,
+}
+
+/// One link in the shader's link buffer.
+struct Link {
+    start: u32,
+    len: u32,
+    center: f32,
+}
+
+/// A link waiting for the GPU, with what the CPU knows about it.
+struct Pending {
+    result: usize,
+    prepared: Prepared,
+    center: f64,
+    unit: f64,
+    /// Weighted mean of the samples, which the delay scale is matched to.
+    mean: f64,
+    len: usize,
+}
+
+impl GpuEstimator {
+    /// Connects to the default adapter of the system.
+    ///
+    /// Fails with [`EstimateError::GpuUnavailable`] without an adapter that runs compute shaders.
+    pub fn new() -> Result<Self, EstimateError> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .map_err(|_| EstimateError::GpuUnavailable)?;
+        let compute = wgpu::DownlevelFlags::COMPUTE_SHADERS;
+        if !adapter.get_downlevel_capabilities().flags.contains(compute) {
+            return Err(EstimateError::GpuUnavailable);
+        }
+        let limits = adapter.limits();
+        let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("gamlr"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        }))
+        .map_err(|_| EstimateError::GpuUnavailable)?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gamlr"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("gpu.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("gamlr"),
+            layout: None,
+            module: &module,
+            entry_point: Some("fit"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let binding = limits.max_storage_buffer_binding_size as usize;
+        Ok(GpuEstimator {
+            device,
+            queue,
+            pipeline,
+            max_samples: binding / SAMPLE_SIZE,
+        })
+    }
+
+    /// Estimates the offset of every trace in `traces` like
+    /// [`estimate_samples`](crate::estimate_samples) with `config`, in the same order.
+    ///
+    /// The synthetic sample is always made of theoretical quantiles, as with
+    /// [`SyntheticSample::Quantiles`], so the seed is ignored and the estimates agree with the
+    /// CPU to single precision; the quantile solver runs to a fixed limit instead of
+    /// [`EstimatorConfig::solver`]. Links with censored samples or too large for a GPU buffer,
+    /// and every link when `config` asks for a fit other than [`GammaFit::Moments`], for a
+    /// regression other than [`Regression::LeastSquares`], for the jackknife or for the refit of
+    /// quantized readings within their intervals of [`EstimatorConfig::resolution`], are
+    /// estimated on the CPU.
+    pub fn estimate_batch<T: AsRef<[Sample]>>(
+        &self,
+        traces: &[T],
+        config: &EstimatorConfig,
+    ) -> Vec<Result<Estimate, EstimateError>> {
+        self.estimate_batch_with_progress(traces, config, |_, _| ControlFlow::Continue(()))
+            .unwrap_or_default()
+    }
+
+    /// [`estimate_batch`](Self::estimate_batch), calling `progress` with the links done and the
+    /// total before each link and once the batch is done. Links queued for the GPU count as done
+    /// once their buffer has been fitted. When `progress` breaks, the job stops with
+    /// [`EstimateError::Cancelled`].
+    pub fn estimate_batch_with_progress<T, F>(
+        &self,
+        traces: &[T],
+        config: &EstimatorConfig,
+        mut progress: F,
+    ) -> Result<Vec<Result<Estimate, EstimateError>>, EstimateError>
+    where
+        T: AsRef<[Sample]>,
+        F: FnMut(usize, usize) -> ControlFlow<()>,
+    {
+        let config = EstimatorConfig {
+            synthetic: SyntheticSample::Quantiles,
+            ..config.clone()
+        };
+        let on_gpu = config.fit == GammaFit::Moments
+            && config.regression == Regression::LeastSquares
+            && !config.jackknife
+            && (config.resolution.is_none() || config.quantization == QuantizationNoise::Dither);
+        let mut results = Vec::with_capacity(traces.len());
+        let mut pending = Vec::new();
+        let mut samples = Vec::new();
+        let mut links = Vec::new();
+        for trace in traces {
+            if progress(results.len() - pending.len(), traces.len()).is_break() {
+                return Err(EstimateError::Cancelled);
+            }
+            let mut trace = trace.as_ref().to_vec();
+            let prepared = match prepare(&mut trace, &config) {
+                Ok(prepared) => prepared,
+                Err(error) => {
+                    results.push(Err(error));
+                    continue;
+                }
+            };
+            let n = trace.len();
+            if !on_gpu || n > self.max_samples || trace.iter().any(|s| s.censored) {
+                let mut synthetic = alloc::vec![0.0; n];
+                results.push(fit_prepared(&mut trace, &mut synthetic, &config, prepared));
+                continue;
+            }
+            if samples.len() + n > self.max_samples || links.len() == MAX_WORKGROUPS.pow(2) {
+                self.flush(
+                    &mut samples,
+                    &mut links,
+                    &mut pending,
+                    &mut results,
+                    &config,
+                );
+            }
+
+            sort_samples(&mut trace);
+            let center = trace[n / 2].value;
+            let w_sum = math::sum(trace.iter().map(|s| s.weight), config.precise);
+            // Mean-weight units keep arbitrary weights within single precision.
+            let unit = w_sum / n as f64;
+            let mean = math::sum(trace.iter().map(|s| s.weight * s.value), config.precise) / w_sum;
+            links.push(Link {
+                start: samples.len() as u32,
+                len: n as u32,
+                center: center as f32,
+            });
+            let positions = plotting_positions(&trace, w_sum, 0.0, config.plotting);
+            for (p, sample) in positions.zip(&trace) {
+                let slot = synthetic_index(p, n) as f64 + 1.0;
+                let q = config.plotting.position(slot, n as f64);
+                samples.push([
+                    (sample.value - center) as f32,
+                    p as f32,
+                    q as f32,
+                    (sample.weight / unit) as f32,
+                ]);
+            }
+            pending.push(Pending {
+                result: results.len(),
+                prepared,
+                center,
+                unit,
+                mean,
+                len: n,
+            });
+            results.push(Err(EstimateError::GpuUnavailable));
+        }
+        self.flush(
+            &mut samples,
+            &mut links,
+            &mut pending,
+            &mut results,
+            &config,
+        );
+        if progress(traces.len(), traces.len()).is_break() {
+            return Err(EstimateError::Cancelled);
+        }
+        Ok(results)
+    }
+
+    /// Fits the queued links on the GPU and stores their estimates in `results`.
+    fn flush(
+        &self,
+        samples: &mut Vec<[f32; 4]>,
+        links: &mut Vec<Link>,
+        pending: &mut Vec<Pending>,
+        results: &mut [Result<Estimate, EstimateError>],
+        config: &EstimatorConfig,
+    ) {
+        if links.is_empty() {
+            return;
+        }
+        let fits = self.dispatch(samples, links);
+        for (i, link) in pending.drain(..).enumerate() {
+            let fit = fits
+                .as_ref()
+                .map_err(|error| *error)
+                .map(|fits| &fits[i * FIT_LEN..][..FIT_LEN]);
+            let result = link.result;
+            results[result] = fit.and_then(|fit| estimate(fit, link, config));
+        }
+        samples.clear();
+        links.clear();
+    }
+
+    /// Runs the shader on one buffer's worth of links, returning [`FIT_LEN`] values per link.
+    fn dispatch(&self, samples: &[[f32; 4]], links: &[Link]) -> Result<Vec<f32>, EstimateError> {
+        let usage = wgpu::BufferUsages::STORAGE;
+        let sample_bytes: Vec<u8> = samples
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let link_bytes: Vec<u8> = links
+            .iter()
+            .flat_map(|link| {
+                [link.start, link.len, link.center.to_bits(), 0]
+                    .into_iter()
+                    .flat_map(u32::to_le_bytes)
+            })
+            .collect();
+        let buffer = |label, contents: &[u8]| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage,
+                })
+        };
+        let sample_buffer = buffer("samples", &sample_bytes);
+        let link_buffer = buffer("links", &link_bytes);
+        let synthetic = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("synthetic"),
+            size: (samples.len() * 4) as u64,
+            usage,
+            mapped_at_creation: false,
+        });
+        let fit_size = (links.len() * FIT_LEN * 4) as u64;
+        let fits = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fits"),
+            size: fit_size,
+            usage: usage | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: fit_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let entries: Vec<wgpu::BindGroupEntry> = [&sample_buffer, &link_buffer, &synthetic, &fits]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gamlr"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let x = links.len().min(MAX_WORKGROUPS);
+            pass.dispatch_workgroups(x as u32, links.len().div_ceil(x) as u32, 1);
+        }
+        encoder.copy_buffer_to_buffer(&fits, 0, &staging, 0, fit_size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |mapped| {
+            let _ = sender.send(mapped);
+        });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|_| EstimateError::GpuUnavailable)?;
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            _ => return Err(EstimateError::GpuUnavailable),
+        }
+        let view = slice
+            .get_mapped_range()
+            .map_err(|_| EstimateError::GpuUnavailable)?;
+        let values = view
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        drop(view);
+        staging.unmap();
+        Ok(values)
+    }
+}
+
+/// The estimate of `link` from the shader output `fit`.
+fn estimate(
+    fit: &[f32],
+    link: Pending,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError> {
+    let [crossing, std_error, slope, alpha] = [0, 1, 2, 3].map(|i| f64::from(fit[i]));
+    // The shader divides by the spreads unguarded; a flat regression yields no line.
+    if !(crossing.is_finite() && slope.is_finite()) {
+        return Err(EstimateError::DegenerateRegression);
+    }
+    let Prepared {
+        non_finite,
+        trimmed,
+        winsorized,
+        subsampled,
+        leap_spanning,
+        outside_window,
+        shift,
+        drift,
+        ..
+    } = link.prepared;
+    let offset = link.center + crossing;
+    let estimate = Estimate {
+        offset: offset - shift - config.path_delay,
+        // The shader weighs in mean-weight units; the standard error scales with 1 / √W.
+        uncertainty: std_error / link.unit.sqrt(),
+        shape: alpha,
+        // Matched to the mean delay as on the CPU.
+        scale: (link.mean - offset) / alpha,
+        samples: link.len,
+        censored: 0,
+        non_finite,
+        trimmed,
+        winsorized,
+        subsampled,
+        leap_spanning,
+        outside_window,
+        shift,
+        drift: drift.map(|d| d.0),
+        drift_uncertainty: drift.map(|d| d.1),
+        source: config.source,
+        monte_carlo_error: (config.repetitions >= 2).then_some(0.0),
+        jackknife_variance: None,
+        sampler_fallbacks: 0,
+        design: None,
+    };
+    config.emit(Event::FitCompleted {
+        offset: estimate.offset,
+        uncertainty: estimate.uncertainty,
+        samples: estimate.samples,
+    });
+    #[cfg(feature = "metrics")]
+    crate::metrics::record(&estimate, slope);
+    Ok(estimate)
+}
+
+/// Drives `future` to completion on the current thread. The futures of `wgpu` on native
+/// backends complete without waking, so polling in a loop suffices.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float;
+    use crate::offset_estimator::{estimate_samples, LcgRng};
+
+    #[test]
+    fn test_gpu_batch_matches_cpu() {
+        let Ok(gpu) = GpuEstimator::new() else {
+            // No adapter on this machine: the CPU path is all there is.
+            return;
+        };
+        let mut rng = LcgRng::new(4);
+        let mut exponential = move |scale: f64| -scale * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        let mut traces: Vec<Vec<Sample>> = (0..300)
+            .map(|link| {
+                let offset = 1000.0 * link as f64;
+                (0..20 + link % 200)
+                    .map(|_| Sample::new(offset + exponential(2.0) + exponential(2.0)))
+                    .collect()
+            })
+            .collect();
+        traces.push(alloc::vec![Sample::new(1.0)]);
+        // Censored samples take the CPU path.
+        traces.push(
+            (0..30)
+                .map(|i| match i % 5 {
+                    0 => Sample::timed_out(9.0),
+                    _ => Sample::new(f64::from(i % 7)),
+                })
+                .collect(),
+        );
+
+        let config = EstimatorConfig::default();
+        let cpu_config = EstimatorConfig {
+            synthetic: SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let estimates = gpu.estimate_batch(&traces, &config);
+        assert_eq!(estimates.len(), traces.len());
+        for (trace, estimate) in traces.iter().zip(&estimates) {
+            let cpu = estimate_samples(trace.iter().copied(), &cpu_config);
+            match (estimate, cpu) {
+                (Ok(gpu), Ok(cpu)) => {
+                    let tolerance = 1e-3 * (1.0 + cpu.uncertainty);
+                    assert!(
+                        (gpu.offset - cpu.offset).abs() < tolerance,
+                        "GPU {} against CPU {}",
+                        gpu.offset,
+                        cpu.offset
+                    );
+                    assert!((gpu.uncertainty - cpu.uncertainty).abs() < 1e-2 * cpu.uncertainty);
+                    assert!((gpu.shape - cpu.shape).abs() < 1e-4);
+                    assert!((gpu.scale - cpu.scale).abs() < tolerance);
+                }
+                (gpu, cpu) => assert_eq!(gpu, &cpu),
+            }
+        }
+    }
+
+    #[test]
+    fn test_gpu_batch_quantized_on_cpu() {
+        let Ok(gpu) = GpuEstimator::new() else {
+            return;
+        };
+        let mut rng = LcgRng::new(8);
+        // Delays read off a timer of 5 units.
+        let trace: Vec<Sample> = (0..200)
+            .map(|_| {
+                let delay = 100.0 - 4.0 * float::ln(1.0 - rng.gen_range(0.0..1.0));
+                Sample::new(5.0 * libm::round(delay / 5.0))
+            })
+            .collect();
+        let config = EstimatorConfig {
+            synthetic: SyntheticSample::Quantiles,
+            resolution: Some(5.0),
+            ..Default::default()
+        };
+        let estimates = gpu.estimate_batch(&[&trace], &config);
+        assert_eq!(
+            estimates[0],
+            estimate_samples(trace.iter().copied(), &config)
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Offset fit of one link per workgroup, see `gpu.rs`.
+//
+// Each sample is a vec4 of its value relative to the link's center (x), its plotting position
+// (y), the position of its synthetic value (z) and its weight (w). Every link yields its
+// crossing point relative to the center, the standard error, the regression slope and the
+// Gamma shape and scale.
+
+struct Link {
+    start: u32,
+    len: u32,
+    center: f32,
+    padding: f32,
+}
+
+@group(0) @binding(0) var<storage, read> samples: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read> links: array<Link>;
+@group(0) @binding(2) var<storage, read_write> synthetic: array<f32>;
+@group(0) @binding(3) var<storage, read_write> fits: array<f32>;
+
+const THREADS: u32 = 64u;
+const FIT_LEN: u32 = 5u;
+const MIN_ALPHA: f32 = 1.0;
+const MAX_ALPHA: f32 = 4.0;
+const MAX_ITERATIONS: u32 = 200u;
+const EPSILON: f32 = 1e-7;
+const TINY: f32 = 1e-30;
+
+var<workgroup> partial: array<f32, THREADS>;
+
+// Sum of `value` over the workgroup, by pairwise reduction.
+fn reduce(local: u32, value: f32) -> f32 {
+    partial[local] = value;
+    workgroupBarrier();
+    for (var stride = THREADS / 2u; stride > 0u; stride = stride / 2u) {
+        if local < stride {
+            partial[local] = partial[local] + partial[local + stride];
+        }
+        workgroupBarrier();
+    }
+    let total = partial[0];
+    workgroupBarrier();
+    return total;
+}
+
+// Lanczos approximation of ln Γ(a) for a > 0.
+fn ln_gamma(a: f32) -> f32 {
+    var tmp = a + 5.5;
+    tmp = tmp - (a + 0.5) * log(tmp);
+    var series = 1.000000000190015;
+    series = series + 76.18009172947146 / (a + 1.0);
+    series = series - 86.50532032941677 / (a + 2.0);
+    series = series + 24.01409824083091 / (a + 3.0);
+    series = series - 1.231739572450155 / (a + 4.0);
+    series = series + 0.1208650973866179e-2 / (a + 5.0);
+    series = series - 0.5395239384953e-5 / (a + 6.0);
+    return -tmp + log(2.5066282746310005 * series / a);
+}
+
+// Regularized lower incomplete Gamma function P(a, x), by its series below a + 1 and by the
+// continued fraction of Q(a, x) above.
+fn gamma_p(a: f32, x: f32, log_gamma: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let scale = exp(a * log(x) - x - log_gamma);
+    if x < a + 1.0 {
+        var term = 1.0 / a;
+        var total = term;
+        for (var n = 1u; n < MAX_ITERATIONS; n = n + 1u) {
+            term = term * x / (a + f32(n));
+            total = total + term;
+            if abs(term) < abs(total) * EPSILON {
+                break;
+            }
+        }
+        return total * scale;
+    }
+    var b = x + 1.0 - a;
+    var c = 1.0 / TINY;
+    var d = 1.0 / b;
+    var h = d;
+    for (var i = 1u; i < MAX_ITERATIONS; i = i + 1u) {
+        let an = -f32(i) * (f32(i) - a);
+        b = b + 2.0;
+        d = an * d + b;
+        if abs(d) < TINY {
+            d = TINY;
+        }
+        c = b + an / c;
+        if abs(c) < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h = h * delta;
+        if abs(delta - 1.0) < EPSILON {
+            break;
+        }
+    }
+    return 1.0 - scale * h;
+}
+
+// Acklam's rational approximation of the standard normal quantile.
+fn normal_quantile(p: f32) -> f32 {
+    if p < 0.02425 || p > 1.0 - 0.02425 {
+        let q = sqrt(-2.0 * log(min(p, 1.0 - p)));
+        let tail = (((((-7.784894002430293e-3 * q - 3.223964580411365e-1) * q - 2.400758277161838) * q
+            - 2.549732539343734) * q + 4.374664141464968) * q + 2.938163982698783)
+            / ((((7.784695709041462e-3 * q + 3.224671290700398e-1) * q + 2.445134137142996) * q
+            + 3.754408661907416) * q + 1.0);
+        return select(-tail, tail, p < 0.5);
+    }
+    let q = p - 0.5;
+    let r = q * q;
+    return (((((-3.969683028665376e1 * r + 2.209460984245205e2) * r - 2.759285104469687e2) * r
+        + 1.38357751867269e2) * r - 3.066479806614716e1) * r + 2.506628277459239) * q
+        / (((((-5.447609879822406e1 * r + 1.615858368580409e2) * r - 1.556989798598866e2) * r
+        + 6.680131188771972e1) * r - 1.328068155288572e1) * r + 1.0);
+}
+
+// Quantile of the Gamma distribution with shape `a` and unit scale: Wilson-Hilferty, refined by
+// Newton steps with a bisection fallback, as on the CPU.
+fn gamma_quantile(a: f32, p: f32) -> f32 {
+    if p <= 0.0 {
+        return 0.0;
+    }
+    let log_gamma = ln_gamma(a);
+    let h = 1.0 / (9.0 * a);
+    let t = 1.0 - h + normal_quantile(p) * sqrt(h);
+    var x = a * t * t * t;
+    if !(x > 0.0) {
+        x = exp((log(p * a) + log_gamma) / a);
+    }
+    var lower = 0.0;
+    var upper = -1.0;
+    for (var i = 0u; i < MAX_ITERATIONS; i = i + 1u) {
+        let error = gamma_p(a, x, log_gamma) - p;
+        if error < 0.0 {
+            lower = x;
+        } else {
+            upper = x;
+        }
+        let density = exp((a - 1.0) * log(x) - x - log_gamma);
+        var next = x - error / density;
+        if !(next > lower && (upper < 0.0 || next < upper)) {
+            next = select(0.5 * (lower + upper), 2.0 * x, upper < 0.0);
+        }
+        if abs(next - x) <= EPSILON * x {
+            return next;
+        }
+        x = next;
+    }
+    return x;
+}
+
+@compute @workgroup_size(THREADS)
+fn fit(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let index = group.x + group.y * groups.x;
+    if index >= arrayLength(&links) {
+        return;
+    }
+    let link = links[index];
+
+    // Weighted method of moments on the raw values, center included.
+    var w = 0.0;
+    var w_sq = 0.0;
+    var wv = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        w = w + s.w;
+        w_sq = w_sq + s.w * s.w;
+        wv = wv + s.w * s.x;
+    }
+    let w_sum = reduce(local, w);
+    let w_sq_sum = reduce(local, w_sq);
+    let mean = reduce(local, wv) / w_sum;
+    var ss = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        ss = ss + s.w * (s.x - mean) * (s.x - mean);
+    }
+    let variance = reduce(local, ss) / (w_sum - w_sq_sum / w_sum);
+    let raw_mean = link.center + mean;
+    let moment_alpha = raw_mean * raw_mean / variance;
+    // A NaN shape ends up at the lower bound, as on the CPU.
+    let alpha = select(MIN_ALPHA, min(moment_alpha, MAX_ALPHA), moment_alpha >= MIN_ALPHA);
+    let beta = variance / raw_mean;
+
+    // Quantile matching: the synthetic value paired with every sample. Each invocation only
+    // reads back what it wrote itself.
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        synthetic[link.start + i] = beta * gamma_quantile(alpha, s.z);
+    }
+
+    // Weighted regression of the synthetic values on the shifted samples, in standardized
+    // coordinates as on the CPU.
+    var wx = 0.0;
+    var wy = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        wx = wx + s.w * (s.x - s.y);
+        wy = wy + s.w * synthetic[link.start + i];
+    }
+    let x_mean = reduce(local, wx) / w_sum;
+    let y_mean = reduce(local, wy) / w_sum;
+    var wxx = 0.0;
+    var wyy = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        let dx = s.x - s.y - x_mean;
+        let dy = synthetic[link.start + i] - y_mean;
+        wxx = wxx + s.w * dx * dx;
+        wyy = wyy + s.w * dy * dy;
+    }
+    let x_scale = sqrt(reduce(local, wxx) / w_sum);
+    let y_scale = sqrt(reduce(local, wyy) / w_sum);
+    var wuv = 0.0;
+    var wuu = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        let u = (s.x - s.y - x_mean) / x_scale;
+        let v = (synthetic[link.start + i] - y_mean) / y_scale;
+        wuv = wuv + s.w * u * v;
+        wuu = wuu + s.w * u * u;
+    }
+    let slope = reduce(local, wuv) / reduce(local, wuu);
+    var residual = 0.0;
+    for (var i = local; i < link.len; i = i + THREADS) {
+        let s = samples[link.start + i];
+        let u = (s.x - s.y - x_mean) / x_scale;
+        let v = (synthetic[link.start + i] - y_mean) / y_scale;
+        residual = residual + s.w * (v - slope * u) * (v - slope * u);
+    }
+    let residual_ss = y_scale * y_scale * reduce(local, residual);
+
+    if local == 0u {
+        let line = slope * y_scale / x_scale;
+        let n = f32(link.len);
+        let sxx = w_sum * x_scale * x_scale;
+        let residual_var = residual_ss / w_sum * n / (n - 2.0);
+        let std_error = sqrt(
+            residual_var / (line * line) * (1.0 / w_sum + y_mean * y_mean / (line * line * sxx))
+        );
+        let out = index * FIT_LEN;
+        fits[out] = x_mean - y_mean / line;
+        fits[out + 1u] = std_error;
+        fits[out + 2u] = line;
+        fits[out + 3u] = alpha;
+        fits[out + 4u] = beta;
+    }
+}
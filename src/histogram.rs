@@ -0,0 +1,397 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::error::EstimateError;
+use crate::float;
+use crate::sample::Sample;
+
+/// Bin layout of a [`Histogram`] over the delays `lower..upper`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bins {
+    /// Bins of equal width.
+    Linear { lower: f64, upper: f64 },
+    /// Bins of equal ratio of their bounds, for delays spanning orders of magnitude. `lower` must
+    /// be positive.
+    Log { lower: f64, upper: f64 },
+}
+
+impl Bins {
+    fn bounds(self) -> (f64, f64) {
+        match self {
+            Bins::Linear { lower, upper } | Bins::Log { lower, upper } => (lower, upper),
+        }
+    }
+
+    /// Position of `x` in `0.0..1.0` across the range, outside it for delays outside the range.
+    fn position(self, x: f64) -> f64 {
+        match self {
+            Bins::Linear { lower, upper } => (x - lower) / (upper - lower),
+            Bins::Log { lower, upper } => match x > 0.0 {
+                true => float::ln(x / lower) / float::ln(upper / lower),
+                false => f64::NEG_INFINITY,
+            },
+        }
+    }
+
+    /// Delay at `position` across the range, the inverse of [`position`](Self::position).
+    fn at(self, position: f64) -> f64 {
+        match self {
+            Bins::Linear { lower, upper } => lower + position * (upper - lower),
+            Bins::Log { lower, upper } => lower * float::pow(upper / lower, position),
+        }
+    }
+}
+
+/// Histogram of the delays with `N` bins, filled sample by sample during ingestion without
+/// allocating, for dashboards and density-based estimators.
+///
+/// Delays below or above the range are counted in [`underflow`](Self::underflow) and
+/// [`overflow`](Self::overflow), censored samples, whose delay is only bounded, in
+/// [`censored`](Self::censored), and non-finite ones nowhere.
+///
+/// ```
+/// use gamlr::{Bins, Histogram, Sample};
+///
+/// let mut histogram = Histogram::<10>::new(Bins::Linear { lower: 0.0, upper: 1.0 }).unwrap();
+/// // Next to the samples pushed into the estimator.
+/// for owd in [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36] {
+///     histogram.push(Sample::new(owd));
+/// }
+/// assert_eq!(&histogram.counts()[3..6], &[8, 1, 1]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram<const N: usize> {
+    bins: Bins,
+    counts: [u64; N],
+    underflow: u64,
+    overflow: u64,
+    censored: u64,
+}
+
+impl<const N: usize> Histogram<N> {
+    /// An empty histogram. Fails with [`EstimateError::InvalidConfig`] naming `bins` unless there
+    /// is at least one bin and the bounds are finite and increasing, and positive for
+    /// [`Bins::Log`].
+    pub fn new(bins: Bins) -> Result<Self, EstimateError> {
+        let (lower, upper) = bins.bounds();
+        let positive = matches!(bins, Bins::Linear { .. }) || lower > 0.0;
+        if N == 0 || !(lower.is_finite() && upper.is_finite() && lower < upper && positive) {
+            return Err(EstimateError::InvalidConfig { field: "bins" });
+        }
+        Ok(Histogram {
+            bins,
+            counts: [0; N],
+            underflow: 0,
+            overflow: 0,
+            censored: 0,
+        })
+    }
+
+    /// Counts `sample` into its bin.
+    pub fn push(&mut self, sample: Sample) {
+        if sample.censored {
+            self.censored = self.censored.saturating_add(1);
+            return;
+        }
+        let slot = match self.bin(sample.value) {
+            Some(Ok(index)) => &mut self.counts[index],
+            Some(Err(true)) => &mut self.underflow,
+            Some(Err(false)) => &mut self.overflow,
+            None => return,
+        };
+        *slot = slot.saturating_add(1);
+    }
+
+    /// Bin of `value`, `Err(true)` below the range and `Err(false)` above it, `None` for NaN.
+    fn bin(&self, value: f64) -> Option<Result<usize, bool>> {
+        let position = self.bins.position(value);
+        if position.is_nan() {
+            return None;
+        }
+        if position < 0.0 {
+            return Some(Err(true));
+        }
+        // Rounding can put a value just below `upper` at position 1.
+        let index = libm::floor(position * N as f64);
+        match index < N as f64 && value < self.bins.bounds().1 {
+            true => Some(Ok((index as usize).min(N - 1))),
+            false => Some(Err(false)),
+        }
+    }
+
+    pub fn bins(&self) -> Bins {
+        self.bins
+    }
+
+    pub fn counts(&self) -> &[u64; N] {
+        &self.counts
+    }
+
+    /// Delays below the range.
+    pub fn underflow(&self) -> u64 {
+        self.underflow
+    }
+
+    /// Delays at or above the upper bound.
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    /// Censored samples.
+    pub fn censored(&self) -> u64 {
+        self.censored
+    }
+
+    /// Observed delays counted, in the bins and outside the range.
+    pub fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .fold(self.underflow.saturating_add(self.overflow), |total, &c| {
+                total.saturating_add(c)
+            })
+    }
+
+    /// Bounds of bin `index`.
+    pub fn edges(&self, index: usize) -> (f64, f64) {
+        let n = N as f64;
+        (
+            self.bins.at(index as f64 / n),
+            self.bins.at((index + 1) as f64 / n),
+        )
+    }
+
+    /// Empirical density of the delays at `x`: the share of the observed delays in the bin of
+    /// `x` over its width. Zero outside the range or before any delay was counted.
+    pub fn density(&self, x: f64) -> f64 {
+        let total = self.total();
+        match self.bin(x) {
+            Some(Ok(index)) if total > 0 => {
+                let (lower, upper) = self.edges(index);
+                self.counts[index] as f64 / total as f64 / (upper - lower)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Adds the counts of `other`, of the same layout, e.g. a histogram filled on another core.
+    /// Fails with [`EstimateError::InvalidConfig`] naming `bins` when the layouts differ.
+    pub fn merge(&mut self, other: &Histogram<N>) -> Result<(), EstimateError> {
+        if self.bins != other.bins {
+            return Err(EstimateError::InvalidConfig { field: "bins" });
+        }
+        for (count, &c) in self.counts.iter_mut().zip(&other.counts) {
+            *count = count.saturating_add(c);
+        }
+        self.underflow = self.underflow.saturating_add(other.underflow);
+        self.overflow = self.overflow.saturating_add(other.overflow);
+        self.censored = self.censored.saturating_add(other.censored);
+        Ok(())
+    }
+
+    /// Length of the [`encode_sparse`](Self::encode_sparse) encoding.
+    pub fn sparse_len(&self) -> usize {
+        let mut len = 0;
+        self.write_sparse(|_| len += 1);
+        len
+    }
+
+    /// Encodes the histogram sparsely into `out`, for shipping from embedded devices over
+    /// constrained links, and returns the length of the encoding.
+    ///
+    /// The encoding is a sequence of LEB128 varints: the underflow, overflow and censored counts,
+    /// the number of non-empty bins, and for each of them in order the gap to the previous one,
+    /// its index for the first bin, followed by its count. The layout of the bins is not
+    /// encoded; the decoder is expected to know it. Fails with
+    /// [`EstimateError::CapacityExceeded`] when `out` is shorter than
+    /// [`sparse_len`](Self::sparse_len).
+    pub fn encode_sparse(&self, out: &mut [u8]) -> Result<usize, EstimateError> {
+        let capacity = out.len();
+        let mut len = 0;
+        self.write_sparse(|byte| {
+            if let Some(slot) = out.get_mut(len) {
+                *slot = byte;
+            }
+            len += 1;
+        });
+        match len <= capacity {
+            true => Ok(len),
+            false => Err(EstimateError::CapacityExceeded { capacity }),
+        }
+    }
+
+    /// [`encode_sparse`](Self::encode_sparse) into a new buffer.
+    #[cfg(feature = "alloc")]
+    pub fn to_sparse(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.sparse_len());
+        self.write_sparse(|byte| out.push(byte));
+        out
+    }
+
+    /// Decodes a histogram of layout `bins` from the whole of `bytes`, as written by
+    /// [`encode_sparse`](Self::encode_sparse).
+    ///
+    /// Fails like [`new`](Self::new) on the layout, and with
+    /// [`EstimateError::MalformedEncoding`] when `bytes` is truncated, has bytes left over, or
+    /// holds a bin beyond the `N` bins.
+    pub fn decode_sparse(bins: Bins, bytes: &[u8]) -> Result<Self, EstimateError> {
+        let mut histogram = Histogram::new(bins)?;
+        let mut at = 0;
+        histogram.underflow = read_varint(bytes, &mut at)?;
+        histogram.overflow = read_varint(bytes, &mut at)?;
+        histogram.censored = read_varint(bytes, &mut at)?;
+        let mut index = 0u64;
+        for i in 0..read_varint(bytes, &mut at)? {
+            let gap = read_varint(bytes, &mut at)?;
+            let count = read_varint(bytes, &mut at)?;
+            index = match i {
+                0 => Some(gap),
+                _ => index.checked_add(1).and_then(|i| i.checked_add(gap)),
+            }
+            .filter(|&index| index < N as u64)
+            .ok_or(EstimateError::MalformedEncoding { offset: at })?;
+            histogram.counts[index as usize] = count;
+        }
+        match at == bytes.len() {
+            true => Ok(histogram),
+            false => Err(EstimateError::MalformedEncoding { offset: at }),
+        }
+    }
+
+    /// Passes the bytes of the sparse encoding to `write`.
+    fn write_sparse(&self, mut write: impl FnMut(u8)) {
+        let nonzero = self.counts.iter().filter(|&&c| c > 0).count();
+        for value in [self.underflow, self.overflow, self.censored, nonzero as u64] {
+            write_varint(value, &mut write);
+        }
+        let mut previous = None;
+        for (index, &count) in self.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let gap = previous.map_or(index, |previous| index - previous - 1);
+            write_varint(gap as u64, &mut write);
+            write_varint(count, &mut write);
+            previous = Some(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.counts = [0; N];
+        self.underflow = 0;
+        self.overflow = 0;
+        self.censored = 0;
+    }
+}
+
+impl<const N: usize> Extend<Sample> for Histogram<N> {
+    fn extend<I: IntoIterator<Item = Sample>>(&mut self, samples: I) {
+        samples.into_iter().for_each(|sample| self.push(sample));
+    }
+}
+
+fn write_varint(mut value: u64, write: &mut impl FnMut(u8)) {
+    while value >= 0x80 {
+        write(value as u8 | 0x80);
+        value >>= 7;
+    }
+    write(value as u8);
+}
+
+/// Reads the varint at `*at`, advancing `at` past it.
+fn read_varint(bytes: &[u8], at: &mut usize) -> Result<u64, EstimateError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let &byte = bytes
+            .get(*at)
+            .ok_or(EstimateError::MalformedEncoding { offset: *at })?;
+        let bits = u64::from(byte & 0x7f);
+        // The tenth byte only holds the top bit.
+        if shift == 63 && bits > 1 {
+            return Err(EstimateError::MalformedEncoding { offset: *at });
+        }
+        *at += 1;
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(EstimateError::MalformedEncoding { offset: *at })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_bins() {
+        let mut linear = Histogram::<4>::new(Bins::Linear {
+            lower: 0.0,
+            upper: 8.0,
+        })
+        .unwrap();
+        linear.extend(
+            [-1.0, 0.0, 1.0, 2.0, 7.99, 8.0, f64::NAN]
+                .map(Sample::new)
+                .into_iter()
+                .chain([Sample::timed_out(10.0)]),
+        );
+        assert_eq!(linear.counts(), &[2, 1, 0, 1]);
+        assert_eq!((linear.underflow(), linear.overflow()), (1, 1));
+        assert_eq!((linear.censored(), linear.total()), (1, 6));
+        assert_eq!(linear.edges(1), (2.0, 4.0));
+        assert!((linear.density(0.5) - 2.0 / 6.0 / 2.0).abs() < 1e-12);
+
+        let mut log = Histogram::<3>::new(Bins::Log {
+            lower: 1.0,
+            upper: 1000.0,
+        })
+        .unwrap();
+        log.extend([0.0, 5.0, 50.0, 500.0, 999.0].map(Sample::new));
+        assert_eq!(log.counts(), &[1, 1, 2]);
+        assert_eq!(log.underflow(), 1);
+        let (lower, upper) = log.edges(1);
+        assert!((lower - 10.0).abs() < 1e-9 && (upper - 100.0).abs() < 1e-9);
+
+        let copy = log.clone();
+        log.merge(&copy).unwrap();
+        assert_eq!(log.counts(), &[2, 2, 4]);
+        assert_eq!(
+            Histogram::<3>::new(Bins::Log {
+                lower: 0.0,
+                upper: 1.0
+            }),
+            Err(EstimateError::InvalidConfig { field: "bins" })
+        );
+    }
+
+    #[test]
+    fn test_histogram_sparse_encoding() {
+        let bins = Bins::Linear {
+            lower: 0.0,
+            upper: 1000.0,
+        };
+        let mut histogram = Histogram::<1000>::new(bins).unwrap();
+        histogram.extend([3.5, 3.7, 10.0, 999.0, 2000.0].map(Sample::new));
+        histogram.extend(core::iter::repeat_n(Sample::new(500.0), 300));
+        let mut out = [0; 32];
+        let len = histogram.encode_sparse(&mut out).unwrap();
+        // Header, then bins 3, 10, 500 and 999 as gaps 3, 6, 489 and 498 with their counts.
+        assert_eq!(
+            &out[..len],
+            &[0, 1, 0, 4, 3, 2, 6, 1, 0xe9, 0x03, 0xac, 0x02, 0xf2, 0x03, 1]
+        );
+        assert_eq!(histogram.sparse_len(), len);
+        assert_eq!(
+            Histogram::<1000>::decode_sparse(bins, &out[..len]).as_ref(),
+            Ok(&histogram)
+        );
+
+        assert_eq!(
+            histogram.encode_sparse(&mut [0; 8]),
+            Err(EstimateError::CapacityExceeded { capacity: 8 })
+        );
+        assert_eq!(
+            Histogram::<1000>::decode_sparse(bins, &out[..len - 1]),
+            Err(EstimateError::MalformedEncoding { offset: len - 1 })
+        );
+        // Bin 999 is out of range of a smaller histogram.
+        assert!(Histogram::<999>::decode_sparse(bins, &out[..len]).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use core::time::Duration;
+
+use crate::error::EstimateError;
+use crate::float;
+use crate::offset_estimator::Estimate;
+
+/// Extrapolation of the offset beyond the last estimate, for holdover when the network
+/// reference disappears.
+///
+/// The offset is carried forward along the drift, and its uncertainty grows with the elapsed
+/// time `t` as `sqrt(u² + (σ_d t)² + q² t³ / 3)`: the uncertainty `u` of the last offset, the
+/// drift uncertainty `σ_d` integrated over `t`, and the random walk of the frequency, of
+/// [`wander`](Self::wander) `q`, which dominates long holdovers.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::{estimate_samples_checked, EstimatorConfig, Holdover, Sample};
+///
+/// let config = EstimatorConfig { detrend: true, ..Default::default() };
+/// let mut samples: [Sample; 100] =
+///     core::array::from_fn(|i| Sample::new(5.0 + 0.01 * i as f64 + (i % 7) as f64).at(i as f64));
+/// let estimate = estimate_samples_checked(&mut samples, &mut [0.0; 100], &config).unwrap();
+/// // Timestamps in seconds.
+/// let holdover = Holdover::from_estimate(estimate, Duration::from_secs(1));
+/// let later = holdover.predict(Duration::from_secs(60));
+/// assert!(later.uncertainty > holdover.estimate.uncertainty);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holdover {
+    /// Last estimate, whose offset refers to the start of the holdover.
+    pub estimate: Estimate,
+    /// Drift of the offset, in sample units per timestamp unit.
+    pub drift: f64,
+    /// Standard error of `drift`.
+    pub drift_uncertainty: f64,
+    /// Standard deviation of the frequency random walk, in sample units per timestamp unit over
+    /// one timestamp unit. Zero by default, trusting the drift to stay put.
+    pub wander: f64,
+    /// Duration of one timestamp unit.
+    pub unit: Duration,
+}
+
+impl Holdover {
+    /// Holdover from an estimate of
+    /// [`EstimatorConfig::detrend`](crate::EstimatorConfig::detrend), using its drift and drift
+    /// uncertainty, or no drift without detrending. `unit` is the duration of one timestamp unit of
+    /// the samples.
+    pub fn from_estimate(estimate: Estimate, unit: Duration) -> Self {
+        Holdover {
+            drift: estimate.drift.unwrap_or(0.0),
+            drift_uncertainty: estimate.drift_uncertainty.unwrap_or(0.0),
+            estimate,
+            wander: 0.0,
+            unit,
+        }
+    }
+
+    pub const fn with_wander(self, wander: f64) -> Self {
+        Holdover { wander, ..self }
+    }
+
+    /// Replaces the drift by the prediction of `model` at the current `covariate`, e.g. the
+    /// oscillator temperature, so that the holdover follows temperature-driven frequency changes.
+    pub fn with_drift_model(self, model: &CovariateDrift, covariate: f64) -> Self {
+        Holdover {
+            drift: model.drift_at(covariate),
+            drift_uncertainty: model.uncertainty_at(covariate),
+            ..self
+        }
+    }
+
+    /// The estimate extrapolated `at` after the start of the holdover.
+    pub fn predict(&self, at: Duration) -> Estimate {
+        let t = at.as_secs_f64() / self.unit.as_secs_f64();
+        let u = self.estimate.uncertainty;
+        let drift = self.drift_uncertainty * t;
+        let wander = self.wander * self.wander * t * t * t / 3.0;
+        Estimate {
+            offset: self.estimate.offset + self.drift * t,
+            uncertainty: float::sqrt(u * u + drift * drift + wander),
+            ..self.estimate.clone()
+        }
+    }
+}
+
+/// A drift measurement together with the environmental covariate it was measured at, e.g. the
+/// [`drift`](Estimate::drift) of a detrended window and the mean oscillator temperature over it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftObservation {
+    /// Measured drift, in sample units per timestamp unit.
+    pub drift: f64,
+    /// Standard error of `drift`.
+    pub uncertainty: f64,
+    /// Covariate at the time of the measurement.
+    pub covariate: f64,
+}
+
+/// Linear model of the drift as a function of an environmental covariate, for oscillators whose
+/// frequency depends on temperature. See [`fit_covariate_drift`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CovariateDrift {
+    /// Drift at the covariate `reference`.
+    pub base: f64,
+    /// Change of the drift per unit of the covariate.
+    pub sensitivity: f64,
+    /// Weighted mean covariate of the observations, where `base` and `sensitivity` are
+    /// uncorrelated.
+    pub reference: f64,
+    /// Variance of `base`.
+    base_var: f64,
+    /// Variance of `sensitivity`.
+    sensitivity_var: f64,
+}
+
+impl CovariateDrift {
+    /// Predicted drift at `covariate`.
+    pub fn drift_at(&self, covariate: f64) -> f64 {
+        self.base + self.sensitivity * (covariate - self.reference)
+    }
+
+    /// Standard error of [`drift_at`](Self::drift_at).
+    pub fn uncertainty_at(&self, covariate: f64) -> f64 {
+        let distance = covariate - self.reference;
+        float::sqrt(self.base_var + distance * distance * self.sensitivity_var)
+    }
+}
+
+/// Fits the drift as a linear function of a covariate by weighted least squares, each
+/// observation weighted by its inverse variance.
+///
+/// When the observations scatter around the line more than their uncertainties allow, the
+/// uncertainties of the fit are inflated by the excess, the reduced chi-square, so that an
+/// incomplete model does not look more precise than the data.
+///
+/// Fails with [`EstimateError::NonFiniteSample`] on a non-finite drift or covariate, with
+/// [`EstimateError::InvalidWeight`] on an uncertainty that is not finite and positive, and with
+/// [`EstimateError::InsufficientSamples`] when fewer than two distinct covariate values are
+/// observed.
+pub fn fit_covariate_drift(
+    observations: &[DriftObservation],
+) -> Result<CovariateDrift, EstimateError> {
+    if let Some(index) = observations
+        .iter()
+        .position(|o| !(o.drift.is_finite() && o.covariate.is_finite()))
+    {
+        return Err(EstimateError::NonFiniteSample { index });
+    }
+    if let Some(index) = observations
+        .iter()
+        .position(|o| !(o.uncertainty.is_finite() && o.uncertainty > 0.0))
+    {
+        return Err(EstimateError::InvalidWeight { index });
+    }
+    let weight = |o: &DriftObservation| 1.0 / (o.uncertainty * o.uncertainty);
+    let w_sum: f64 = observations.iter().map(weight).sum();
+    let mean = |value: fn(&DriftObservation) -> f64| {
+        observations
+            .iter()
+            .map(|o| weight(o) * value(o))
+            .sum::<f64>()
+            / w_sum
+    };
+    let reference = mean(|o| o.covariate);
+    let base = mean(|o| o.drift);
+    let scc: f64 = observations
+        .iter()
+        .map(|o| weight(o) * (o.covariate - reference) * (o.covariate - reference))
+        .sum();
+    let scd: f64 = observations
+        .iter()
+        .map(|o| weight(o) * (o.covariate - reference) * (o.drift - base))
+        .sum();
+    if observations.len() < 2 || scc.is_nan() || scc <= 0.0 {
+        return Err(EstimateError::InsufficientSamples {
+            got: usize::from(!observations.is_empty()),
+            need: 2,
+        });
+    }
+    let sensitivity = scd / scc;
+    let m = observations.len();
+    let chi_square: f64 = observations
+        .iter()
+        .map(|o| {
+            let residual = o.drift - base - sensitivity * (o.covariate - reference);
+            weight(o) * residual * residual
+        })
+        .sum();
+    let excess = match m > 2 {
+        true => (chi_square / (m - 2) as f64).max(1.0),
+        false => 1.0,
+    };
+    Ok(CovariateDrift {
+        base,
+        sensitivity,
+        reference,
+        base_var: excess / w_sum,
+        sensitivity_var: excess / scc,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_holdover_predict() {
+        let estimate = Estimate {
+            drift: Some(2e-3),
+            drift_uncertainty: Some(1e-3),
+            ..Estimate::from_offset(10.0, 0.3)
+        };
+        let holdover = Holdover::from_estimate(estimate, Duration::from_millis(10));
+        let now = holdover.predict(Duration::ZERO);
+        assert_eq!((now.offset, now.uncertainty), (10.0, 0.3));
+
+        // 4 seconds are 400 timestamp units.
+        let later = holdover.predict(Duration::from_secs(4));
+        assert!((later.offset - 10.8).abs() < 1e-12);
+        assert!((later.uncertainty - 0.5).abs() < 1e-12);
+
+        let wandering = holdover.with_wander(1e-5).predict(Duration::from_secs(4));
+        assert_eq!(wandering.offset, later.offset);
+        let expected = (0.25f64 + 1e-10 * 400f64.powi(3) / 3.0).sqrt();
+        assert!((wandering.uncertainty - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fit_covariate_drift() {
+        // 3e-6 per degree around 1e-5 at 25 degrees, measured to 1e-7.
+        let observations: [DriftObservation; 5] = core::array::from_fn(|i| {
+            let covariate = 20.0 + 2.5 * i as f64;
+            let noise = [1e-7, -1e-7, 0.0, 1e-7, -1e-7][i];
+            DriftObservation {
+                drift: 1e-5 + 3e-6 * (covariate - 25.0) + noise,
+                uncertainty: 1e-7,
+                covariate,
+            }
+        });
+        let model = fit_covariate_drift(&observations).unwrap();
+        assert!((model.sensitivity - 3e-6).abs() < 1e-7, "{model:?}");
+        assert!((model.drift_at(30.0) - 2.5e-5).abs() < 3e-7, "{model:?}");
+        assert!(model.uncertainty_at(40.0) > model.uncertainty_at(25.0));
+
+        let holdover =
+            Holdover::from_estimate(Estimate::from_offset(0.0, 0.0), Duration::from_secs(1))
+                .with_drift_model(&model, 30.0);
+        let later = holdover.predict(Duration::from_secs(1000));
+        assert!((later.offset - 2.5e-2).abs() < 3e-4);
+
+        let flat = [observations[0], observations[0]];
+        assert_eq!(
+            fit_covariate_drift(&flat),
+            Err(EstimateError::InsufficientSamples { got: 1, need: 2 })
+        );
+        let mut broken = observations;
+        broken[3].uncertainty = 0.0;
+        assert_eq!(
+            fit_covariate_drift(&broken),
+            Err(EstimateError::InvalidWeight { index: 3 })
+        );
+    }
+}
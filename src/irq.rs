@@ -0,0 +1,182 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "alloc")]
+use crate::online::OnlineEstimator;
+use crate::sample::Sample;
+
+/// Fixed-capacity lock-free queue carrying samples from an interrupt handler to thread context.
+///
+/// Timestamping typically happens in interrupt handlers, while the estimation math is far too
+/// heavy for them. The queue is split into a [`SampleProducer`], whose [`push`](SampleProducer::push)
+/// is wait-free and safe to call from an interrupt, and a [`SampleConsumer`] that drains the
+/// samples into an [`OnlineEstimator`] in thread context.
+///
+/// Only atomic loads and stores are used, so the queue also works on cores without
+/// compare-and-swap such as Cortex-M0. When the queue is full, new samples are dropped and counted.
+///
+/// ```
+/// use gamlr::SampleQueue;
+///
+/// let mut queue = SampleQueue::<64>::new();
+/// let (mut producer, mut consumer) = queue.split();
+/// // In the interrupt handler:
+/// let _ = producer.push(0.35.into());
+/// // In thread context, typically through `drain_into` an `OnlineEstimator`:
+/// assert_eq!(consumer.pop().map(|s| s.value), Some(0.35));
+/// ```
+pub struct SampleQueue<const N: usize> {
+    slots: [UnsafeCell<Sample>; N],
+    /// Next position to write, in `0..2 * N` so that a full queue differs from an empty one.
+    head: AtomicUsize,
+    /// Next position to read, in `0..2 * N`.
+    tail: AtomicUsize,
+    /// Samples dropped because the queue was full. Only written by the producer.
+    dropped: AtomicUsize,
+}
+
+// SAFETY: slots are only accessed through the single producer and single consumer handed out by
+// `split`, and a slot is never read and written at the same time: the producer only writes slots
+// outside `tail..head`, the consumer only reads slots inside it, and both publish their progress
+// with release stores observed through acquire loads.
+unsafe impl<const N: usize> Sync for SampleQueue<N> {}
+
+impl<const N: usize> Default for SampleQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SampleQueue<N> {
+    pub const fn new() -> Self {
+        SampleQueue {
+            slots: [const { UnsafeCell::new(Sample::new(0.0)) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer halves.
+    pub fn split(&mut self) -> (SampleProducer<'_, N>, SampleConsumer<'_, N>) {
+        let queue = &*self;
+        (SampleProducer { queue }, SampleConsumer { queue })
+    }
+
+    fn len(head: usize, tail: usize) -> usize {
+        (head + 2 * N - tail) % (2 * N)
+    }
+}
+
+/// Interrupt-side half of a [`SampleQueue`].
+pub struct SampleProducer<'a, const N: usize> {
+    queue: &'a SampleQueue<N>,
+}
+
+impl<const N: usize> SampleProducer<'_, N> {
+    /// Enqueues `sample`, handing it back if the queue is full.
+    pub fn push(&mut self, sample: Sample) -> Result<(), Sample> {
+        let queue = self.queue;
+        let head = queue.head.load(Ordering::Relaxed);
+        let tail = queue.tail.load(Ordering::Acquire);
+        if N == 0 || SampleQueue::<N>::len(head, tail) == N {
+            let dropped = queue.dropped.load(Ordering::Relaxed);
+            queue
+                .dropped
+                .store(dropped.wrapping_add(1), Ordering::Relaxed);
+            return Err(sample);
+        }
+        // SAFETY: the slot at `head` is outside `tail..head`, so the consumer is not reading it.
+        unsafe { *queue.slots[head % N].get() = sample };
+        queue.head.store((head + 1) % (2 * N), Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Thread-side half of a [`SampleQueue`].
+pub struct SampleConsumer<'a, const N: usize> {
+    queue: &'a SampleQueue<N>,
+}
+
+impl<const N: usize> SampleConsumer<'_, N> {
+    /// Dequeues the oldest sample, if any.
+    pub fn pop(&mut self) -> Option<Sample> {
+        let queue = self.queue;
+        let tail = queue.tail.load(Ordering::Relaxed);
+        let head = queue.head.load(Ordering::Acquire);
+        if N == 0 || SampleQueue::<N>::len(head, tail) == 0 {
+            return None;
+        }
+        // SAFETY: the slot at `tail` is inside `tail..head`, so the producer is not writing it.
+        let sample = unsafe { *queue.slots[tail % N].get() };
+        queue.tail.store((tail + 1) % (2 * N), Ordering::Release);
+        Some(sample)
+    }
+
+    /// Moves every queued sample into `estimator`, returning how many were moved.
+    #[cfg(feature = "alloc")]
+    pub fn drain_into(&mut self, estimator: &mut OnlineEstimator) -> usize {
+        let mut moved = 0;
+        while let Some(sample) = self.pop() {
+            estimator.push(sample);
+            moved += 1;
+        }
+        moved
+    }
+
+    /// Number of samples dropped so far because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use crate::config::EstimatorConfig;
+
+    #[test]
+    fn test_sample_queue_full_and_wraparound() {
+        let mut queue = SampleQueue::<3>::new();
+        let (mut producer, mut consumer) = queue.split();
+        for round in 0..5 {
+            let base = f64::from(round) * 10.0;
+            for i in 0..3 {
+                assert_eq!(producer.push(Sample::new(base + f64::from(i))), Ok(()));
+            }
+            assert_eq!(producer.push(Sample::new(-1.0)), Err(Sample::new(-1.0)));
+            for i in 0..3 {
+                assert_eq!(consumer.pop().map(|s| s.value), Some(base + f64::from(i)));
+            }
+            assert_eq!(consumer.pop(), None);
+        }
+        assert_eq!(consumer.dropped(), 5);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_sample_queue_across_threads() {
+        let mut queue = SampleQueue::<16>::new();
+        let (mut producer, mut consumer) = queue.split();
+        let mut online = OnlineEstimator::new(EstimatorConfig::default(), 10_000);
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..1000 {
+                    let sample = Sample::new(f64::from(i));
+                    while producer.push(sample).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            let mut received = 0;
+            while received < 1000 {
+                received += consumer.drain_into(&mut online);
+            }
+        });
+        assert_eq!(online.len(), 1000);
+    }
+}
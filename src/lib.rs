@@ -1,7 +1,157 @@
 #![no_std]
+// Without a sample buffer backend the pipeline has no entry point.
+#![cfg_attr(not(any(feature = "alloc", feature = "heapless")), allow(dead_code))]
+#[cfg(any(feature = "alloc", test))]
 extern crate alloc;
 extern crate libm;
+#[cfg(any(feature = "std", test))]
+extern crate std;
 
+mod admission;
+mod approx;
+#[cfg(feature = "tokio")]
+mod background;
+#[cfg(feature = "alloc")]
+mod batch;
+#[cfg(feature = "alloc")]
+mod bayes;
+#[cfg(feature = "alloc")]
+mod cache;
+#[cfg(feature = "alloc")]
+mod calibration;
+mod cancel;
+mod config;
+#[cfg(feature = "toml")]
+pub mod config_file;
+mod cusum;
+mod discipline;
+#[cfg(feature = "embedded-time")]
+mod embedded;
+mod error;
+mod event;
+mod ewma;
+#[cfg(feature = "heapless")]
+pub mod fixed;
+mod float;
+mod fusion;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod histogram;
+mod holdover;
+mod irq;
+pub mod linfit;
+#[cfg(feature = "alloc")]
+mod matching;
+pub mod math;
+mod mcmc;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod mixture;
+#[cfg(test)]
+mod no_panic;
 mod offset_estimator;
+#[cfg(feature = "alloc")]
+mod online;
+mod particle;
+mod phc;
+mod preprocess;
+mod refclock;
+mod ring;
+mod roughtime;
+mod sample;
+#[cfg(feature = "alloc")]
+mod segment;
+#[cfg(feature = "alloc")]
+mod selection;
+mod sprt;
+#[cfg(feature = "alloc")]
+mod stats;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "opentelemetry")]
+mod telemetry;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub mod timestamping;
+mod units;
+#[cfg(feature = "alloc")]
+mod validation;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod wraparound;
 
-pub use offset_estimator::estimate;
+pub use admission::{Admission, AdmissionConfig, AdmissionControl};
+#[cfg(feature = "tokio")]
+pub use background::{spawn_estimator, EstimatorHandle, LatestEstimate};
+#[cfg(feature = "alloc")]
+pub use batch::{BatchEstimates, BatchEstimator};
+#[cfg(feature = "alloc")]
+pub use bayes::{estimate_bayesian, Posterior};
+#[cfg(feature = "alloc")]
+pub use cache::{CacheConfig, CachedEstimator};
+#[cfg(feature = "alloc")]
+pub use calibration::{
+    calibrate_asymmetry, decompose_round_trips, estimate_bidirectional, AsymmetryCalibration,
+    BidirectionalEstimate, CalibrationReference, PeerDelayExchange, RttDecomposition,
+};
+pub use cancel::CancelToken;
+pub use config::{
+    CoarseCenter, CoarseWindow, DelayPrior, EstimatorConfig, FastMode, GammaFit, LeapFilter,
+    LeapSeconds, NegativePolicy, NonFinitePolicy, PlottingPosition, QuantizationNoise, Regression,
+    SolverOptions, SourceQuality, Subsampling, SyntheticSample, TailPolicy, DEFAULT_MIN_SAMPLES,
+};
+pub use cusum::{CusumConfig, DriftChange, DriftMonitor};
+pub use discipline::{Correction, Discipline, DisciplineConfig};
+#[cfg(feature = "embedded-time")]
+pub use embedded::{instant_nanos, ClockSampler};
+pub use error::EstimateError;
+pub use event::Event;
+pub use ewma::{EwmaChart, EwmaConfig, Health};
+pub use fusion::fuse;
+#[cfg(feature = "gpu")]
+pub use gpu::GpuEstimator;
+pub use histogram::{Bins, Histogram};
+pub use holdover::{fit_covariate_drift, CovariateDrift, DriftObservation, Holdover};
+pub use irq::{SampleConsumer, SampleProducer, SampleQueue};
+#[cfg(feature = "alloc")]
+pub use matching::{MatchConfig, MatchCounts, SequenceMatcher};
+pub use mcmc::{sample_posterior, sample_posterior_checked, McmcConfig, McmcSummary};
+#[cfg(feature = "alloc")]
+pub use mixture::{estimate_fast_path, FastPath};
+pub use mixture::{fit_mixture, GammaComponent, MixtureConfig, MixtureFit};
+#[cfg(all(feature = "alloc", feature = "uom"))]
+pub use offset_estimator::estimate_times;
+#[cfg(feature = "alloc")]
+pub use offset_estimator::{
+    estimate, estimate_delays, estimate_pairs, estimate_samples, estimate_ticks, estimate_weighted,
+    estimate_with, try_estimate_samples,
+};
+pub use offset_estimator::{estimate_samples_checked, Estimate, RegressionDesign};
+#[cfg(feature = "alloc")]
+pub use online::{Eviction, OnlineEstimator, SampleStore};
+pub use particle::{ParticleFilter, Track, TrackerConfig};
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub use phc::PtpClock;
+#[cfg(feature = "std")]
+pub use phc::SystemClock;
+pub use phc::{ClockSource, HardwareClock};
+pub use refclock::{LeapIndicator, RefclockSample, SHM_TIME_LEN, SOCK_SAMPLE_LEN};
+pub use ring::SampleRing;
+pub use roughtime::RoughtimeResponse;
+pub use sample::{Sample, TimestampPair};
+#[cfg(feature = "alloc")]
+pub use segment::{segment_drift, DriftSegment, SegmentConfig};
+#[cfg(feature = "alloc")]
+pub use selection::{
+    fault_tolerant_intersection, marzullo, select, Intersection, Selection, SelectionConfig,
+};
+pub use sprt::{OffsetShift, ShiftConfig, ShiftMonitor};
+#[cfg(feature = "alloc")]
+pub use stats::{DelayMetrics, DelayStats, DelayVariation};
+#[cfg(feature = "async")]
+pub use stream::{EstimateStream, NoTicks};
+pub use units::{Micros, Millis, Nanos, Seconds, TimeUnit};
+#[cfg(feature = "alloc")]
+pub use validation::{cross_validate, cross_validate_with_progress, test_offset_zero};
+#[cfg(feature = "wasm")]
+pub use wasm::PerformanceClock;
+pub use wraparound::Wraparound;
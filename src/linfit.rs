@@ -0,0 +1,623 @@
+//! Weighted least-squares fits of a straight line, the regression behind the offset estimate,
+//! without `std` and without an allocator.
+//!
+//! ```
+//! use gamlr::linfit;
+//!
+//! let points = [(0.0, 1.0), (1.0, 3.1), (2.0, 4.9), (3.0, 7.0)];
+//! let fit = linfit::fit(points.iter().map(|&(x, y)| (x, y, 1.0)), false).unwrap();
+//! assert!((fit.slope - 2.0).abs() < 0.1);
+//! assert!((fit.intercept - 1.0).abs() < 0.1);
+//! // Where the line crosses y = 0.
+//! assert!((fit.solve(0.0) + 0.5).abs() < 0.1);
+//! ```
+
+use crate::config::Regression;
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+
+/// A line `y = intercept + slope · x` fitted to weighted points.
+///
+/// Weights act as frequencies normalized to the number of points, so that the residual variance
+/// keeps `n - 2` degrees of freedom whatever their scale. The intercept is at `x = 0`, so
+/// abscissae of large magnitude such as epoch timestamps are best centered first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// Standard error of `slope`.
+    pub slope_error: f64,
+    /// Standard error of `intercept`.
+    pub intercept_error: f64,
+    /// Weighted variance of the residuals around the line.
+    pub residual_variance: f64,
+    /// Weighted mean of the abscissae.
+    pub x_mean: f64,
+    /// Weighted mean of the ordinates.
+    pub y_mean: f64,
+    /// Weighted standard deviation of the abscissae.
+    pub x_spread: f64,
+    /// Weighted standard deviation of the ordinates.
+    pub y_spread: f64,
+    /// Total weight of the points.
+    pub weight: f64,
+    /// Number of points.
+    pub points: usize,
+}
+
+impl LineFit {
+    /// Ordinate of the line at `x`.
+    pub fn predict(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+
+    /// Abscissa at which the line reaches `y`, the inverse prediction.
+    pub fn solve(&self, y: f64) -> f64 {
+        (y - self.intercept) / self.slope
+    }
+
+    /// Standard error of [`solve`](Self::solve) at `y` by the delta method. It grows with the
+    /// distance of `y` from the mean ordinate, so extrapolations far outside the points are
+    /// uncertain.
+    pub fn solve_error(&self, y: f64) -> f64 {
+        let slope_sq = self.slope * self.slope;
+        let sxx = self.weight * self.x_spread * self.x_spread;
+        let distance = y - self.y_mean;
+        float::sqrt(
+            self.residual_variance / slope_sq
+                * (1.0 / self.weight + distance * distance / (slope_sq * sxx)),
+        )
+    }
+
+    /// Residual of the point `(x, y)`, its ordinate minus the line's.
+    pub fn residual(&self, x: f64, y: f64) -> f64 {
+        y - self.predict(x)
+    }
+
+    /// Residuals of `points`, given as to [`fit`].
+    pub fn residuals<'a, I>(&'a self, points: I) -> impl Iterator<Item = f64> + 'a
+    where
+        I: IntoIterator<Item = (f64, f64, f64)>,
+        I::IntoIter: 'a,
+    {
+        points.into_iter().map(|(x, y, _)| self.residual(x, y))
+    }
+}
+
+/// Fits a line to the `(x, y, weight)` points by weighted least squares, with the compensated
+/// sums of [`EstimatorConfig::precise`](crate::EstimatorConfig::precise) when `precise` is set.
+///
+/// The points are iterated several times, so `points` has to be cheap to clone, e.g. an iterator
+/// over a slice. The slope is regressed on standardized coordinates, whose products neither
+/// overflow nor cancel at large magnitudes.
+///
+/// Fails with [`EstimateError::InsufficientSamples`] on fewer than two points, and with
+/// [`EstimateError::DegenerateRegression`] when the abscissae do not spread beyond their rounding
+/// error. Weights are not validated; a zero total weight is degenerate.
+pub fn fit<I>(points: I, precise: bool) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    fit_with(points, Regression::LeastSquares, precise)
+}
+
+/// Fits a line to the `(x, y, weight)` points by Deming regression, the errors-in-variables model
+/// where both coordinates are noisy and `variance_ratio` is the error variance of `y` over that
+/// of `x`. A ratio of one minimizes the orthogonal distances to the line. Noise in `x` attenuates
+/// the slope of [`fit`]; here it does not, provided the ratio is about right.
+///
+/// The standard errors are those of [`fit`] around the Deming line, which neglect the noise in
+/// `x`. Fails like [`fit`], and with [`EstimateError::InvalidConfig`] naming `variance_ratio`
+/// unless it is finite and positive.
+pub fn fit_deming<I>(
+    points: I,
+    variance_ratio: f64,
+    precise: bool,
+) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    fit_with(points, Regression::Deming { variance_ratio }, precise)
+}
+
+/// Fits a line to the `(x, y, weight)` points by Passing–Bablok regression: the slope is the
+/// shifted median of the slopes between all pairs of points, the intercept the median of
+/// `y - slope · x`. It assumes nothing about the distribution of the errors, which may be in
+/// both coordinates, and is robust to outliers. The weights are ignored by the line, but not by
+/// the means, spreads and standard errors, which are those of [`fit`] around it.
+///
+/// The pairwise slopes are not stored but counted, at most 128 times over for the median, so the
+/// fit takes `O(n²)` time and no memory. Fails like [`fit`].
+///
+/// H. Passing and W. Bablok. "A New Biometrical Procedure for Testing the Equality of
+/// Measurements from Two Different Analytical Methods". Journal of Clinical Chemistry and
+/// Clinical Biochemistry, Vol. 21 (1983), pp. 709-720.
+pub fn fit_passing_bablok<I>(points: I, precise: bool) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    fit_with(points, Regression::PassingBablok, precise)
+}
+
+/// Fits a line to the `(x, y, weight)` points by Siegel's repeated-median regression: the slope
+/// is the median over the points of the median of their slopes to the other points, the
+/// intercept the median of `y - slope · x`. It has the highest breakdown point of the lines,
+/// withstanding up to half of the points being outliers, against 29% for Theil–Sen. The
+/// weights are ignored by the line, like in [`fit_passing_bablok`], and the inner medians of an
+/// even number of slopes are the lower of the two middle ones.
+///
+/// The slopes are counted rather than stored, so the fit takes `O(n²)` time and no memory.
+/// Fails like [`fit`].
+///
+/// A. F. Siegel. "Robust regression using repeated medians". Biometrika, Vol. 69, No. 1 (1982),
+/// pp. 242-244.
+pub fn fit_repeated_median<I>(points: I, precise: bool) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    fit_with(points, Regression::RepeatedMedian, precise)
+}
+
+/// Fits a line to the `(x, y, weight)` points by least trimmed squares: the line minimizing the
+/// sum of the `⌈coverage · n⌉` smallest weighted squared residuals, which is the least-squares
+/// fit of those points. With `coverage` from 0.5 to 1, it withstands up to `1 - coverage` of the
+/// points being outliers while staying in the least-squares family, standard errors included,
+/// which are those of the kept points. Fails with [`EstimateError::InvalidConfig`] naming
+/// `coverage` outside that range, and otherwise like [`fit`].
+///
+/// The fit concentrates from the [`fit`] and [`fit_repeated_median`] lines, refitting to the
+/// points of smallest residuals until the sum stops decreasing, and keeps the better one. This
+/// finds the minimum on all but contrived clouds, in `O(n²)` time and no memory.
+///
+/// P. J. Rousseeuw and K. Van Driessen. "Computing LTS Regression for Large Data Sets". Data
+/// Mining and Knowledge Discovery, Vol. 12 (2006), pp. 29-45.
+pub fn fit_trimmed_squares<I>(
+    points: I,
+    coverage: f64,
+    precise: bool,
+) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    fit_with(points, Regression::TrimmedSquares { coverage }, precise)
+}
+
+/// Fits a line to the `(x, y, weight)` points with `regression`, see [`fit`], [`fit_deming`],
+/// [`fit_passing_bablok`], [`fit_repeated_median`] and [`fit_trimmed_squares`]. Fails with
+/// [`EstimateError::DegenerateRegression`] when the line is vertical.
+pub fn fit_with<I>(
+    points: I,
+    regression: Regression,
+    precise: bool,
+) -> Result<LineFit, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64, f64)>,
+    I::IntoIter: Clone,
+{
+    match regression {
+        Regression::Deming { variance_ratio }
+            if !(variance_ratio.is_finite() && variance_ratio > 0.0) =>
+        {
+            return Err(EstimateError::InvalidConfig {
+                field: "variance_ratio",
+            });
+        }
+        Regression::TrimmedSquares { coverage } if !(0.5..=1.0).contains(&coverage) => {
+            return Err(EstimateError::InvalidConfig { field: "coverage" });
+        }
+        Regression::TrimmedSquares { coverage } => {
+            return trimmed_squares(points.into_iter(), coverage, precise);
+        }
+        _ => {}
+    }
+    fit_line(points.into_iter(), regression, precise)
+}
+
+/// [`fit_with`] once `regression` is validated, but for the trimmed squares.
+fn fit_line<I>(points: I, regression: Regression, precise: bool) -> Result<LineFit, EstimateError>
+where
+    I: Iterator<Item = (f64, f64, f64)> + Clone,
+{
+    let n = points.clone().count();
+    if n < 2 {
+        return Err(EstimateError::InsufficientSamples { got: n, need: 2 });
+    }
+    let points = || points.clone();
+    let w_sum = math::sum(points().map(|(_, _, w)| w), precise);
+    let x_mean = math::sum(points().map(|(x, _, w)| w * x), precise) / w_sum;
+    let y_mean = math::sum(points().map(|(_, y, w)| w * y), precise) / w_sum;
+    let x_scale = float::sqrt(
+        math::sum(
+            points().map(|(x, _, w)| w * float::pow(x - x_mean, 2.0)),
+            precise,
+        ) / w_sum,
+    );
+    let y_scale = float::sqrt(
+        math::sum(
+            points().map(|(_, y, w)| w * float::pow(y - y_mean, 2.0)),
+            precise,
+        ) / w_sum,
+    );
+    if !resolved(x_scale, libm::fabs(x_mean)) {
+        return Err(EstimateError::DegenerateRegression);
+    }
+    // A flat line has no spread to standardize away.
+    let y_unit = if y_scale > 0.0 { y_scale } else { 1.0 };
+
+    // On standardized coordinates the line passes through the origin and only the slope remains.
+    let standardized =
+        || points().map(move |(x, y, w)| ((x - x_mean) / x_scale, (y - y_mean) / y_unit, w));
+    let suv = math::sum(standardized().map(|(u, v, w)| w * u * v), precise);
+    let suu = math::sum(standardized().map(|(u, _, w)| w * u * u), precise);
+    let slope = match regression {
+        // Trimmed squares end on a least-squares fit of their subset.
+        Regression::LeastSquares | Regression::TrimmedSquares { .. } => suv / suu,
+        // A flat cloud has no direction.
+        Regression::Deming { .. } if suv == 0.0 => 0.0,
+        Regression::Deming { variance_ratio } => {
+            let ratio = variance_ratio * (x_scale * x_scale) / (y_unit * y_unit);
+            let svv = math::sum(standardized().map(|(_, v, w)| w * v * v), precise);
+            let d = svv - ratio * suu;
+            (d + float::sqrt(d * d + 4.0 * ratio * suv * suv)) / (2.0 * suv)
+        }
+        Regression::PassingBablok => passing_bablok_slope(&points()) * x_scale / y_unit,
+        Regression::RepeatedMedian => repeated_median_slope(&points()) * x_scale / y_unit,
+    };
+    let beta = slope * y_unit / x_scale;
+    if !beta.is_finite() {
+        return Err(EstimateError::DegenerateRegression);
+    }
+    let (intercept, residual_ss) = match regression {
+        Regression::PassingBablok | Regression::RepeatedMedian => {
+            let intercept = median(n, |t| {
+                points().filter(|&(x, y, _)| y - beta * x <= t).count()
+            });
+            let residual = |(x, y, _): (f64, f64, f64)| y - intercept - beta * x;
+            let ss = math::sum(
+                points().map(|p| p.2 * float::pow(residual(p), 2.0)),
+                precise,
+            );
+            (intercept, ss)
+        }
+        _ => {
+            let ss = y_unit
+                * y_unit
+                * math::sum(
+                    standardized().map(|(u, v, w)| w * float::pow(v - slope * u, 2.0)),
+                    precise,
+                );
+            (y_mean - beta * x_mean, ss)
+        }
+    };
+    let residual_variance = residual_ss / w_sum * n as f64 / (n as f64 - 2.0);
+
+    let sxx = w_sum * x_scale * x_scale;
+    Ok(LineFit {
+        slope: beta,
+        intercept,
+        slope_error: float::sqrt(residual_variance / sxx),
+        intercept_error: float::sqrt(residual_variance * (1.0 / w_sum + x_mean * x_mean / sxx)),
+        residual_variance,
+        x_mean,
+        y_mean,
+        x_spread: x_scale,
+        y_spread: y_scale,
+        weight: w_sum,
+        points: n,
+    })
+}
+
+/// Most concentration steps of [`fit_trimmed_squares`] from one start. Each strictly decreases
+/// the trimmed sum, so they stop long before on real data.
+const MAX_CONCENTRATIONS: usize = 64;
+
+/// [`fit_trimmed_squares`] with a valid `coverage`.
+fn trimmed_squares<I>(points: I, coverage: f64, precise: bool) -> Result<LineFit, EstimateError>
+where
+    I: Iterator<Item = (f64, f64, f64)> + Clone,
+{
+    let n = points.clone().count();
+    let keep = (libm::ceil(coverage * n as f64) as usize).min(n);
+    let square = |line: &LineFit, (x, y, _): (f64, f64, f64)| float::pow(line.residual(x, y), 2.0);
+    let concentrate = |mut line: LineFit| {
+        let mut trimmed = f64::INFINITY;
+        for _ in 0..MAX_CONCENTRATIONS {
+            let cutoff = order_statistic(keep, |t| {
+                points.clone().filter(|&p| square(&line, p) <= t).count()
+            });
+            let kept = points.clone().filter(move |&p| square(&line, p) <= cutoff);
+            let sum = math::sum(kept.clone().map(|p| p.2 * square(&line, p)), precise);
+            if sum >= trimmed {
+                break;
+            }
+            trimmed = sum;
+            line = fit_line(kept, Regression::LeastSquares, precise)?;
+        }
+        Ok::<_, EstimateError>((trimmed, line))
+    };
+    let start = |regression| fit_line(points.clone(), regression, precise);
+    let (least, line) = concentrate(start(Regression::LeastSquares)?)?;
+    let (median, robust) = concentrate(start(Regression::RepeatedMedian)?)?;
+    Ok(if median < least { robust } else { line })
+}
+
+/// Calls `visit` with the slope between every pair of `points` that Passing–Bablok keeps: not
+/// identical, and not of slope -1, with which pairs would cancel under the median.
+fn for_each_slope<I>(points: &I, mut visit: impl FnMut(f64))
+where
+    I: Iterator<Item = (f64, f64, f64)> + Clone,
+{
+    let mut rest = points.clone();
+    while let Some((x0, y0, _)) = rest.next() {
+        for (x1, y1, _) in rest.clone() {
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            if dx == 0.0 && dy == 0.0 {
+                continue;
+            }
+            // Tied abscissae give infinite slopes, signed by the ordinates.
+            let slope = dy / dx;
+            if slope != -1.0 {
+                visit(slope);
+            }
+        }
+    }
+}
+
+/// Shifted median of the pairwise slopes: the median once the `K` slopes below -1 are counted
+/// out, which makes the estimate consistent when both coordinates are noisy.
+fn passing_bablok_slope<I>(points: &I) -> f64
+where
+    I: Iterator<Item = (f64, f64, f64)> + Clone,
+{
+    let (mut count, mut below) = (0, 0);
+    for_each_slope(points, |slope| {
+        count += 1;
+        below += usize::from(slope < -1.0);
+    });
+    // Points spread along x have distinct pairs, so every one of them has slope -1.
+    if count == 0 {
+        return -1.0;
+    }
+    let at_most = |t: f64| {
+        let mut c = 0;
+        for_each_slope(points, |slope| c += usize::from(slope <= t));
+        c
+    };
+    let order = |k: usize| order_statistic(k.clamp(1, count), at_most);
+    match count % 2 {
+        1 => order(count.div_ceil(2) + below),
+        _ => 0.5 * (order(count / 2 + below) + order(count / 2 + below + 1)),
+    }
+}
+
+/// Median over the points of the lower median of their slopes to the other points. A point has
+/// its inner median at most `t` when at least half of its slopes are, so each count of the outer
+/// bisection is one pass over the pairs.
+fn repeated_median_slope<I>(points: &I) -> f64
+where
+    I: Iterator<Item = (f64, f64, f64)> + Clone,
+{
+    // Identical points have no slope, and points identical to all others no median.
+    let slopes = |i: usize, (x0, y0, _): (f64, f64, f64)| {
+        points
+            .clone()
+            .enumerate()
+            .filter_map(move |(j, (x1, y1, _))| {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                (j != i && !(dx == 0.0 && dy == 0.0)).then_some(dy / dx)
+            })
+    };
+    let centers = points
+        .clone()
+        .enumerate()
+        .filter(|&(i, p)| slopes(i, p).next().is_some())
+        .count();
+    median(centers, |t| {
+        points
+            .clone()
+            .enumerate()
+            .filter(|&(i, p)| {
+                let (count, at_most) = slopes(i, p).fold((0usize, 0), |(count, at_most), slope| {
+                    (count + 1, at_most + usize::from(slope <= t))
+                });
+                count > 0 && at_most >= count.div_ceil(2)
+            })
+            .count()
+    })
+}
+
+/// Median of `n` values, given the count of values at most `t` for any `t`.
+fn median(n: usize, at_most: impl Fn(f64) -> usize) -> f64 {
+    match n % 2 {
+        1 => order_statistic(n.div_ceil(2), &at_most),
+        _ => 0.5 * (order_statistic(n / 2, &at_most) + order_statistic(n / 2 + 1, &at_most)),
+    }
+}
+
+/// The `k`-th smallest of some values, one-based, given the count of values at most `t` for
+/// any `t`. Bisects the order of the `f64` bit patterns, so it is exact after at most 64 counts.
+fn order_statistic(k: usize, at_most: impl Fn(f64) -> usize) -> f64 {
+    // Maps the floats, infinities included, monotonically onto the unsigned integers.
+    let key = |x: f64| {
+        let bits = x.to_bits();
+        match bits >> 63 {
+            1 => !bits,
+            _ => bits | 1 << 63,
+        }
+    };
+    let value = |key: u64| match key >> 63 {
+        1 => f64::from_bits(key & !(1 << 63)),
+        _ => f64::from_bits(!key),
+    };
+    let (mut lower, mut upper) = (key(f64::NEG_INFINITY), key(f64::INFINITY));
+    while lower < upper {
+        let middle = lower + (upper - lower) / 2;
+        if at_most(value(middle)) >= k {
+            upper = middle;
+        } else {
+            lower = middle + 1;
+        }
+    }
+    value(lower)
+}
+
+/// Whether a spread `scale` of values around `magnitude` exceeds their rounding error.
+pub(crate) fn resolved(scale: f64, magnitude: f64) -> bool {
+    scale > 4.0 * f64::EPSILON * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_matches_closed_form() {
+        let points = [
+            (1.0, 2.0, 1.0),
+            (2.0, 2.5, 2.0),
+            (4.0, 5.5, 1.0),
+            (5.0, 5.0, 0.5),
+        ];
+        let fit = fit(points, true).unwrap();
+        // Weighted normal equations.
+        let [sw, swx, swy, swxx, swxy] = points.iter().fold([0.0; 5], |s, &(x, y, w)| {
+            [
+                s[0] + w,
+                s[1] + w * x,
+                s[2] + w * y,
+                s[3] + w * x * x,
+                s[4] + w * x * y,
+            ]
+        });
+        let slope = (sw * swxy - swx * swy) / (sw * swxx - swx * swx);
+        let intercept = (swy - slope * swx) / sw;
+        assert!((fit.slope - slope).abs() < 1e-12);
+        assert!((fit.intercept - intercept).abs() < 1e-12);
+        assert!((fit.solve(fit.predict(3.0)) - 3.0).abs() < 1e-12);
+
+        let residuals = fit.residuals(points);
+        let ss: f64 = residuals.zip(&points).map(|(r, p)| p.2 * r * r).sum();
+        assert!((ss / sw * 4.0 / 2.0 - fit.residual_variance).abs() < 1e-12);
+        let sxx = swxx - swx * swx / sw;
+        assert!((fit.slope_error - (fit.residual_variance / sxx).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_deming_removes_attenuation() {
+        // Both coordinates of y = 2x carry unit noise.
+        let mut rng = crate::offset_estimator::LcgRng::new(3);
+        let points: std::vec::Vec<_> = (0..2000)
+            .map(|i| {
+                let x = f64::from(i % 10);
+                let noisy = 2.0 * x + rng.marsaglia_polar_sample();
+                (x + rng.marsaglia_polar_sample(), noisy, 1.0)
+            })
+            .collect();
+        // Var(x) = 8.25: least squares shrinks the slope by 8.25 / 9.25.
+        let ols = fit(points.iter().copied(), false).unwrap();
+        assert!(
+            (ols.slope - 2.0 * 8.25 / 9.25).abs() < 0.05,
+            "{}",
+            ols.slope
+        );
+        let deming = fit_deming(points.iter().copied(), 1.0, false).unwrap();
+        assert!((deming.slope - 2.0).abs() < 0.05, "{}", deming.slope);
+        assert_eq!(
+            fit_deming(points.iter().copied(), 0.0, false),
+            Err(EstimateError::InvalidConfig {
+                field: "variance_ratio"
+            })
+        );
+    }
+
+    #[test]
+    fn test_passing_bablok_ignores_outliers() {
+        let mut points: std::vec::Vec<_> = (0..40)
+            .map(|i| {
+                let x = f64::from(i);
+                (x, 3.0 + 0.5 * x + 0.1 * f64::from(i % 3), 1.0)
+            })
+            .collect();
+        points[5].1 = 100.0;
+        points[30].1 = -50.0;
+        let robust = fit_passing_bablok(points.iter().copied(), false).unwrap();
+        assert!((robust.slope - 0.5).abs() < 0.01, "{}", robust.slope);
+        assert!((robust.intercept - 3.1).abs() < 0.1, "{}", robust.intercept);
+        let ols = fit(points.iter().copied(), false).unwrap();
+        assert!((ols.slope - 0.5).abs() > 0.1);
+
+        assert_eq!(
+            order_statistic(2, |t| [3.0, -1.0, 2.0].iter().filter(|&&v| v <= t).count()),
+            2.0
+        );
+
+        // Only slopes of -1, all counted out of the median.
+        let falling =
+            fit_passing_bablok([(0.0, 0.0, 1.0), (1.0, -1.0, 1.0), (2.0, -2.0, 1.0)], false)
+                .unwrap();
+        assert_eq!((falling.slope, falling.intercept), (-1.0, 0.0));
+    }
+
+    #[test]
+    fn test_repeated_median_breakdown() {
+        // 40% of the points on another line still leave the slope of the majority.
+        let points: std::vec::Vec<_> = (0..50)
+            .map(|i| {
+                let x = f64::from(i);
+                match i % 5 {
+                    0 | 1 => (x, 80.0 - 2.0 * x, 1.0),
+                    _ => (x, 1.0 + 0.5 * x, 1.0),
+                }
+            })
+            .collect();
+        let line = fit_repeated_median(points.iter().copied(), false).unwrap();
+        assert!((line.slope - 0.5).abs() < 1e-12, "{}", line.slope);
+        assert!((line.intercept - 1.0).abs() < 1e-12, "{}", line.intercept);
+        assert!((fit(points.iter().copied(), false).unwrap().slope - 0.5).abs() > 0.5);
+    }
+
+    #[test]
+    fn test_trimmed_squares_keeps_the_majority() {
+        let points: std::vec::Vec<_> = (0..60)
+            .map(|i| {
+                let x = f64::from(i);
+                match i % 4 {
+                    0 => (x, 200.0 + 3.0 * x, 1.0),
+                    _ => (x, 1.0 + 0.5 * x + 0.01 * f64::from(i % 3), 1.0),
+                }
+            })
+            .collect();
+        let line = fit_trimmed_squares(points.iter().copied(), 0.7, false).unwrap();
+        assert!((line.slope - 0.5).abs() < 1e-3, "{}", line.slope);
+        assert_eq!(line.points, 42);
+        assert!(line.slope_error < 1e-3);
+        assert_eq!(
+            fit_trimmed_squares(points, 0.4, false),
+            Err(EstimateError::InvalidConfig { field: "coverage" })
+        );
+    }
+
+    #[test]
+    fn test_fit_degenerate() {
+        assert_eq!(
+            fit([(1.0, 2.0, 1.0)], false),
+            Err(EstimateError::InsufficientSamples { got: 1, need: 2 })
+        );
+        assert_eq!(
+            fit([(3.0, 1.0, 1.0), (3.0, 2.0, 1.0)], false),
+            Err(EstimateError::DegenerateRegression)
+        );
+        // A constant ordinate is a flat line, not a degenerate one.
+        let flat = fit([(1.0, 2.0, 1.0), (2.0, 2.0, 1.0), (3.0, 2.0, 1.0)], false).unwrap();
+        assert_eq!(
+            (flat.slope, flat.intercept, flat.residual_variance),
+            (0.0, 2.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::error::EstimateError;
+use crate::sample::{Sample, TimestampPair};
+
+/// Parameters of [`SequenceMatcher`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchConfig {
+    /// Number of sequence numbers a record may arrive behind the latest one of its end, the
+    /// horizon records are held for their match. A probe is lost once the receive records went
+    /// that far past it without it, a receive record is unmatched once the send records did, and
+    /// a record that far behind is late. 64 by default.
+    pub reordering: u64,
+    /// Number of sequence numbers an end may run ahead of the other before its oldest records
+    /// are settled without a match, bounding the records held while the other end is silent,
+    /// e.g. during an outage of the receiver. At least `reordering`; 1024 by default.
+    pub outstanding: u64,
+    /// Delay to record lost probes at as [timed out](Sample::timed_out), so that the fit sees
+    /// them as censored rather than not at all, e.g. the receive timeout of the prober. `None`
+    /// leaves lost probes out.
+    pub timeout: Option<f64>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            reordering: 64,
+            outstanding: 1024,
+            timeout: None,
+        }
+    }
+}
+
+/// Bookkeeping of a [`SequenceMatcher`].
+///
+/// A. Morton, L. Ciavattone, G. Ramachandran, S. Shalunov, J. Perser. "Packet Reordering
+/// Metrics". RFC 4737, 2006.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchCounts {
+    /// Probes whose send and receive records were matched.
+    pub matched: usize,
+    /// Probes sent and never received within the reordering window.
+    pub lost: usize,
+    /// Records of a sequence number already recorded, e.g. duplicated or retransmitted packets,
+    /// ignored.
+    pub duplicates: usize,
+    /// Receive records without a send record within the reordering window, and records
+    /// arriving after it, ignored.
+    pub unmatched: usize,
+    /// Probes received after a probe of a later sequence number, the reordered packets of
+    /// RFC 4737.
+    pub reordered: usize,
+    /// Largest number of sequence numbers a probe was received behind the latest one received
+    /// before it, the depth the reordering window must cover.
+    pub reordering_extent: u64,
+}
+
+/// Matcher of the send and receive records of probes by sequence number into one-way delay
+/// samples, for probers that log both ends separately.
+///
+/// The records of each end come in any order within [`MatchConfig::reordering`] sequence numbers of
+/// its latest one, and either end may run ahead of the other: a receive record may well precede the
+/// send record of its probe when the logs of two hosts are merged. The delay of every matched probe
+/// is differenced as for a [`TimestampPair`]. Records of a sequence number already recorded are
+/// dropped, keeping the first arrival of a duplicated or retransmitted packet, whose later copies
+/// would otherwise pile up in the tail of the delays. A probe that falls out of the window without
+/// a receive record is lost, recorded as a censored sample at [`MatchConfig::timeout`], timestamped
+/// at its send time, if any.
+///
+/// ```
+/// use gamlr::{MatchConfig, SequenceMatcher};
+///
+/// let mut matcher = SequenceMatcher::new(MatchConfig::default()).unwrap();
+/// matcher.send(1, 100.0);
+/// matcher.send(2, 200.0);
+/// matcher.receive(2, 235.0);
+/// matcher.receive(1, 140.0);
+/// matcher.receive(1, 141.0);
+/// let delays: Vec<f64> = matcher.drain().map(|s| s.value).collect();
+/// assert_eq!(delays, [35.0, 40.0]);
+/// assert_eq!(matcher.counts().duplicates, 1);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceMatcher {
+    config: MatchConfig,
+    sends: BTreeMap<u64, f64>,
+    receives: BTreeMap<u64, f64>,
+    /// Sequence numbers matched or lost within the window, to tell duplicates.
+    done: BTreeSet<u64>,
+    /// Latest sequence numbers sent and received.
+    sent: Option<u64>,
+    received: Option<u64>,
+    ready: VecDeque<Sample>,
+    counts: MatchCounts,
+}
+
+impl SequenceMatcher {
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `outstanding` is below
+    /// `reordering` or `timeout` is not positive and finite.
+    pub fn new(config: MatchConfig) -> Result<Self, EstimateError> {
+        let valid = [
+            ("outstanding", config.outstanding >= config.reordering),
+            (
+                "timeout",
+                config.timeout.is_none_or(|t| t.is_finite() && t > 0.0),
+            ),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(SequenceMatcher {
+            config,
+            sends: BTreeMap::new(),
+            receives: BTreeMap::new(),
+            done: BTreeSet::new(),
+            sent: None,
+            received: None,
+            ready: VecDeque::new(),
+            counts: MatchCounts::default(),
+        })
+    }
+
+    pub fn config(&self) -> &MatchConfig {
+        &self.config
+    }
+
+    pub fn counts(&self) -> MatchCounts {
+        self.counts
+    }
+
+    /// Records that probe `sequence` was sent at `sent`, by the clock of the sender.
+    pub fn send(&mut self, sequence: u64, sent: f64) {
+        if self.admit(sequence, self.sent, |matcher| {
+            matcher.sends.contains_key(&sequence)
+        }) {
+            self.sent = Some(self.sent.map_or(sequence, |sent| sent.max(sequence)));
+            match self.receives.remove(&sequence) {
+                Some(received) => self.complete(sequence, TimestampPair::new(sent, received)),
+                None => {
+                    self.sends.insert(sequence, sent);
+                }
+            }
+        }
+        self.expire();
+    }
+
+    /// Records that probe `sequence` was received at `received`, by the clock of the receiver.
+    pub fn receive(&mut self, sequence: u64, received: f64) {
+        if self.admit(sequence, self.received, |matcher| {
+            matcher.receives.contains_key(&sequence)
+        }) {
+            self.arrive(sequence);
+            match self.sends.remove(&sequence) {
+                Some(sent) => self.complete(sequence, TimestampPair::new(sent, received)),
+                None => {
+                    self.receives.insert(sequence, received);
+                }
+            }
+        }
+        self.expire();
+    }
+
+    /// Records that probe `sequence` was received with both of its timestamps, e.g. by a
+    /// protocol carrying the send time in the packet, superseding its send record if any.
+    pub fn record(&mut self, sequence: u64, pair: TimestampPair) {
+        if self.admit(sequence, self.received, |matcher| {
+            matcher.receives.contains_key(&sequence)
+        }) {
+            self.arrive(sequence);
+            self.sent = Some(self.sent.map_or(sequence, |sent| sent.max(sequence)));
+            self.sends.remove(&sequence);
+            self.complete(sequence, pair);
+        }
+        self.expire();
+    }
+
+    /// Samples of the probes matched or lost since the last call, in that order.
+    pub fn drain(&mut self) -> impl Iterator<Item = Sample> + '_ {
+        self.ready.drain(..)
+    }
+
+    /// Declares every probe still waiting for its receive record lost, and drops the receive
+    /// records still waiting for their send record, e.g. at the end of a capture.
+    pub fn finish(&mut self) {
+        self.evict_sends(u64::MAX);
+        self.evict_receives(u64::MAX);
+        self.done.clear();
+    }
+
+    /// Whether a record of `sequence` is new and within the window behind `latest` of its end,
+    /// counting it otherwise. `pending` tells whether the same end already recorded it.
+    fn admit(
+        &mut self,
+        sequence: u64,
+        latest: Option<u64>,
+        pending: impl Fn(&Self) -> bool,
+    ) -> bool {
+        if self.done.contains(&sequence) || pending(self) {
+            self.counts.duplicates += 1;
+            return false;
+        }
+        if latest.is_some_and(|latest| latest.saturating_sub(sequence) > self.config.reordering) {
+            self.counts.unmatched += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Counts the arrival of probe `sequence` as reordered when a later one arrived before it.
+    fn arrive(&mut self, sequence: u64) {
+        match self.received {
+            Some(latest) if latest > sequence => {
+                self.counts.reordered += 1;
+                let extent = latest - sequence;
+                self.counts.reordering_extent = self.counts.reordering_extent.max(extent);
+            }
+            _ => self.received = Some(sequence),
+        }
+    }
+
+    fn complete(&mut self, sequence: u64, pair: TimestampPair) {
+        self.counts.matched += 1;
+        self.done.insert(sequence);
+        self.ready.push_back(pair.into());
+    }
+
+    /// Settles the records that fell out of the window behind the latest sequence numbers of
+    /// the other end, or out of the outstanding records behind those of their own end.
+    fn expire(&mut self) {
+        let MatchConfig {
+            reordering,
+            outstanding,
+            ..
+        } = self.config;
+        let behind = |latest: Option<u64>, window: u64| {
+            latest.map_or(0, |latest| latest.saturating_sub(window))
+        };
+        let lost = behind(self.received, reordering).max(behind(self.sent, outstanding));
+        let unmatched = behind(self.sent, reordering).max(behind(self.received, outstanding));
+        self.evict_sends(lost);
+        self.evict_receives(unmatched);
+        self.done = self.done.split_off(&lost.min(unmatched));
+    }
+
+    /// Declares the probes sent before `oldest` and still waiting for their receive record lost.
+    fn evict_sends(&mut self, oldest: u64) {
+        let kept = self.sends.split_off(&oldest);
+        for (_, sent) in core::mem::replace(&mut self.sends, kept) {
+            self.counts.lost += 1;
+            if let Some(timeout) = self.config.timeout {
+                self.ready.push_back(Sample::timed_out(timeout).at(sent));
+            }
+        }
+    }
+
+    /// Drops the receive records before `oldest` still waiting for their send record.
+    fn evict_receives(&mut self, oldest: u64) {
+        let kept = self.receives.split_off(&oldest);
+        self.counts.unmatched += core::mem::replace(&mut self.receives, kept).len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_sequence_matcher() {
+        let mut matcher = SequenceMatcher::new(MatchConfig {
+            reordering: 4,
+            timeout: Some(1000.0),
+            ..Default::default()
+        })
+        .unwrap();
+        // Probe 2 is lost, 3 is duplicated, 5 is received before it is logged as sent and 0
+        // arrives after the window has passed it.
+        for sequence in [1, 2, 3, 4, 5, 6] {
+            if sequence != 5 {
+                matcher.send(sequence, 100.0 * sequence as f64);
+            }
+            match sequence {
+                2 => {}
+                3 => {
+                    matcher.receive(3, 340.0);
+                    matcher.receive(3, 345.0);
+                }
+                _ => matcher.receive(sequence, 100.0 * sequence as f64 + 40.0),
+            }
+        }
+        matcher.send(5, 500.0);
+        matcher.send(7, 700.0);
+        matcher.receive(7, 740.0);
+        matcher.receive(0, 10.0);
+        matcher.send(8, 800.0);
+        let samples: Vec<Sample> = matcher.drain().collect();
+        let delays: Vec<f64> = samples.iter().map(|s| s.value).collect();
+        assert_eq!(delays, [40.0, 40.0, 40.0, 40.0, 40.0, 40.0, 1000.0]);
+        assert!(samples[6].censored && samples[6].timestamp == Some(200.0));
+        assert_eq!(
+            matcher.counts(),
+            MatchCounts {
+                matched: 6,
+                lost: 1,
+                duplicates: 1,
+                unmatched: 1,
+                reordered: 0,
+                reordering_extent: 0,
+            }
+        );
+        matcher.finish();
+        assert_eq!(matcher.counts().lost, 2);
+        assert_eq!(
+            SequenceMatcher::new(MatchConfig {
+                timeout: Some(0.0),
+                ..Default::default()
+            }),
+            Err(EstimateError::InvalidConfig { field: "timeout" })
+        );
+    }
+
+    #[test]
+    fn test_sequence_matcher_duplicates() {
+        let mut matcher = SequenceMatcher::new(MatchConfig::default()).unwrap();
+        // Every other packet arrives twice, the copy 500 later.
+        for sequence in 0..100 {
+            let sent = 1000.0 * sequence as f64;
+            matcher.record(sequence, TimestampPair::new(sent, sent + 40.0));
+            if sequence % 2 == 0 {
+                matcher.record(sequence, TimestampPair::new(sent, sent + 540.0));
+            }
+        }
+        matcher.send(100, 100_000.0);
+        matcher.record(100, TimestampPair::new(100_000.0, 100_040.0));
+        matcher.finish();
+        assert!(matcher.drain().all(|sample| sample.value == 40.0));
+        let counts = matcher.counts();
+        assert_eq!(
+            (counts.matched, counts.duplicates, counts.lost),
+            (101, 50, 0)
+        );
+    }
+
+    #[test]
+    fn test_sequence_matcher_reordering() {
+        let mut matcher = SequenceMatcher::new(MatchConfig {
+            reordering: 3,
+            ..Default::default()
+        })
+        .unwrap();
+        for sequence in 0..10 {
+            matcher.send(sequence, 100.0 * sequence as f64);
+        }
+        // Probe 1 arrives after 2, and 3 after 6, still within the window of 6.
+        for sequence in [0, 2, 1, 4, 5, 6, 3, 7, 8, 9] {
+            matcher.receive(sequence, 100.0 * sequence as f64 + 40.0);
+        }
+        matcher.finish();
+        assert_eq!(matcher.drain().count(), 10);
+        let counts = matcher.counts();
+        assert_eq!((counts.reordered, counts.reordering_extent), (2, 3));
+        assert_eq!((counts.lost, counts.unmatched), (0, 0));
+    }
+
+    #[test]
+    fn test_sequence_matcher_silent_end() {
+        let config = MatchConfig {
+            outstanding: 100,
+            ..Default::default()
+        };
+        // The receiver never answers, and then a receiver without a sender.
+        let mut matcher = SequenceMatcher::new(config.clone()).unwrap();
+        for sequence in 0..10_000 {
+            matcher.send(sequence, sequence as f64);
+            assert!(matcher.sends.len() <= 101 && matcher.done.len() <= 101);
+        }
+        assert_eq!(matcher.counts().lost, 9_899);
+        let mut matcher = SequenceMatcher::new(config).unwrap();
+        for sequence in 0..10_000 {
+            matcher.receive(sequence, sequence as f64);
+        }
+        assert!(matcher.receives.len() <= 101);
+        assert_eq!(matcher.counts().unmatched, 9_899);
+        assert_eq!(
+            SequenceMatcher::new(MatchConfig {
+                outstanding: 10,
+                ..Default::default()
+            }),
+            Err(EstimateError::InvalidConfig {
+                field: "outstanding"
+            })
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use crate::cancel::{self, CancelToken};
+use crate::config::DelayPrior;
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+use crate::offset_estimator::LcgRng;
+use crate::preprocess;
+use crate::sample::Sample;
+
+/// Target acceptance rate of each one-dimensional random-walk update.
+const TARGET_ACCEPTANCE: f64 = 0.44;
+/// Number of iterations between two proposal scale adaptations during burn-in.
+const ADAPT_EVERY: usize = 50;
+
+/// Parameters of the Metropolis–Hastings sampler behind [`sample_posterior`]. The sampler runs
+/// exactly `burn_in + thin * draws` iterations unless cancelled, so its cost is fixed in advance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct McmcConfig {
+    /// Seed of the sampler's random number generator.
+    pub seed: u64,
+    /// Iterations discarded before the first draw, during which the proposal scales adapt.
+    pub burn_in: usize,
+    /// Iterations per recorded draw; values above one reduce the autocorrelation of the draws.
+    pub thin: usize,
+    /// Token checked every few dozen iterations; once cancelled, the sampler stops with
+    /// [`EstimateError::Cancelled`].
+    pub cancel: Option<CancelToken>,
+}
+
+impl Default for McmcConfig {
+    fn default() -> Self {
+        McmcConfig {
+            seed: 0,
+            burn_in: 2000,
+            thin: 5,
+            cancel: None,
+        }
+    }
+}
+
+/// Diagnostics of a [`sample_posterior`] run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct McmcSummary {
+    /// Fraction of proposals accepted after burn-in, averaged over the three parameters.
+    pub acceptance: f64,
+    /// Posterior mean of the offset over the recorded draws.
+    pub offset: f64,
+}
+
+/// State of the chain: offset, log shape and log scale of the shifted Gamma delay model.
+#[derive(Debug, Clone, Copy)]
+struct State {
+    offset: f64,
+    log_shape: f64,
+    log_scale: f64,
+}
+
+/// Log posterior density of the shifted Gamma model, up to a constant.
+struct Model<'a> {
+    samples: &'a [Sample],
+    prior: DelayPrior,
+    /// Total weight of the uncensored samples.
+    weight: f64,
+}
+
+impl Model<'_> {
+    /// Weighted sums of the log delays and of the delays below `offset`, the sufficient
+    /// statistics of the Gamma likelihood of the uncensored samples. `None` outside the support.
+    fn statistics(&self, offset: f64) -> Option<(f64, f64)> {
+        let mut log_delays = 0.0;
+        let mut delays = 0.0;
+        for sample in self.samples {
+            let delay = sample.value - offset;
+            if delay <= 0.0 && !sample.censored {
+                return None;
+            }
+            if sample.censored {
+                continue;
+            }
+            log_delays += sample.weight * float::ln(delay);
+            delays += sample.weight * delay;
+        }
+        Some((log_delays, delays))
+    }
+
+    /// Log posterior of the log-parametrized state given its sufficient statistics, including
+    /// the Jacobian of the log transform. The shape has a Gamma prior and the scale an inverse
+    /// Gamma prior, as in [`DelayPrior`]. Censored samples contribute the log probability of a
+    /// delay beyond their timeout.
+    fn log_posterior(&self, state: &State, (log_delays, delays): (f64, f64)) -> f64 {
+        let DelayPrior {
+            shape,
+            scale,
+            strength,
+        } = self.prior;
+        let alpha = float::exp(state.log_shape);
+        let beta = float::exp(state.log_scale);
+        let likelihood = (alpha - 1.0) * log_delays
+            - delays / beta
+            - self.weight * (libm::lgamma(alpha) + alpha * state.log_scale);
+        let survival: f64 = self
+            .samples
+            .iter()
+            .filter(|s| s.censored)
+            .map(|s| {
+                let timeout = (s.value - state.offset) / beta;
+                s.weight * float::ln(math::gamma_q(alpha, timeout))
+            })
+            .sum();
+        let shape_prior = strength * state.log_shape - strength / shape * alpha;
+        let (a0, b0) = (strength * shape, strength * shape * scale);
+        let scale_prior = -a0 * state.log_scale - b0 / beta;
+        likelihood + survival + shape_prior + scale_prior
+    }
+}
+
+/// Draws from the posterior distribution of the offset under the model
+/// `sample = offset + delay`, `delay ~ Gamma(α, β)`, with `prior` on α and β and a flat prior on
+/// the offset, filling `draws` with one offset per recorded iteration.
+///
+/// A Metropolis-within-Gibbs random walk updates the offset, `ln α` and `ln β` in turn, adapting
+/// each proposal scale during [`McmcConfig::burn_in`]. The sampler uses the crate's own random
+/// number generator and no allocation, so it runs without `std` and without an allocator; the
+/// samples are used as they are, without the preprocessing of
+/// [`estimate_samples`](crate::estimate_samples).
+///
+/// [Censored](Sample::censored) samples enter through the probability of a delay beyond their
+/// timeout.
+///
+/// Fails on non-finite samples, on negative or non-finite weights, when fewer than two
+/// uncensored samples are given, and with [`EstimateError::InvalidConfig`] naming `prior` unless every prior
+/// parameter is finite and positive and the prior delay is resolvable at the magnitude of the
+/// samples.
+pub fn sample_posterior(
+    samples: &[Sample],
+    prior: &DelayPrior,
+    config: &McmcConfig,
+    draws: &mut [f64],
+) -> Result<McmcSummary, EstimateError> {
+    if !prior.is_valid() {
+        return Err(EstimateError::InvalidConfig { field: "prior" });
+    }
+    preprocess::validate(samples)?;
+    let observed = samples.iter().filter(|s| !s.censored).count();
+    if observed < 2 {
+        return Err(EstimateError::InsufficientSamples {
+            got: observed,
+            need: 2,
+        });
+    }
+
+    let model = Model {
+        samples,
+        prior: *prior,
+        weight: samples
+            .iter()
+            .filter(|s| !s.censored)
+            .map(|s| s.weight)
+            .sum(),
+    };
+    let observed = || samples.iter().filter(|s| !s.censored);
+    let min = observed().map(|s| s.value).fold(f64::INFINITY, f64::min);
+    let max = observed()
+        .map(|s| s.value)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let spread = (max - min).max(prior.shape * prior.scale);
+
+    // Start just below the smallest sample, with the prior shape and scale.
+    let mut state = State {
+        offset: min - 0.1 * spread,
+        log_shape: float::ln(prior.shape),
+        log_scale: float::ln(prior.scale),
+    };
+    // Only outside the support when the spread is below the resolution of the samples.
+    let Some(mut statistics) = model.statistics(state.offset) else {
+        return Err(EstimateError::InvalidConfig { field: "prior" });
+    };
+    let mut current = model.log_posterior(&state, statistics);
+
+    let mut rng = LcgRng::new(config.seed);
+    let mut scales = [0.1 * spread, 0.5, 0.5];
+    let mut accepted = [0usize; 3];
+    let thin = config.thin.max(1);
+    let total = config.burn_in + draws.len() * thin;
+    let mut recorded = 0;
+    let mut offset_sum = 0.0;
+    for iteration in 0..total {
+        if iteration.is_multiple_of(ADAPT_EVERY) {
+            cancel::check(&config.cancel)?;
+        }
+        for (parameter, scale) in scales.iter().enumerate() {
+            let mut proposal = state;
+            let step = scale * rng.marsaglia_polar_sample();
+            match parameter {
+                0 => proposal.offset += step,
+                1 => proposal.log_shape += step,
+                _ => proposal.log_scale += step,
+            }
+            let proposed_statistics = if parameter == 0 {
+                model.statistics(proposal.offset)
+            } else {
+                Some(statistics)
+            };
+            let Some(proposed_statistics) = proposed_statistics else {
+                continue;
+            };
+            let candidate = model.log_posterior(&proposal, proposed_statistics);
+            if float::ln(rng.gen_range(0.0..1.0)) < candidate - current {
+                state = proposal;
+                statistics = proposed_statistics;
+                current = candidate;
+                accepted[parameter] += 1;
+            }
+        }
+
+        if iteration < config.burn_in {
+            if (iteration + 1).is_multiple_of(ADAPT_EVERY) {
+                for (scale, accepted) in scales.iter_mut().zip(accepted.iter_mut()) {
+                    let rate = *accepted as f64 / ADAPT_EVERY as f64;
+                    *scale *= float::exp(rate - TARGET_ACCEPTANCE);
+                    *accepted = 0;
+                }
+            }
+            if iteration + 1 == config.burn_in {
+                accepted = [0; 3];
+            }
+        } else if (iteration - config.burn_in + 1).is_multiple_of(thin) {
+            draws[recorded] = state.offset;
+            offset_sum += state.offset;
+            recorded += 1;
+        }
+    }
+
+    let iterations = (total - config.burn_in).max(1) as f64;
+    Ok(McmcSummary {
+        acceptance: accepted.iter().sum::<usize>() as f64 / (3.0 * iterations),
+        offset: offset_sum / recorded as f64,
+    })
+}
+
+/// Panic-free [`sample_posterior`], for safety-critical builds. Rejects with
+/// [`EstimateError::InvalidConfig`] what the plain variant adjusts or trusts: a `thin` of zero
+/// (field `thin`), an empty `draws` (field `draws`) and a number of iterations that overflows
+/// `usize` (field `burn_in`). The `no_panic` test harness covers it with degenerate input.
+pub fn sample_posterior_checked(
+    samples: &[Sample],
+    prior: &DelayPrior,
+    config: &McmcConfig,
+    draws: &mut [f64],
+) -> Result<McmcSummary, EstimateError> {
+    if config.thin == 0 {
+        return Err(EstimateError::InvalidConfig { field: "thin" });
+    }
+    if draws.is_empty() {
+        return Err(EstimateError::InvalidConfig { field: "draws" });
+    }
+    let total = draws
+        .len()
+        .checked_mul(config.thin)
+        .and_then(|recorded| recorded.checked_add(config.burn_in));
+    if total.is_none() {
+        return Err(EstimateError::InvalidConfig { field: "burn_in" });
+    }
+    sample_posterior(samples, prior, config, draws)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_posterior_recovers_offset() {
+        let mut rng = LcgRng::new(11);
+        let mut exponential = move || -4.0 * float::ln(1.0 - rng.gen_range(0.0..1.0));
+        let mut samples = [Sample::new(0.0); 200];
+        for sample in samples.iter_mut() {
+            sample.value = 20.0 + exponential() + exponential();
+        }
+        let prior = DelayPrior {
+            shape: 2.0,
+            scale: 4.0,
+            strength: 5.0,
+        };
+        let mut draws = [0.0; 500];
+        let summary =
+            sample_posterior(&samples, &prior, &McmcConfig::default(), &mut draws).unwrap();
+
+        assert!(
+            (summary.offset - 20.0).abs() < 1.0,
+            "Offset {}",
+            summary.offset
+        );
+        assert!(summary.acceptance > 0.1 && summary.acceptance < 0.9);
+        let min = samples
+            .iter()
+            .map(|s| s.value)
+            .fold(f64::INFINITY, f64::min);
+        assert!(draws.iter().all(|&d| d < min));
+
+        static STALE: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(true);
+        let config = McmcConfig {
+            cancel: Some(CancelToken::from_static(&STALE)),
+            ..Default::default()
+        };
+        assert_eq!(
+            sample_posterior(&samples, &prior, &config, &mut draws),
+            Err(EstimateError::Cancelled)
+        );
+    }
+}
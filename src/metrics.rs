@@ -0,0 +1,232 @@
+//! Process-wide metrics of the estimator, exported in the Prometheus text format so that
+//! operators can alert on the quality of the synchronization.
+//!
+//! Every estimate updates the metrics, whichever entry point produced it. [`metrics`] takes a
+//! snapshot, whose [`Display`](core::fmt::Display) implementation renders the exposition format
+//! to serve on a `/metrics` endpoint:
+//!
+//! ```
+//! use gamlr::metrics;
+//!
+//! gamlr::estimate([0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36], None);
+//! let body = metrics::metrics().to_string();
+//! assert!(body.contains("# TYPE gamlr_estimates_total counter"));
+//! ```
+
+use core::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::offset_estimator::Estimate;
+
+/// Two-sided 95% quantile of the standard normal distribution.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// `f64` stored by its bits.
+struct Gauge(AtomicU64);
+
+impl Gauge {
+    const fn new() -> Self {
+        Gauge(AtomicU64::new(f64::NAN.to_bits()))
+    }
+
+    fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+static ESTIMATES: AtomicU64 = AtomicU64::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+static SAMPLES: AtomicU64 = AtomicU64::new(0);
+static REJECTIONS: AtomicU64 = AtomicU64::new(0);
+static OFFSET: Gauge = Gauge::new();
+static UNCERTAINTY: Gauge = Gauge::new();
+static FIT_SAMPLES: Gauge = Gauge::new();
+static FIT_CENSORED: Gauge = Gauge::new();
+static SLOPE: Gauge = Gauge::new();
+
+/// Snapshot of the metrics, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Estimates produced.
+    pub estimates: u64,
+    /// Estimation runs that failed.
+    pub failures: u64,
+    /// Samples that entered the fits, over all estimates.
+    pub samples: u64,
+    /// Candidates rejected by the random Gamma sampler, over all estimates. A steep rise means
+    /// fits with shapes the sampler handles poorly.
+    pub rejections: u64,
+    /// Offset of the last estimate, NaN before the first.
+    pub offset: f64,
+    /// Uncertainty of the last estimate.
+    pub uncertainty: f64,
+    /// Samples in the last fit.
+    pub fit_samples: f64,
+    /// Censored samples in the last fit.
+    pub fit_censored: f64,
+    /// Slope of the quantile regression of the last fit, near one when the fitted Gamma
+    /// distribution describes the delays well.
+    pub fit_slope: f64,
+}
+
+impl Metrics {
+    /// Width of the 95% confidence interval of the last offset.
+    pub fn confidence_width(&self) -> f64 {
+        2.0 * Z_95 * self.uncertainty
+    }
+}
+
+/// The current metrics.
+pub fn metrics() -> Metrics {
+    Metrics {
+        estimates: ESTIMATES.load(Ordering::Relaxed),
+        failures: FAILURES.load(Ordering::Relaxed),
+        samples: SAMPLES.load(Ordering::Relaxed),
+        rejections: REJECTIONS.load(Ordering::Relaxed),
+        offset: OFFSET.get(),
+        uncertainty: UNCERTAINTY.get(),
+        fit_samples: FIT_SAMPLES.get(),
+        fit_censored: FIT_CENSORED.get(),
+        fit_slope: SLOPE.get(),
+    }
+}
+
+/// Records an estimate whose quantile regression had `slope`.
+pub(crate) fn record(estimate: &Estimate, slope: f64) {
+    ESTIMATES.fetch_add(1, Ordering::Relaxed);
+    SAMPLES.fetch_add(estimate.samples as u64, Ordering::Relaxed);
+    OFFSET.set(estimate.offset);
+    UNCERTAINTY.set(estimate.uncertainty);
+    FIT_SAMPLES.set(estimate.samples as f64);
+    FIT_CENSORED.set(estimate.censored as f64);
+    SLOPE.set(slope);
+}
+
+pub(crate) fn record_failure() {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_rejections(rejected: usize) {
+    REJECTIONS.fetch_add(rejected as u64, Ordering::Relaxed);
+}
+
+/// A sample value in the exposition format, which spells the special values its own way.
+struct Value(f64);
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            x if x.is_nan() => f.write_str("NaN"),
+            f64::INFINITY => f.write_str("+Inf"),
+            f64::NEG_INFINITY => f.write_str("-Inf"),
+            x => write!(f, "{x}"),
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = [
+            (
+                "gamlr_estimates_total",
+                "Estimates produced.",
+                self.estimates,
+            ),
+            (
+                "gamlr_estimate_failures_total",
+                "Estimation runs that failed.",
+                self.failures,
+            ),
+            (
+                "gamlr_samples_total",
+                "Samples that entered the fits.",
+                self.samples,
+            ),
+            (
+                "gamlr_sampler_rejections_total",
+                "Candidates rejected by the Gamma sampler.",
+                self.rejections,
+            ),
+        ];
+        for (name, help, value) in counters {
+            writeln!(
+                f,
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+            )?;
+        }
+        let gauges = [
+            (
+                "gamlr_offset",
+                "Offset of the last estimate, in sample units.",
+                self.offset,
+            ),
+            (
+                "gamlr_offset_uncertainty",
+                "Standard error of the last offset.",
+                self.uncertainty,
+            ),
+            (
+                "gamlr_offset_ci95_width",
+                "Width of the 95% confidence interval of the last offset.",
+                self.confidence_width(),
+            ),
+            (
+                "gamlr_fit_samples",
+                "Samples in the last fit.",
+                self.fit_samples,
+            ),
+            (
+                "gamlr_fit_censored_samples",
+                "Censored samples in the last fit.",
+                self.fit_censored,
+            ),
+            (
+                "gamlr_fit_slope",
+                "Slope of the quantile regression of the last fit.",
+                self.fit_slope,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let value = Value(value);
+            writeln!(
+                f,
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn test_metrics() {
+        let before = metrics();
+        let samples = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+        assert!(crate::estimate(samples, None).is_finite());
+        assert!(crate::estimate(samples[..1].iter().copied(), None).is_nan());
+        let after = metrics();
+        // Other tests estimate concurrently.
+        assert!(after.estimates > before.estimates);
+        assert!(after.failures > before.failures);
+        assert!(after.samples >= before.samples + 10);
+
+        let snapshot = Metrics {
+            offset: f64::NAN,
+            uncertainty: f64::INFINITY,
+            ..after
+        };
+        let text = snapshot.to_string();
+        assert!(text.contains("# TYPE gamlr_offset gauge\ngamlr_offset NaN\n"));
+        assert!(text.contains("\ngamlr_offset_ci95_width +Inf\n"));
+        let total = std::format!("\ngamlr_estimates_total {}\n", after.estimates);
+        assert!(text.contains(&total));
+    }
+}
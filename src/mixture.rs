@@ -0,0 +1,438 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use crate::cancel::{self, CancelToken};
+#[cfg(feature = "alloc")]
+use crate::config::EstimatorConfig;
+use crate::config::SolverOptions;
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+#[cfg(feature = "alloc")]
+use crate::offset_estimator::{fit_prepared, prepare, Estimate};
+use crate::preprocess;
+use crate::sample::Sample;
+
+/// Golden ratio conjugate, the step of the golden-section search over the offset.
+const GOLDEN: f64 = 0.618_033_988_749_894_9;
+/// Number of golden-section steps; each shrinks the offset bracket by the golden ratio.
+const OFFSET_STEPS: usize = 60;
+/// Doublings of the distance of the offset bracket below the smallest sample before giving up.
+const BRACKET_DOUBLINGS: usize = 64;
+
+/// Newton steps refining the closed-form shape estimate of each M-step.
+const SHAPE_NEWTON_STEPS: usize = 3;
+
+/// Parameters of [`fit_mixture`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixtureConfig {
+    /// Bounds on the EM run at each candidate offset: at most `max_iterations` iterations, until
+    /// the log-likelihood improves by less than `tolerance` per unit of sample weight.
+    pub solver: SolverOptions,
+    /// Token checked at each EM iteration; once cancelled, the fit stops with
+    /// [`EstimateError::Cancelled`].
+    pub cancel: Option<CancelToken>,
+}
+
+impl Default for MixtureConfig {
+    fn default() -> Self {
+        MixtureConfig {
+            solver: SolverOptions {
+                max_iterations: 200,
+                tolerance: 1e-9,
+            },
+            cancel: None,
+        }
+    }
+}
+
+/// One Gamma component of a delay mixture, e.g. the delays of one route.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GammaComponent {
+    /// Fraction of the samples drawn from this component.
+    pub weight: f64,
+    /// Gamma shape α, at least one.
+    pub shape: f64,
+    /// Gamma scale β, in the unit of the samples.
+    pub scale: f64,
+}
+
+impl GammaComponent {
+    /// Mean delay of the component.
+    pub fn mean(&self) -> f64 {
+        self.shape * self.scale
+    }
+}
+
+/// Outcome of [`fit_mixture`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixtureFit<const K: usize> {
+    /// Offset shared by all components.
+    pub offset: f64,
+    /// Fitted components, in ascending order of their mean delay.
+    pub components: [GammaComponent; K],
+    /// Weighted log-likelihood of the samples under the fit.
+    pub log_likelihood: f64,
+}
+
+impl<const K: usize> MixtureFit<K> {
+    /// Index into [`components`](Self::components) of the component most likely to have
+    /// produced a sample of `value`.
+    pub fn classify(&self, value: f64) -> usize {
+        let delay = value - self.offset;
+        let mut best = (0, f64::NEG_INFINITY);
+        for (k, component) in self.components.iter().enumerate() {
+            let log_density = float::ln(component.weight)
+                + math::gamma_ln_pdf(component.shape, component.scale, delay);
+            if log_density > best.1 {
+                best = (k, log_density);
+            }
+        }
+        best.0
+    }
+
+    /// Whether a sample of `value` most likely took the fast path, i.e. belongs to the component
+    /// with the smallest mean delay rather than having been queued.
+    pub fn is_fast_path(&self, value: f64) -> bool {
+        self.classify(value) == 0
+    }
+}
+
+/// Outcome of [`estimate_fast_path`].
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FastPath {
+    /// Estimate from the fast-path samples alone.
+    pub estimate: Estimate,
+    /// Number of samples labeled as queued and left out.
+    pub queued: usize,
+    /// Two-component mixture the samples were labeled with.
+    pub mixture: MixtureFit<2>,
+}
+
+/// Estimates the offset from the fast-path samples only.
+///
+/// Queued packets only add queueing delay on top of the path delay and carry no extra
+/// information about the offset, while their long tail distorts the single Gamma fit. After the
+/// preprocessing configured in `config`, a two-component [`fit_mixture`] separates the fast-path
+/// samples from the queued ones, and the estimator runs on the former; censored samples count as
+/// queued. The mixture runs with the [solver options](EstimatorConfig::solver) and the
+/// [token](EstimatorConfig::cancel) of `config`. Fails like
+/// [`estimate_samples`](crate::estimate_samples), also when too few fast-path samples remain.
+#[cfg(feature = "alloc")]
+pub fn estimate_fast_path<I>(
+    samples: I,
+    config: &EstimatorConfig,
+) -> Result<FastPath, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let mut samples: Vec<Sample> = samples.into_iter().collect();
+    let prepared = prepare(&mut samples, config)?;
+    let mixture_config = MixtureConfig {
+        solver: config.solver,
+        cancel: config.cancel.clone(),
+    };
+    let mixture = fit_mixture::<2>(&samples, &mixture_config)?;
+    let before = samples.len();
+    samples.retain(|s| !s.censored && mixture.is_fast_path(s.value));
+    let queued = before - samples.len();
+    let need = config.min_samples.max(2);
+    if samples.len() < need {
+        return Err(EstimateError::InsufficientSamples {
+            got: samples.len(),
+            need,
+        });
+    }
+    let mut synthetic = alloc::vec![0.0; samples.len()];
+    Ok(FastPath {
+        estimate: fit_prepared(&mut samples, &mut synthetic, config, prepared)?,
+        queued,
+        mixture,
+    })
+}
+
+/// Jointly estimates the common offset and a mixture of `K` Gamma delay distributions by
+/// expectation–maximization, modeling each sample as `offset + delay` with the delay drawn from
+/// one of the components.
+///
+/// On paths load-balanced across routes of different lengths the delays are multimodal, which
+/// biases the single Gamma fit of [`estimate_samples`](crate::estimate_samples); here each route
+/// gets its own component while the offset stays shared. The offset is found by golden-section
+/// search on the profile likelihood below the smallest sample, running EM on the delays implied
+/// by each candidate. The search starts one sample spread below the smallest sample and reaches
+/// twice as far until the likelihood falls, for paths whose delay floor exceeds the spread. Shapes
+/// are kept at one or above, where the likelihood stays bounded as the offset approaches the
+/// smallest sample. Works without allocation on samples as they are, without the preprocessing of
+/// [`estimate_samples`](crate::estimate_samples).
+/// [Censored](Sample::censored) samples are ignored.
+///
+/// Fails on non-finite samples and invalid weights, with [`EstimateError::InvalidConfig`] naming
+/// `components` when `K` is zero, when fewer than `2 * K` uncensored samples are given, and with
+/// [`EstimateError::NotConverged`] naming `mixture` when EM exceeds its iteration limit or the
+/// likelihood keeps rising as the offset recedes.
+pub fn fit_mixture<const K: usize>(
+    samples: &[Sample],
+    config: &MixtureConfig,
+) -> Result<MixtureFit<K>, EstimateError> {
+    if K == 0 {
+        return Err(EstimateError::InvalidConfig {
+            field: "components",
+        });
+    }
+    preprocess::validate(samples)?;
+    let need = (2 * K).max(2);
+    let observed = || samples.iter().filter(|s| !s.censored);
+    if observed().count() < need {
+        return Err(EstimateError::InsufficientSamples {
+            got: observed().count(),
+            need,
+        });
+    }
+
+    let min = observed().map(|s| s.value).fold(f64::INFINITY, f64::min);
+    let max = observed()
+        .map(|s| s.value)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let span = (max - min).max(f64::MIN_POSITIVE);
+
+    // Widen the bracket until the profile likelihood falls toward its lower end.
+    let mut reach = span;
+    let mut inner = run_em::<K>(samples, min - reach, config)?.1;
+    let mut bracketed = false;
+    for _ in 0..BRACKET_DOUBLINGS {
+        let outer = run_em::<K>(samples, min - 2.0 * reach, config)?.1;
+        reach *= 2.0;
+        if outer <= inner {
+            bracketed = true;
+            break;
+        }
+        inner = outer;
+    }
+    if !bracketed {
+        return Err(EstimateError::NotConverged { solver: "mixture" });
+    }
+
+    // Golden-section search for the offset maximizing the profile likelihood.
+    let (mut lower, mut upper) = (min - reach, min - 1e-9 * span);
+    let mut left = upper - GOLDEN * (upper - lower);
+    let mut right = lower + GOLDEN * (upper - lower);
+    let mut left_fit = run_em::<K>(samples, left, config)?;
+    let mut right_fit = run_em::<K>(samples, right, config)?;
+    for _ in 0..OFFSET_STEPS {
+        if left_fit.1 >= right_fit.1 {
+            upper = right;
+            right = left;
+            right_fit = left_fit;
+            left = upper - GOLDEN * (upper - lower);
+            left_fit = run_em::<K>(samples, left, config)?;
+        } else {
+            lower = left;
+            left = right;
+            left_fit = right_fit;
+            right = lower + GOLDEN * (upper - lower);
+            right_fit = run_em::<K>(samples, right, config)?;
+        }
+    }
+    let (offset, (mut components, log_likelihood)) = if left_fit.1 >= right_fit.1 {
+        (left, left_fit)
+    } else {
+        (right, right_fit)
+    };
+    components.sort_unstable_by(|a, b| a.mean().total_cmp(&b.mean()));
+    Ok(MixtureFit {
+        offset,
+        components,
+        log_likelihood,
+    })
+}
+
+/// Runs EM on the delays `sample - offset`, returning the components and their log-likelihood.
+fn run_em<const K: usize>(
+    samples: &[Sample],
+    offset: f64,
+    config: &MixtureConfig,
+) -> Result<([GammaComponent; K], f64), EstimateError> {
+    let samples = || samples.iter().filter(|s| s.weight > 0.0 && !s.censored);
+    let w_sum = samples().map(|s| s.weight).sum::<f64>();
+    let max_delay = samples().map(|s| s.value - offset).fold(0.0, f64::max);
+    // Spread the initial component means evenly over the observed delays.
+    let mut components = [GammaComponent {
+        weight: 1.0 / K as f64,
+        shape: 2.0,
+        scale: 0.0,
+    }; K];
+    for (k, component) in components.iter_mut().enumerate() {
+        component.scale = max_delay * (k as f64 + 0.5) / K as f64 / component.shape;
+    }
+
+    let mut log_likelihood = f64::NEG_INFINITY;
+    for _ in 0..config.solver.max_iterations.max(1) {
+        cancel::check(&config.cancel)?;
+        // E-step, accumulating the sufficient statistics of the M-step directly.
+        let mut responsibility = [0.0; K];
+        let mut delays = [0.0; K];
+        let mut log_delays = [0.0; K];
+        let mut total = 0.0;
+        for sample in samples() {
+            let delay = sample.value - offset;
+            let mut log_densities = [0.0; K];
+            for (log_density, component) in log_densities.iter_mut().zip(&components) {
+                *log_density = float::ln(component.weight)
+                    + math::gamma_ln_pdf(component.shape, component.scale, delay);
+            }
+            let peak = log_densities
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let norm = peak
+                + float::ln(
+                    log_densities
+                        .iter()
+                        .map(|l| float::exp(l - peak))
+                        .sum::<f64>(),
+                );
+            total += sample.weight * norm;
+            for k in 0..K {
+                let r = sample.weight * float::exp(log_densities[k] - norm);
+                responsibility[k] += r;
+                delays[k] += r * delay;
+                log_delays[k] += r * float::ln(delay);
+            }
+        }
+
+        // M-step: Minka's closed-form approximation of the Gamma shape MLE, refined by his
+        // generalized Newton iteration on `ln α - ψ(α) = s`.
+        for k in 0..K {
+            if responsibility[k].is_nan() || responsibility[k] <= 0.0 {
+                continue;
+            }
+            let mean = delays[k] / responsibility[k];
+            let s = float::ln(mean) - log_delays[k] / responsibility[k];
+            let mut shape = (3.0 - s + float::sqrt((s - 3.0) * (s - 3.0) + 24.0 * s)) / (12.0 * s);
+            for _ in 0..SHAPE_NEWTON_STEPS {
+                let residual = s - float::ln(shape) + math::digamma(shape);
+                let slope = shape * shape * (1.0 / shape - math::trigamma(shape));
+                shape = 1.0 / (1.0 / shape + residual / slope);
+            }
+            let shape = if shape.is_nan() { 1.0 } else { shape.max(1.0) };
+            components[k] = GammaComponent {
+                weight: responsibility[k] / w_sum,
+                shape,
+                scale: mean / shape,
+            };
+        }
+
+        let converged = (total - log_likelihood).abs() < config.solver.tolerance * w_sum;
+        log_likelihood = total;
+        // A non-finite likelihood marks a hopeless candidate offset, not a slow solver.
+        if converged || !total.is_finite() {
+            return Ok((components, log_likelihood));
+        }
+    }
+    Err(EstimateError::NotConverged { solver: "mixture" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset_estimator::LcgRng;
+
+    #[test]
+    fn test_fit_mixture_two_routes() {
+        let mut rng = LcgRng::new(13);
+        let mut erlang = move |shape: usize, scale: f64| {
+            (0..shape)
+                .map(|_| -scale * float::ln(1.0 - rng.gen_range(0.0..1.0)))
+                .sum::<f64>()
+        };
+        let mut samples = [Sample::new(0.0); 1000];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            // Alternate between a short route and a long one.
+            let delay = if i % 3 == 0 {
+                erlang(40, 1.0)
+            } else {
+                erlang(2, 1.0)
+            };
+            sample.value = 50.0 + delay;
+        }
+        let fit = fit_mixture::<2>(&samples, &MixtureConfig::default()).unwrap();
+
+        assert!((fit.offset - 50.0).abs() < 0.5, "Offset {}", fit.offset);
+        let [short, long] = fit.components;
+        assert!((short.mean() - 2.0).abs() < 0.5, "Short route {short:?}");
+        assert!((long.mean() - 40.0).abs() < 2.0, "Long route {long:?}");
+        assert!((long.weight - 1.0 / 3.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_fit_mixture_high_shape() {
+        // The delay floor of 20 is several times the spread of the delays.
+        let mut rng = LcgRng::new(29);
+        let mut samples = [Sample::new(0.0); 2000];
+        for sample in samples.iter_mut() {
+            let delay = (0..200)
+                .map(|_| -0.1 * float::ln(1.0 - rng.gen_range(0.0..1.0)))
+                .sum::<f64>();
+            sample.value = 10.0 + delay;
+        }
+        let min = samples
+            .iter()
+            .map(|s| s.value)
+            .fold(f64::INFINITY, f64::min);
+        let max = samples.iter().map(|s| s.value).fold(0.0, f64::max);
+        let fit = fit_mixture::<1>(&samples, &MixtureConfig::default()).unwrap();
+        assert!(fit.offset < min - (max - min), "Offset {}", fit.offset);
+    }
+
+    #[test]
+    fn test_fit_mixture_rejects_tiny_batches() {
+        let samples = [Sample::new(1.0), Sample::new(2.0), Sample::new(3.0)];
+        assert_eq!(
+            fit_mixture::<2>(&samples, &MixtureConfig::default()),
+            Err(EstimateError::InsufficientSamples { got: 3, need: 4 })
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_estimate_fast_path_ignores_queued_samples() {
+        let mut rng = LcgRng::new(21);
+        let mut erlang = move |shape: usize, scale: f64| {
+            (0..shape)
+                .map(|_| -scale * float::ln(1.0 - rng.gen_range(0.0..1.0)))
+                .sum::<f64>()
+        };
+        // Every other packet waits behind a long queue.
+        let samples: alloc::vec::Vec<Sample> = (0..600)
+            .map(|i| {
+                let queueing = if i % 2 == 0 {
+                    30.0 + erlang(30, 1.0)
+                } else {
+                    0.0
+                };
+                Sample::new(80.0 + erlang(2, 1.0) + queueing)
+            })
+            .collect();
+        let fast = estimate_fast_path(samples.clone(), &EstimatorConfig::default()).unwrap();
+
+        assert!(
+            (fast.queued as f64 - 300.0).abs() < 15.0,
+            "Queued {}",
+            fast.queued
+        );
+        assert_eq!(fast.estimate.samples + fast.queued, 600);
+        assert!(fast.mixture.is_fast_path(81.0));
+        assert!(!fast.mixture.is_fast_path(150.0));
+
+        let cancelled = EstimatorConfig {
+            cancel: Some(crate::CancelToken::new()),
+            ..Default::default()
+        };
+        cancelled.cancel.as_ref().unwrap().cancel();
+        assert_eq!(
+            estimate_fast_path(samples, &cancelled),
+            Err(EstimateError::Cancelled)
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Test harness in the spirit of the `no_panic` crate for the `_checked` API: instead of a link
+//! time proof, every checked function runs on the cross product of degenerate batches and
+//! configurations, and any panic fails the test.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::config::{
+    CoarseCenter, CoarseWindow, DelayPrior, EstimatorConfig, FastMode, GammaFit, NegativePolicy,
+    NonFinitePolicy, PlottingPosition, SolverOptions, Subsampling, SyntheticSample, TailPolicy,
+};
+use crate::mcmc::{sample_posterior_checked, McmcConfig};
+use crate::offset_estimator::estimate_samples_checked;
+use crate::sample::Sample;
+
+/// Length of the batch buffers; each case runs on several prefixes of them.
+const LEN: usize = 24;
+
+fn assert_no_panic(name: &str, case: usize, f: impl FnOnce()) {
+    assert!(
+        catch_unwind(AssertUnwindSafe(f)).is_ok(),
+        "{name} panicked on case {case}"
+    );
+}
+
+/// Degenerate batches: empty, tiny, constant, non-finite, extreme magnitudes, broken weights and
+/// timestamps, and censoring everywhere.
+fn batches() -> impl Iterator<Item = ([Sample; LEN], usize)> {
+    fn regular(i: usize) -> Sample {
+        Sample::new(100.0 + (i * 7 % 11) as f64)
+    }
+    let cases: [fn(usize) -> Sample; 14] = [
+        regular,
+        |_| Sample::new(5.0),
+        |_| Sample::new(0.0),
+        |i| Sample::new(if i == 3 { f64::NAN } else { 1.0 + i as f64 }),
+        |i| {
+            Sample::new(if i % 2 == 0 {
+                f64::INFINITY
+            } else {
+                f64::NEG_INFINITY
+            })
+        },
+        |i| Sample::new(-(i as f64)),
+        |i| Sample::new(f64::MAX / (1.0 + i as f64)),
+        |i| Sample::new(f64::MIN_POSITIVE * i as f64),
+        |i| regular(i).with_weight(0.0),
+        |i| regular(i).with_weight(if i == 5 { -1.0 } else { f64::NAN }),
+        |i| regular(i).with_weight(f64::MAX),
+        |i| regular(i).at(if i == 2 { f64::NAN } else { i as f64 }),
+        |i| Sample::timed_out(10.0 + i as f64),
+        |i| match i % 3 {
+            0 => Sample::timed_out(1.0),
+            _ => regular(i),
+        },
+    ];
+    cases.into_iter().flat_map(move |case| {
+        [0, 1, 2, 3, LEN].into_iter().map(move |len| {
+            let mut batch = [Sample::new(0.0); LEN];
+            for (i, sample) in batch.iter_mut().enumerate() {
+                *sample = case(i);
+            }
+            (batch, len)
+        })
+    })
+}
+
+fn configs() -> impl Iterator<Item = EstimatorConfig> {
+    let base = EstimatorConfig {
+        min_samples: 2,
+        ..Default::default()
+    };
+    let stingy = SolverOptions {
+        max_iterations: 0,
+        tolerance: f64::NAN,
+    };
+    [
+        base.clone(),
+        EstimatorConfig {
+            min_samples: 0,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            min_samples: usize::MAX,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            synthetic: SyntheticSample::Quantiles,
+            plotting: PlottingPosition::Weibull,
+            fit: GammaFit::LMoments,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            fit: GammaFit::ProbabilityWeighted,
+            plotting: PlottingPosition::Blom,
+            repetitions: 3,
+            jackknife: true,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            synthetic: SyntheticSample::Quantiles,
+            solver: stingy,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            solver: stingy,
+            ..base.clone()
+        },
+        EstimatorConfig {
+            non_finite: NonFinitePolicy::Drop,
+            negative: NegativePolicy::Shift,
+            tails: TailPolicy::Trim {
+                lower: 1.0,
+                upper: 1.0,
+            },
+            ..base.clone()
+        },
+        EstimatorConfig {
+            non_finite: NonFinitePolicy::Replace(f64::NAN),
+            negative: NegativePolicy::Reject,
+            tails: TailPolicy::Winsorize {
+                lower: f64::NAN,
+                upper: 2.0,
+            },
+            ..base.clone()
+        },
+        EstimatorConfig {
+            detrend: true,
+            half_life: Some(1.0),
+            tails: TailPolicy::Grubbs { significance: 0.9 },
+            ..base.clone()
+        },
+        EstimatorConfig {
+            half_life: Some(0.0),
+            precise: true,
+            tails: TailPolicy::GeneralizedEsd {
+                max_outliers: usize::MAX,
+                significance: 0.5,
+            },
+            ..base.clone()
+        },
+        EstimatorConfig {
+            fast: Some(FastMode {
+                max_samples: 3,
+                subsampling: Subsampling::Reservoir,
+            }),
+            ..base.clone()
+        },
+        EstimatorConfig {
+            fast: Some(FastMode {
+                max_samples: 0,
+                subsampling: Subsampling::Stride,
+            }),
+            ..base.clone()
+        },
+        EstimatorConfig {
+            coarse: Some(CoarseWindow {
+                center: CoarseCenter::Minimum,
+                width: 1.0,
+            }),
+            ..base.clone()
+        },
+        EstimatorConfig {
+            coarse: Some(CoarseWindow {
+                center: CoarseCenter::Median,
+                width: f64::NAN,
+            }),
+            ..base.clone()
+        },
+        EstimatorConfig {
+            half_life: Some(f64::NAN),
+            seed: Some(u64::MAX),
+            ..base
+        },
+    ]
+    .into_iter()
+}
+
+#[test]
+fn test_estimate_samples_checked_never_panics() {
+    let (mut case, mut fitted) = (0, 0);
+    for config in configs() {
+        for (mut batch, len) in batches() {
+            let mut synthetic = [0.0; LEN];
+            assert_no_panic("estimate_samples_checked", case, || {
+                let result =
+                    estimate_samples_checked(&mut batch[..len], &mut synthetic[..len], &config);
+                fitted += usize::from(result.is_ok());
+            });
+            case += 1;
+        }
+    }
+    // The harness is only meaningful if some of the cases get through to the fit.
+    assert!(fitted > 0);
+    let mut batch = [Sample::new(1.0); 4];
+    assert_eq!(
+        estimate_samples_checked(&mut batch, &mut [0.0; 3], &EstimatorConfig::default()),
+        Err(crate::EstimateError::CapacityExceeded { capacity: 3 })
+    );
+}
+
+#[test]
+fn test_sample_posterior_checked_never_panics() {
+    let priors = [
+        DelayPrior {
+            shape: 2.0,
+            scale: 4.0,
+            strength: 5.0,
+        },
+        DelayPrior {
+            shape: f64::NAN,
+            scale: 0.0,
+            strength: f64::INFINITY,
+        },
+    ];
+    let configs = [
+        McmcConfig {
+            burn_in: 10,
+            thin: 1,
+            seed: 3,
+            cancel: None,
+        },
+        McmcConfig {
+            burn_in: 0,
+            thin: 0,
+            seed: 3,
+            cancel: None,
+        },
+        McmcConfig {
+            burn_in: usize::MAX,
+            thin: 1,
+            seed: 3,
+            cancel: None,
+        },
+        McmcConfig {
+            burn_in: 1,
+            thin: usize::MAX,
+            seed: 3,
+            cancel: None,
+        },
+    ];
+    let mut case = 0;
+    for prior in &priors {
+        for config in &configs {
+            for (batch, len) in batches() {
+                for count in [0, 5] {
+                    let mut draws = [0.0; 5];
+                    assert_no_panic("sample_posterior_checked", case, || {
+                        let _ = sample_posterior_checked(
+                            &batch[..len],
+                            prior,
+                            config,
+                            &mut draws[..count],
+                        );
+                    });
+                    case += 1;
+                }
+            }
+        }
+    }
+}
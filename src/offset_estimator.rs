@@ -1,5 +1,21 @@
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
+use crate::approx;
+use crate::cancel;
+use crate::config::{
+    EstimatorConfig, GammaFit, PlottingPosition, QuantizationNoise, Regression, SolverOptions,
+    SourceQuality, SyntheticSample,
+};
+use crate::error::EstimateError;
+use crate::event::Event;
+use crate::float;
+use crate::linfit;
+use crate::math;
+use crate::preprocess;
+use crate::sample::{sort_samples, Sample, SampleBuffer, SliceBuffer};
+use crate::units::TimeUnit;
+
 const MAX_ALPHA: f64 = 4.0;
 const MIN_ALPHA: f64 = 1.0;
 /// Predefined constants from "The Art of Computer Programming, Volume 2, Section 3.2.1" by Donald E. Knuth.
@@ -19,6 +35,7 @@ const C: u64 = 1442695040888963407;
 /// * D. H. Lehmer. "Mathematical methods in large-scale computing units".
 ///   Proceedings of a Second Symposium on Large Scale Digital Calculating Machinery;
 ///   Annals of the Computation Laboratory, Harvard Univ. 26 (1951): 141-146.
+#[derive(Debug, Clone)]
 pub struct LcgRng {
     state: u64,
     a: u64,
@@ -50,80 +67,419 @@ impl LcgRng {
     /// References:
     /// George Marsaglia. "Generating a Variable from the Tail of the Normal Distribution".
     /// Technometrics, Vol. 6, No. 3 (Aug., 1964), pp. 101-102.
-    fn marsaglia_polar_sample(&mut self) -> f64 {
+    pub(crate) fn marsaglia_polar_sample(&mut self) -> f64 {
         loop {
             let u: f64 = self.gen_range(-1.0..1.0);
             let v: f64 = self.gen_range(-1.0..1.0);
             let s = u * u + v * v;
             if s < 1.0 && s != 0.0 {
-                let z0 = u * libm::sqrt(-2.0 * libm::log(s) / s);
+                let z0 = u * approx::sqrt(-2.0 * approx::ln(s) / s);
                 return z0;
             }
         }
     }
 
-    fn next_u64(&mut self) -> u64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
         self.state = (self.a.wrapping_mul(self.state).wrapping_add(self.c)) % self.m;
         self.state
     }
 }
 
 /// Estimates the alpha and beta parameters for the Gamma distribution based on the sample data provided,
-/// using the median instead of the mean.
-fn estimate_gamma_parameters(x: &[f64]) -> (f64, f64) {
-    let n = x.len() as f64;
-    let mean_x = x.iter().sum::<f64>() / n;
-    let sum_sq_diff = x.iter().map(|&xi| libm::pow(xi - mean_x, 2.0)).sum::<f64>();
-    let var_x = sum_sq_diff / (n - 1.0);
+/// using the weighted method of moments with reliability weights.
+///
+/// The values are those `value` assigns to the samples. With `precise` set, the sums are
+/// compensated, see [`EstimatorConfig::precise`].
+fn estimate_gamma_parameters(
+    x: &[Sample],
+    value: impl Fn(&Sample) -> f64,
+    precise: bool,
+) -> (f64, f64) {
+    let w_sum = math::sum(x.iter().map(|s| s.weight), precise);
+    let w_sq_sum = math::sum(x.iter().map(|s| s.weight * s.weight), precise);
+    let mean_x = math::sum(x.iter().map(|s| s.weight * value(s)), precise) / w_sum;
+    let sum_sq_diff = math::sum(
+        x.iter()
+            .map(|s| s.weight * float::pow(value(s) - mean_x, 2.0)),
+        precise,
+    );
+    // Reduces to the usual n - 1 when every weight is one.
+    let var_x = sum_sq_diff / (w_sum - w_sq_sum / w_sum);
 
-    let alpha = libm::pow(mean_x, 2.0) / var_x;
+    let alpha = float::pow(mean_x, 2.0) / var_x;
     let beta = var_x / mean_x;
 
     (alpha, beta)
 }
 
-/// Sorts the input values in ascending order and returns the sorted vector.
-fn sort_values<'a, I>(values: I) -> Vec<f64>
-where
-    I: IntoIterator<Item = &'a f64>,
-    I::IntoIter: ExactSizeIterator + Clone,
-{
-    let mut sorted_values: Vec<_> = values.into_iter().cloned().collect();
-    sorted_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    sorted_values
+/// Unbiased probability-weighted moments `b0`, `b1` and `b2` of the sorted samples, with
+/// `b_r = E[X F(X)^r]`, on the values `value` assigns to them.
+///
+/// Ranks are counted in units of the mean weight, so for unit weights the i-th of `n` samples
+/// gets the usual `F = (i - 1) / (n - 1)` in `b1` and `(i - 1)(i - 2) / ((n - 1)(n - 2))` in `b2`.
+fn probability_weighted_moments(
+    x: &[Sample],
+    value: impl Fn(&Sample) -> f64,
+    precise: bool,
+) -> [f64; 3] {
+    let w_sum = math::sum(x.iter().map(|s| s.weight), precise);
+    let unit = w_sum / x.len() as f64;
+    let n = x.len() as f64;
+    let moment = |order: usize| {
+        let mut w_before = 0.0;
+        let terms = x.iter().map(|s| {
+            let rank = (w_before + 0.5 * (s.weight - unit)) / unit;
+            w_before += s.weight;
+            let f = match order {
+                0 => 1.0,
+                1 => rank / (n - 1.0),
+                _ => rank * (rank - 1.0) / ((n - 1.0) * (n - 2.0)),
+            };
+            s.weight * f * value(s)
+        });
+        math::sum(terms, precise) / w_sum
+    };
+    [moment(0), moment(1), moment(2)]
 }
 
-/// Generates random values drawn from a Gamma distribution using the method described in:
+/// Probability-weighted moment estimates of the Gamma parameters on the values `value` assigns to
+/// the samples, which must be sorted.
+///
+/// The moments `b0` and `b1` give the L-coefficient of variation `t = (2 b1 - b0) / b0`, which
+/// Hosking's rational approximation maps to the shape.
+///
+/// J. R. M. Hosking. "L-moments: analysis and estimation of distributions using linear
+/// combinations of order statistics". Journal of the Royal Statistical Society, Series B,
+/// Vol. 52, No. 1 (1990), pp. 105-124.
+fn gamma_pwm(x: &[Sample], value: impl Fn(&Sample) -> f64, precise: bool) -> (f64, f64) {
+    let [b0, b1, _] = probability_weighted_moments(x, value, precise);
+    let alpha = gamma_shape_from_l_cv((2.0 * b1 - b0) / b0);
+    (alpha, b0 / alpha)
+}
+
+/// Gamma shape with the L-coefficient of variation `t`, by Hosking's rational approximation.
+fn gamma_shape_from_l_cv(t: f64) -> f64 {
+    if t < 0.5 {
+        let z = core::f64::consts::PI * t * t;
+        (1.0 - 0.308 * z) / (z - 0.05812 * z * z + 0.01765 * z * z * z)
+    } else {
+        let z = 1.0 - t;
+        (0.7213 * z - 0.5947 * z * z) / (1.0 - 2.1817 * z + 1.2113 * z * z)
+    }
+}
+
+/// L-moment estimates of the shape and scale of the delay on the values `value` assigns to the
+/// samples, which must be sorted.
+///
+/// The L-skewness `τ3 = λ3 / λ2` does not depend on the offset and gives the shape by Hosking's
+/// approximation for the Pearson type III distribution; the L-scale `λ2` then gives the scale.
+/// Both are linear in the order statistics, which keeps them nearly unbiased on small batches and
+/// robust to outliers.
+///
+/// J. R. M. Hosking and J. R. Wallis. "Regional Frequency Analysis", Appendix A.9. Cambridge
+/// University Press, 1997.
+fn gamma_l_moments(x: &[Sample], value: impl Fn(&Sample) -> f64, precise: bool) -> (f64, f64) {
+    let [b0, b1, b2] = probability_weighted_moments(x, value, precise);
+    let l2 = 2.0 * b1 - b0;
+    let l3 = 6.0 * b2 - 6.0 * b1 + b0;
+    let t3 = libm::fabs(l3 / l2);
+    let alpha = if t3 < 1.0 / 3.0 {
+        let z = 3.0 * core::f64::consts::PI * t3 * t3;
+        (1.0 + 0.2906 * z) / (z + 0.1882 * z * z + 0.0442 * z * z * z)
+    } else {
+        let z = 1.0 - t3;
+        (0.36067 * z - 0.59567 * z * z + 0.25361 * z * z * z)
+            / (1.0 - 2.78861 * z + 2.56096 * z * z - 0.77045 * z * z * z)
+    };
+    // λ2 = β Γ(α + 1/2) / (√π Γ(α)).
+    let ratio = float::exp(libm::lgamma(alpha) - libm::lgamma(alpha + 0.5));
+    (alpha, l2 * float::sqrt(core::f64::consts::PI) * ratio)
+}
+
+/// Sorts the input values in ascending order. NaNs, e.g. from a degenerate Gamma fit, sort last
+/// instead of panicking.
+fn sort_values(values: &mut [f64]) {
+    values.sort_unstable_by(f64::total_cmp);
+}
+
+/// Fills `out` with random values drawn from a Gamma distribution using the method described in:
 ///
 /// George Marsaglia, Wai Wan Tsang. "A Simple Method for Generating Gamma Variables".
 /// ACM Transactions on Mathematical Software, Vol. 26, No. 3, September 2000, Pages 363-372.
-fn generate_random_gamma_values(alpha: f64, beta: f64, num_samples: usize, seed: u64) -> Vec<f64> {
+///
+/// A value that takes more than `max_attempts` candidates, e.g. on adversarial parameters or a
+/// degenerate random stream, is replaced by the Gamma quantile at the midpoint of its slot, so
+/// the time spent per value stays bounded. Returns the number of values replaced.
+fn fill_random_gamma_values(
+    alpha: f64,
+    beta: f64,
+    seed: u64,
+    max_attempts: usize,
+    out: &mut [f64],
+) -> usize {
     let mut rng = LcgRng::new(seed);
-    (0..num_samples)
-        .map(|_| {
-            let d = alpha - 1.0 / 3.0;
-            let c = (1.0 / 3.0) / libm::sqrt(d);
-
-            loop {
-                let x = rng.marsaglia_polar_sample();
-                let v = 1.0 + c * x;
-                if v <= 0.0 {
-                    continue;
-                }
-
-                let v = v * v * v;
-                let u = rng.gen_range(0.0..1.0);
-
-                let x_squared = x * x;
-
-                if u < 1.0 - 0.0331 * x_squared * x_squared
-                    || libm::log(u) < 0.5 * x_squared + d * (1.0 - v + libm::log(v))
-                {
-                    break d * v * beta;
-                }
+    let d = alpha - 1.0 / 3.0;
+    let c = (1.0 / 3.0) / approx::sqrt(d);
+    let n = out.len() as f64;
+    let mut fallbacks = 0;
+    for (i, slot) in out.iter_mut().enumerate() {
+        let mut attempts = 0..max_attempts;
+        let drawn = loop {
+            if attempts.next().is_none() {
+                break None;
             }
-        })
-        .collect()
+            let x = rng.marsaglia_polar_sample();
+            let v = 1.0 + c * x;
+            if v <= 0.0 {
+                continue;
+            }
+
+            let v = v * v * v;
+            let u = rng.gen_range(0.0..1.0);
+
+            let x_squared = x * x;
+
+            if u < 1.0 - 0.0331 * x_squared * x_squared
+                || approx::ln(u) < 0.5 * x_squared + d * (1.0 - v + approx::ln(v))
+            {
+                break Some(d * v * beta);
+            }
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_rejections(
+            max_attempts - attempts.len() - usize::from(drawn.is_some()),
+        );
+        *slot = match drawn {
+            Some(value) => value,
+            None => {
+                fallbacks += 1;
+                beta * math::gamma_quantile(alpha, (i as f64 + 0.5) / n)
+            }
+        };
+    }
+    fallbacks
+}
+
+/// Fills `out` with the quantiles of the Gamma distribution at the plotting positions of
+/// `out.len()` samples, which come out sorted. Fails with [`EstimateError::NotConverged`] naming
+/// `quantile` if a quantile does not converge within `options`.
+fn fill_gamma_quantiles(
+    alpha: f64,
+    beta: f64,
+    positions: PlottingPosition,
+    options: &SolverOptions,
+    out: &mut [f64],
+) -> Result<(), EstimateError> {
+    let n = out.len() as f64;
+    for (i, slot) in out.iter_mut().enumerate() {
+        let p = positions.position(i as f64 + 1.0, n);
+        let Some(quantile) = math::gamma_quantile_within(alpha, p, options) else {
+            return Err(EstimateError::NotConverged { solver: "quantile" });
+        };
+        *slot = beta * quantile;
+    }
+    Ok(())
+}
+
+/// Generates random values drawn from a Gamma distribution, see [`fill_random_gamma_values`].
+#[cfg(all(test, feature = "alloc"))]
+fn generate_random_gamma_values(alpha: f64, beta: f64, num_samples: usize, seed: u64) -> Vec<f64> {
+    let mut values = alloc::vec![0.0; num_samples];
+    let max_attempts = SolverOptions::default().max_iterations;
+    fill_random_gamma_values(alpha, beta, seed, max_attempts, &mut values);
+    values
+}
+
+/// Result of an offset estimation run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// Estimated clock offset, in the unit of the input samples.
+    pub offset: f64,
+    /// Standard error of `offset` implied by the scatter around the quantile regression line.
+    pub uncertainty: f64,
+    /// Shape of the fitted Gamma delay distribution, see [`pdf`](Self::pdf).
+    pub shape: f64,
+    /// Scale of the fitted Gamma delay distribution, in the unit of the samples, so that
+    /// `shape * scale` is the mean observed delay above `offset`.
+    pub scale: f64,
+    /// Number of samples that entered the fit.
+    pub samples: usize,
+    /// Number of those samples that were [censored](crate::Sample::censored) timeouts.
+    pub censored: usize,
+    /// Number of non-finite samples dropped or replaced by the configured policy.
+    pub non_finite: usize,
+    /// Number of samples discarded by [`TailPolicy::Trim`](crate::TailPolicy::Trim) or as
+    /// outliers, e.g. by [`TailPolicy::Grubbs`](crate::TailPolicy::Grubbs).
+    pub trimmed: usize,
+    /// Number of samples clamped by [`TailPolicy::Winsorize`](crate::TailPolicy::Winsorize).
+    pub winsorized: usize,
+    /// Number of samples left out by [`EstimatorConfig::fast`](crate::EstimatorConfig::fast).
+    pub subsampled: usize,
+    /// Number of samples around a leap second dropped by
+    /// [`EstimatorConfig::leap`](crate::EstimatorConfig::leap).
+    pub leap_spanning: usize,
+    /// Number of samples outside the [`EstimatorConfig::coarse`](crate::EstimatorConfig::coarse)
+    /// window.
+    pub outside_window: usize,
+    /// Translation applied to the samples by [`NegativePolicy::Shift`](crate::NegativePolicy::Shift),
+    /// already removed from `offset`.
+    pub shift: f64,
+    /// Slope of the trend removed by [`EstimatorConfig::detrend`](crate::EstimatorConfig::detrend),
+    /// in sample units per timestamp unit. `None` unless detrending.
+    pub drift: Option<f64>,
+    /// Standard error of `drift` implied by the scatter around the trend line.
+    pub drift_uncertainty: Option<f64>,
+    /// Quality of the reference the samples were measured against.
+    pub source: SourceQuality,
+    /// Standard deviation of `offset` across independent synthetic samples, the part of the
+    /// error due to the random number generator alone. `None` unless
+    /// [`EstimatorConfig::repetitions`](crate::EstimatorConfig::repetitions) asks for it.
+    pub monte_carlo_error: Option<f64>,
+    /// Leave-one-out jackknife variance of `offset`, capturing its sensitivity to the individual
+    /// input samples. `None` unless [`EstimatorConfig::jackknife`](crate::EstimatorConfig::jackknife)
+    /// is set.
+    pub jackknife_variance: Option<f64>,
+    /// Number of values of the synthetic sample the rejection sampler gave up on after
+    /// [`SolverOptions::max_iterations`](crate::SolverOptions::max_iterations) candidates, drawn
+    /// from the inverse distribution function instead. Zero unless the fitted shape or the random
+    /// number generator is pathological.
+    pub sampler_fallbacks: usize,
+    /// Conditioning of the quantile regression, to tell when the crossing point is numerically
+    /// fragile. `None` for estimates fitted on the GPU.
+    pub design: Option<RegressionDesign>,
+}
+
+/// Conditioning of the quantile regression behind an [`Estimate`].
+///
+/// The offset is where the regression line crosses zero, below every synthetic value, so it is
+/// always an extrapolation. It is fragile when the points barely spread, when a few extreme
+/// points steer the line, or when the crossing lies far outside the points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionDesign {
+    /// Weighted standard deviation of the samples shifted by their plotting positions, in sample
+    /// units. Near the resolution of the samples the slope is mostly rounding error.
+    pub spread: f64,
+    /// Largest weighted leverage of a regression point, `w_i / W · (1 + u_i²)` with `u_i` the
+    /// standardized abscissa. The leverages sum to two; values well above `4 / n` flag points
+    /// that dominate the fit.
+    pub max_leverage: f64,
+    /// Distance from the mean abscissa to the crossing point, in units of `spread`. The variance
+    /// of the offset grows with its square.
+    pub extrapolation: f64,
+}
+
+impl Estimate {
+    /// Root distance of the estimate: its uncertainty plus the root dispersion of its source.
+    /// Fusion and selection weigh estimates by this value.
+    pub fn distance(&self) -> f64 {
+        self.uncertainty + self.source.root_dispersion
+    }
+
+    /// Density of the fitted model `offset + Gamma(shape, scale)` at the sample value `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        float::exp(math::gamma_ln_pdf(self.shape, self.scale, x - self.offset))
+    }
+
+    /// Probability under the fitted model that a sample is at most `x`. The chance that a probe
+    /// exceeds `x` is `1.0 - cdf(x)`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        math::gamma_cdf(self.shape, self.scale, x - self.offset)
+    }
+
+    /// t statistic of the offset against `reference`, its distance from it in
+    /// [uncertainties](Self::uncertainty).
+    pub fn t_statistic(&self, reference: f64) -> f64 {
+        (self.offset - reference) / self.uncertainty
+    }
+
+    /// Two-sided p-value of the offset differing from `reference`, from the
+    /// [t statistic](Self::t_statistic) and the `n - 2` degrees of freedom of the regression on
+    /// the `n` uncensored samples, e.g. to correct the clock only below 0.05. NaN for fewer than
+    /// three such samples, as for estimates that do not come from a regression.
+    pub fn p_value(&self, reference: f64) -> f64 {
+        let observed = self.samples - self.censored;
+        if observed < 3 {
+            return f64::NAN;
+        }
+        math::student_t_sf(self.t_statistic(reference), observed as f64 - 2.0)
+    }
+
+    /// The estimate of samples in the unit `S`, e.g. the [`Nanos`](crate::Nanos) of
+    /// [`estimate_delays`], in the unit `U`: every offset, delay and the errors scaled, the
+    /// drift in `U` per timestamp unit.
+    ///
+    /// ```
+    /// use gamlr::{estimate_samples_checked, EstimatorConfig, Micros, Millis, Sample};
+    ///
+    /// // Delays in µs.
+    /// let mut owds = [340.0, 360.0, 350.0, 410.0, 330.0, 380.0, 520.0, 350.0, 370.0, 360.0]
+    ///     .map(Sample::new);
+    /// let config = EstimatorConfig::default();
+    /// let estimate = estimate_samples_checked(&mut owds, &mut [0.0; 10], &config).unwrap();
+    /// let millis = estimate.convert::<Micros, Millis>();
+    /// assert!((millis.offset - estimate.offset / 1e3).abs() < 1e-12);
+    /// ```
+    pub fn convert<S: TimeUnit, U: TimeUnit>(&self) -> Estimate {
+        let scale = |value: f64| value * U::PER_SECOND / S::PER_SECOND;
+        Estimate {
+            offset: scale(self.offset),
+            uncertainty: scale(self.uncertainty),
+            scale: scale(self.scale),
+            shift: scale(self.shift),
+            drift: self.drift.map(scale),
+            drift_uncertainty: self.drift_uncertainty.map(scale),
+            source: SourceQuality {
+                root_dispersion: scale(self.source.root_dispersion),
+                ..self.source
+            },
+            monte_carlo_error: self.monte_carlo_error.map(scale),
+            jackknife_variance: self.jackknife_variance.map(|v| scale(scale(v))),
+            design: self.design.map(|design| RegressionDesign {
+                spread: scale(design.spread),
+                ..design
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// [`offset`](Self::offset) as a dimensional quantity, for samples in the unit `S`.
+    #[cfg(feature = "uom")]
+    pub fn offset_time<S: TimeUnit>(&self) -> uom::si::f64::Time {
+        S::new(self.offset).time()
+    }
+
+    /// [`uncertainty`](Self::uncertainty) as a dimensional quantity, for samples in the unit
+    /// `S`.
+    #[cfg(feature = "uom")]
+    pub fn uncertainty_time<S: TimeUnit>(&self) -> uom::si::f64::Time {
+        S::new(self.uncertainty).time()
+    }
+
+    /// An estimate carrying only an offset and its uncertainty, with all sample counts zero.
+    pub(crate) fn from_offset(offset: f64, uncertainty: f64) -> Self {
+        Estimate {
+            offset,
+            uncertainty,
+            shape: f64::NAN,
+            scale: f64::NAN,
+            samples: 0,
+            censored: 0,
+            non_finite: 0,
+            trimmed: 0,
+            winsorized: 0,
+            subsampled: 0,
+            leap_spanning: 0,
+            outside_window: 0,
+            shift: 0.0,
+            drift: None,
+            drift_uncertainty: None,
+            source: SourceQuality::default(),
+            monte_carlo_error: None,
+            jackknife_variance: None,
+            sampler_fallbacks: 0,
+            design: None,
+        }
+    }
 }
 
 /// Estimates the offset between two networked devices based on one-way delay time (OWD) measurements
@@ -131,65 +487,797 @@ fn generate_random_gamma_values(alpha: f64, beta: f64, num_samples: usize, seed:
 ///
 /// Edmar Mota-Garcia and Rogelio Hasimoto-Beltran: "A new model-based clock-offset approximation over IP networks"
 /// Computer Communications, Volume 53, 2014, Pages 26-36, ISSN 0140-3664, https://doi.org/10.1016/j.comcom.2014.07.006.
+///
+/// This is a convenience wrapper around [`estimate_with`] using the default configuration,
+/// but for [`EstimatorConfig::min_samples`]: it fits batches of as few as two samples, as it
+/// always has. It returns NaN whenever [`estimate_with`] fails.
+#[cfg(feature = "alloc")]
 pub fn estimate<I>(time_values: I, seed: Option<u64>) -> f64
 where
     I: IntoIterator<Item = f64>,
 {
-    let time_values_vec: Vec<f64> = time_values.into_iter().collect();
-    let n = time_values_vec.len();
-    let (mut alpha, beta) = estimate_gamma_parameters(&time_values_vec);
-    alpha = alpha.max(MIN_ALPHA).min(MAX_ALPHA);
-    let lcg_seed = LcgRng::new(0).next_u64();
-    let random_values = generate_random_gamma_values(alpha, beta, n, seed.unwrap_or(lcg_seed));
-    let sorted = sort_values(&time_values_vec);
-    let random_sorted = sort_values(&random_values);
+    let config = EstimatorConfig {
+        seed,
+        min_samples: 2,
+        ..Default::default()
+    };
+    estimate_with(time_values, &config)
+        .map(|estimate| estimate.offset)
+        .unwrap_or(f64::NAN)
+}
+
+/// Estimates the offset between two networked devices based on one-way delay time (OWD) measurements,
+/// honoring the preprocessing and sampling options in `config`.
+#[cfg(feature = "alloc")]
+pub fn estimate_with<I>(time_values: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = f64>,
+{
+    estimate_samples(time_values.into_iter().map(Sample::new), config)
+}
 
-    estimate_offset(&sorted, &random_sorted)
+/// Estimates the offset from the send and receive timestamps of the probes, differenced as
+/// described at [`TimestampPair`](crate::TimestampPair): the offset of the receiver clock from
+/// the sender clock, with the delays timestamped at their receive times for
+/// [`EstimatorConfig::detrend`] and [`EstimatorConfig::half_life`].
+#[cfg(feature = "alloc")]
+pub fn estimate_pairs<I>(pairs: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = crate::TimestampPair>,
+{
+    estimate_samples(pairs.into_iter().map(Sample::from), config)
 }
 
-/// Calculates the offset between the generated gamma values and the sorted time values.
+/// Estimates the offset from delays in a stated unit, e.g. [`Micros`](crate::Micros), to keep
+/// probes collected in one unit from being estimated as another. The estimate, like the
+/// [`path_delay`](EstimatorConfig::path_delay) and the other durations of `config`, is in the
+/// unit of the delays; [`Estimate::convert`] takes it to another.
+#[cfg(feature = "alloc")]
+pub fn estimate_delays<U, I>(delays: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    U: TimeUnit,
+    I: IntoIterator<Item = U>,
+{
+    estimate_samples(
+        delays.into_iter().map(|delay| Sample::new(delay.get())),
+        config,
+    )
+}
+
+/// Estimates the offset from dimensional delays, which can be in any unit of time. The estimate
+/// is in seconds, and so are the durations of `config`; see [`Estimate::offset_time`] for the
+/// offset as a quantity.
 ///
-/// Edmar Mota-Garcia and Rogelio Hasimoto-Beltran: "A new model-based clock-offset approximation over IP networks"
-/// Computer Communications, Volume 53, 2014, Pages 26-36, ISSN 0140-3664, https://doi.org/10.1016/j.comcom.2014.07.006.
-pub fn estimate_offset(x_sort: &[f64], y: &[f64]) -> f64 {
-    let n = x_sort.len();
-    let mut y_regression = Vec::new();
-    let mut x_regression = Vec::new();
+/// ```
+/// use gamlr::{estimate_times, EstimatorConfig, Seconds};
+/// use uom::si::f64::Time;
+/// use uom::si::time::{microsecond, millisecond};
+///
+/// let owds = [340.0, 360.0, 350.0, 410.0, 330.0, 380.0, 520.0, 350.0, 370.0, 360.0];
+/// let delays = owds.map(Time::new::<microsecond>);
+/// let estimate = estimate_times(delays, &EstimatorConfig::default()).unwrap();
+/// assert!(estimate.offset_time::<Seconds>().get::<millisecond>() < 0.33);
+/// ```
+#[cfg(all(feature = "alloc", feature = "uom"))]
+pub fn estimate_times<I>(delays: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = uom::si::f64::Time>,
+{
+    estimate_delays(delays.into_iter().map(crate::Seconds::from), config)
+}
+
+/// Estimates the offset from delays read as raw counts of timer `ticks` of length `period`, in
+/// the unit of the offset, e.g. the differences of two free-running counters. The delays are
+/// taken as intervals of one tick, see [`EstimatorConfig::resolution`], which `period`
+/// overrides.
+///
+/// ```
+/// use gamlr::{estimate_ticks, EstimatorConfig};
+///
+/// // Delays of 0.5 to 0.9 µs read off a 100 ns timer.
+/// let ticks = [5, 6, 5, 7, 9, 5, 6, 6, 8, 5, 7, 6];
+/// let estimate = estimate_ticks(ticks, 100.0, &EstimatorConfig::default()).unwrap();
+/// assert!(estimate.offset < 500.0);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn estimate_ticks<I>(
+    ticks: I,
+    period: f64,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = i64>,
+{
+    let config = EstimatorConfig {
+        resolution: Some(period),
+        ..config.clone()
+    };
+    let samples = ticks
+        .into_iter()
+        .map(|ticks| Sample::new(ticks as f64 * period));
+    estimate_samples(samples, &config)
+}
+
+/// Estimates the offset from `(value, weight)` pairs, where the weight expresses the relative
+/// reliability of each measurement, e.g. to favor hardware-timestamped probes over software ones.
+///
+/// Weights enter the moment estimates, the plotting positions and the regression. They must be
+/// finite and non-negative; samples with zero weight are ignored.
+#[cfg(feature = "alloc")]
+pub fn estimate_weighted<I>(samples: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = (f64, f64)>,
+{
+    let samples = samples
+        .into_iter()
+        .map(|(value, weight)| Sample::weighted(value, weight));
+    estimate_samples(samples, config)
+}
 
-    for i in 0..n {
-        let rank = (i + 1) as f64;
-        let p_value = (rank - 0.5) / n as f64;
-        y_regression.push(y[i]);
-        x_regression.push(x_sort[i] - p_value);
+/// Estimates the offset from fully specified [`Sample`]s, e.g. timestamped samples combined with
+/// [`EstimatorConfig::half_life`](crate::EstimatorConfig::half_life) to favor recent measurements.
+#[cfg(feature = "alloc")]
+pub fn estimate_samples<I>(samples: I, config: &EstimatorConfig) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let mut samples: Vec<Sample> = samples.into_iter().collect();
+    let mut synthetic = alloc::vec![0.0; samples.len()];
+    run(&mut samples, &mut synthetic, config)
+}
+
+/// [`estimate_samples`] with fallible allocation, for memory-constrained targets with an
+/// allocator: every buffer is reserved with `try_reserve`, so that a trace too large to buffer
+/// fails with [`EstimateError::OutOfMemory`] instead of aborting.
+#[cfg(feature = "alloc")]
+pub fn try_estimate_samples<I>(
+    samples: I,
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let samples = samples.into_iter();
+    let mut buffer: Vec<Sample> = Vec::new();
+    let out_of_memory = |samples| EstimateError::OutOfMemory { samples };
+    let hint = samples.size_hint().0;
+    buffer.try_reserve(hint).map_err(|_| out_of_memory(hint))?;
+    for sample in samples {
+        if buffer.len() == buffer.capacity() {
+            let len = buffer.len();
+            buffer.try_reserve(1).map_err(|_| out_of_memory(len + 1))?;
+        }
+        buffer.push(sample);
     }
+    let mut synthetic: Vec<f64> = Vec::new();
+    synthetic
+        .try_reserve_exact(buffer.len())
+        .map_err(|_| out_of_memory(buffer.len()))?;
+    synthetic.resize(buffer.len(), 0.0);
+    run(&mut buffer, &mut synthetic, config)
+}
 
-    let x_mean = x_regression.iter().sum::<f64>() / x_regression.len() as f64;
-    let y_mean = y_regression.iter().sum::<f64>() / y_regression.len() as f64;
+/// Panic-free [`estimate_samples`](crate::estimate_samples) on caller-provided buffers, for
+/// safety-critical builds without an allocator. `samples` is filtered and reordered in place,
+/// and `synthetic` is scratch space for the synthetic Gamma sample.
+///
+/// The pipeline does no unchecked arithmetic, unwrapping or slice indexing whose bounds are not
+/// established beforehand, so the function returns on every input; the `no_panic` test harness
+/// covers it with degenerate batches and configurations.
+///
+/// Fails like [`estimate_samples`](crate::estimate_samples), and with
+/// [`EstimateError::CapacityExceeded`] when `synthetic` holds fewer values than `samples`.
+pub fn estimate_samples_checked(
+    samples: &mut [Sample],
+    synthetic: &mut [f64],
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError> {
+    if synthetic.len() < samples.len() {
+        return Err(EstimateError::CapacityExceeded {
+            capacity: synthetic.len(),
+        });
+    }
+    run(&mut SliceBuffer::new(samples), synthetic, config)
+}
 
-    // Perform linear regression to estimate the slope (beta) and intercept (gamma)
-    let beta = {
-        let numerator = x_regression
-            .iter()
-            .zip(y_regression.iter())
-            .map(|(x, y)| (x - x_mean) * (y - y_mean))
-            .sum::<f64>();
-        let denominator = x_regression
+/// What the preprocessing stages did to a batch, see [`prepare`].
+pub(crate) struct Prepared {
+    pub non_finite: usize,
+    pub trimmed: usize,
+    pub winsorized: usize,
+    pub subsampled: usize,
+    pub leap_spanning: usize,
+    pub outside_window: usize,
+    pub shift: f64,
+    /// Slope removed by the detrending and its standard error.
+    pub drift: Option<(f64, f64)>,
+    /// One of the readings of the timer of [`EstimatorConfig::resolution`], shifted like the
+    /// samples, while they still sit in the intervals around the readings.
+    pub grid: Option<f64>,
+}
+
+/// Runs the preprocessing stages configured in `config` on `samples`, in place, and checks that
+/// enough samples remain.
+pub(crate) fn prepare(
+    samples: &mut impl SampleBuffer,
+    config: &EstimatorConfig,
+) -> Result<Prepared, EstimateError> {
+    if let Some(half_life) = config.half_life {
+        preprocess::apply_recency(samples, half_life)?;
+    }
+    preprocess::filter_weights(samples)?;
+    let non_finite = preprocess::filter_non_finite(samples, config.non_finite)?;
+    let leap_spanning = match &config.leap {
+        Some(filter) => preprocess::exclude_leaps(samples, filter)?,
+        None => 0,
+    };
+    let mut grid = match (config.resolution, config.quantization) {
+        (Some(resolution), QuantizationNoise::Model) => {
+            preprocess::dequantize(samples, resolution)?
+        }
+        (Some(resolution), QuantizationNoise::Dither) => {
+            preprocess::dither(samples, resolution, seed(config))?;
+            None
+        }
+        (None, _) => None,
+    };
+    let drift = match config.detrend {
+        true => Some(preprocess::detrend(samples, config.precise)?),
+        false => None,
+    };
+    let subsampled = match config.fast {
+        Some(mode) => preprocess::subsample(samples, mode, seed(config))?,
+        None => 0,
+    };
+    let outside_window = match config.coarse {
+        Some(window) => preprocess::apply_window(samples, window)?,
+        None => 0,
+    };
+    let (trimmed, winsorized) = preprocess::handle_tails(samples, config.tails)?;
+    let shift = preprocess::handle_negative(samples, config.negative)?;
+    // Detrending moves the samples off their intervals.
+    if drift.is_some() {
+        grid = None;
+    }
+    let counts = [
+        (non_finite, Event::NonFinite { count: non_finite }),
+        (
+            leap_spanning,
+            Event::LeapSpanning {
+                count: leap_spanning,
+            },
+        ),
+        (subsampled, Event::Subsampled { count: subsampled }),
+        (
+            outside_window,
+            Event::OutsideWindow {
+                count: outside_window,
+            },
+        ),
+        (
+            trimmed + winsorized,
+            Event::Tails {
+                trimmed,
+                winsorized,
+            },
+        ),
+    ];
+    for (_, event) in counts.into_iter().filter(|&(count, _)| count > 0) {
+        config.emit(event);
+    }
+    if shift != 0.0 {
+        config.emit(Event::Shifted { shift });
+    }
+    let n = samples.iter().filter(|s| !s.censored).count();
+    let need = config.min_samples.max(2);
+    if n < need {
+        return Err(EstimateError::InsufficientSamples { got: n, need });
+    }
+    Ok(Prepared {
+        non_finite,
+        trimmed,
+        winsorized,
+        subsampled,
+        leap_spanning,
+        outside_window,
+        shift,
+        drift,
+        grid: grid.map(|reading| reading + shift),
+    })
+}
+
+/// Runs the estimation pipeline on `samples`, which are filtered and reordered in place.
+/// `synthetic` is scratch space for the synthetic Gamma sample and must hold at least as many
+/// values as there are samples.
+pub(crate) fn run(
+    samples: &mut impl SampleBuffer,
+    synthetic: &mut [f64],
+    config: &EstimatorConfig,
+) -> Result<Estimate, EstimateError> {
+    #[cfg(feature = "opentelemetry")]
+    let telemetry = crate::telemetry::start(samples.len(), config);
+    let result = prepare(samples, config)
+        .and_then(|prepared| fit_prepared(samples, synthetic, config, prepared));
+    if let Err(error) = result {
+        config.emit(Event::Failed(error));
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_failure();
+    }
+    #[cfg(feature = "opentelemetry")]
+    crate::telemetry::finish(telemetry, &result);
+    result
+}
+
+/// Seed of the random number generators of a fit, [`EstimatorConfig::seed`] or a fixed one.
+pub(crate) fn seed(config: &EstimatorConfig) -> u64 {
+    config.seed.unwrap_or_else(|| LcgRng::new(0).next_u64())
+}
+
+/// Fits the model to `samples` already passed through [`prepare`], see [`run`].
+pub(crate) fn fit_prepared(
+    samples: &mut [Sample],
+    synthetic: &mut [f64],
+    config: &EstimatorConfig,
+    prepared: Prepared,
+) -> Result<Estimate, EstimateError> {
+    let Prepared {
+        non_finite,
+        trimmed,
+        winsorized,
+        subsampled,
+        leap_spanning,
+        outside_window,
+        shift,
+        drift,
+        grid,
+    } = prepared;
+    let (drift, drift_uncertainty) = (drift.map(|d| d.0), drift.map(|d| d.1));
+    let n = samples.len();
+    sort_samples(samples);
+    let (mut alpha, mut beta) = fit_gamma(samples, config)?;
+    let synthetic = &mut synthetic[..n];
+    let seed = seed(config);
+    let sampler_fallbacks = fill_synthetic(alpha, beta, seed, config, synthetic)?;
+    if sampler_fallbacks > 0 {
+        config.emit(Event::SamplerFallback {
+            count: sampler_fallbacks,
+        });
+    }
+    // Censored samples sort last; they only take up the top plotting positions.
+    let observed = samples.iter().take_while(|s| !s.censored).count();
+    let tail_weight = math::sum(samples[observed..].iter().map(|s| s.weight), config.precise);
+    let mut fit = estimate_offset(
+        &samples[..observed],
+        synthetic,
+        tail_weight,
+        config.plotting,
+        config.regression,
+        config.precise,
+    )?;
+    if let (Some(resolution), Some(grid)) = (config.resolution, grid) {
+        let mut settled = false;
+        for _ in 0..config.solver.max_iterations {
+            let previous = fit.offset;
+            let scale = delay_scale(&samples[..observed], fit.offset, alpha, config.precise);
+            let delays = (fit.offset, alpha, scale);
+            place_in_intervals(&mut samples[..observed], grid, resolution, delays);
+            (alpha, beta) = fit_gamma(samples, config)?;
+            fill_synthetic(alpha, beta, seed, config, synthetic)?;
+            fit = estimate_offset(
+                &samples[..observed],
+                synthetic,
+                tail_weight,
+                config.plotting,
+                config.regression,
+                config.precise,
+            )?;
+            settled = libm::fabs(fit.offset - previous) <= config.solver.tolerance * resolution;
+            if settled {
+                break;
+            }
+        }
+        if !settled {
+            return Err(EstimateError::NotConverged {
+                solver: "resolution",
+            });
+        }
+    }
+    let monte_carlo_error = match (config.repetitions >= 2, config.synthetic) {
+        (false, _) => None,
+        (true, SyntheticSample::Random) => Some(monte_carlo_error(
+            &samples[..observed],
+            synthetic,
+            tail_weight,
+            (alpha, beta),
+            seed,
+            config,
+        )?),
+        (true, SyntheticSample::Quantiles) => Some(0.0),
+    };
+    let jackknife_variance = match config.jackknife {
+        true => Some(jackknife_variance(
+            samples, observed, synthetic, seed, config,
+        )?),
+        false => None,
+    };
+
+    let estimate = Estimate {
+        offset: fit.offset - shift - config.path_delay,
+        uncertainty: fit.std_error,
+        shape: alpha,
+        scale: delay_scale(&samples[..observed], fit.offset, alpha, config.precise),
+        samples: n,
+        censored: n - observed,
+        non_finite,
+        trimmed,
+        winsorized,
+        subsampled,
+        leap_spanning,
+        outside_window,
+        shift,
+        drift,
+        drift_uncertainty,
+        source: config.source,
+        monte_carlo_error,
+        jackknife_variance,
+        sampler_fallbacks,
+        design: Some(fit.design),
+    };
+    config.emit(Event::FitCompleted {
+        offset: estimate.offset,
+        uncertainty: estimate.uncertainty,
+        samples: estimate.samples,
+    });
+    #[cfg(feature = "metrics")]
+    crate::metrics::record(&estimate, fit.slope);
+    Ok(estimate)
+}
+
+/// Fits the Gamma model to `samples`, sorted as by [`sort_samples`], with the estimator in
+/// [`EstimatorConfig::fit`] and the shape clamped to the range the sampler handles.
+///
+/// Censored samples are handled by expectation–maximization: each is replaced by its expected
+/// value beyond the timeout under the current fit, and the moments are refitted until the
+/// parameters settle within [`EstimatorConfig::solver`]. Fails with
+/// [`EstimateError::NotConverged`] naming `censored` otherwise.
+pub(crate) fn fit_gamma(
+    samples: &[Sample],
+    config: &EstimatorConfig,
+) -> Result<(f64, f64), EstimateError> {
+    // Shape of the last fit before clamping.
+    let unclamped = core::cell::Cell::new(f64::NAN);
+    // `max`/`min` rather than `clamp`: a NaN alpha has to end up inside the range too,
+    // otherwise the rejection sampler never terminates.
+    #[allow(clippy::manual_clamp)]
+    let clamp = |(alpha, beta): (f64, f64)| {
+        unclamped.set(alpha);
+        (alpha.max(MIN_ALPHA).min(MAX_ALPHA), beta)
+    };
+    let done = |fit: (f64, f64)| {
+        // NaN compares unequal too.
+        if unclamped.get() != fit.0 {
+            config.emit(Event::ShapeClamped {
+                shape: unclamped.get(),
+                clamped: fit.0,
+            });
+        }
+        Ok(fit)
+    };
+    let precise = config.precise;
+    let fit_values = |value: &dyn Fn(&Sample) -> f64| {
+        clamp(match config.fit {
+            GammaFit::Moments => estimate_gamma_parameters(samples, value, precise),
+            GammaFit::ProbabilityWeighted => gamma_pwm(samples, value, precise),
+            GammaFit::LMoments => gamma_l_moments(samples, value, precise),
+        })
+    };
+    let mut fit = fit_values(&|s| s.value);
+    if !samples.iter().any(|s| s.censored) {
+        return done(fit);
+    }
+    let SolverOptions {
+        max_iterations,
+        tolerance,
+    } = config.solver;
+    for _ in 0..max_iterations {
+        cancel::check(&config.cancel)?;
+        let (alpha, beta) = fit;
+        let value = |s: &Sample| match s.censored {
+            true => censored_mean(alpha, beta, s.value),
+            false => s.value,
+        };
+        fit = fit_values(&value);
+        let settled = |old: f64, new: f64| libm::fabs(new - old) <= tolerance * libm::fabs(new);
+        // A NaN fit never settles; it is left to the caller like an uncensored one.
+        if (settled(alpha, fit.0) && settled(beta, fit.1)) || fit.1.is_nan() {
+            return done(fit);
+        }
+    }
+    Err(EstimateError::NotConverged { solver: "censored" })
+}
+
+/// Scale of the delay model `offset + Gamma(shape, scale)` in sample units, matching its mean
+/// `shape * scale` to the mean delay of the uncensored `samples` above `offset`. The slope of
+/// the quantile regression does not serve: its abscissae are the samples shifted by their
+/// plotting positions, which reverse the order of samples spread over less than one unit.
+fn delay_scale(samples: &[Sample], offset: f64, shape: f64, precise: bool) -> f64 {
+    let w_sum = math::sum(samples.iter().map(|s| s.weight), precise);
+    let mean = math::sum(samples.iter().map(|s| s.weight * s.value), precise) / w_sum;
+    (mean - offset) / shape
+}
+
+/// Places the sorted, uncensored `samples` spread over the intervals of one `resolution`
+/// around the readings on the `grid` at the quantiles of their weight midpoints within their
+/// interval under the delay model `(offset, shape, scale)`. An interval the model gives no
+/// probability keeps its samples spread evenly.
+fn place_in_intervals(
+    samples: &mut [Sample],
+    grid: f64,
+    resolution: f64,
+    (offset, shape, scale): (f64, f64, f64),
+) {
+    let reading = |value: f64| grid + resolution * libm::round((value - grid) / resolution);
+    let cdf = |value: f64| math::gamma_p(shape, ((value - offset) / scale).max(0.0));
+    let mut start = 0;
+    while start < samples.len() {
+        let center = reading(samples[start].value);
+        let tied = samples[start..]
             .iter()
-            .map(|x| libm::pow(x - x_mean, 2.0))
-            .sum::<f64>();
-        numerator / denominator
+            .take_while(|s| reading(s.value) == center)
+            .count();
+        let group = &mut samples[start..start + tied];
+        start += tied;
+        let (low, high) = (center - 0.5 * resolution, center + 0.5 * resolution);
+        let (below, within) = (cdf(low), cdf(high) - cdf(low));
+        let total: f64 = group.iter().map(|s| s.weight).sum();
+        let mut before = 0.0;
+        for sample in group.iter_mut() {
+            let position = match total > 0.0 {
+                true => (before + 0.5 * sample.weight) / total,
+                false => 0.5,
+            };
+            before += sample.weight;
+            let quantile = offset + scale * math::gamma_quantile(shape, below + position * within);
+            sample.value = match within > 1e-12 && quantile > low && quantile < high {
+                true => quantile,
+                false => low + position * resolution,
+            };
+        }
+    }
+}
+
+/// Expected value of a `Gamma(alpha, beta)` variable known to exceed `timeout`.
+fn censored_mean(alpha: f64, beta: f64, timeout: f64) -> f64 {
+    let survival = math::gamma_sf(alpha, beta, timeout);
+    if timeout <= 0.0 || survival.is_nan() {
+        return alpha * beta;
+    }
+    if survival <= 0.0 {
+        return timeout;
+    }
+    let mean = alpha * beta * math::gamma_sf(alpha + 1.0, beta, timeout) / survival;
+    mean.max(timeout)
+}
+
+/// Fills `out` with the sorted synthetic sample of the Gamma distribution described by `config`,
+/// returning the number of values the rejection sampler gave up on, see
+/// [`fill_random_gamma_values`].
+fn fill_synthetic(
+    alpha: f64,
+    beta: f64,
+    seed: u64,
+    config: &EstimatorConfig,
+    out: &mut [f64],
+) -> Result<usize, EstimateError> {
+    match config.synthetic {
+        SyntheticSample::Random => {
+            let max_attempts = config.solver.max_iterations;
+            let fallbacks = fill_random_gamma_values(alpha, beta, seed, max_attempts, out);
+            sort_values(out);
+            Ok(fallbacks)
+        }
+        SyntheticSample::Quantiles => {
+            fill_gamma_quantiles(alpha, beta, config.plotting, &config.solver, out)?;
+            Ok(0)
+        }
+    }
+}
+
+/// Standard deviation of the offset over [`EstimatorConfig::repetitions`] synthetic samples
+/// drawn from the fitted Gamma distribution, the first of them with `seed`. `synthetic` is
+/// overwritten.
+fn monte_carlo_error(
+    samples: &[Sample],
+    synthetic: &mut [f64],
+    tail_weight: f64,
+    (alpha, beta): (f64, f64),
+    seed: u64,
+    config: &EstimatorConfig,
+) -> Result<f64, EstimateError> {
+    let mut seeds = LcgRng::new(seed);
+    let mut next_seed = seed;
+    let mut offsets = math::RunningVariance::default();
+    for _ in 0..config.repetitions {
+        fill_synthetic(alpha, beta, next_seed, config, synthetic)?;
+        let fit = estimate_offset(
+            samples,
+            synthetic,
+            tail_weight,
+            config.plotting,
+            config.regression,
+            config.precise,
+        )?;
+        offsets.push(fit.offset);
+        next_seed = seeds.next_u64();
+    }
+    Ok(float::sqrt(
+        offsets.sum_sq() / (config.repetitions - 1) as f64,
+    ))
+}
+
+/// Leave-one-out jackknife variance of the offset, `(n - 1) / n * Σ (θ_i - θ̄)²`, where `θ_i` is
+/// the offset refitted from the sorted `samples` without the i-th of the `observed` uncensored
+/// ones. `synthetic` is overwritten; `samples` is left as it was.
+fn jackknife_variance(
+    samples: &mut [Sample],
+    observed: usize,
+    synthetic: &mut [f64],
+    seed: u64,
+    config: &EstimatorConfig,
+) -> Result<f64, EstimateError> {
+    let n = samples.len();
+    let tail_weight = math::sum(samples[observed..].iter().map(|s| s.weight), config.precise);
+    let mut offsets = math::RunningVariance::default();
+    for i in 0..observed {
+        // Move the left-out sample to the end, keeping the others sorted.
+        samples[i..].rotate_left(1);
+        let kept = &samples[..n - 1];
+        let refit = |synthetic: &mut [f64]| {
+            let (alpha, beta) = fit_gamma(kept, config)?;
+            fill_synthetic(alpha, beta, seed, config, synthetic)?;
+            estimate_offset(
+                &kept[..observed - 1],
+                synthetic,
+                tail_weight,
+                config.plotting,
+                config.regression,
+                config.precise,
+            )
+        };
+        let fit = refit(&mut synthetic[..n - 1]);
+        samples[i..].rotate_right(1);
+        offsets.push(fit?.offset);
+    }
+    Ok((observed - 1) as f64 / observed as f64 * offsets.sum_sq())
+}
+
+/// Crossing point of the quantile regression together with its standard error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct OffsetFit {
+    pub offset: f64,
+    pub std_error: f64,
+    /// Synthetic units per sample unit; delays are the synthetic values divided by it.
+    pub slope: f64,
+    pub design: RegressionDesign,
+}
+
+/// Plotting positions of the sorted, uncensored `x_sort` of total weight `w_sum`, see
+/// [`estimate_offset`]. Tied samples share the midrank of their group,
+/// `(W_before + W_tied / 2) / w̄ + 1/2`.
+pub(crate) fn plotting_positions(
+    x_sort: &[Sample],
+    w_sum: f64,
+    tail_weight: f64,
+    positions: PlottingPosition,
+) -> impl Iterator<Item = f64> + Clone + '_ {
+    let unit = w_sum / x_sort.len() as f64;
+    let count = (w_sum + tail_weight) / unit;
+    let mut w_before = 0.0;
+    let mut group = (0, 0.0);
+    x_sort.iter().enumerate().map(move |(i, sample)| {
+        if i == group.0 {
+            let tied = x_sort[i..].iter().take_while(|s| s.value == sample.value);
+            let (tied_count, w_tied) = tied.fold((0, 0.0), |(c, w), s| (c + 1, w + s.weight));
+            let rank = (w_before + 0.5 * w_tied) / unit + 0.5;
+            group = (i + tied_count, positions.position(rank, count));
+            w_before += w_tied;
+        }
+        group.1
+    })
+}
+
+/// Index of the value of a synthetic sample of `len` sorted values paired with the plotting
+/// position `p`.
+pub(crate) fn synthetic_index(p: f64, len: usize) -> usize {
+    ((p * len as f64) as usize).min(len.saturating_sub(1))
+}
+
+/// Calculates the offset between the generated gamma values and the sorted time values.
+///
+/// Each sample is paired with the synthetic value at its plotting position in `positions`. Its
+/// rank is weighted, `i = (W_before + w / 2) / w̄ + 1/2` with `w̄` the mean weight, so that
+/// Hazen positions become `(W_before + w / 2) / W`; tied values share the average rank of their
+/// group. `tail_weight` is the weight of censored samples ranked above every sample in `x_sort`;
+/// it counts towards `W` without adding regression points.
+///
+/// Fails with [`EstimateError::InsufficientSamples`] without uncensored samples, and with
+/// [`EstimateError::DegenerateRegression`] when the shifted samples or the synthetic values do
+/// not spread beyond their rounding error, where the line and its crossing point are undefined.
+///
+/// Edmar Mota-Garcia and Rogelio Hasimoto-Beltran: "A new model-based clock-offset approximation over IP networks"
+/// Computer Communications, Volume 53, 2014, Pages 26-36, ISSN 0140-3664, https://doi.org/10.1016/j.comcom.2014.07.006.
+pub(crate) fn estimate_offset(
+    x_sort: &[Sample],
+    y: &[f64],
+    tail_weight: f64,
+    positions: PlottingPosition,
+    regression: Regression,
+    precise: bool,
+) -> Result<OffsetFit, EstimateError> {
+    if x_sort.is_empty() || y.is_empty() {
+        return Err(EstimateError::InsufficientSamples {
+            got: x_sort.len(),
+            need: 2,
+        });
+    }
+    let w_sum = math::sum(x_sort.iter().map(|s| s.weight), precise);
+    // Samples are taken relative to the middle one before the small plotting positions are
+    // subtracted: for epoch-relative magnitudes `value - p` would otherwise round `p` away.
+    // Subtracting nearby values is exact, so this loses nothing.
+    let center = x_sort[x_sort.len() / 2].value;
+
+    // Regression points (x, y, weight): each sample shifted by its plotting position, against
+    // the synthetic value at that position.
+    let points = || {
+        plotting_positions(x_sort, w_sum, tail_weight, positions)
+            .zip(x_sort)
+            .map(move |(p_value, sample)| {
+                let index = synthetic_index(p_value, y.len());
+                ((sample.value - center) - p_value, y[index], sample.weight)
+            })
     };
-    let gamma = { y_mean - beta * x_mean };
 
-    // Return the point where the regression line crosses the x-axis (y = 0)
-    -gamma / beta
+    let line = linfit::fit_with(points(), regression, precise)?;
+    // Both spreads are standardized away; below the resolution of the values they are noise.
+    if !linfit::resolved(line.x_spread, libm::fabs(center) + libm::fabs(line.x_mean))
+        || !linfit::resolved(line.y_spread, libm::fabs(line.y_mean))
+    {
+        return Err(EstimateError::DegenerateRegression);
+    }
+    // The crossing point (y = 0) in sample units, with its standard error by the delta method.
+    let crossing = line.solve(0.0);
+    let std_error = line.solve_error(0.0);
+    let max_leverage = points()
+        .map(|(x, _, w)| {
+            w / line.weight * (1.0 + float::pow((x - line.x_mean) / line.x_spread, 2.0))
+        })
+        .fold(0.0, f64::max);
+
+    Ok(OffsetFit {
+        offset: center + crossing,
+        std_error,
+        slope: line.slope,
+        design: RegressionDesign {
+            spread: line.x_spread,
+            max_leverage,
+            extrapolation: libm::fabs(crossing - line.x_mean) / line.x_spread,
+        },
+    })
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::*;
+    use crate::config::{
+        CoarseCenter, CoarseWindow, FastMode, LeapFilter, LeapSeconds, Subsampling, TailPolicy,
+    };
+
+    fn unweighted(values: &[f64]) -> Vec<Sample> {
+        values.iter().copied().map(Sample::new).collect()
+    }
 
     #[test]
+    #[allow(clippy::manual_range_contains)]
     fn test_lcg_rng_output_range() {
         let mut rng = LcgRng::new(12345);
         for _ in 0..100 {
@@ -220,10 +1308,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sampler_falls_back_to_quantiles() {
+        // Without a single candidate allowed, every value is a quantile.
+        let mut values = [0.0; 50];
+        assert_eq!(fill_random_gamma_values(3.0, 2.0, 3, 0, &mut values), 50);
+        for (i, value) in values.iter().enumerate() {
+            let p = (i as f64 + 0.5) / values.len() as f64;
+            assert_eq!(*value, 2.0 * math::gamma_quantile(3.0, p));
+        }
+        // With a single candidate, only the rejected ones are.
+        let mut values = [0.0; 1000];
+        let fallbacks = fill_random_gamma_values(1.0, 2.0, 3, 1, &mut values);
+        assert!(fallbacks > 0 && fallbacks < 100, "{fallbacks}");
+
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 5);
+        let estimate = estimate_with(values, &EstimatorConfig::default()).unwrap();
+        assert_eq!(estimate.sampler_fallbacks, 0);
+    }
+
     #[test]
     fn test_estimate_gamma_parameters() {
         let data = alloc::vec![1.53, 2.00, 2.75, 3.10, 4.93, 5.33];
-        let (alpha, beta) = estimate_gamma_parameters(&data);
+        let (alpha, beta) = estimate_gamma_parameters(&unweighted(&data), |s| s.value, false);
 
         let expected_alpha = 4.48;
         let expected_beta = 0.73;
@@ -246,7 +1353,8 @@ mod tests {
         let seed = 500;
         let values = generate_random_gamma_values(alpha, beta, n, seed);
 
-        let (alpha_hat, beta_hat) = estimate_gamma_parameters(&values);
+        let (alpha_hat, beta_hat) =
+            estimate_gamma_parameters(&unweighted(&values), |s| s.value, false);
 
         assert!(
             (alpha_hat - alpha).abs() / alpha < 1e-1,
@@ -258,6 +1366,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_gamma_pwm() {
+        let mut values = generate_random_gamma_values(3.0, 2.0, 2000, 8);
+        sort_values(&mut values);
+        let (alpha, beta) = gamma_pwm(&unweighted(&values), |s| s.value, false);
+        assert!((alpha - 3.0).abs() < 0.3, "Alpha {alpha}");
+        assert!((beta - 2.0).abs() < 0.2, "Beta {beta}");
+
+        // A single congestion outlier moves the shape far less than it moves the variance.
+        let mut small = generate_random_gamma_values(3.0, 2.0, 20, 8);
+        sort_values(&mut small);
+        let clean = unweighted(&small);
+        let mut noisy = clean.clone();
+        noisy[19].value = 100.0;
+        let pwm = |x: &[Sample]| gamma_pwm(x, |s| s.value, false).0;
+        let moments = |x: &[Sample]| estimate_gamma_parameters(x, |s| s.value, false).0;
+        assert!(pwm(&noisy) / pwm(&clean) > moments(&noisy) / moments(&clean));
+
+        let config = EstimatorConfig {
+            fit: GammaFit::ProbabilityWeighted,
+            ..Default::default()
+        };
+        let shifted = small.iter().map(|v| v + 50.0);
+        assert!(estimate_with(shifted, &config).unwrap().offset.is_finite());
+    }
+
+    #[test]
+    fn test_gamma_l_moments_ignore_offset() {
+        let mut values = generate_random_gamma_values(3.0, 2.0, 2000, 9);
+        sort_values(&mut values);
+        let shifted: Vec<f64> = values.iter().map(|v| v + 50.0).collect();
+        let (alpha, beta) = gamma_l_moments(&unweighted(&shifted), |s| s.value, false);
+        assert!((alpha - 3.0).abs() < 0.5, "Alpha {alpha}");
+        assert!((beta - 2.0).abs() < 0.3, "Beta {beta}");
+
+        let config = EstimatorConfig {
+            fit: GammaFit::LMoments,
+            synthetic: SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let result = estimate_with(shifted, &config).unwrap();
+        assert!(
+            (result.offset - 50.0).abs() < 2.0,
+            "Offset {}",
+            result.offset
+        );
+    }
+
     #[test]
     fn test_estimate_offset() {
         let alpha1 = 4.0;
@@ -266,7 +1422,16 @@ mod tests {
         let seed = 500;
         let mut values_sorted = generate_random_gamma_values(alpha1, beta1, n, seed);
         values_sorted.sort_unstable_by(|a, b| a.partial_cmp(b).expect("Can't sort NaN, aborting"));
-        let offset = estimate_offset(&values_sorted, &values_sorted);
+        let offset = estimate_offset(
+            &unweighted(&values_sorted),
+            &values_sorted,
+            0.0,
+            PlottingPosition::Hazen,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap()
+        .offset;
 
         assert!(
             offset.abs() < 1e-1,
@@ -274,6 +1439,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_estimate_offset_epoch_magnitudes() {
+        let mut values_sorted = generate_random_gamma_values(4.0, 100.0, 1000, 500);
+        values_sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+        let epoch = 1.7e12;
+        let shifted: Vec<Sample> = values_sorted
+            .iter()
+            .map(|v| Sample::new(v + epoch))
+            .collect();
+
+        let base = estimate_offset(
+            &unweighted(&values_sorted),
+            &values_sorted,
+            0.0,
+            PlottingPosition::Hazen,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        let fit = estimate_offset(
+            &shifted,
+            &values_sorted,
+            0.0,
+            PlottingPosition::Hazen,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        assert!(
+            (fit.offset - epoch - base.offset).abs() < 1e-2,
+            "Offset {} not shifted by the epoch from {}",
+            fit.offset,
+            base.offset
+        );
+        assert!((fit.std_error - base.std_error).abs() < 1e-3);
+    }
+
     #[test]
     fn test_estimate() {
         let alpha = 4.0;
@@ -288,4 +1490,819 @@ mod tests {
             "Mean offset {offset:} does not match expected value"
         );
     }
+
+    #[test]
+    fn test_estimate_with_drops_non_finite() {
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 7);
+        let mut corrupted = values.clone();
+        corrupted.insert(10, f64::NAN);
+        corrupted.push(f64::INFINITY);
+        let config = EstimatorConfig {
+            seed: Some(7),
+            non_finite: crate::NonFinitePolicy::Drop,
+            ..Default::default()
+        };
+
+        let result = estimate_with(corrupted, &config).unwrap();
+        assert_eq!(result.non_finite, 2);
+        assert_eq!(result.samples, values.len());
+        assert_eq!(result.offset, estimate(values, Some(7)));
+    }
+
+    #[test]
+    fn test_estimate_with_shift_is_translation_invariant() {
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 3);
+        let ahead: Vec<f64> = values.iter().map(|v| v - 1000.0).collect();
+        let further_ahead: Vec<f64> = values.iter().map(|v| v - 1500.0).collect();
+        let config = EstimatorConfig {
+            seed: Some(3),
+            negative: crate::NegativePolicy::Shift,
+            ..Default::default()
+        };
+
+        let near = estimate_with(ahead, &config).unwrap();
+        let far = estimate_with(further_ahead, &config).unwrap();
+        assert!(near.shift > 0.0);
+        assert!(
+            (near.offset - far.offset - 500.0).abs() < 1e-6,
+            "Offsets {} and {} not translated back",
+            near.offset,
+            far.offset
+        );
+    }
+
+    #[test]
+    fn test_estimate_with_insufficient_samples() {
+        let config = EstimatorConfig::default();
+        assert_eq!(
+            estimate_with([1.0, 2.0, 3.0], &config),
+            Err(EstimateError::InsufficientSamples { got: 3, need: 10 })
+        );
+
+        let config = EstimatorConfig {
+            min_samples: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            estimate_with([1.0], &config),
+            Err(EstimateError::InsufficientSamples { got: 1, need: 2 })
+        );
+        assert!(estimate([1.0], None).is_nan());
+        assert!(estimate([3.0, 1.0, 4.0, 1.5, 5.0], None).is_finite());
+    }
+
+    #[test]
+    fn test_estimate_with_trim_ignores_congestion_outliers() {
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 11);
+        let mut congested = values.clone();
+        congested.extend(core::iter::repeat_n(1.0e6, 20));
+        let config = EstimatorConfig {
+            seed: Some(11),
+            tails: crate::TailPolicy::Trim {
+                lower: 0.0,
+                upper: 0.02,
+            },
+            ..Default::default()
+        };
+
+        let clean = estimate_with(values, &config).unwrap();
+        let trimmed = estimate_with(congested, &config).unwrap();
+        assert_eq!(trimmed.trimmed, 20);
+        assert!(
+            (trimmed.offset - clean.offset).abs() < 50.0,
+            "Trimmed offset {} too far from {}",
+            trimmed.offset,
+            clean.offset
+        );
+    }
+
+    #[test]
+    fn test_estimate_weighted() {
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 5);
+        let config = EstimatorConfig {
+            seed: Some(5),
+            ..Default::default()
+        };
+
+        let unit = estimate_weighted(values.iter().map(|&v| (v, 1.0)), &config).unwrap();
+        assert_eq!(
+            unit.offset,
+            estimate_with(values.clone(), &config).unwrap().offset
+        );
+
+        // Unreliable samples far out in the tail barely move a weighted estimate.
+        let noisy = values
+            .iter()
+            .map(|&v| (v, 1.0))
+            .chain(core::iter::repeat_n((5000.0, 1e-6), 50));
+        let weighted = estimate_weighted(noisy, &config).unwrap();
+        assert!(
+            (weighted.offset - unit.offset).abs() < 50.0,
+            "Weighted offset {} too far from {}",
+            weighted.offset,
+            unit.offset
+        );
+    }
+
+    #[test]
+    fn test_estimate_samples_recency_decay() {
+        // The path's delay floor moved by 500 halfway through the capture.
+        let old = generate_random_gamma_values(4.0, 100.0, 500, 21);
+        let new = generate_random_gamma_values(4.0, 100.0, 500, 22);
+        let samples = old
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| Sample::new(v).at(i as f64))
+            .chain(
+                new.iter()
+                    .enumerate()
+                    .map(|(i, &v)| Sample::new(v + 500.0).at((500 + i) as f64)),
+            );
+        let config = EstimatorConfig {
+            seed: Some(21),
+            half_life: Some(50.0),
+            ..Default::default()
+        };
+
+        let decayed = estimate_samples(samples, &config).unwrap();
+        let recent = estimate_with(new.iter().map(|v| v + 500.0), &config).unwrap();
+        assert!(
+            (decayed.offset - recent.offset).abs() < 100.0,
+            "Decayed offset {} does not track recent offset {}",
+            decayed.offset,
+            recent.offset
+        );
+    }
+
+    #[test]
+    fn test_estimate_uncertainty_shrinks_with_sample_count() {
+        let config = EstimatorConfig {
+            seed: Some(9),
+            ..Default::default()
+        };
+        let small = estimate_with(generate_random_gamma_values(4.0, 100.0, 100, 9), &config);
+        let large = estimate_with(generate_random_gamma_values(4.0, 100.0, 10000, 9), &config);
+        let (small, large) = (small.unwrap(), large.unwrap());
+
+        assert!(small.uncertainty.is_finite() && small.uncertainty > 0.0);
+        assert!(
+            large.uncertainty < small.uncertainty,
+            "Uncertainty {} not below {}",
+            large.uncertainty,
+            small.uncertainty
+        );
+    }
+
+    #[test]
+    fn test_estimate_precise_matches_naive_on_small_batches() {
+        let values = generate_random_gamma_values(4.0, 100.0, 1000, 5);
+        let naive = EstimatorConfig {
+            seed: Some(5),
+            ..Default::default()
+        };
+        let precise = EstimatorConfig {
+            precise: true,
+            ..naive.clone()
+        };
+        let naive = estimate_with(values.iter().copied(), &naive).unwrap();
+        let precise = estimate_with(values.iter().copied(), &precise).unwrap();
+
+        assert!((naive.offset - precise.offset).abs() < 1e-6);
+        assert!((naive.uncertainty - precise.uncertainty).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_estimate_deming() {
+        let values: Vec<f64> = generate_random_gamma_values(4.0, 10.0, 1000, 13)
+            .into_iter()
+            .map(|v| v + 500.0)
+            .collect();
+        let config = |regression| EstimatorConfig {
+            regression,
+            synthetic: SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let deming = Regression::Deming {
+            variance_ratio: 1.0,
+        };
+        let ols = estimate_with(values.iter().copied(), &config(Regression::LeastSquares)).unwrap();
+        let orthogonal = estimate_with(values.iter().copied(), &config(deming)).unwrap();
+        assert!(
+            (orthogonal.offset - 500.0).abs() < 5.0,
+            "{}",
+            orthogonal.offset
+        );
+        assert!((orthogonal.offset - ols.offset).abs() < 1.0);
+        assert_ne!(orthogonal.offset, ols.offset);
+
+        let invalid = Regression::Deming {
+            variance_ratio: f64::NAN,
+        };
+        assert_eq!(
+            estimate_with(values, &config(invalid)),
+            Err(EstimateError::InvalidConfig {
+                field: "variance_ratio"
+            })
+        );
+    }
+
+    #[test]
+    fn test_estimate_ticks() {
+        // A 40-unit timer, comparable to the offset of 30 and the scale of the delays.
+        let config = EstimatorConfig::default();
+        let (mut rounded, mut ticked, mut dithered) = (0.0, 0.0, 0.0);
+        for seed in 0..5 {
+            let values = generate_random_gamma_values(4.0, 10.0, 300, seed);
+            let exact = estimate_with(values.iter().map(|v| v + 30.0), &config).unwrap();
+            let ticks: Vec<i64> = values
+                .iter()
+                .map(|v| libm::round((v + 30.0) / 40.0) as i64)
+                .collect();
+            let readings = ticks.iter().map(|&t| t as f64 * 40.0);
+            rounded += estimate_with(readings, &config).unwrap().offset - exact.offset;
+            let estimate = estimate_ticks(ticks.iter().copied(), 40.0, &config).unwrap();
+            ticked += estimate.offset - exact.offset;
+            let dither = EstimatorConfig {
+                quantization: QuantizationNoise::Dither,
+                ..config.clone()
+            };
+            dithered += estimate_ticks(ticks, 40.0, &dither).unwrap().offset - exact.offset;
+        }
+        assert!(rounded / 5.0 < -10.0, "{rounded}");
+        assert!(libm::fabs(ticked / 5.0) < 4.0, "{ticked}");
+        // Dither breaks up the staircase in one fit, within half a tick of the delay floor.
+        assert!(libm::fabs(dithered / 5.0) < 20.0, "{dithered}");
+        assert_eq!(
+            estimate_ticks([1, 2, 3], 0.0, &config),
+            Err(EstimateError::InvalidConfig {
+                field: "resolution"
+            })
+        );
+    }
+
+    #[test]
+    fn test_estimate_robust_regressions() {
+        let mut values: Vec<f64> = generate_random_gamma_values(4.0, 10.0, 200, 13)
+            .into_iter()
+            .map(|v| v + 500.0)
+            .collect();
+        let config = |regression| EstimatorConfig {
+            regression,
+            synthetic: SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let shift = |values: &[f64], regression| {
+            let estimate = |values: &[f64]| {
+                estimate_with(values.iter().copied(), &config(regression))
+                    .unwrap()
+                    .offset
+            };
+            (estimate(values) - estimate(&values[..200])).abs()
+        };
+        values.extend([5000.0; 4]);
+        let robust = shift(&values, Regression::PassingBablok);
+        let siegel = shift(&values, Regression::RepeatedMedian);
+        let trimmed = shift(&values, Regression::TrimmedSquares { coverage: 0.9 });
+        let ols = shift(&values, Regression::LeastSquares);
+        assert!(
+            robust < 25.0 && siegel < 25.0 && trimmed < 25.0 && ols > 100.0,
+            "{robust} {siegel} {trimmed} {ols}"
+        );
+    }
+
+    #[test]
+    fn test_p_value() {
+        let values = generate_random_gamma_values(2.0, 10.0, 100, 17);
+        let estimate =
+            estimate_with(values.iter().map(|v| v + 1000.0), &Default::default()).unwrap();
+        let t = estimate.t_statistic(1000.0);
+        assert_eq!(t, (estimate.offset - 1000.0) / estimate.uncertainty);
+        assert_eq!(estimate.p_value(estimate.offset), 1.0);
+        assert!(estimate.p_value(0.0) < 1e-12);
+        let p = estimate.p_value(estimate.offset + 2.0 * estimate.uncertainty);
+        assert!((p - 0.048).abs() < 0.001, "{p}");
+        assert!(Estimate::from_offset(1.0, 1.0).p_value(0.0).is_nan());
+    }
+
+    #[test]
+    fn test_regression_design() {
+        let values = generate_random_gamma_values(4.0, 100.0, 200, 31);
+        let design = |values: &[f64]| {
+            estimate_with(values.iter().copied(), &EstimatorConfig::default())
+                .unwrap()
+                .design
+                .unwrap()
+        };
+        let clean = design(&values);
+        assert!(clean.spread > 100.0 && clean.spread < 400.0, "{clean:?}");
+        assert!(clean.max_leverage > 2.0 / 200.0 && clean.max_leverage < 0.1);
+        assert!(clean.extrapolation > 1.0);
+
+        // A far outlier dominates the line.
+        let mut outlier = values.clone();
+        outlier[0] = 1e5;
+        assert!(design(&outlier).max_leverage > 0.5);
+    }
+
+    #[test]
+    fn test_estimate_degenerate_samples_do_not_panic() {
+        // Zero mean and variance make the Gamma scale NaN, and so every synthetic value; tied
+        // samples share a single regression abscissa.
+        for value in [0.0, 5.0, 1.7e12] {
+            assert_eq!(
+                estimate_with([value; 20], &EstimatorConfig::default()),
+                Err(EstimateError::DegenerateRegression)
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_quantiles_is_seed_independent() {
+        let values = generate_random_gamma_values(4.0, 100.0, 500, 17);
+        let config = |seed| EstimatorConfig {
+            seed: Some(seed),
+            synthetic: SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let first = estimate_with(values.iter().copied(), &config(1)).unwrap();
+        let second = estimate_with(values.iter().copied(), &config(2)).unwrap();
+
+        assert_eq!(first.offset, second.offset);
+        assert!(first.offset.abs() < 50.0, "Offset {}", first.offset);
+    }
+
+    #[test]
+    fn test_estimate_monte_carlo_error() {
+        let values = generate_random_gamma_values(4.0, 100.0, 200, 31);
+        let config = EstimatorConfig {
+            seed: Some(31),
+            repetitions: 20,
+            ..Default::default()
+        };
+        let result = estimate_with(values.iter().copied(), &config).unwrap();
+        let error = result.monte_carlo_error.unwrap();
+        assert!(
+            error.is_finite() && error > 0.0,
+            "Monte Carlo error {error}"
+        );
+
+        // The single-run offset is unchanged by asking for the error.
+        let single = EstimatorConfig {
+            repetitions: 0,
+            ..config
+        };
+        let plain = estimate_with(values.iter().copied(), &single).unwrap();
+        assert_eq!(plain.offset, result.offset);
+        assert_eq!(plain.monte_carlo_error, None);
+    }
+
+    #[test]
+    fn test_estimate_jackknife_variance() {
+        let config = EstimatorConfig {
+            seed: Some(3),
+            synthetic: SyntheticSample::Quantiles,
+            jackknife: true,
+            ..Default::default()
+        };
+        let values = generate_random_gamma_values(4.0, 100.0, 30, 3);
+        let result = estimate_with(values.iter().copied(), &config).unwrap();
+        let variance = result.jackknife_variance.unwrap();
+        assert!(
+            variance.is_finite() && variance > 0.0,
+            "Variance {variance}"
+        );
+
+        // A gross outlier makes the offset far more sensitive to individual samples.
+        let mut noisy = values.clone();
+        noisy[0] = 5000.0;
+        let noisy = estimate_with(noisy, &config).unwrap();
+        assert!(noisy.jackknife_variance.unwrap() > variance);
+    }
+
+    #[test]
+    fn test_estimate_offset_midranks_ties() {
+        // Millisecond timer: every delay is one of a few distinct values.
+        let tied: Vec<f64> = [3.0, 4.0, 4.0, 5.0, 5.0, 5.0, 7.0, 7.0, 9.0]
+            .iter()
+            .flat_map(|&v| [v, v])
+            .collect();
+        let grouped: Vec<Sample> = [(3.0, 2.0), (4.0, 4.0), (5.0, 6.0), (7.0, 4.0), (9.0, 2.0)]
+            .iter()
+            .map(|&(v, w)| Sample::weighted(v, w))
+            .collect();
+        let mut y = alloc::vec![0.0; tied.len()];
+        let solver = SolverOptions::default();
+        fill_gamma_quantiles(2.0, 1.0, PlottingPosition::Hazen, &solver, &mut y).unwrap();
+
+        // Tied samples behave like a single sample carrying their combined weight.
+        let ties = estimate_offset(
+            &unweighted(&tied),
+            &y,
+            0.0,
+            PlottingPosition::Hazen,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        let groups = estimate_offset(
+            &grouped,
+            &y,
+            0.0,
+            PlottingPosition::Hazen,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        assert!((ties.offset - groups.offset).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_estimate_plotting_positions() {
+        let values = generate_random_gamma_values(2.0, 10.0, 15, 5);
+        let offset = |plotting| {
+            let config = EstimatorConfig {
+                synthetic: SyntheticSample::Quantiles,
+                plotting,
+                ..Default::default()
+            };
+            estimate_with(values.iter().copied(), &config)
+                .unwrap()
+                .offset
+        };
+        let weibull = offset(PlottingPosition::Weibull);
+        let blom = offset(PlottingPosition::Blom);
+        let hazen = offset(PlottingPosition::Hazen);
+        assert!(weibull.is_finite() && blom.is_finite() && hazen.is_finite());
+        assert!(weibull != blom && blom != hazen);
+
+        // Ranks are in units of the mean weight, so scaling all weights changes nothing.
+        let mut sorted = values.clone();
+        sort_values(&mut sorted);
+        let mut y = alloc::vec![0.0; sorted.len()];
+        let solver = SolverOptions::default();
+        fill_gamma_quantiles(2.0, 10.0, PlottingPosition::Blom, &solver, &mut y).unwrap();
+        let doubled: Vec<Sample> = sorted.iter().map(|&v| Sample::weighted(v, 2.0)).collect();
+        let unit = estimate_offset(
+            &unweighted(&sorted),
+            &y,
+            0.0,
+            PlottingPosition::Blom,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        let scaled = estimate_offset(
+            &doubled,
+            &y,
+            0.0,
+            PlottingPosition::Blom,
+            Regression::LeastSquares,
+            false,
+        )
+        .unwrap();
+        assert!((unit.offset - scaled.offset).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimate_fitted_distribution() {
+        let values: Vec<f64> = generate_random_gamma_values(4.0, 10.0, 1000, 21)
+            .into_iter()
+            .map(|v| v + 50.0)
+            .collect();
+        let result = estimate_with(values.iter().copied(), &EstimatorConfig::default()).unwrap();
+        assert_eq!(result.cdf(result.offset), 0.0);
+        assert_eq!(result.pdf(result.offset - 1.0), 0.0);
+
+        // Exceedance probabilities follow the measured ones.
+        for threshold in [80.0, 100.0, 130.0] {
+            let measured = values.iter().filter(|&&v| v > threshold).count() as f64 / 1000.0;
+            let fitted = 1.0 - result.cdf(threshold);
+            assert!((fitted - measured).abs() < 0.05, "{fitted} vs {measured}");
+        }
+
+        // The density integrates to the distribution function.
+        let (a, b) = (70.0, 110.0);
+        let steps = 400;
+        let h = (b - a) / steps as f64;
+        let integral: f64 = (0..steps)
+            .map(|i| h * result.pdf(a + (i as f64 + 0.5) * h))
+            .sum();
+        assert!((integral - (result.cdf(b) - result.cdf(a))).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_estimate_fitted_distribution_in_seconds() {
+        let owds = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+        let result = estimate_with(owds, &EstimatorConfig::default()).unwrap();
+        assert!(result.scale > 0.0, "Scale {}", result.scale);
+        let mut distinct = owds.to_vec();
+        distinct.sort_by(f64::total_cmp);
+        distinct.dedup();
+        let cdf: Vec<f64> = distinct.iter().map(|&x| result.cdf(x)).collect();
+        assert!(cdf.windows(2).all(|w| w[0] < w[1]), "{cdf:?}");
+        assert!(cdf[0] < 0.1 && cdf[cdf.len() - 1] > 0.9, "{cdf:?}");
+        assert!(owds.iter().all(|&x| result.pdf(x).is_finite()));
+    }
+
+    #[test]
+    fn test_estimate_tails_keep_censored() {
+        let mut samples: Vec<Sample> = generate_random_gamma_values(2.0, 5.0, 90, 31)
+            .into_iter()
+            .map(|v| Sample::new(50.0 + v))
+            .collect();
+        samples.extend((0..10).map(|_| Sample::timed_out(200.0)));
+        for tails in [
+            TailPolicy::Trim {
+                lower: 0.0,
+                upper: 0.1,
+            },
+            TailPolicy::Winsorize {
+                lower: 0.0,
+                upper: 0.1,
+            },
+        ] {
+            let config = EstimatorConfig {
+                tails,
+                ..Default::default()
+            };
+            let result = estimate_samples(samples.iter().copied(), &config).unwrap();
+            assert_eq!(result.censored, 10);
+            assert_eq!(result.trimmed + result.winsorized, 9);
+        }
+    }
+
+    #[test]
+    fn test_estimate_solver_limits() {
+        let values = generate_random_gamma_values(2.0, 10.0, 50, 6);
+        let bounded = |synthetic, max_iterations| EstimatorConfig {
+            synthetic,
+            solver: SolverOptions {
+                max_iterations,
+                tolerance: 1e-12,
+            },
+            ..Default::default()
+        };
+        // The sampler falls back to quantiles rather than failing.
+        let random = estimate_with(values.iter().copied(), &bounded(SyntheticSample::Random, 0));
+        assert_eq!(random.unwrap().sampler_fallbacks, values.len());
+        let result = estimate_with(
+            values.iter().copied(),
+            &bounded(SyntheticSample::Quantiles, 0),
+        );
+        assert!(matches!(result, Err(EstimateError::NotConverged { .. })));
+        let quantiles = bounded(SyntheticSample::Quantiles, 1);
+        assert_eq!(
+            estimate_with(values.iter().copied(), &quantiles),
+            Err(EstimateError::NotConverged { solver: "quantile" })
+        );
+
+        let mut samples: Vec<Sample> = values.iter().copied().map(Sample::new).collect();
+        samples.push(Sample::timed_out(20.0));
+        assert_eq!(
+            estimate_samples(
+                samples.iter().copied(),
+                &bounded(SyntheticSample::Random, 2)
+            ),
+            Err(EstimateError::NotConverged { solver: "censored" })
+        );
+        assert!(estimate_samples(samples, &EstimatorConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_censored_samples() {
+        // Probes time out 15 units after the offset of 20; about one in ten is lost.
+        let timeout = 35.0;
+        let values: Vec<f64> = generate_random_gamma_values(2.0, 4.0, 2000, 17)
+            .into_iter()
+            .map(|delay| 20.0 + delay)
+            .collect();
+        let samples: Vec<Sample> = values
+            .iter()
+            .map(|&value| match value > timeout {
+                true => Sample::timed_out(timeout),
+                false => Sample::new(value),
+            })
+            .collect();
+        let observed: Vec<Sample> = samples.iter().filter(|s| !s.censored).copied().collect();
+        let lost = samples.len() - observed.len();
+        assert!(lost > 0);
+
+        // Dropping the timeouts understates the delay spread; imputing them recovers some of it,
+        // and brings the offset close to what the complete measurements give.
+        let config = EstimatorConfig::default();
+        let complete = estimate_with(values.iter().copied(), &config).unwrap();
+        let dropped = estimate_samples(observed.iter().copied(), &config).unwrap();
+        let censored = estimate_samples(samples.iter().copied(), &config).unwrap();
+        assert_eq!(censored.censored, lost);
+        assert_eq!(censored.samples, samples.len());
+        assert!(
+            (censored.offset - complete.offset).abs() < (dropped.offset - complete.offset).abs(),
+            "Offsets {} {} {}",
+            complete.offset,
+            dropped.offset,
+            censored.offset
+        );
+
+        let scale = |samples: &[Sample]| fit_gamma(samples, &EstimatorConfig::default()).unwrap().1;
+        assert!(scale(&observed) < scale(&samples));
+
+        // The imputation stops once the token is cancelled.
+        let cancelled = EstimatorConfig {
+            cancel: Some(crate::CancelToken::new()),
+            ..Default::default()
+        };
+        cancelled.cancel.as_ref().unwrap().cancel();
+        assert_eq!(
+            fit_gamma(&samples, &cancelled),
+            Err(EstimateError::Cancelled)
+        );
+        assert!(fit_gamma(&observed, &cancelled).is_ok());
+    }
+
+    #[test]
+    fn test_try_estimate_samples() {
+        let values = generate_random_gamma_values(2.0, 4.0, 200, 3);
+        let config = EstimatorConfig::default();
+        let samples = || values.iter().map(|&v| Sample::new(v));
+        assert_eq!(
+            try_estimate_samples(samples(), &config),
+            estimate_samples(samples(), &config)
+        );
+
+        // An input claiming more samples than the address space holds cannot be buffered.
+        struct Endless;
+        impl Iterator for Endless {
+            type Item = Sample;
+            fn next(&mut self) -> Option<Sample> {
+                Some(Sample::new(1.0))
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (usize::MAX, None)
+            }
+        }
+        assert_eq!(
+            try_estimate_samples(Endless, &config),
+            Err(EstimateError::OutOfMemory {
+                samples: usize::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_estimate_fast_mode() {
+        let values: Vec<f64> = generate_random_gamma_values(2.0, 4.0, 20_000, 9)
+            .into_iter()
+            .map(|delay| 50.0 + delay)
+            .collect();
+        let full = estimate_with(values.iter().copied(), &EstimatorConfig::default()).unwrap();
+        for subsampling in [Subsampling::Stride, Subsampling::Reservoir] {
+            let config = EstimatorConfig {
+                fast: Some(FastMode {
+                    max_samples: 1000,
+                    subsampling,
+                }),
+                ..Default::default()
+            };
+            let fast = estimate_with(values.iter().copied(), &config).unwrap();
+            assert_eq!(fast.samples, 1000);
+            assert_eq!(fast.subsampled, 19_000);
+            assert!(
+                (fast.offset - full.offset).abs() < 1.0,
+                "{subsampling:?} offset {} against {}",
+                fast.offset,
+                full.offset
+            );
+        }
+
+        let config = EstimatorConfig {
+            fast: Some(FastMode {
+                max_samples: 1,
+                subsampling: Subsampling::Stride,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            estimate_with(values, &config),
+            Err(EstimateError::InvalidConfig { field: "fast" })
+        );
+    }
+
+    #[test]
+    fn test_estimate_coarse_window() {
+        // One probe in twenty is held up by a retransmission timeout.
+        let values: Vec<f64> = generate_random_gamma_values(2.0, 4.0, 2000, 21)
+            .into_iter()
+            .enumerate()
+            .map(|(i, delay)| 50.0 + delay + if i % 20 == 0 { 1000.0 } else { 0.0 })
+            .collect();
+        let plain = estimate_with(values.iter().copied(), &EstimatorConfig::default()).unwrap();
+        for center in [CoarseCenter::Median, CoarseCenter::Minimum] {
+            let config = EstimatorConfig {
+                coarse: Some(CoarseWindow { center, width: 4.0 }),
+                ..Default::default()
+            };
+            let windowed = estimate_with(values.iter().copied(), &config).unwrap();
+            assert!(windowed.outside_window >= 100, "{center:?} {windowed:?}");
+            assert!(
+                (windowed.offset - 50.0).abs() < (plain.offset - 50.0).abs(),
+                "{center:?} offset {} against {}",
+                windowed.offset,
+                plain.offset
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_pairs() {
+        // The receiver clock is 100 ahead of the sender clock.
+        let delays = generate_random_gamma_values(3.0, 5.0, 200, 24);
+        let pairs = delays.iter().enumerate().map(|(i, delay)| {
+            let sent = 1e4 * i as f64;
+            crate::TimestampPair::new(sent, sent + 20.0 + delay + 100.0)
+        });
+        let config = EstimatorConfig::default();
+        let estimate = estimate_pairs(pairs, &config).unwrap();
+        let differenced = estimate_with(delays.iter().map(|d| 120.0 + d), &config).unwrap();
+        assert!((estimate.offset - differenced.offset).abs() < 1e-9);
+        assert!((estimate.offset - 120.0).abs() < 2.0, "{estimate:?}");
+    }
+
+    #[test]
+    fn test_estimate_leap_filter() {
+        // Delays in ms, timestamps in s: the remote clock applies the leap second at 500 ten
+        // seconds late.
+        let samples: Vec<Sample> = generate_random_gamma_values(2.0, 4.0, 1000, 23)
+            .into_iter()
+            .enumerate()
+            .map(|(i, delay)| {
+                let late = if (500..510).contains(&i) {
+                    -1000.0
+                } else {
+                    0.0
+                };
+                Sample::new(50.0 + delay + late).at(i as f64)
+            })
+            .collect();
+        let plain = estimate_samples(samples.iter().copied(), &EstimatorConfig::default());
+        assert!(plain.unwrap().offset < 0.0);
+        let unaffected = samples.iter().filter(|s| s.value > 0.0).copied();
+        let clean = estimate_samples(unaffected, &EstimatorConfig::default()).unwrap();
+        fn tai_utc(utc: f64) -> f64 {
+            if utc < 500.0 {
+                36.0
+            } else {
+                37.0
+            }
+        }
+        for leaps in [LeapSeconds::Table(&[500.0]), LeapSeconds::Offset(tai_utc)] {
+            let filter = LeapFilter {
+                leaps,
+                window: 20.0,
+            };
+            assert!(filter.spans_leap(480.0) && !filter.spans_leap(520.0));
+            let config = EstimatorConfig {
+                leap: Some(filter),
+                ..Default::default()
+            };
+            let estimate = estimate_samples(samples.iter().copied(), &config).unwrap();
+            assert_eq!(estimate.leap_spanning, 40);
+            assert!((estimate.offset - clean.offset).abs() < 1.0, "{estimate:?}");
+        }
+    }
+
+    #[test]
+    fn test_estimate_detrend() {
+        // The remote clock gains 0.05 per probe interval over the capture, so the offset at the
+        // newest probe is 50 + 0.05 * 999.
+        let delays = generate_random_gamma_values(2.0, 4.0, 1000, 22);
+        let samples = || {
+            delays
+                .iter()
+                .enumerate()
+                .map(|(i, delay)| Sample::new(50.0 + 0.05 * i as f64 + delay).at(i as f64))
+        };
+        let plain = estimate_samples(samples(), &EstimatorConfig::default()).unwrap();
+        assert_eq!(plain.drift, None);
+        let config = EstimatorConfig {
+            detrend: true,
+            ..Default::default()
+        };
+        let detrended = estimate_samples(samples(), &config).unwrap();
+        let drift = detrended.drift.unwrap();
+        assert!((drift - 0.05).abs() < 1e-3, "drift {drift}");
+        let drift_uncertainty = detrended.drift_uncertainty.unwrap();
+        assert!(drift_uncertainty > 0.0 && drift_uncertainty < 1e-3);
+        assert!((drift - 0.05).abs() < 4.0 * drift_uncertainty);
+        // The same delays without drift, as seen at the newest probe.
+        let still = delays.iter().map(|delay| 50.0 + 0.05 * 999.0 + delay);
+        let truth = estimate_with(still, &EstimatorConfig::default()).unwrap();
+        assert!(
+            (detrended.offset - truth.offset).abs() < 0.5,
+            "offset {} against {}",
+            detrended.offset,
+            truth.offset
+        );
+        // Without detrending the scale absorbs the drift.
+        assert!(detrended.scale < plain.scale);
+    }
 }
@@ -0,0 +1,489 @@
+use alloc::collections::VecDeque;
+
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::ewma::{EwmaChart, EwmaConfig, Health};
+use crate::math;
+use crate::offset_estimator::{estimate_samples, seed, Estimate, LcgRng};
+use crate::ring::SampleRing;
+use crate::sample::Sample;
+
+/// Largest window allocated up front; bigger windows grow as samples arrive.
+const PREALLOCATED: usize = 4096;
+
+/// Probabilities of the charted samples are kept this far from 0 and 1, so that a sample
+/// outside the support of the model charts as a six-sigma residual rather than an infinite one.
+const PROBABILITY_FLOOR: f64 = 1e-9;
+
+/// Sample an [`OnlineEstimator`] drops when a sample arrives at a full window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eviction {
+    /// The oldest one, keeping the most recent samples.
+    #[default]
+    Oldest,
+    /// The one whose neighbours in time are closest to each other, thinning the window evenly
+    /// over the whole stream while keeping its first and latest samples. Without timestamps on
+    /// the samples, the oldest one.
+    ///
+    /// Each push into a full window scans the window for the closest neighbours and shifts the
+    /// samples after the evicted one, costing `O(n)` in the capacity `n`, so a stream of `m`
+    /// samples costs `O(m n)`; prefer [`Oldest`](Self::Oldest) or [`Reservoir`](Self::Reservoir)
+    /// for windows of many thousands of samples at high rates.
+    Stratified,
+    /// A uniformly random one, or the newcomer, keeping a uniformly random subset of every sample
+    /// pushed, drawn with [`EstimatorConfig::seed`].
+    ///
+    /// J. S. Vitter. "Random Sampling with a Reservoir". ACM Transactions on Mathematical
+    /// Software, Vol. 11, No. 1 (1985), pp. 37-57.
+    Reservoir,
+}
+
+/// Storage of the window of an [`OnlineEstimator`], in arrival order: a `VecDeque` growing up to
+/// the capacity of the estimator, or a [`SampleRing`] in fixed memory.
+pub trait SampleStore {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The `index`-th oldest sample.
+    fn get(&self, index: usize) -> Option<&Sample>;
+
+    /// Adds `sample` as the newest one; the estimator only does so below its capacity.
+    fn push_back(&mut self, sample: Sample);
+
+    /// Removes the `index`-th oldest sample.
+    fn remove(&mut self, index: usize) -> Option<Sample>;
+
+    /// Replaces the `index`-th oldest sample by `sample`.
+    fn replace(&mut self, index: usize, sample: Sample) -> Option<Sample>;
+
+    fn clear(&mut self);
+}
+
+impl SampleStore for VecDeque<Sample> {
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&Sample> {
+        VecDeque::get(self, index)
+    }
+
+    fn push_back(&mut self, sample: Sample) {
+        VecDeque::push_back(self, sample);
+    }
+
+    fn remove(&mut self, index: usize) -> Option<Sample> {
+        VecDeque::remove(self, index)
+    }
+
+    fn replace(&mut self, index: usize, sample: Sample) -> Option<Sample> {
+        self.get_mut(index)
+            .map(|slot| core::mem::replace(slot, sample))
+    }
+
+    fn clear(&mut self) {
+        VecDeque::clear(self);
+    }
+}
+
+impl<const N: usize> SampleStore for SampleRing<N> {
+    fn len(&self) -> usize {
+        SampleRing::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<&Sample> {
+        SampleRing::get(self, index)
+    }
+
+    fn push_back(&mut self, sample: Sample) {
+        self.push(sample);
+    }
+
+    fn remove(&mut self, index: usize) -> Option<Sample> {
+        SampleRing::remove(self, index)
+    }
+
+    fn replace(&mut self, index: usize, sample: Sample) -> Option<Sample> {
+        SampleRing::replace(self, index, sample)
+    }
+
+    fn clear(&mut self) {
+        SampleRing::clear(self);
+    }
+}
+
+/// Estimator over a sliding window of the most recent samples.
+///
+/// ```
+/// use gamlr::{EstimatorConfig, OnlineEstimator};
+///
+/// let mut online = OnlineEstimator::new(EstimatorConfig::default(), 1000);
+/// for owd in [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36] {
+///     online.push(owd);
+/// }
+/// assert!(online.estimate().is_ok());
+/// ```
+///
+/// With a [chart](Self::with_chart), the samples pushed after a [refresh](Self::refresh) are
+/// scored against its estimate, as the normal quantiles of their probabilities under the fitted
+/// model, and the [health](Self::health) tells when these residuals have stopped looking like
+/// draws from it:
+///
+/// ```
+/// use gamlr::{EstimatorConfig, EwmaConfig, Health, OnlineEstimator};
+///
+/// let mut online = OnlineEstimator::new(EstimatorConfig::default(), 1000)
+///     .with_chart(EwmaConfig::default())
+///     .unwrap();
+/// # let owds = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+/// for owd in owds {
+///     online.push(owd);
+/// }
+/// online.refresh().unwrap();
+/// for owd in owds {
+///     online.push(owd);
+/// }
+/// assert!(matches!(online.health(), Health::InControl { .. }));
+/// // The path changed: every delay is 100 ms longer.
+/// for owd in owds {
+///     online.push(owd + 0.1);
+/// }
+/// assert!(matches!(online.health(), Health::OutOfControl { .. }));
+/// ```
+///
+/// The window is bounded by a number of samples, or by a [memory budget](Self::from_budget) for
+/// services ingesting unbounded streams, and the [eviction](Self::with_eviction) policy picks
+/// the samples it drops. The window is a `VecDeque` by default, or a [`SampleRing`] of a size
+/// fixed at build time with [`in_ring`](OnlineEstimator::in_ring).
+#[derive(Debug, Clone)]
+pub struct OnlineEstimator<S = VecDeque<Sample>> {
+    config: EstimatorConfig,
+    window: S,
+    capacity: usize,
+    eviction: Eviction,
+    /// Samples pushed since the last clear, for the reservoir.
+    pushed: u64,
+    rng: LcgRng,
+    chart: Option<EwmaChart>,
+    /// Estimate of the last refresh, the model the chart scores the samples against.
+    model: Option<Estimate>,
+}
+
+impl OnlineEstimator {
+    /// Creates an estimator keeping at most `capacity` samples; older samples are evicted first.
+    pub fn new(config: EstimatorConfig, capacity: usize) -> Self {
+        // Bounded so that a huge capacity does not abort on allocation up front.
+        let window = VecDeque::with_capacity(capacity.min(PREALLOCATED));
+        Self::with_window(config, window, capacity)
+    }
+
+    /// Creates an estimator whose window takes at most `bytes` of memory.
+    pub fn from_budget(config: EstimatorConfig, bytes: usize) -> Self {
+        Self::new(config, bytes / core::mem::size_of::<Sample>())
+    }
+}
+
+impl<const N: usize> OnlineEstimator<SampleRing<N>> {
+    /// Creates an estimator keeping at most `N` samples in a [`SampleRing`], which allocates
+    /// nothing as samples arrive.
+    ///
+    /// ```
+    /// use gamlr::{EstimatorConfig, OnlineEstimator};
+    ///
+    /// let mut online = OnlineEstimator::<gamlr::SampleRing<8>>::in_ring(EstimatorConfig {
+    ///     min_samples: 8,
+    ///     ..Default::default()
+    /// });
+    /// for owd in [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36] {
+    ///     online.push(owd);
+    /// }
+    /// assert_eq!(online.len(), 8);
+    /// assert!(online.estimate().is_ok());
+    /// ```
+    pub fn in_ring(config: EstimatorConfig) -> Self {
+        Self::with_window(config, SampleRing::new(), N)
+    }
+}
+
+impl<S: SampleStore> OnlineEstimator<S> {
+    fn with_window(config: EstimatorConfig, window: S, capacity: usize) -> Self {
+        OnlineEstimator {
+            rng: LcgRng::new(seed(&config)),
+            config,
+            window,
+            capacity,
+            eviction: Eviction::Oldest,
+            pushed: 0,
+            chart: None,
+            model: None,
+        }
+    }
+
+    /// Drops the samples of a full window according to `eviction` rather than oldest first.
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = eviction;
+        self
+    }
+
+    /// Charts the samples against the model of the last [refresh](Self::refresh), see
+    /// [`EwmaChart`]. Fails like [`EwmaChart::new`]. A model without a finite, positive shape and
+    /// scale scores no sample, leaving the health as it was.
+    pub fn with_chart(mut self, config: EwmaConfig) -> Result<Self, EstimateError> {
+        self.chart = Some(EwmaChart::new(config)?);
+        Ok(self)
+    }
+
+    /// Adds a sample to the window, evicting one according to the [`Eviction`] policy when the
+    /// window is full.
+    pub fn push(&mut self, sample: impl Into<Sample>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let sample = sample.into();
+        self.pushed += 1;
+        if let (Some(chart), Some(model)) = (&mut self.chart, &self.model) {
+            let scored = |v: f64| v.is_finite() && v > 0.0;
+            if !sample.censored && scored(model.shape) && scored(model.scale) {
+                let probability = model
+                    .cdf(sample.value)
+                    .clamp(PROBABILITY_FLOOR, 1.0 - PROBABILITY_FLOOR);
+                chart.update(math::normal_quantile(probability));
+            }
+        }
+        if self.window.len() < self.capacity {
+            self.window.push_back(sample);
+            return;
+        }
+        match self.eviction {
+            Eviction::Oldest => {
+                self.window.remove(0);
+                self.window.push_back(sample);
+            }
+            Eviction::Stratified => {
+                let crowded = self.crowded(&sample);
+                self.window.remove(crowded);
+                self.window.push_back(sample);
+            }
+            Eviction::Reservoir => {
+                // Algorithm R: the n-th sample replaces a random slot with probability
+                // capacity / n.
+                let n = self.pushed as f64;
+                let slot = self.rng.gen_range(0.0..n) as usize;
+                self.window.replace(slot, sample);
+            }
+        }
+    }
+
+    /// Position of the sample of the window, `incoming` coming after the last one, whose
+    /// neighbours have the closest timestamps, the first if any sample has none.
+    fn crowded(&self, incoming: &Sample) -> usize {
+        let len = self.window.len();
+        let time = |i: usize| match i == len {
+            true => incoming.timestamp,
+            false => self.window.get(i).and_then(|s| s.timestamp),
+        };
+        let mut crowded = (0, f64::INFINITY);
+        for i in 1..len {
+            match (time(i - 1), time(i + 1)) {
+                (Some(before), Some(after)) if after - before < crowded.1 => {
+                    crowded = (i, after - before);
+                }
+                (Some(_), Some(_)) => {}
+                _ => return 0,
+            }
+        }
+        crowded.0
+    }
+
+    /// Estimates the offset from the samples currently in the window.
+    pub fn estimate(&self) -> Result<Estimate, EstimateError> {
+        let samples = (0..self.window.len()).filter_map(|i| self.window.get(i));
+        estimate_samples(samples.copied(), &self.config)
+    }
+
+    /// [`estimate`](Self::estimate), which then becomes the model the chart scores the samples
+    /// pushed next against. The chart carries on across refreshes, so that a model refitted to
+    /// a window straddling a path change is still flagged.
+    pub fn refresh(&mut self) -> Result<Estimate, EstimateError> {
+        let estimate = self.estimate()?;
+        self.model = Some(estimate.clone());
+        Ok(estimate)
+    }
+
+    /// Health of the model according to the chart, [`Health::Unknown`] without a chart or
+    /// before the first sample scored.
+    pub fn health(&self) -> Health {
+        self.chart
+            .as_ref()
+            .map_or(Health::Unknown, EwmaChart::health)
+    }
+
+    pub fn config(&self) -> &EstimatorConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// Empties the window and forgets the model and the residuals charted.
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.pushed = 0;
+        self.model = None;
+        if let Some(chart) = &mut self.chart {
+            chart.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_online_estimator_window() {
+        let mut online = OnlineEstimator::new(EstimatorConfig::default(), 3);
+        for value in 0..5 {
+            online.push(f64::from(value));
+        }
+        assert_eq!(online.len(), 3);
+        let values: alloc::vec::Vec<f64> = online.window.iter().map(|s| s.value).collect();
+        assert_eq!(values, alloc::vec![2.0, 3.0, 4.0]);
+        assert_eq!(
+            online.estimate(),
+            Err(EstimateError::InsufficientSamples { got: 3, need: 10 })
+        );
+    }
+
+    #[test]
+    fn test_online_estimator_eviction() {
+        let config = EstimatorConfig::default();
+        let size = core::mem::size_of::<Sample>();
+        let timestamps = |online: &OnlineEstimator| -> alloc::vec::Vec<f64> {
+            online.window.iter().filter_map(|s| s.timestamp).collect()
+        };
+        let mut stratified = OnlineEstimator::from_budget(config.clone(), 11 * size + size / 2)
+            .with_eviction(Eviction::Stratified);
+        let mut reservoir = OnlineEstimator::new(config, 100).with_eviction(Eviction::Reservoir);
+        for t in 0..1001 {
+            stratified.push(Sample::new(1.0).at(f64::from(t)));
+            reservoir.push(Sample::new(1.0).at(f64::from(t)));
+        }
+        // Spread over the whole stream rather than its last 11 samples.
+        let kept = timestamps(&stratified);
+        assert_eq!((kept.len(), kept[0], kept[10]), (11, 0.0, 1000.0));
+        assert!(kept.windows(2).all(|pair| pair[1] - pair[0] <= 200.0));
+        let kept = timestamps(&reservoir);
+        let mean = kept.iter().sum::<f64>() / kept.len() as f64;
+        assert!(
+            kept.len() == 100 && (400.0..600.0).contains(&mean),
+            "{mean}"
+        );
+    }
+
+    #[test]
+    fn test_online_estimator_in_ring() {
+        let mut rng = crate::offset_estimator::LcgRng::new(12);
+        let samples: alloc::vec::Vec<Sample> = (0..500)
+            .map(|i| {
+                let delay = -5.0 * crate::float::ln(1.0 - rng.gen_range(0.0..1.0));
+                Sample::new(100.0 + delay).at(f64::from(i) + rng.gen_range(0.0..0.5))
+            })
+            .collect();
+        for eviction in [Eviction::Oldest, Eviction::Stratified, Eviction::Reservoir] {
+            let config = EstimatorConfig::default();
+            let mut deque = OnlineEstimator::new(config.clone(), 64).with_eviction(eviction);
+            let mut ring =
+                OnlineEstimator::<SampleRing<64>>::in_ring(config).with_eviction(eviction);
+            for &sample in &samples {
+                deque.push(sample);
+                ring.push(sample);
+            }
+            assert_eq!(ring.len(), 64);
+            let window: alloc::vec::Vec<Sample> = ring.window.iter().copied().collect();
+            assert!(window.iter().eq(deque.window.iter()), "{eviction:?}");
+            assert_eq!(ring.estimate(), deque.estimate());
+            // The sorted copy of the ring follows evictions from anywhere in the window.
+            let mut sorted = window;
+            crate::sample::sort_samples(&mut sorted);
+            assert_eq!(ring.window.sorted(), &sorted[..]);
+        }
+
+        let ring = OnlineEstimator::<SampleRing<16>>::in_ring(EstimatorConfig::default());
+        let mut cached = crate::CachedEstimator::new(ring, Default::default());
+        samples[..20].iter().for_each(|&s| cached.push(s));
+        assert!(cached.estimate(core::time::Duration::ZERO).is_ok());
+        assert_eq!(cached.online().len(), 16);
+    }
+
+    #[test]
+    fn test_online_estimator_health() {
+        let mut rng = crate::offset_estimator::LcgRng::new(3);
+        let mut erlang = move |offset: f64| {
+            let mut exponential = || -crate::float::ln(1.0 - rng.gen_range(0.0..1.0));
+            offset + 5.0 * (exponential() + exponential())
+        };
+        let mut online = OnlineEstimator::new(EstimatorConfig::default(), 400)
+            .with_chart(EwmaConfig::default())
+            .unwrap();
+        for _ in 0..200 {
+            online.push(erlang(100.0));
+        }
+        assert_eq!(online.health(), Health::Unknown);
+        online.refresh().unwrap();
+        for _ in 0..200 {
+            online.push(erlang(100.0));
+            assert!(matches!(online.health(), Health::InControl { .. }));
+        }
+        // A path change of one delay scale is flagged within a few dozen samples.
+        online.refresh().unwrap();
+        assert!((0..50).any(|_| {
+            online.push(erlang(105.0));
+            matches!(online.health(), Health::OutOfControl { .. })
+        }));
+        online.clear();
+        assert_eq!(online.health(), Health::Unknown);
+    }
+
+    #[test]
+    fn test_online_estimator_health_in_seconds() {
+        let owds = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+        let mut online = OnlineEstimator::new(EstimatorConfig::default(), 400)
+            .with_chart(EwmaConfig::default())
+            .unwrap();
+        for owd in owds {
+            online.push(owd);
+        }
+        let model = online.refresh().unwrap();
+        // Draws from the fitted model itself.
+        let mut rng = crate::offset_estimator::LcgRng::new(8);
+        let mut draw = move || {
+            let p = rng.gen_range(0.0..1.0);
+            model.offset + model.scale * math::gamma_quantile(model.shape, p)
+        };
+        for _ in 0..40 {
+            online.push(draw());
+            assert!(matches!(online.health(), Health::InControl { .. }));
+        }
+
+        // A model without a usable scale scores nothing.
+        online.clear();
+        for owd in owds {
+            online.push(owd);
+        }
+        online.refresh().unwrap();
+        if let Some(model) = &mut online.model {
+            model.scale = -0.157;
+        }
+        online.push(draw());
+        assert_eq!(online.health(), Health::Unknown);
+    }
+}
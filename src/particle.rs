@@ -0,0 +1,335 @@
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+use crate::offset_estimator::LcgRng;
+use crate::sample::Sample;
+
+/// Parameters of a [`ParticleFilter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackerConfig {
+    /// Shape of the Gamma delay distribution of the path.
+    pub shape: f64,
+    /// Scale of the Gamma delay distribution of the path, in the unit of the samples.
+    pub scale: f64,
+    /// Standard deviation of the offset random walk over one unit of time, on top of the drift.
+    pub offset_noise: f64,
+    /// Standard deviation of the drift random walk over one unit of time.
+    pub drift_noise: f64,
+    /// Standard deviation of the drift assumed before the first sample.
+    pub initial_drift: f64,
+    /// Seed of the filter's random number generator.
+    pub seed: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            shape: 2.0,
+            scale: 1.0,
+            offset_noise: 1e-3,
+            drift_noise: 1e-6,
+            initial_drift: 1e-4,
+            seed: 0,
+        }
+    }
+}
+
+/// Tracked clock state, see [`ParticleFilter::track`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Track {
+    /// Posterior mean of the offset at `time`.
+    pub offset: f64,
+    /// Posterior mean of the drift, in offset units per unit of time.
+    pub drift: f64,
+    /// Posterior standard deviation of the offset.
+    pub uncertainty: f64,
+    /// Timestamp of the latest sample.
+    pub time: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Particle {
+    offset: f64,
+    drift: f64,
+    log_weight: f64,
+}
+
+const EMPTY: Particle = Particle {
+    offset: 0.0,
+    drift: 0.0,
+    log_weight: 0.0,
+};
+
+/// Tracks the clock offset and drift through a stream of timestamped one-way delay samples with
+/// `N` particles, without allocating.
+///
+/// Each sample is modeled as `offset + drift * t + delay` with a Gamma distributed delay, so the
+/// filter copes with the heavily skewed delays a Kalman filter mismodels. When no particle
+/// explains a sample any more, e.g. after a route change or a clock step, the particles are
+/// spread out again around it instead of diverging. Particles are resampled systematically
+/// whenever their effective number drops below `N / 2`.
+///
+/// ```
+/// use gamlr::{ParticleFilter, Sample, TrackerConfig};
+///
+/// let mut filter = ParticleFilter::<256>::new(TrackerConfig::default());
+/// for t in 0..100 {
+///     let owd = 5.0 + [1.2, 2.5, 1.8, 3.1][t % 4];
+///     filter.update(Sample::new(owd).at(t as f64)).unwrap();
+/// }
+/// assert!((filter.track().unwrap().offset - 5.0).abs() < 1.5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParticleFilter<const N: usize> {
+    config: TrackerConfig,
+    particles: [Particle; N],
+    rng: LcgRng,
+    time: Option<f64>,
+    updates: usize,
+}
+
+impl<const N: usize> ParticleFilter<N> {
+    pub fn new(config: TrackerConfig) -> Self {
+        ParticleFilter {
+            rng: LcgRng::new(config.seed),
+            config,
+            particles: [EMPTY; N],
+            time: None,
+            updates: 0,
+        }
+    }
+
+    /// Incorporates one sample, which must carry a timestamp no older than the previous one.
+    ///
+    /// Fails with [`EstimateError::InvalidTimestamp`] on a missing, non-finite or decreasing
+    /// timestamp, [`EstimateError::NonFiniteSample`] on a non-finite value, and
+    /// [`EstimateError::InvalidConfig`] naming `shape` or `scale` unless both are finite and
+    /// positive. The index in the error counts the samples passed to the filter.
+    ///
+    /// A [censored](Sample::censored) sample weighs the particles by the probability of a delay
+    /// beyond its timeout; it is ignored until a first uncensored sample has placed them.
+    pub fn update(&mut self, sample: Sample) -> Result<(), EstimateError> {
+        let index = self.updates;
+        let TrackerConfig { shape, scale, .. } = self.config;
+        if !(shape.is_finite() && shape > 0.0) {
+            return Err(EstimateError::InvalidConfig { field: "shape" });
+        }
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(EstimateError::InvalidConfig { field: "scale" });
+        }
+        let Some(time) = sample.timestamp.filter(|t| t.is_finite()) else {
+            return Err(EstimateError::InvalidTimestamp { index });
+        };
+        if self.time.is_some_and(|previous| time < previous) {
+            return Err(EstimateError::InvalidTimestamp { index });
+        }
+        if !sample.value.is_finite() {
+            return Err(EstimateError::NonFiniteSample { index });
+        }
+
+        self.updates += 1;
+        match self.time {
+            Some(previous) => self.predict(time - previous),
+            None if sample.censored => return Ok(()),
+            None => self.scatter(sample.value),
+        }
+        self.time = Some(time);
+
+        if sample.censored {
+            let log_survival =
+                |offset: f64| float::ln(math::gamma_sf(shape, scale, sample.value - offset));
+            // A timeout no particle explains carries no information on where to look instead.
+            if self
+                .particles
+                .iter()
+                .any(|p| log_survival(p.offset).is_finite())
+            {
+                for particle in self.particles.iter_mut() {
+                    particle.log_weight += log_survival(particle.offset);
+                }
+                self.normalize();
+            }
+        } else {
+            for particle in self.particles.iter_mut() {
+                particle.log_weight +=
+                    math::gamma_ln_pdf(shape, scale, sample.value - particle.offset);
+            }
+        }
+        if !sample.censored && !self.normalize() {
+            // No particle explains the sample: the delay model or the clock has changed.
+            self.scatter(sample.value);
+            for particle in self.particles.iter_mut() {
+                particle.log_weight =
+                    math::gamma_ln_pdf(shape, scale, sample.value - particle.offset);
+            }
+            self.normalize();
+        }
+        if self.effective_size() < 0.5 * N as f64 {
+            self.resample();
+        }
+        Ok(())
+    }
+
+    /// Current posterior summary, `None` before the first sample.
+    pub fn track(&self) -> Option<Track> {
+        let time = self.time?;
+        let mut offset = 0.0;
+        let mut drift = 0.0;
+        for particle in &self.particles {
+            let weight = float::exp(particle.log_weight);
+            offset += weight * particle.offset;
+            drift += weight * particle.drift;
+        }
+        let variance = self
+            .particles
+            .iter()
+            .map(|p| float::exp(p.log_weight) * float::pow(p.offset - offset, 2.0))
+            .sum::<f64>();
+        Some(Track {
+            offset,
+            drift,
+            uncertainty: float::sqrt(variance),
+            time,
+        })
+    }
+
+    pub fn config(&self) -> &TrackerConfig {
+        &self.config
+    }
+
+    /// Spreads the particles over the offsets that make `value` a plausible delay, with equal
+    /// weights.
+    fn scatter(&mut self, value: f64) {
+        let TrackerConfig {
+            shape,
+            scale,
+            initial_drift,
+            ..
+        } = self.config;
+        let weight = -float::ln(N as f64);
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            let p = (i as f64 + 0.5) / N as f64;
+            *particle = Particle {
+                offset: value - scale * math::gamma_quantile(shape, p),
+                drift: initial_drift * self.rng.marsaglia_polar_sample(),
+                log_weight: weight,
+            };
+        }
+    }
+
+    /// Moves every particle `dt` units of time forward.
+    fn predict(&mut self, dt: f64) {
+        let spread = float::sqrt(dt);
+        for particle in self.particles.iter_mut() {
+            particle.drift += self.config.drift_noise * spread * self.rng.marsaglia_polar_sample();
+            particle.offset += particle.drift * dt
+                + self.config.offset_noise * spread * self.rng.marsaglia_polar_sample();
+        }
+    }
+
+    /// Makes the weights sum to one, returning false if they are all zero.
+    fn normalize(&mut self) -> bool {
+        let peak = self
+            .particles
+            .iter()
+            .map(|p| p.log_weight)
+            .fold(f64::NEG_INFINITY, f64::max);
+        if !peak.is_finite() {
+            return false;
+        }
+        let total = self
+            .particles
+            .iter()
+            .map(|p| float::exp(p.log_weight - peak))
+            .sum::<f64>();
+        let shift = peak + float::ln(total);
+        for particle in self.particles.iter_mut() {
+            particle.log_weight -= shift;
+        }
+        true
+    }
+
+    fn effective_size(&self) -> f64 {
+        let sum_sq = self
+            .particles
+            .iter()
+            .map(|p| float::exp(2.0 * p.log_weight))
+            .sum::<f64>();
+        1.0 / sum_sq
+    }
+
+    /// Systematic resampling: one uniform draw places `N` equally spaced pointers on the
+    /// cumulative weights.
+    fn resample(&mut self) {
+        let start = self.rng.gen_range(0.0..1.0) / N as f64;
+        let weight = -float::ln(N as f64);
+        let mut resampled = [EMPTY; N];
+        let mut cumulative = 0.0;
+        let mut source = 0;
+        for (i, slot) in resampled.iter_mut().enumerate() {
+            let pointer = start + i as f64 / N as f64;
+            while source < N - 1
+                && cumulative + float::exp(self.particles[source].log_weight) < pointer
+            {
+                cumulative += float::exp(self.particles[source].log_weight);
+                source += 1;
+            }
+            *slot = Particle {
+                log_weight: weight,
+                ..self.particles[source]
+            };
+        }
+        self.particles = resampled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_particle_filter_tracks_offset_and_drift() {
+        let mut rng = LcgRng::new(5);
+        let mut exponential = move || -float::ln(1.0 - rng.gen_range(0.0..1.0));
+        let config = TrackerConfig {
+            initial_drift: 1e-2,
+            drift_noise: 1e-5,
+            ..Default::default()
+        };
+        let mut filter = ParticleFilter::<512>::new(config);
+        let drift = 4e-3;
+        for t in 0..2000 {
+            let t = f64::from(t);
+            let owd = 100.0 + drift * t + exponential() + exponential();
+            filter.update(Sample::new(owd).at(t)).unwrap();
+        }
+        let track = filter.track().unwrap();
+        let truth = 100.0 + drift * 1999.0;
+        assert!(
+            (track.offset - truth).abs() < 0.5,
+            "Offset {}",
+            track.offset
+        );
+        assert!((track.drift - drift).abs() < 1e-3, "Drift {}", track.drift);
+    }
+
+    #[test]
+    fn test_particle_filter_recovers_from_step() {
+        let mut filter = ParticleFilter::<128>::new(TrackerConfig::default());
+        for t in 0..50 {
+            let owd = 10.0 + [1.0, 2.0, 1.5, 3.0][t % 4];
+            filter.update(Sample::new(owd).at(t as f64)).unwrap();
+        }
+        // The remote clock steps back by 500: every sample now falls below all particles.
+        for t in 50..100 {
+            let owd = -490.0 + [1.0, 2.0, 1.5, 3.0][t % 4];
+            filter.update(Sample::new(owd).at(t as f64)).unwrap();
+        }
+        let offset = filter.track().unwrap().offset;
+        assert!((offset + 490.0).abs() < 2.0, "Offset {offset}");
+        assert_eq!(
+            filter.update(Sample::new(1.0).at(10.0)),
+            Err(EstimateError::InvalidTimestamp { index: 100 })
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use core::time::Duration;
+
+use crate::sample::{nanos_sample, Sample};
+
+/// A clock the measurement helpers read the local receive time from, such as the PTP hardware
+/// clock (PHC) of a NIC, which timestamps packets in hardware, or the system clock.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::HardwareClock;
+///
+/// struct Fixed;
+///
+/// impl HardwareClock for Fixed {
+///     type Error = ();
+///     fn now(&self) -> Result<Duration, ()> {
+///         Ok(Duration::from_micros(1500))
+///     }
+/// }
+///
+/// let sample = Fixed.receive(Duration::from_micros(1000)).unwrap();
+/// assert_eq!(sample.value, 500_000.0);
+/// ```
+pub trait HardwareClock {
+    type Error;
+
+    /// Current time of the clock since its epoch, the Unix epoch for PHCs disciplined to TAI or
+    /// UTC.
+    fn now(&self) -> Result<Duration, Self::Error>;
+
+    /// Builds the sample of a probe stamped `sent` by the remote clock and received now, in
+    /// nanoseconds like `timestamping::owd_sample`. The sample is
+    /// timestamped with the local receive time.
+    fn receive(&self, sent: Duration) -> Result<Sample, Self::Error> {
+        Ok(nanos_sample(sent, self.now()?))
+    }
+}
+
+/// A clock that cannot fail to read, such as `performance.now()` in a browser, stamping both the
+/// send and the receive times of the probes of a measurement helper, so that they share one
+/// unit and epoch.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::ClockSource;
+///
+/// struct Fixed;
+///
+/// impl ClockSource for Fixed {
+///     fn now(&self) -> Duration {
+///         Duration::from_micros(1500)
+///     }
+/// }
+///
+/// assert_eq!(Fixed.receive(Duration::from_micros(1000)).value, 500_000.0);
+/// ```
+pub trait ClockSource {
+    /// Current time of the clock since its epoch.
+    fn now(&self) -> Duration;
+
+    /// Builds the sample of a probe stamped `sent` and received now, in nanoseconds like
+    /// [`HardwareClock::receive`].
+    fn receive(&self, sent: Duration) -> Sample {
+        nanos_sample(sent, self.now())
+    }
+}
+
+/// The system clock, `CLOCK_REALTIME`, as a [`HardwareClock`], for hosts without a PHC.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl HardwareClock for SystemClock {
+    type Error = std::time::SystemTimeError;
+
+    fn now(&self) -> Result<Duration, Self::Error> {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+    }
+}
+
+/// A Linux PTP hardware clock, a `/dev/ptpN` character device, read through its dynamic POSIX
+/// clock.
+#[cfg(all(feature = "linux", target_os = "linux"))]
+#[derive(Debug)]
+pub struct PtpClock {
+    device: std::fs::File,
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+impl PtpClock {
+    /// Opens the PHC at `path`, e.g. `/dev/ptp0`; `ethtool -T` names the PHC of an interface.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(PtpClock {
+            device: std::fs::File::open(path)?,
+        })
+    }
+
+    /// The dynamic clock id of the open device, `FD_TO_CLOCKID` of the kernel headers.
+    fn clock_id(&self) -> libc::clockid_t {
+        use std::os::fd::AsRawFd;
+        ((!self.device.as_raw_fd()) << 3) | 3
+    }
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+impl HardwareClock for PtpClock {
+    type Error = std::io::Error;
+
+    fn now(&self) -> Result<Duration, Self::Error> {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` outlives the call, which only writes to it.
+        if unsafe { libc::clock_gettime(self.clock_id(), &mut ts) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let secs = u64::try_from(ts.tv_sec).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        let nanos = u32::try_from(ts.tv_nsec).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A clock advancing by 250 µs on every read.
+    struct MockClock(Cell<Duration>);
+
+    impl HardwareClock for MockClock {
+        type Error = ();
+
+        fn now(&self) -> Result<Duration, ()> {
+            self.0.set(self.0.get() + Duration::from_micros(250));
+            Ok(self.0.get())
+        }
+    }
+
+    /// A clock advancing by `step` on every read, without failing.
+    struct Steady {
+        now: Cell<Duration>,
+        step: Duration,
+    }
+
+    impl ClockSource for Steady {
+        fn now(&self) -> Duration {
+            self.now.set(self.now.get() + self.step);
+            self.now.get()
+        }
+    }
+
+    #[test]
+    fn test_clock_source_receive() {
+        let clock = Steady {
+            now: Cell::new(Duration::from_secs(1)),
+            step: Duration::from_micros(250),
+        };
+        // Both ends of a loopback probe stamped by the same clock, one read apart.
+        let sent = clock.now();
+        let sample = clock.receive(sent);
+        assert_eq!(sample.value, 250_000.0);
+        assert_eq!(sample.timestamp, Some(1_000_500_000.0));
+    }
+
+    #[test]
+    fn test_hardware_clock_receive() {
+        let clock = MockClock(Cell::new(Duration::ZERO));
+        let sample = clock.receive(Duration::from_micros(100)).unwrap();
+        assert_eq!(sample.value, 150_000.0);
+        assert_eq!(sample.timestamp, Some(250_000.0));
+        // A remote clock ahead of the local one yields a negative delay.
+        assert_eq!(
+            clock.receive(Duration::from_millis(1)).unwrap().value,
+            -500_000.0
+        );
+
+        #[cfg(feature = "std")]
+        assert!(SystemClock.now().unwrap() > Duration::from_secs(1_600_000_000));
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        assert!(PtpClock::open("/dev/ptp-does-not-exist").is_err());
+    }
+}
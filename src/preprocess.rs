@@ -0,0 +1,671 @@
+use crate::config::{
+    CoarseCenter, CoarseWindow, FastMode, LeapFilter, NegativePolicy, NonFinitePolicy, Subsampling,
+    TailPolicy,
+};
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+use crate::offset_estimator::LcgRng;
+use crate::sample::{order, sort_samples, Sample, SampleBuffer};
+
+/// Scales the weight of every timestamped sample by `0.5^(age / half_life)`, where the age is
+/// measured from the newest timestamp in the batch.
+pub(crate) fn apply_recency(samples: &mut [Sample], half_life: f64) -> Result<(), EstimateError> {
+    if !(half_life.is_finite() && half_life > 0.0) {
+        return Err(EstimateError::InvalidConfig { field: "half_life" });
+    }
+    if let Some(index) = samples
+        .iter()
+        .position(|s| s.timestamp.is_some_and(|t| !t.is_finite()))
+    {
+        return Err(EstimateError::InvalidTimestamp { index });
+    }
+    let Some(newest) = samples.iter().filter_map(|s| s.timestamp).reduce(f64::max) else {
+        return Ok(());
+    };
+    for sample in samples.iter_mut() {
+        if let Some(t) = sample.timestamp {
+            sample.weight *= libm::exp2(-(newest - t) / half_life);
+        }
+    }
+    Ok(())
+}
+
+/// Drops the samples timestamped around a leap second of `filter`, returning how many.
+///
+/// Fails with [`EstimateError::InvalidConfig`] naming `leap` when the window is negative or not
+/// finite.
+pub(crate) fn exclude_leaps(
+    samples: &mut impl SampleBuffer,
+    filter: &LeapFilter,
+) -> Result<usize, EstimateError> {
+    if !(filter.window.is_finite() && filter.window >= 0.0) {
+        return Err(EstimateError::InvalidConfig { field: "leap" });
+    }
+    let before = samples.len();
+    samples.retain_samples(|s| !s.timestamp.is_some_and(|t| filter.spans_leap(t)));
+    Ok(before - samples.len())
+}
+
+/// Spreads the uncensored samples sharing a value over the interval of one `resolution` centered
+/// on it, each at the midpoint of its weight within the group, so that the quantized values fill
+/// their intervals the way the delays they stand for do.
+///
+/// Returns the smallest of the values, `None` without uncensored samples. Fails with
+/// [`EstimateError::InvalidConfig`] naming `resolution` when it is not positive and finite.
+pub(crate) fn dequantize(
+    samples: &mut [Sample],
+    resolution: f64,
+) -> Result<Option<f64>, EstimateError> {
+    check_resolution(resolution)?;
+    sort_samples(samples);
+    let observed = samples.iter().take_while(|s| !s.censored).count();
+    let smallest = samples[..observed].first().map(|s| s.value);
+    let mut start = 0;
+    while start < observed {
+        let value = samples[start].value;
+        let tied = samples[start..observed]
+            .iter()
+            .take_while(|s| s.value == value)
+            .count();
+        let group = &mut samples[start..start + tied];
+        let total: f64 = group.iter().map(|s| s.weight).sum();
+        let mut below = 0.0;
+        for sample in group.iter_mut() {
+            let position = match total > 0.0 {
+                true => (below + 0.5 * sample.weight) / total,
+                false => 0.5,
+            };
+            below += sample.weight;
+            sample.value = value + resolution * (position - 0.5);
+        }
+        start += tied;
+    }
+    Ok(smallest)
+}
+
+/// Adds uniform noise over the interval of one `resolution` centered on zero to every uncensored
+/// sample, drawn from a generator seeded with `seed`.
+///
+/// Fails like [`dequantize`].
+pub(crate) fn dither(
+    samples: &mut [Sample],
+    resolution: f64,
+    seed: u64,
+) -> Result<(), EstimateError> {
+    check_resolution(resolution)?;
+    let mut rng = LcgRng::new(seed);
+    for sample in samples.iter_mut().filter(|s| !s.censored) {
+        sample.value += resolution * (rng.gen_range(0.0..1.0) - 0.5);
+    }
+    Ok(())
+}
+
+fn check_resolution(resolution: f64) -> Result<(), EstimateError> {
+    match resolution.is_finite() && resolution > 0.0 {
+        true => Ok(()),
+        false => Err(EstimateError::InvalidConfig {
+            field: "resolution",
+        }),
+    }
+}
+
+/// Removes the weighted least-squares linear trend of the uncensored timestamped samples from
+/// every timestamped sample, timeout thresholds included, so that the residuals are the delays
+/// the newest timestamp would have seen. Samples without timestamp are left as they are.
+///
+/// Returns the slope removed, in sample units per timestamp unit, and its standard error; zero
+/// and infinity when fewer than two distinct timestamps are observed. The standard error is also
+/// infinite with only two observed points, which the line fits exactly.
+pub(crate) fn detrend(samples: &mut [Sample], precise: bool) -> Result<(f64, f64), EstimateError> {
+    if let Some(index) = samples
+        .iter()
+        .position(|s| s.timestamp.is_some_and(|t| !t.is_finite()))
+    {
+        return Err(EstimateError::InvalidTimestamp { index });
+    }
+    let Some(newest) = samples.iter().filter_map(|s| s.timestamp).reduce(f64::max) else {
+        return Ok((0.0, f64::INFINITY));
+    };
+    // Ages relative to the newest timestamp keep epoch-scale timestamps well conditioned.
+    let points = || {
+        samples
+            .iter()
+            .filter(|s| !s.censored)
+            .filter_map(|s| s.timestamp.map(|t| (t - newest, s.value, s.weight)))
+    };
+    let w_sum = math::sum(points().map(|(_, _, w)| w), precise);
+    let t_mean = math::sum(points().map(|(t, _, w)| w * t), precise) / w_sum;
+    let v_mean = math::sum(points().map(|(_, v, w)| w * v), precise) / w_sum;
+    let stt = math::sum(
+        points().map(|(t, _, w)| w * (t - t_mean) * (t - t_mean)),
+        precise,
+    );
+    let stv = math::sum(
+        points().map(|(t, v, w)| w * (t - t_mean) * (v - v_mean)),
+        precise,
+    );
+    let slope = stv / stt;
+    if !slope.is_finite() {
+        return Ok((0.0, f64::INFINITY));
+    }
+    // Sandwich variance of the weighted slope, reducing to `s² / Stt` for unit weights.
+    let residual = |(t, v, _): (f64, f64, f64)| v - v_mean - slope * (t - t_mean);
+    let m = points().count() as f64;
+    if m < 3.0 {
+        return Ok(remove_trend(samples, slope, newest, f64::INFINITY));
+    }
+    let residual_var =
+        math::sum(points().map(|p| p.2 * residual(p) * residual(p)), precise) / w_sum * m
+            / (m - 2.0);
+    let leverage = math::sum(
+        points().map(|(t, _, w)| w * w * (t - t_mean) * (t - t_mean)),
+        precise,
+    );
+    let std_error = float::sqrt(residual_var * leverage) / stt;
+    Ok(remove_trend(samples, slope, newest, std_error))
+}
+
+/// Subtracts `slope` times the age before `newest` from every timestamped sample, passing the
+/// slope and its `std_error` through.
+fn remove_trend(samples: &mut [Sample], slope: f64, newest: f64, std_error: f64) -> (f64, f64) {
+    for sample in samples.iter_mut() {
+        if let Some(t) = sample.timestamp {
+            sample.value -= slope * (t - newest);
+        }
+    }
+    (slope, std_error)
+}
+
+/// Fails on non-finite values and on negative or non-finite weights, for the routines that take
+/// samples as they are instead of preprocessing them.
+pub(crate) fn validate(samples: &[Sample]) -> Result<(), EstimateError> {
+    if let Some(index) = samples.iter().position(|s| !s.value.is_finite()) {
+        return Err(EstimateError::NonFiniteSample { index });
+    }
+    if let Some(index) = samples
+        .iter()
+        .position(|s| !s.weight.is_finite() || s.weight < 0.0)
+    {
+        return Err(EstimateError::InvalidWeight { index });
+    }
+    Ok(())
+}
+
+/// Fails on negative or non-finite weights and drops samples with zero weight,
+/// which carry no information.
+pub(crate) fn filter_weights(samples: &mut impl SampleBuffer) -> Result<(), EstimateError> {
+    if let Some(index) = samples
+        .iter()
+        .position(|s| !s.weight.is_finite() || s.weight < 0.0)
+    {
+        return Err(EstimateError::InvalidWeight { index });
+    }
+    samples.retain_samples(|s| s.weight > 0.0);
+    Ok(())
+}
+
+/// Applies the non-finite `policy` to `samples` in place.
+///
+/// Returns the number of samples that were dropped or replaced.
+pub(crate) fn filter_non_finite(
+    samples: &mut impl SampleBuffer,
+    policy: NonFinitePolicy,
+) -> Result<usize, EstimateError> {
+    match policy {
+        NonFinitePolicy::Reject => match samples.iter().position(|s| !s.value.is_finite()) {
+            Some(index) => Err(EstimateError::NonFiniteSample { index }),
+            None => Ok(0),
+        },
+        NonFinitePolicy::Drop => {
+            let before = samples.len();
+            samples.retain_samples(|s| s.value.is_finite());
+            Ok(before - samples.len())
+        }
+        NonFinitePolicy::Replace(replacement) => {
+            let mut replaced = 0;
+            for s in samples.iter_mut().filter(|s| !s.value.is_finite()) {
+                s.value = replacement;
+                replaced += 1;
+            }
+            Ok(replaced)
+        }
+    }
+}
+
+/// Reduces `samples` to at most [`FastMode::max_samples`], drawing reservoir subsets with
+/// `seed`.
+///
+/// Returns the number of samples left out.
+pub(crate) fn subsample(
+    samples: &mut impl SampleBuffer,
+    mode: FastMode,
+    seed: u64,
+) -> Result<usize, EstimateError> {
+    let FastMode {
+        max_samples,
+        subsampling,
+    } = mode;
+    if max_samples < 2 {
+        return Err(EstimateError::InvalidConfig { field: "fast" });
+    }
+    let n = samples.len();
+    if n <= max_samples {
+        return Ok(0);
+    }
+    match subsampling {
+        Subsampling::Stride => {
+            let stride = n.div_ceil(max_samples);
+            let mut index = 0usize;
+            samples.retain_samples(|_| {
+                let keep = index.is_multiple_of(stride);
+                index += 1;
+                keep
+            });
+        }
+        Subsampling::Reservoir => {
+            // Algorithm R: the i-th sample replaces a random reservoir slot with probability
+            // max_samples / (i + 1).
+            let mut rng = LcgRng::new(seed);
+            for i in max_samples..n {
+                let slot = (rng.gen_range(0.0..(i + 1) as f64) as usize).min(i);
+                if slot < max_samples {
+                    samples.swap(slot, i);
+                }
+            }
+            samples.truncate_samples(max_samples);
+        }
+    }
+    Ok(n - samples.len())
+}
+
+/// Discards the uncensored samples outside `window`, see [`CoarseWindow`]. The quantiles are
+/// found by selection rather than sorting, in linear time.
+///
+/// Returns the number of samples discarded.
+pub(crate) fn apply_window(
+    samples: &mut impl SampleBuffer,
+    window: CoarseWindow,
+) -> Result<usize, EstimateError> {
+    if !(window.width.is_finite() && window.width > 0.0) {
+        return Err(EstimateError::InvalidConfig { field: "coarse" });
+    }
+    let observed = samples.iter().filter(|s| !s.censored).count();
+    if observed == 0 {
+        return Ok(0);
+    }
+    // Censored samples order last, so the k-th smallest sample is the k-th uncensored one.
+    let mut quantile = |fraction: f64| {
+        let index = ((observed - 1) as f64 * fraction) as usize;
+        samples.select_nth_unstable_by(index, order).1.value
+    };
+    let spread = quantile(0.75) - quantile(0.25);
+    let center = match window.center {
+        CoarseCenter::Median => quantile(0.5),
+        CoarseCenter::Minimum => quantile(0.0),
+    };
+    if spread <= 0.0 {
+        return Ok(0);
+    }
+    let reach = window.width * spread;
+    let before = samples.len();
+    samples.retain_samples(|s| s.censored || libm::fabs(s.value - center) <= reach);
+    Ok(before - samples.len())
+}
+
+/// Applies the negative `policy` to `samples` in place.
+///
+/// Returns the amount added to every sample, which has to be subtracted from the resulting offset.
+pub(crate) fn handle_negative(
+    samples: &mut [Sample],
+    policy: NegativePolicy,
+) -> Result<f64, EstimateError> {
+    let Some(index) = samples.iter().position(|s| s.value < 0.0) else {
+        return Ok(0.0);
+    };
+    match policy {
+        NegativePolicy::Allow => Ok(0.0),
+        NegativePolicy::Reject => Err(EstimateError::NegativeSample { index }),
+        NegativePolicy::Shift => {
+            let shift = -samples
+                .iter()
+                .map(|s| s.value)
+                .fold(f64::INFINITY, f64::min);
+            samples.iter_mut().for_each(|s| s.value += shift);
+            Ok(shift)
+        }
+    }
+}
+
+/// Number of samples out of `n` covered by `fraction`. Fails with
+/// [`EstimateError::InvalidConfig`] naming `fraction` unless it is in `[0, 0.5)`.
+fn tail_count(n: usize, fraction: f64) -> Result<usize, EstimateError> {
+    if !(0.0..0.5).contains(&fraction) {
+        return Err(EstimateError::InvalidConfig { field: "fraction" });
+    }
+    Ok((n as f64 * fraction) as usize)
+}
+
+/// Applies the tail `policy` to `samples`. Tail fractions count uncensored samples, not weight,
+/// and only those are trimmed or clamped; censored samples are left as they are.
+///
+/// Returns the number of samples removed and the number of samples clamped.
+pub(crate) fn handle_tails(
+    samples: &mut impl SampleBuffer,
+    policy: TailPolicy,
+) -> Result<(usize, usize), EstimateError> {
+    // Censored samples sort last, after the `observed` ones the fractions apply to.
+    let observed = samples.iter().filter(|s| !s.censored).count();
+    Ok(match policy {
+        TailPolicy::Keep => (0, 0),
+        TailPolicy::Trim { lower, upper } => {
+            let low = tail_count(observed, lower)?;
+            let high = tail_count(observed, upper)?.min(observed - low);
+            sort_samples(samples);
+            let mut index = 0;
+            samples.retain_samples(|_| {
+                index += 1;
+                index > low && (index <= observed - high || index > observed)
+            });
+            (low + high, 0)
+        }
+        TailPolicy::Winsorize { lower, upper } => {
+            let (low, high) = (tail_count(observed, lower)?, tail_count(observed, upper)?);
+            if observed == 0 {
+                return Ok((0, 0));
+            }
+            let low = low.min(observed - 1);
+            let high = high.min(observed - 1 - low);
+            sort_samples(samples);
+            let floor = samples[low].value;
+            let ceiling = samples[observed - 1 - high].value;
+            samples[..low].iter_mut().for_each(|s| s.value = floor);
+            samples[observed - high..observed]
+                .iter_mut()
+                .for_each(|s| s.value = ceiling);
+            (0, low + high)
+        }
+        TailPolicy::Grubbs { significance } => {
+            (remove_deviates(samples, significance, observed, true)?, 0)
+        }
+        TailPolicy::GeneralizedEsd {
+            max_outliers,
+            significance,
+        } => (
+            remove_deviates(samples, significance, max_outliers, false)?,
+            0,
+        ),
+    })
+}
+
+/// Discards the uncensored outliers of `samples` by extreme studentized deviates at the
+/// `significance` level: the sample farthest from the mean, in standard deviations, is tested
+/// against the two-sided Grubbs critical value and set aside, up to `max_outliers` times or
+/// until two samples remain.
+///
+/// With `sequential`, the repeated Grubbs test, the first deviate the test keeps ends the
+/// search. Otherwise, the generalized ESD test, every step is taken and the outliers are the
+/// samples set aside up to the last deviate the test rejects, so that a cluster of similar
+/// outliers, which inflates the standard deviation and masks the first ones, is still found.
+///
+/// F. E. Grubbs. "Procedures for Detecting Outlying Observations in Samples". Technometrics,
+/// Vol. 11, No. 1 (1969), pp. 1-21. B. Rosner. "Percentage Points for a Generalized ESD
+/// Many-Outlier Procedure". Technometrics, Vol. 25, No. 2 (1983), pp. 165-172.
+///
+/// Returns the number of samples discarded.
+fn remove_deviates(
+    samples: &mut impl SampleBuffer,
+    significance: f64,
+    max_outliers: usize,
+    sequential: bool,
+) -> Result<usize, EstimateError> {
+    if !(significance > 0.0 && significance < 1.0) {
+        return Err(EstimateError::InvalidConfig { field: "tails" });
+    }
+    // The candidates are then the ends of the uncensored samples, which sort first.
+    sort_samples(samples);
+    let observed = samples.iter().filter(|s| !s.censored).count();
+    let (mut low, mut high) = (0, observed);
+    let mut outliers = (low, high);
+    for _ in 0..max_outliers {
+        if high - low <= 2 {
+            break;
+        }
+        let kept = &samples[low..high];
+        let n = kept.len() as f64;
+        let mean = math::sum(kept.iter().map(|s| s.value), false) / n;
+        let variance =
+            math::sum(kept.iter().map(|s| float::pow(s.value - mean, 2.0)), false) / (n - 1.0);
+        let (below, above) = (mean - kept[0].value, kept[kept.len() - 1].value - mean);
+        let t = math::student_t_critical(significance / n, n - 2.0);
+        let critical = (n - 1.0) * float::sqrt(t * t / (n * (n - 2.0 + t * t)));
+        let outlier = below.max(above) > critical * float::sqrt(variance);
+        if sequential && !outlier {
+            break;
+        }
+        match below > above {
+            true => low += 1,
+            false => high -= 1,
+        }
+        if outlier {
+            outliers = (low, high);
+        }
+    }
+    let (low, high) = outliers;
+    let mut index = 0;
+    samples.retain_samples(|_| {
+        index += 1;
+        index > low && (index <= high || index > observed)
+    });
+    Ok(low + observed - high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn samples(values: &[f64]) -> Vec<Sample> {
+        values.iter().copied().map(Sample::new).collect()
+    }
+
+    fn values(samples: &[Sample]) -> Vec<f64> {
+        samples.iter().map(|s| s.value).collect()
+    }
+
+    #[test]
+    fn test_filter_non_finite_reject() {
+        let mut batch = samples(&[1.0, f64::NAN, 2.0]);
+        assert_eq!(
+            filter_non_finite(&mut batch, NonFinitePolicy::Reject),
+            Err(EstimateError::NonFiniteSample { index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_filter_non_finite_drop_and_replace() {
+        let mut batch = samples(&[1.0, f64::NAN, f64::INFINITY, 2.0]);
+        assert_eq!(filter_non_finite(&mut batch, NonFinitePolicy::Drop), Ok(2));
+        assert_eq!(values(&batch), alloc::vec![1.0, 2.0]);
+
+        let mut batch = samples(&[f64::NEG_INFINITY, 3.0]);
+        assert_eq!(
+            filter_non_finite(&mut batch, NonFinitePolicy::Replace(0.5)),
+            Ok(1)
+        );
+        assert_eq!(values(&batch), alloc::vec![0.5, 3.0]);
+    }
+
+    #[test]
+    fn test_handle_negative() {
+        let mut batch = samples(&[-2.0, 1.0, 3.0]);
+        assert_eq!(
+            handle_negative(&mut batch, NegativePolicy::Reject),
+            Err(EstimateError::NegativeSample { index: 0 })
+        );
+        assert_eq!(handle_negative(&mut batch, NegativePolicy::Allow), Ok(0.0));
+        assert_eq!(handle_negative(&mut batch, NegativePolicy::Shift), Ok(2.0));
+        assert_eq!(values(&batch), alloc::vec![0.0, 3.0, 5.0]);
+    }
+
+    #[test]
+    fn test_handle_tails_trim() {
+        let mut batch: Vec<Sample> = (0..20).rev().map(|i| Sample::new(f64::from(i))).collect();
+        let removed = handle_tails(
+            &mut batch,
+            TailPolicy::Trim {
+                lower: 0.05,
+                upper: 0.1,
+            },
+        );
+        assert_eq!(removed, Ok((3, 0)));
+        assert_eq!(batch.first().map(|s| s.value), Some(1.0));
+        assert_eq!(batch.last().map(|s| s.value), Some(17.0));
+
+        for (lower, upper) in [(0.5, 0.0), (0.0, -0.1), (f64::NAN, 0.1)] {
+            assert_eq!(
+                handle_tails(&mut batch, TailPolicy::Trim { lower, upper }),
+                Err(EstimateError::InvalidConfig { field: "fraction" })
+            );
+        }
+    }
+
+    #[test]
+    fn test_handle_tails_winsorize() {
+        let mut batch: Vec<Sample> = (0..10).map(|i| Sample::new(f64::from(i))).collect();
+        let policy = TailPolicy::Winsorize {
+            lower: 0.1,
+            upper: 0.2,
+        };
+        assert_eq!(handle_tails(&mut batch, policy), Ok((0, 3)));
+        assert_eq!(
+            values(&batch),
+            alloc::vec![1.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 7.0, 7.0]
+        );
+        // Timeouts neither count toward the fractions nor get clamped.
+        let mut batch: Vec<Sample> = (0..10).map(|i| Sample::new(f64::from(i))).collect();
+        batch.extend([Sample::timed_out(20.0), Sample::timed_out(30.0)]);
+        assert_eq!(handle_tails(&mut batch, policy), Ok((0, 3)));
+        assert_eq!(values(&batch[8..]), alloc::vec![7.0, 7.0, 20.0, 30.0]);
+        let trim = TailPolicy::Trim {
+            lower: 0.0,
+            upper: 0.2,
+        };
+        assert_eq!(handle_tails(&mut batch, trim), Ok((2, 0)));
+        assert_eq!(values(&batch[6..]), alloc::vec![6.0, 7.0, 20.0, 30.0]);
+
+        let mut batch = samples(&[3.0, 1.0]);
+        let policy = TailPolicy::Winsorize {
+            lower: 0.49,
+            upper: 0.49,
+        };
+        assert_eq!(handle_tails(&mut batch, policy), Ok((0, 0)));
+        let policy = TailPolicy::Winsorize {
+            lower: 0.0,
+            upper: 1.0,
+        };
+        assert_eq!(
+            handle_tails(&mut Vec::new(), policy),
+            Err(EstimateError::InvalidConfig { field: "fraction" })
+        );
+    }
+
+    #[test]
+    fn test_handle_tails_grubbs() {
+        let policy = TailPolicy::Grubbs { significance: 0.05 };
+        // Two-sided critical value 2.290 for ten samples, 2.215 for nine.
+        let mut batch = samples(&[5.0, 4.0, 6.0, 5.5, 4.5, 5.2, 4.8, 5.1, 4.9, 9.0]);
+        batch.push(Sample::timed_out(1.0));
+        assert_eq!(handle_tails(&mut batch, policy), Ok((1, 0)));
+        assert_eq!(batch.len(), 10);
+        assert!(batch.iter().all(|s| s.value < 9.0 || s.censored));
+
+        let mut clean: Vec<Sample> = (0..20).map(|i| Sample::new(f64::from(i % 7))).collect();
+        assert_eq!(handle_tails(&mut clean, policy), Ok((0, 0)));
+        assert_eq!(
+            handle_tails(&mut clean, TailPolicy::Grubbs { significance: 0.0 }),
+            Err(EstimateError::InvalidConfig { field: "tails" })
+        );
+    }
+
+    #[test]
+    fn test_handle_tails_generalized_esd() {
+        // Two spikes mask each other from the Grubbs test, but not from the ESD test.
+        let values = [5.0, 4.0, 6.0, 5.5, 4.5, 5.2, 4.8, 5.1, 4.9, 5.3, 12.0, 12.5];
+        let mut batch = samples(&values);
+        let grubbs = TailPolicy::Grubbs { significance: 0.05 };
+        assert_eq!(handle_tails(&mut batch, grubbs), Ok((0, 0)));
+        let esd = TailPolicy::GeneralizedEsd {
+            max_outliers: 3,
+            significance: 0.05,
+        };
+        assert_eq!(handle_tails(&mut batch, esd), Ok((2, 0)));
+        assert_eq!(batch.last().map(|s| s.value), Some(6.0));
+    }
+
+    #[test]
+    fn test_filter_weights() {
+        let mut weighted = alloc::vec![Sample::weighted(1.0, 2.0), Sample::weighted(2.0, -1.0)];
+        assert_eq!(
+            filter_weights(&mut weighted),
+            Err(EstimateError::InvalidWeight { index: 1 })
+        );
+
+        let mut weighted = alloc::vec![Sample::weighted(1.0, 0.0), Sample::weighted(2.0, 0.5)];
+        assert_eq!(filter_weights(&mut weighted), Ok(()));
+        assert_eq!(weighted, alloc::vec![Sample::weighted(2.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_apply_recency() {
+        let mut batch = alloc::vec![
+            Sample::new(1.0).at(0.0),
+            Sample::new(2.0).at(10.0),
+            Sample::new(3.0).with_weight(2.0).at(20.0),
+            Sample::new(4.0),
+        ];
+        assert_eq!(apply_recency(&mut batch, 10.0), Ok(()));
+        let weights: Vec<f64> = batch.iter().map(|s| s.weight).collect();
+        assert_eq!(weights, alloc::vec![0.25, 0.5, 2.0, 1.0]);
+
+        assert_eq!(
+            apply_recency(&mut batch, 0.0),
+            Err(EstimateError::InvalidConfig { field: "half_life" })
+        );
+        batch[1].timestamp = Some(f64::NAN);
+        assert_eq!(
+            apply_recency(&mut batch, 1.0),
+            Err(EstimateError::InvalidTimestamp { index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_detrend() {
+        let mut batch = alloc::vec![
+            Sample::new(1.0).at(100.0),
+            Sample::new(3.0).at(102.0),
+            Sample::new(1.5).at(101.0),
+            Sample::new(2.5).at(101.0),
+            Sample::timed_out(9.0).at(99.0),
+            Sample::new(7.0),
+        ];
+        let (slope, std_error) = detrend(&mut batch, false).unwrap();
+        assert_eq!(slope, 1.0);
+        // Residuals 0, 0, -0.5 and 0.5 around the line: s² = 0.25 over Stt = 2.
+        assert!((std_error - 0.125f64.sqrt()).abs() < 1e-12, "{std_error}");
+        let values: Vec<f64> = batch.iter().map(|s| s.value).collect();
+        assert_eq!(values, alloc::vec![3.0, 3.0, 2.5, 3.5, 12.0, 7.0]);
+
+        let mut pair = alloc::vec![Sample::new(1.0).at(5.0), Sample::new(2.0).at(6.0)];
+        assert_eq!(detrend(&mut pair, false), Ok((1.0, f64::INFINITY)));
+        assert_eq!(pair[0].value, 2.0);
+
+        let mut flat = alloc::vec![Sample::new(1.0).at(5.0), Sample::new(2.0).at(5.0)];
+        assert_eq!(detrend(&mut flat, false), Ok((0.0, f64::INFINITY)));
+        flat[0].timestamp = Some(f64::INFINITY);
+        assert_eq!(
+            detrend(&mut flat, false),
+            Err(EstimateError::InvalidTimestamp { index: 0 })
+        );
+    }
+}
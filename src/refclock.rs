@@ -0,0 +1,177 @@
+use core::time::Duration;
+
+use crate::float;
+use crate::offset_estimator::Estimate;
+
+/// `SOCK_MAGIC` of chrony's `struct sock_sample`.
+const SOCK_MAGIC: i32 = 0x534f_434b;
+/// Size of chrony's `struct sock_sample` with a 64-bit `time_t`.
+pub const SOCK_SAMPLE_LEN: usize = 40;
+/// Size of the `struct shmTime` of the ntpd SHM driver with a 64-bit `time_t`.
+pub const SHM_TIME_LEN: usize = 96;
+
+/// Leap second warning passed along with a [`RefclockSample`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeapIndicator {
+    #[default]
+    None,
+    /// A second is inserted at the end of the day.
+    Insert,
+    /// A second is deleted at the end of the day.
+    Delete,
+}
+
+impl LeapIndicator {
+    /// Value of the `leap` field of both formats.
+    fn code(self) -> i32 {
+        match self {
+            LeapIndicator::None => 0,
+            LeapIndicator::Insert => 1,
+            LeapIndicator::Delete => 2,
+        }
+    }
+}
+
+/// An estimate in the form NTP daemons take from a reference clock, so that the estimator can
+/// serve as a refclock of chrony, through its `SOCK` or `SHM` driver, or of ntpd, through its
+/// `SHM` driver.
+///
+/// The byte layouts are those of 64-bit Linux with native byte order, where `time_t` and `long`
+/// are 64 bits wide.
+///
+/// ```no_run
+/// use std::os::unix::net::UnixDatagram;
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// use gamlr::{Estimate, RefclockSample};
+///
+/// # fn estimate() -> Estimate { unimplemented!() }
+/// // chrony.conf: refclock SOCK /var/run/gamlr.sock
+/// let socket = UnixDatagram::unbound()?;
+/// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+/// let sample = RefclockSample::from_estimate(&estimate(), Duration::from_nanos(1), now);
+/// socket.send_to(&sample.to_sock(), "/var/run/gamlr.sock")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RefclockSample {
+    /// Local time of the sample, since the Unix epoch.
+    pub time: Duration,
+    /// True minus local time in seconds, the correction the local clock needs.
+    pub offset: f64,
+    /// Precision of the sample as a power of two of seconds, as in NTP.
+    pub precision: i32,
+    pub leap: LeapIndicator,
+}
+
+impl RefclockSample {
+    /// The sample taken at local `time` of `estimate`, whose samples are in `unit`. The
+    /// estimated offset is local minus remote time, so the refclock offset carries the opposite
+    /// sign, and the precision is the uncertainty rounded up to a power of two.
+    pub fn from_estimate(estimate: &Estimate, unit: Duration, time: Duration) -> Self {
+        let unit = unit.as_secs_f64();
+        let uncertainty = estimate.uncertainty * unit;
+        let precision = match uncertainty > 0.0 && uncertainty.is_finite() {
+            // Between a nanosecond and a second.
+            true => libm::ceil(float::ln(uncertainty) / core::f64::consts::LN_2).clamp(-30.0, 0.0),
+            false => 0.0,
+        };
+        RefclockSample {
+            time,
+            offset: -estimate.offset * unit,
+            precision: precision as i32,
+            leap: LeapIndicator::None,
+        }
+    }
+
+    /// chrony's `struct sock_sample`, the datagram its `SOCK` refclock driver reads.
+    pub fn to_sock(&self) -> [u8; SOCK_SAMPLE_LEN] {
+        let mut out = [0; SOCK_SAMPLE_LEN];
+        out[0..8].copy_from_slice(&(self.time.as_secs() as i64).to_ne_bytes());
+        out[8..16].copy_from_slice(&i64::from(self.time.subsec_micros()).to_ne_bytes());
+        out[16..24].copy_from_slice(&self.offset.to_ne_bytes());
+        // `pulse` stays zero: the sample carries its own time.
+        out[28..32].copy_from_slice(&self.leap.code().to_ne_bytes());
+        out[36..40].copy_from_slice(&SOCK_MAGIC.to_ne_bytes());
+        out
+    }
+
+    /// The `struct shmTime` of the SHM refclock driver of ntpd and chrony, in mode 1 with
+    /// `count` and the `valid` flag set. The clock time stamp is the local time corrected by the
+    /// offset, the receive time stamp the local time.
+    ///
+    /// A writer owning the segment passes a `count` one above the one it wrote last; readers
+    /// discard a sample whose count changed while they read it.
+    pub fn to_shm(&self, count: i32) -> [u8; SHM_TIME_LEN] {
+        // In integer nanoseconds: a double holds epoch times to a few hundred nanoseconds only.
+        let nanos = self.time.as_nanos() as i128 + libm::round(self.offset * 1e9) as i128;
+        let clock = Duration::new(
+            (nanos.max(0) / 1_000_000_000) as u64,
+            (nanos.max(0) % 1_000_000_000) as u32,
+        );
+        let mut out = [0; SHM_TIME_LEN];
+        let mut put = |at: usize, bytes: &[u8]| out[at..at + bytes.len()].copy_from_slice(bytes);
+        put(0, &1i32.to_ne_bytes());
+        put(4, &count.to_ne_bytes());
+        put(8, &(clock.as_secs() as i64).to_ne_bytes());
+        put(16, &(clock.subsec_micros() as i32).to_ne_bytes());
+        put(24, &(self.time.as_secs() as i64).to_ne_bytes());
+        put(32, &(self.time.subsec_micros() as i32).to_ne_bytes());
+        put(36, &self.leap.code().to_ne_bytes());
+        put(40, &self.precision.to_ne_bytes());
+        // `nsamples` stays zero.
+        put(48, &1i32.to_ne_bytes());
+        put(52, &clock.subsec_nanos().to_ne_bytes());
+        put(56, &self.time.subsec_nanos().to_ne_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i32_at(bytes: &[u8], at: usize) -> i32 {
+        i32::from_ne_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn i64_at(bytes: &[u8], at: usize) -> i64 {
+        i64::from_ne_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_refclock_formats() {
+        // 250 µs fast, known to 3 µs.
+        let estimate = Estimate::from_offset(250_000.0, 3_000.0);
+        let time = Duration::new(1_700_000_000, 999_900_000);
+        let sample = RefclockSample {
+            leap: LeapIndicator::Insert,
+            ..RefclockSample::from_estimate(&estimate, Duration::from_nanos(1), time)
+        };
+        assert!((sample.offset + 250e-6).abs() < 1e-15);
+        // 3 µs rounds up to 2^-18 s.
+        assert_eq!(sample.precision, -18);
+
+        let sock = sample.to_sock();
+        assert_eq!(i64_at(&sock, 0), 1_700_000_000);
+        assert_eq!(i64_at(&sock, 8), 999_900);
+        assert_eq!(
+            f64::from_ne_bytes(sock[16..24].try_into().unwrap()),
+            sample.offset
+        );
+        assert_eq!((i32_at(&sock, 24), i32_at(&sock, 28)), (0, 1));
+        assert_eq!(i32_at(&sock, 36), 0x534f434b);
+
+        let shm = sample.to_shm(7);
+        assert_eq!((i32_at(&shm, 0), i32_at(&shm, 4)), (1, 7));
+        // The true time is 250 µs behind, still within the same second.
+        assert_eq!(i64_at(&shm, 8), 1_700_000_000);
+        assert_eq!(i32_at(&shm, 16), 999_650);
+        assert_eq!(
+            (i64_at(&shm, 24), i32_at(&shm, 32)),
+            (1_700_000_000, 999_900)
+        );
+        assert_eq!((i32_at(&shm, 36), i32_at(&shm, 40)), (1, -18));
+        assert_eq!((i32_at(&shm, 48), i32_at(&shm, 52)), (1, 999_650_000));
+        assert_eq!(i32_at(&shm, 56), 999_900_000);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+#[cfg(feature = "alloc")]
+use crate::offset_estimator::estimate_samples;
+use crate::offset_estimator::{estimate_samples_checked, Estimate};
+use crate::sample::{order, Sample};
+
+/// Store of the `N` most recent samples in fixed memory, overwriting the oldest one when full,
+/// for sliding-window estimation on targets where the footprint must be known at build time.
+///
+/// Next to the samples in arrival order, the store keeps them sorted by delay, so that both
+/// orders iterate without copying or sorting; a push costs `O(N)` moves to keep the sorted copy
+/// in place.
+///
+/// The ring also backs the window of `OnlineEstimator::in_ring`, whose footprint is then fixed
+/// at build time, eviction policies and chart included; without an allocator, push into a ring
+/// and estimate with [`estimate_checked`](Self::estimate_checked).
+///
+/// ```
+/// use gamlr::{Sample, SampleRing};
+///
+/// let mut ring = SampleRing::<3>::new();
+/// ring.extend([0.41, 0.35, 0.38].map(Sample::new));
+/// assert_eq!(ring.push(Sample::new(0.36)), Some(Sample::new(0.41)));
+/// let arrival: Vec<f64> = ring.iter().map(|s| s.value).collect();
+/// assert_eq!(arrival, [0.35, 0.38, 0.36]);
+/// let sorted: Vec<f64> = ring.sorted().iter().map(|s| s.value).collect();
+/// assert_eq!(sorted, [0.35, 0.36, 0.38]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleRing<const N: usize> {
+    /// Samples in arrival order from `head`, wrapping around.
+    samples: [Sample; N],
+    /// The same samples in the [order](crate::sample::order) of the pipeline, in `..len`.
+    sorted: [Sample; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Default for SampleRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SampleRing<N> {
+    pub const fn new() -> Self {
+        SampleRing {
+            samples: [Sample::new(0.0); N],
+            sorted: [Sample::new(0.0); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Adds `sample`, returning the oldest sample it overwrote when the store was full, or
+    /// `sample` itself when `N` is zero.
+    pub fn push(&mut self, sample: Sample) -> Option<Sample> {
+        if N == 0 {
+            return Some(sample);
+        }
+        let evicted = match self.len == N {
+            true => {
+                let oldest = core::mem::replace(&mut self.samples[self.head], sample);
+                self.head = (self.head + 1) % N;
+                self.remove_sorted(&oldest);
+                Some(oldest)
+            }
+            false => {
+                self.samples[(self.head + self.len) % N] = sample;
+                self.len += 1;
+                None
+            }
+        };
+        self.insert_sorted(sample);
+        evicted
+    }
+
+    /// The `index`-th oldest sample.
+    pub fn get(&self, index: usize) -> Option<&Sample> {
+        (index < self.len).then(|| &self.samples[(self.head + index) % N])
+    }
+
+    /// Removes the `index`-th oldest sample, moving the newer ones down unless it is the oldest.
+    pub fn remove(&mut self, index: usize) -> Option<Sample> {
+        if index >= self.len {
+            return None;
+        }
+        let removed = self.samples[(self.head + index) % N];
+        match index {
+            0 => self.head = (self.head + 1) % N,
+            _ => {
+                for i in index..self.len - 1 {
+                    self.samples[(self.head + i) % N] = self.samples[(self.head + i + 1) % N];
+                }
+            }
+        }
+        self.remove_sorted(&removed);
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Replaces the `index`-th oldest sample by `sample` in place, returning the one replaced.
+    pub fn replace(&mut self, index: usize, sample: Sample) -> Option<Sample> {
+        if index >= self.len {
+            return None;
+        }
+        let replaced = core::mem::replace(&mut self.samples[(self.head + index) % N], sample);
+        self.remove_sorted(&replaced);
+        self.insert_sorted(sample);
+        Some(replaced)
+    }
+
+    /// Inserts `sample` into the sorted copy, whose last slot is free.
+    fn insert_sorted(&mut self, sample: Sample) {
+        // After the samples ordering equal to it, so that ties stay in arrival order.
+        let sorted = &mut self.sorted[..self.len];
+        let at = sorted[..self.len - 1].partition_point(|s| order(s, &sample).is_le());
+        sorted[self.len - 1] = sample;
+        sorted[at..].rotate_right(1);
+    }
+
+    /// Removes `sample` from the sorted copy, leaving its last slot free.
+    fn remove_sorted(&mut self, sample: &Sample) {
+        let sorted = &mut self.sorted[..self.len];
+        let first = sorted.partition_point(|s| order(s, sample).is_lt());
+        let at = sorted[first..]
+            .iter()
+            .take_while(|s| order(s, sample).is_eq())
+            .position(|s| identical(s, sample))
+            .map_or(first, |offset| first + offset);
+        sorted[at..].rotate_left(1);
+    }
+
+    /// The samples from the oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = &Sample> + '_ {
+        let (older, newer) = self.as_slices();
+        older.iter().chain(newer)
+    }
+
+    /// The samples from the oldest to the newest, as the two contiguous runs they are stored in.
+    pub fn as_slices(&self) -> (&[Sample], &[Sample]) {
+        let end = self.head + self.len;
+        match end > N {
+            true => (&self.samples[self.head..], &self.samples[..end - N]),
+            false => (&self.samples[self.head..end], &[]),
+        }
+    }
+
+    /// The samples by ascending delay, the censored ones last.
+    pub fn sorted(&self) -> &[Sample] {
+        &self.sorted[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Estimates the offset from the samples in the store in arrival order, see
+    /// [`estimate_samples`](crate::estimate_samples).
+    #[cfg(feature = "alloc")]
+    pub fn estimate(&self, config: &EstimatorConfig) -> Result<Estimate, EstimateError> {
+        estimate_samples(self.iter().copied(), config)
+    }
+
+    /// [`estimate_samples_checked`] on a copy of the samples in arrival order on the stack,
+    /// without allocating, the same as [`estimate`](Self::estimate).
+    pub fn estimate_checked(
+        &self,
+        synthetic: &mut [f64],
+        config: &EstimatorConfig,
+    ) -> Result<Estimate, EstimateError> {
+        let mut samples = [Sample::new(0.0); N];
+        for (slot, sample) in samples.iter_mut().zip(self.iter()) {
+            *slot = *sample;
+        }
+        estimate_samples_checked(&mut samples[..self.len], synthetic, config)
+    }
+}
+
+impl<const N: usize> Extend<Sample> for SampleRing<N> {
+    fn extend<I: IntoIterator<Item = Sample>>(&mut self, samples: I) {
+        samples.into_iter().for_each(|sample| {
+            self.push(sample);
+        });
+    }
+}
+
+/// Whether `a` and `b` are the same sample, bit for bit, NaNs included.
+fn identical(a: &Sample, b: &Sample) -> bool {
+    a.value.to_bits() == b.value.to_bits()
+        && a.weight.to_bits() == b.weight.to_bits()
+        && a.timestamp.map(f64::to_bits) == b.timestamp.map(f64::to_bits)
+        && a.censored == b.censored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_ring() {
+        let mut ring = SampleRing::<4>::new();
+        // Ties of delay told apart by their timestamps.
+        for (at, value) in [3.0, 1.0, 3.0, 2.0, f64::NAN, 3.0, 0.5]
+            .into_iter()
+            .enumerate()
+        {
+            ring.push(Sample::new(value).at(at as f64));
+        }
+        let arrival: alloc::vec::Vec<f64> = ring.iter().filter_map(|s| s.timestamp).collect();
+        assert_eq!(arrival, [3.0, 4.0, 5.0, 6.0]);
+        let sorted: alloc::vec::Vec<f64> =
+            ring.sorted().iter().filter_map(|s| s.timestamp).collect();
+        assert_eq!(sorted, [6.0, 3.0, 5.0, 4.0]);
+        ring.push(Sample::timed_out(1.0));
+        assert!(ring.sorted()[3].censored && ring.is_full());
+        assert_eq!(
+            SampleRing::<0>::new().push(Sample::new(1.0)),
+            Some(Sample::new(1.0))
+        );
+
+        let mut rng = crate::offset_estimator::LcgRng::new(5);
+        let mut ring = SampleRing::<200>::new();
+        for _ in 0..500 {
+            let exponential = -crate::float::ln(1.0 - rng.gen_range(0.0..1.0));
+            ring.push(Sample::new(100.0 + 5.0 * exponential));
+        }
+        // Stride subsampling keeps every other sample in arrival order.
+        let config = EstimatorConfig {
+            fast: Some(crate::FastMode {
+                max_samples: 100,
+                subsampling: crate::Subsampling::Stride,
+            }),
+            ..Default::default()
+        };
+        let estimate = ring.estimate_checked(&mut [0.0; 200], &config).unwrap();
+        assert_eq!(estimate.subsampled, 100);
+        let mut samples: alloc::vec::Vec<Sample> = ring.iter().copied().collect();
+        let expected = estimate_samples_checked(&mut samples, &mut [0.0; 200], &config);
+        assert_eq!(Ok(estimate), expected);
+    }
+}
@@ -0,0 +1,118 @@
+use core::time::Duration;
+
+use crate::offset_estimator::Estimate;
+
+/// The signed time of a Roughtime response: the server's `MIDP` midpoint and `RADI` radius,
+/// since the Unix epoch, converted from the wire units of the protocol version in use.
+///
+/// Roughtime gives a coarse but authenticated anchor: the signed interval bounds the local clock
+/// even when the precise one-way delay estimates come from unauthenticated peers. Turned into an
+/// [`Estimate`] with [`observation`](Self::observation), the response can be passed to
+/// [`fuse`](crate::fuse), [`select`](crate::select) or [`marzullo`](crate::marzullo) together with
+/// the gamma estimates, where a peer that disagrees with the anchor ends up a falseticker.
+///
+/// ```
+/// use core::time::Duration;
+/// use gamlr::RoughtimeResponse;
+///
+/// let response = RoughtimeResponse {
+///     midpoint: Duration::new(1_700_000_000, 0),
+///     radius: Duration::from_millis(1),
+/// };
+/// // Sent and received 20 ms apart, 5 ms behind the server.
+/// let sent = Duration::new(1_699_999_999, 985_000_000);
+/// let received = Duration::new(1_700_000_000, 5_000_000);
+/// let observation = response.observation(sent, received, Duration::from_micros(1));
+/// assert_eq!(observation.offset, -5_000.0);
+/// assert_eq!(observation.distance(), 11_000.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoughtimeResponse {
+    /// Time the server signed, since the Unix epoch.
+    pub midpoint: Duration,
+    /// Uncertainty of `midpoint` the server vouches for.
+    pub radius: Duration,
+}
+
+impl RoughtimeResponse {
+    /// The offset bounded by the response to a request `sent` and `received` at the given local
+    /// times, since the Unix epoch, in `unit`.
+    ///
+    /// The server took the midpoint at some moment of the round trip, so the true offset of the
+    /// local clock, local minus server time like the one-way delay estimates, lies within the
+    /// radius plus half the round trip of the offset at the middle of the round trip. This half
+    /// round trip is the uncertainty of the returned estimate and the radius its
+    /// [root dispersion](crate::SourceQuality::root_dispersion), so that its
+    /// [`distance`](Estimate::distance) is the half width of the bounding interval. The offset is
+    /// NaN when the reply was received before the request was sent.
+    pub fn observation(&self, sent: Duration, received: Duration, unit: Duration) -> Estimate {
+        let unit = unit.as_nanos() as f64;
+        let Some(round_trip) = received.checked_sub(sent) else {
+            return Estimate::from_offset(f64::NAN, f64::INFINITY);
+        };
+        // In integer nanoseconds: a double holds epoch times to a few hundred nanoseconds only.
+        let middle = sent.as_nanos() as i128 + (round_trip.as_nanos() / 2) as i128;
+        let offset = (middle - self.midpoint.as_nanos() as i128) as f64;
+        let mut observation =
+            Estimate::from_offset(offset / unit, round_trip.as_nanos() as f64 / 2.0 / unit);
+        observation.source.root_dispersion = self.radius.as_nanos() as f64 / unit;
+        observation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roughtime_observation() {
+        let response = RoughtimeResponse {
+            midpoint: Duration::new(1_700_000_000, 250_000),
+            radius: Duration::from_micros(500),
+        };
+        let sent = Duration::new(1_700_000_000, 100_000);
+        let observation = response.observation(
+            sent,
+            sent + Duration::from_micros(301),
+            Duration::from_nanos(1),
+        );
+        assert_eq!(observation.offset, 500.0);
+        assert_eq!(observation.uncertainty, 150_500.0);
+        assert_eq!(observation.source.root_dispersion, 500_000.0);
+
+        let late = response.observation(
+            sent,
+            sent - Duration::from_nanos(1),
+            Duration::from_nanos(1),
+        );
+        assert!(late.offset.is_nan());
+        // Ignored when fused.
+        assert!(crate::fuse(&[late]).offset.is_nan());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_roughtime_anchor_rejects_falseticker() {
+        // Two precise peers agree with the anchor at 40 µs; a third is 5 ms off.
+        let anchor = RoughtimeResponse {
+            midpoint: Duration::from_secs(1_000),
+            radius: Duration::from_micros(100),
+        }
+        .observation(
+            Duration::from_micros(1_000_000_000 - 10 + 40),
+            Duration::from_micros(1_000_000_000 + 10 + 40),
+            Duration::from_micros(1),
+        );
+        let peers = [
+            anchor,
+            Estimate::from_offset(41.0, 1.0),
+            Estimate::from_offset(39.5, 1.0),
+            Estimate::from_offset(5_040.0, 1.0),
+        ];
+        let intersection = crate::marzullo(&peers, 1.0).unwrap();
+        assert_eq!(intersection.truechimers, [0, 1, 2]);
+        assert_eq!(intersection.falsetickers, [3]);
+        let fused = crate::fuse(&peers[..3]);
+        assert!((fused.offset - 40.25).abs() < 0.1, "{fused:?}");
+    }
+}
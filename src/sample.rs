@@ -0,0 +1,199 @@
+/// A one-way delay measurement with its reliability weight and, optionally, its capture time.
+///
+/// ```
+/// use gamlr::Sample;
+///
+/// let sample = Sample::new(0.35).with_weight(2.0).at(1_700_000_000.0);
+/// assert_eq!(sample.timestamp, Some(1_700_000_000.0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// Measured one-way delay.
+    pub value: f64,
+    /// Relative reliability of the measurement, one by default.
+    pub weight: f64,
+    /// Capture time of the measurement, in any unit consistent across the batch.
+    pub timestamp: Option<f64>,
+    /// Whether the probe timed out or was lost, so that its delay is only known to exceed
+    /// `value`, the timeout. Such right-censored samples enter the fit through the probability of
+    /// exceeding the timeout instead of being dropped, which would bias the fit whenever loss
+    /// correlates with delay.
+    pub censored: bool,
+}
+
+impl Sample {
+    pub const fn new(value: f64) -> Self {
+        Sample {
+            value,
+            weight: 1.0,
+            timestamp: None,
+            censored: false,
+        }
+    }
+
+    /// A right-censored sample for a probe that got no answer within `timeout`.
+    pub const fn timed_out(timeout: f64) -> Self {
+        Sample {
+            censored: true,
+            ..Sample::new(timeout)
+        }
+    }
+
+    pub const fn weighted(value: f64, weight: f64) -> Self {
+        Sample::new(value).with_weight(weight)
+    }
+
+    pub const fn with_weight(self, weight: f64) -> Self {
+        Sample { weight, ..self }
+    }
+
+    pub const fn at(self, timestamp: f64) -> Self {
+        Sample {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+}
+
+impl From<f64> for Sample {
+    fn from(value: f64) -> Self {
+        Sample::new(value)
+    }
+}
+
+/// Send and receive timestamps of a probe, read from the clocks of the sender and the receiver,
+/// for callers that would otherwise difference them by hand and risk doing it the wrong way
+/// round. Its [`Sample`] is the delay `received - sent`, timestamped at `received`, whose offset
+/// is that of the receiver clock from the sender clock, receiver minus sender time, plus the
+/// delay floor.
+///
+/// Timestamps far from zero lose the resolution of their difference, about a microsecond for
+/// epoch nanoseconds, so they are best taken relative to a recent epoch.
+///
+/// ```
+/// use gamlr::{Sample, TimestampPair};
+///
+/// let sample = Sample::from(TimestampPair::new(1000.0, 1350.0));
+/// assert_eq!((sample.value, sample.timestamp), (350.0, Some(1350.0)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampPair {
+    /// Send time, read from the clock of the sender.
+    pub sent: f64,
+    /// Receive time, read from the clock of the receiver.
+    pub received: f64,
+}
+
+impl TimestampPair {
+    pub const fn new(sent: f64, received: f64) -> Self {
+        TimestampPair { sent, received }
+    }
+
+    /// One-way delay of the probe, `received - sent`.
+    pub fn delay(&self) -> f64 {
+        self.received - self.sent
+    }
+}
+
+impl From<TimestampPair> for Sample {
+    fn from(pair: TimestampPair) -> Self {
+        Sample::new(pair.delay()).at(pair.received)
+    }
+}
+
+/// One-way delay sample of a probe `sent` and `received` at the given times of the remote and
+/// local clocks, with the delay and the receive timestamp in nanoseconds.
+pub(crate) fn nanos_sample(sent: core::time::Duration, received: core::time::Duration) -> Sample {
+    let delay = match received.checked_sub(sent) {
+        Some(delay) => delay.as_nanos() as f64,
+        None => -((sent - received).as_nanos() as f64),
+    };
+    Sample::new(delay).at(received.as_nanos() as f64)
+}
+
+/// Sorts `samples` by value in ascending order, with the censored samples, whose delay exceeds
+/// every observed one, after all others.
+pub(crate) fn sort_samples(samples: &mut [Sample]) {
+    samples.sort_unstable_by(order);
+}
+
+/// Order of [`sort_samples`].
+pub(crate) fn order(a: &Sample, b: &Sample) -> core::cmp::Ordering {
+    a.censored
+        .cmp(&b.censored)
+        .then(a.value.total_cmp(&b.value))
+}
+
+/// Storage the estimation pipeline filters samples in, so that it runs on both `Vec` and
+/// `heapless::Vec`.
+pub(crate) trait SampleBuffer: core::ops::DerefMut<Target = [Sample]> {
+    fn retain_samples(&mut self, keep: impl FnMut(&Sample) -> bool);
+    fn truncate_samples(&mut self, len: usize);
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl SampleBuffer for alloc::vec::Vec<Sample> {
+    fn retain_samples(&mut self, keep: impl FnMut(&Sample) -> bool) {
+        self.retain(keep);
+    }
+
+    fn truncate_samples(&mut self, len: usize) {
+        self.truncate(len);
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> SampleBuffer for heapless::Vec<Sample, N> {
+    fn retain_samples(&mut self, keep: impl FnMut(&Sample) -> bool) {
+        self.retain(keep);
+    }
+
+    fn truncate_samples(&mut self, len: usize) {
+        self.truncate(len);
+    }
+}
+
+/// Caller-provided slice the pipeline filters samples in, keeping the retained ones at its
+/// start. `len` never exceeds the length of the slice.
+pub(crate) struct SliceBuffer<'a> {
+    slice: &'a mut [Sample],
+    len: usize,
+}
+
+impl<'a> SliceBuffer<'a> {
+    pub(crate) fn new(slice: &'a mut [Sample]) -> Self {
+        let len = slice.len();
+        SliceBuffer { slice, len }
+    }
+}
+
+impl core::ops::Deref for SliceBuffer<'_> {
+    type Target = [Sample];
+
+    fn deref(&self) -> &[Sample] {
+        &self.slice[..self.len]
+    }
+}
+
+impl core::ops::DerefMut for SliceBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [Sample] {
+        &mut self.slice[..self.len]
+    }
+}
+
+impl SampleBuffer for SliceBuffer<'_> {
+    fn retain_samples(&mut self, mut keep: impl FnMut(&Sample) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.slice[i]) {
+                self.slice.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    fn truncate_samples(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
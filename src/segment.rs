@@ -0,0 +1,273 @@
+use alloc::vec::Vec;
+
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::float;
+use crate::offset_estimator::{estimate_samples, Estimate};
+use crate::sample::Sample;
+
+/// Parameters of [`segment_drift`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentConfig {
+    /// Cost of opening a segment, in units of the delay variance. `None` uses the BIC penalty
+    /// `3 ln n` of a change point together with the offset and skew of its segment.
+    pub penalty: Option<f64>,
+    /// Fewest samples in a segment; shorter regimes are merged into their neighbors.
+    pub min_len: usize,
+}
+
+impl Default for SegmentConfig {
+    fn default() -> Self {
+        SegmentConfig {
+            penalty: None,
+            min_len: 30,
+        }
+    }
+}
+
+/// One drift regime found by [`segment_drift`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftSegment {
+    /// Timestamp of the first sample of the segment.
+    pub start: f64,
+    /// Timestamp of the last sample of the segment, the time [`Estimate::offset`] refers to.
+    pub end: f64,
+    /// Number of samples in the segment.
+    pub len: usize,
+    /// Detrended estimate of the segment; its [`drift`](Estimate::drift) is the skew of the
+    /// regime.
+    pub estimate: Result<Estimate, EstimateError>,
+}
+
+impl DriftSegment {
+    /// Offset at time `t` under the regime, extrapolated along its skew from [`end`](Self::end).
+    /// NaN when the segment could not be estimated.
+    pub fn offset_at(&self, t: f64) -> f64 {
+        match &self.estimate {
+            Ok(estimate) => estimate.offset + estimate.drift.unwrap_or(0.0) * (t - self.end),
+            Err(_) => f64::NAN,
+        }
+    }
+}
+
+/// Weighted sums of a run of samples, from which the residual sum of squares of its
+/// least-squares line follows in constant time.
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    w: f64,
+    wt: f64,
+    wv: f64,
+    wtt: f64,
+    wtv: f64,
+    wvv: f64,
+}
+
+impl Moments {
+    fn add(self, t: f64, v: f64, w: f64) -> Self {
+        Moments {
+            w: self.w + w,
+            wt: self.wt + w * t,
+            wv: self.wv + w * v,
+            wtt: self.wtt + w * t * t,
+            wtv: self.wtv + w * t * v,
+            wvv: self.wvv + w * v * v,
+        }
+    }
+
+    /// Residual sum of squares of the line fitted to the samples between `self` and `end`.
+    fn residual_ss(self, end: Moments) -> f64 {
+        let w = end.w - self.w;
+        if w <= 0.0 {
+            return 0.0;
+        }
+        let (wt, wv) = (end.wt - self.wt, end.wv - self.wv);
+        let stt = end.wtt - self.wtt - wt * wt / w;
+        let stv = end.wtv - self.wtv - wt * wv / w;
+        let svv = end.wvv - self.wvv - wv * wv / w;
+        let explained = if stt > 0.0 { stv * stv / stt } else { 0.0 };
+        (svv - explained).max(0.0)
+    }
+}
+
+/// Segments a long timestamped OWD series into piecewise-linear drift regimes and estimates
+/// the offset and skew of each, for the post-hoc analysis of long captures where the frequency
+/// of a clock changes, e.g. with temperature or after a discipline step.
+///
+/// The samples are ordered by timestamp and split where a new segment pays for its
+/// [`SegmentConfig::penalty`], minimizing the residual sum of squares of the per-segment
+/// least-squares lines, scaled by the delay variance, plus the penalties. The optimal split is
+/// found by dynamic programming with PELT pruning (Killick et al., 2012), in about linear time.
+/// The delay variance is estimated from the differences of successive samples, which
+/// the drift hardly affects. Censored samples, invalid values and invalid weights take no part
+/// in the split. Each segment is then estimated with
+/// [`EstimatorConfig::detrend`](crate::EstimatorConfig::detrend) set, so its offset refers to its
+/// last timestamp and its drift is the skew of the regime.
+///
+/// Fails with [`EstimateError::InvalidTimestamp`] on samples without a finite timestamp, and with
+/// [`EstimateError::InvalidConfig`] naming `penalty` or `min_len` when the penalty is negative or
+/// NaN or segments of fewer than two samples are allowed. The estimates of the segments fail
+/// individually.
+pub fn segment_drift<I>(
+    samples: I,
+    segmentation: &SegmentConfig,
+    config: &EstimatorConfig,
+) -> Result<Vec<DriftSegment>, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let mut samples: Vec<Sample> = samples.into_iter().collect();
+    if let Some(index) = samples
+        .iter()
+        .position(|s| s.timestamp.is_none_or(|t| !t.is_finite()))
+    {
+        return Err(EstimateError::InvalidTimestamp { index });
+    }
+    let min_len = segmentation.min_len;
+    if min_len < 2 {
+        return Err(EstimateError::InvalidConfig { field: "min_len" });
+    }
+    let n = samples.len();
+    let penalty = match segmentation.penalty {
+        Some(penalty) if penalty.is_nan() || penalty < 0.0 => {
+            return Err(EstimateError::InvalidConfig { field: "penalty" });
+        }
+        Some(penalty) => penalty,
+        None => 3.0 * float::ln(n.max(1) as f64),
+    };
+    let time = |s: &Sample| s.timestamp.unwrap_or(0.0);
+    samples.sort_by(|a, b| time(a).total_cmp(&time(b)));
+
+    let config = EstimatorConfig {
+        detrend: true,
+        ..config.clone()
+    };
+    Ok(split(&samples, min_len, penalty)
+        .into_iter()
+        .map(|(start, end)| {
+            let segment = &samples[start..end];
+            DriftSegment {
+                start: time(&segment[0]),
+                end: time(&segment[segment.len() - 1]),
+                len: segment.len(),
+                estimate: estimate_samples(segment.iter().copied(), &config),
+            }
+        })
+        .collect())
+}
+
+/// Bounds of the optimal segments of the time-ordered `samples`, see [`segment_drift`].
+fn split(samples: &[Sample], min_len: usize, penalty: f64) -> Vec<(usize, usize)> {
+    let n = samples.len();
+    let usable =
+        |s: &&Sample| !s.censored && s.value.is_finite() && s.weight.is_finite() && s.weight > 0.0;
+    if n == 0 {
+        return Vec::new();
+    }
+    // Sums relative to the first sample keep the differences of the prefix sums accurate.
+    let origin = samples[0].timestamp.unwrap_or(0.0);
+    let level = samples.iter().find(usable).map_or(0.0, |s| s.value);
+    let mut prefix = Vec::with_capacity(n + 1);
+    prefix.push(Moments::default());
+    for s in samples {
+        let last = prefix[prefix.len() - 1];
+        prefix.push(match usable(&s) {
+            true => last.add(
+                s.timestamp.unwrap_or(origin) - origin,
+                s.value - level,
+                s.weight,
+            ),
+            false => last,
+        });
+    }
+    // Von Neumann's estimate: successive differences cancel the slowly varying trend.
+    let values: Vec<f64> = samples.iter().filter(usable).map(|s| s.value).collect();
+    let variance = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]) * (pair[1] - pair[0]))
+        .sum::<f64>()
+        / (2.0 * values.len().saturating_sub(1) as f64);
+    if n / 2 < min_len || !(variance.is_finite() && variance > 0.0) {
+        return alloc::vec![(0, n)];
+    }
+
+    let cost = |start: usize, end: usize| prefix[start].residual_ss(prefix[end]) / variance;
+    // best[t]: least cost of segmenting the first t samples; last[t]: start of its last
+    // segment. Only prefixes of at least `min_len` samples can be segmented.
+    let mut best = alloc::vec![f64::INFINITY; n + 1];
+    let mut last = alloc::vec![0; n + 1];
+    best[0] = -penalty;
+    let mut candidates: Vec<usize> = Vec::new();
+    for end in min_len..=n {
+        let start = end - min_len;
+        if best[start].is_finite() {
+            candidates.push(start);
+        }
+        for &start in &candidates {
+            let total = best[start] + cost(start, end) + penalty;
+            if total < best[end] {
+                best[end] = total;
+                last[end] = start;
+            }
+        }
+        // PELT: a start that is already worse than the best split of `end` can never win later.
+        let bound = best[end];
+        candidates.retain(|&start| best[start] + cost(start, end) <= bound);
+    }
+
+    let mut bounds = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        bounds.push((last[end], end));
+        end = last[end];
+    }
+    bounds.reverse();
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset_estimator::LcgRng;
+
+    #[test]
+    fn test_segment_drift() {
+        // The skew changes from 0.02 to -0.03 at t = 600.
+        let mut rng = LcgRng::new(5);
+        let samples: Vec<Sample> = (0..1200)
+            .map(|i| {
+                let t = i as f64;
+                let offset = match t < 600.0 {
+                    true => 10.0 + 0.02 * t,
+                    false => 22.0 - 0.03 * (t - 600.0),
+                };
+                let delay = -float::ln(1.0 - rng.gen_range(0.0..1.0));
+                Sample::new(offset + delay).at(t)
+            })
+            .collect();
+        let segments = segment_drift(
+            samples,
+            &SegmentConfig::default(),
+            &EstimatorConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(segments.len(), 2, "{segments:?}");
+        assert!((segments[0].end - 599.0).abs() <= 20.0, "{segments:?}");
+        assert_eq!(segments.iter().map(|s| s.len).sum::<usize>(), 1200);
+        let skews: Vec<f64> = segments
+            .iter()
+            .map(|s| s.estimate.as_ref().unwrap().drift.unwrap())
+            .collect();
+        assert!((skews[0] - 0.02).abs() < 3e-3, "{skews:?}");
+        assert!((skews[1] + 0.03).abs() < 3e-3, "{skews:?}");
+        assert!((segments[1].offset_at(900.0) - 13.0).abs() < 1.0);
+
+        assert_eq!(
+            segment_drift(
+                [Sample::new(1.0)],
+                &SegmentConfig::default(),
+                &EstimatorConfig::default()
+            ),
+            Err(EstimateError::InvalidTimestamp { index: 0 })
+        );
+    }
+}
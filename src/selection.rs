@@ -0,0 +1,360 @@
+use alloc::vec::Vec;
+
+use crate::float;
+use crate::fusion::fuse;
+use crate::offset_estimator::Estimate;
+
+/// Scale factor turning a median absolute deviation into a standard deviation for normal data.
+const MAD_SCALE: f64 = 1.4826;
+
+/// Parameters of the peer selection and clustering procedure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionConfig {
+    /// Peers further than this many scale units from the median offset are discarded as outliers.
+    /// The scale is the larger of the peer's own root distance and the scaled median absolute
+    /// deviation of all offsets.
+    pub outlier_threshold: f64,
+    /// Peers whose stratum exceeds the best available stratum by more than this are discarded
+    /// before any other step, so that lower tiers of a hierarchy are only used as fallback.
+    pub stratum_tolerance: u8,
+    /// Clustering stops once this many peers survive.
+    pub min_survivors: usize,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        SelectionConfig {
+            outlier_threshold: 3.0,
+            stratum_tolerance: 1,
+            min_survivors: 3,
+        }
+    }
+}
+
+/// Outcome of [`select`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    /// System offset fused from the surviving peers.
+    pub system: Estimate,
+    /// Indices of the surviving peers in the input slice, in ascending order.
+    pub survivors: Vec<usize>,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        0.5 * (values[mid - 1] + values[mid])
+    } else {
+        values[mid]
+    }
+}
+
+/// Selects and clusters per-peer estimates in the manner of ntpd, producing a system offset.
+///
+/// 1. Peers with a non-finite offset or root distance are discarded, and so are peers whose
+///    stratum is more than [`SelectionConfig::stratum_tolerance`] above the best remaining one.
+/// 2. Peers whose offset is an outlier with respect to the median of all offsets are discarded.
+/// 3. While more than [`SelectionConfig::min_survivors`] peers remain, the peer with the largest
+///    select jitter (RMS offset difference to the other survivors) is pruned, unless that jitter
+///    is already below the smallest peer root distance.
+/// 4. The survivors are combined with [`fuse`].
+pub fn select(peers: &[Estimate], config: &SelectionConfig) -> Selection {
+    let mut survivors: Vec<usize> = (0..peers.len())
+        .filter(|&i| peers[i].offset.is_finite() && peers[i].distance().is_finite())
+        .collect();
+    if let Some(best) = survivors.iter().map(|&i| peers[i].source.stratum).min() {
+        let worst = best.saturating_add(config.stratum_tolerance);
+        survivors.retain(|&i| peers[i].source.stratum <= worst);
+    }
+
+    let mut offsets: Vec<f64> = survivors.iter().map(|&i| peers[i].offset).collect();
+    let center = median(&mut offsets);
+    let mut deviations: Vec<f64> = offsets.iter().map(|o| (o - center).abs()).collect();
+    let spread = MAD_SCALE * median(&mut deviations);
+    survivors.retain(|&i| {
+        let scale = spread.max(peers[i].distance());
+        (peers[i].offset - center).abs() <= config.outlier_threshold * scale
+    });
+
+    while survivors.len() > config.min_survivors.max(1) {
+        let (worst, select_jitter) = survivors
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let sum_sq = survivors
+                    .iter()
+                    .map(|&j| float::pow(peers[i].offset - peers[j].offset, 2.0))
+                    .sum::<f64>();
+                (position, float::sqrt(sum_sq / (survivors.len() - 1) as f64))
+            })
+            .fold((0, f64::NEG_INFINITY), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+        let min_peer_jitter = survivors
+            .iter()
+            .map(|&i| peers[i].distance())
+            .fold(f64::INFINITY, f64::min);
+        if select_jitter <= min_peer_jitter {
+            break;
+        }
+        survivors.remove(worst);
+    }
+
+    let chosen: Vec<Estimate> = survivors.iter().map(|&i| peers[i].clone()).collect();
+    Selection {
+        system: fuse(&chosen),
+        survivors,
+    }
+}
+
+/// Outcome of [`marzullo`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intersection {
+    /// Lower bound of the interval agreed on by the largest number of sources.
+    pub lower: f64,
+    /// Upper bound of that interval.
+    pub upper: f64,
+    /// Indices of sources whose interval overlaps `[lower, upper]`.
+    pub truechimers: Vec<usize>,
+    /// Indices of the remaining sources, including those with a non-finite offset or root distance.
+    pub falsetickers: Vec<usize>,
+}
+
+impl Intersection {
+    /// Center of the agreed interval, the fault-tolerant midpoint offset.
+    pub fn midpoint(&self) -> f64 {
+        0.5 * (self.lower + self.upper)
+    }
+}
+
+/// Source intervals `offset ± width * distance` of the usable peers.
+fn intervals(peers: &[Estimate], width: f64) -> impl Iterator<Item = (usize, f64, f64)> + '_ {
+    peers
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.offset.is_finite() && p.distance().is_finite())
+        .map(move |(i, p)| {
+            let half = (width * p.distance()).abs();
+            (i, p.offset - half, p.offset + half)
+        })
+}
+
+/// Interval edges sorted so that start edges (`-1`) precede end edges (`1`) at the same point,
+/// which makes touching intervals intersect.
+fn sorted_edges(peers: &[Estimate], width: f64) -> Vec<(f64, i8)> {
+    let mut edges: Vec<(f64, i8)> = intervals(peers, width)
+        .flat_map(|(_, lower, upper)| [(lower, -1), (upper, 1)])
+        .collect();
+    edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    edges
+}
+
+/// Splits the peers into those whose interval overlaps `[lower, upper]` and the rest.
+fn classify(peers: &[Estimate], width: f64, lower: f64, upper: f64) -> Intersection {
+    let truechimers: Vec<usize> = intervals(peers, width)
+        .filter(|&(_, l, u)| l <= upper && u >= lower)
+        .map(|(i, _, _)| i)
+        .collect();
+    let falsetickers = (0..peers.len())
+        .filter(|i| !truechimers.contains(i))
+        .collect();
+    Intersection {
+        lower,
+        upper,
+        truechimers,
+        falsetickers,
+    }
+}
+
+/// Finds the interval shared by the most `offset ± width * distance` source intervals using
+/// Marzullo's algorithm, classifying the sources that miss it as falsetickers.
+///
+/// Returns `None` when no interval is shared by a strict majority of the usable sources.
+///
+/// K. A. Marzullo. "Maintaining the time in a distributed system". PhD thesis, Stanford University, 1984.
+pub fn marzullo(peers: &[Estimate], width: f64) -> Option<Intersection> {
+    let usable = intervals(peers, width).count();
+    let edges = sorted_edges(peers, width);
+
+    let mut best = 0;
+    let mut count = 0;
+    let mut interval = (f64::NAN, f64::NAN);
+    for (position, &(point, kind)) in edges.iter().enumerate() {
+        count -= i32::from(kind);
+        if kind < 0 && count > best {
+            best = count;
+            interval = (point, edges.get(position + 1).map_or(point, |e| e.0));
+        }
+    }
+    if 2 * best as usize <= usable {
+        return None;
+    }
+
+    Some(classify(peers, width, interval.0, interval.1))
+}
+
+/// Computes the fault-tolerant intersection of the `offset ± width * distance` source
+/// intervals: the smallest interval holding every point covered by at least `n - faults` of the
+/// `n` sources.
+///
+/// As long as at most `faults` sources are faulty and their intervals may be arbitrary
+/// (Byzantine), the interval is guaranteed to contain the true offset of every correct source.
+/// Sources with a non-finite offset or root distance count as faulty. Returns `None` unless
+/// `n > 3 * faults`, the bound under which the resulting interval stays as narrow as the
+/// correct intervals allow.
+///
+/// L. Lamport, P. M. Melliar-Smith. "Synchronizing clocks in the presence of faults".
+/// Journal of the ACM, Vol. 32, No. 1, January 1985, Pages 52-78.
+pub fn fault_tolerant_intersection(
+    peers: &[Estimate],
+    width: f64,
+    faults: usize,
+) -> Option<Intersection> {
+    let n = peers.len();
+    if faults.checked_mul(3).is_none_or(|bound| n <= bound) {
+        return None;
+    }
+    let need = (n - faults) as i32;
+
+    let mut count = 0;
+    let mut lower = None;
+    let mut upper = None;
+    for &(point, kind) in &sorted_edges(peers, width) {
+        if kind > 0 && count >= need {
+            upper = Some(point);
+        }
+        count -= i32::from(kind);
+        if kind < 0 && count >= need && lower.is_none() {
+            lower = Some(point);
+        }
+    }
+
+    Some(classify(peers, width, lower?, upper?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_discards_outliers_and_clusters() {
+        let peers = [
+            Estimate::from_offset(10.0, 1.0),
+            Estimate::from_offset(10.5, 1.0),
+            Estimate::from_offset(9.8, 1.0),
+            Estimate::from_offset(250.0, 1.0),
+            Estimate::from_offset(14.0, 0.5),
+            Estimate::from_offset(f64::NAN, 1.0),
+        ];
+        let selection = select(&peers, &SelectionConfig::default());
+
+        assert_eq!(selection.survivors, alloc::vec![0, 1, 2]);
+        assert!((selection.system.offset - 10.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_select_keeps_agreeing_peers() {
+        let peers = [
+            Estimate::from_offset(1.0, 2.0),
+            Estimate::from_offset(1.5, 2.0),
+            Estimate::from_offset(0.5, 2.0),
+            Estimate::from_offset(1.2, 2.0),
+        ];
+        let selection = select(&peers, &SelectionConfig::default());
+        assert_eq!(selection.survivors, alloc::vec![0, 1, 2, 3]);
+
+        let selection = select(&[], &SelectionConfig::default());
+        assert!(selection.survivors.is_empty());
+        assert!(selection.system.offset.is_nan());
+    }
+
+    #[test]
+    fn test_marzullo_flags_falsetickers() {
+        let peers = [
+            Estimate::from_offset(10.0, 1.0),
+            Estimate::from_offset(11.0, 1.0),
+            Estimate::from_offset(10.5, 0.25),
+            Estimate::from_offset(20.0, 1.0),
+            Estimate::from_offset(f64::NAN, 1.0),
+        ];
+        let intersection = marzullo(&peers, 1.0).unwrap();
+
+        assert_eq!(intersection.lower, 10.25);
+        assert_eq!(intersection.upper, 10.75);
+        assert_eq!(intersection.truechimers, alloc::vec![0, 1, 2]);
+        assert_eq!(intersection.falsetickers, alloc::vec![3, 4]);
+    }
+
+    #[test]
+    fn test_marzullo_requires_majority() {
+        let peers = [
+            Estimate::from_offset(0.0, 1.0),
+            Estimate::from_offset(10.0, 1.0),
+        ];
+        assert_eq!(marzullo(&peers, 1.0), None);
+        assert_eq!(marzullo(&[], 1.0), None);
+    }
+
+    #[test]
+    fn test_fault_tolerant_intersection() {
+        let peers = [
+            Estimate::from_offset(10.0, 1.0),
+            Estimate::from_offset(10.5, 1.0),
+            Estimate::from_offset(9.5, 1.0),
+            Estimate::from_offset(-500.0, 1.0),
+        ];
+        let intersection = fault_tolerant_intersection(&peers, 1.0, 1).unwrap();
+
+        assert_eq!(intersection.lower, 9.5);
+        assert_eq!(intersection.upper, 10.5);
+        assert_eq!(intersection.midpoint(), 10.0);
+        assert_eq!(intersection.falsetickers, alloc::vec![3]);
+    }
+
+    #[test]
+    fn test_fault_tolerant_intersection_bounds() {
+        let peers = [
+            Estimate::from_offset(10.0, 1.0),
+            Estimate::from_offset(10.5, 1.0),
+            Estimate::from_offset(f64::NAN, 1.0),
+        ];
+        assert_eq!(fault_tolerant_intersection(&peers, 1.0, 1), None);
+
+        let peers = [
+            Estimate::from_offset(10.0, 1.0),
+            Estimate::from_offset(10.5, 1.0),
+            Estimate::from_offset(f64::NAN, 1.0),
+            Estimate::from_offset(f64::NAN, 1.0),
+        ];
+        assert_eq!(fault_tolerant_intersection(&peers, 1.0, 1), None);
+    }
+
+    #[test]
+    fn test_select_prefers_low_strata() {
+        let tiered = |offset: f64, stratum: u8| Estimate {
+            source: crate::SourceQuality {
+                stratum,
+                root_dispersion: 0.0,
+            },
+            ..Estimate::from_offset(offset, 1.0)
+        };
+        let peers = [
+            tiered(10.0, 1),
+            tiered(10.2, 2),
+            tiered(12.0, 4),
+            tiered(11.9, 4),
+        ];
+        let selection = select(&peers, &SelectionConfig::default());
+
+        assert_eq!(selection.survivors, alloc::vec![0, 1]);
+        assert_eq!(selection.system.source.stratum, 1);
+    }
+}
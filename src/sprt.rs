@@ -0,0 +1,213 @@
+use crate::error::EstimateError;
+use crate::float;
+use crate::offset_estimator::Estimate;
+
+/// Parameters of [`ShiftMonitor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftConfig {
+    /// Smallest change of the offset worth reacting to, in the unit of the samples.
+    pub threshold: f64,
+    /// Probability of reporting a shift while the offset holds, per test. 0.001 by default.
+    pub false_alarm: f64,
+    /// Probability of missing a shift of `threshold`, per test. 0.01 by default.
+    pub missed_shift: f64,
+}
+
+impl Default for ShiftConfig {
+    fn default() -> Self {
+        ShiftConfig {
+            threshold: 1.0,
+            false_alarm: 1e-3,
+            missed_shift: 0.01,
+        }
+    }
+}
+
+/// Shift of the offset reported by [`ShiftMonitor::update`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffsetShift {
+    /// Offset before the shift, the reference of the test.
+    pub reference: f64,
+    /// Offset of the estimate that decided the test, the new reference.
+    pub offset: f64,
+    /// Number of estimates the test took to decide.
+    pub estimates: usize,
+}
+
+/// Likelihood ratio of a shift in one direction, see [`ShiftMonitor`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Test {
+    log_ratio: f64,
+    estimates: usize,
+}
+
+/// Monitor of successive estimates detecting when the offset has shifted, e.g. after the remote
+/// clock was stepped, with Wald's sequential probability ratio test.
+///
+/// Each estimate is compared with a reference, the first estimate and then the one that decided
+/// the last shift: the log-likelihood ratios that the offset moved by
+/// [`threshold`](ShiftConfig::threshold) up or down rather than not at all accumulate under the
+/// normal model of the [uncertainties](Estimate::uncertainty) of both, until one of them crosses
+/// a decision boundary. Accepting the shift reports it and rebases the reference; rejecting it
+/// starts the test over. Only as many estimates are used as it takes to decide at the
+/// configured error rates, few for a large shift and more for one near the threshold.
+///
+/// ```
+/// use gamlr::{ShiftConfig, ShiftMonitor};
+///
+/// let mut monitor = ShiftMonitor::new(ShiftConfig { threshold: 5.0, ..Default::default() }).unwrap();
+/// # let estimate = |offset: f64| {
+/// #     let mut samples = [gamlr::Sample::new(0.0); 20];
+/// #     for (i, s) in samples.iter_mut().enumerate() {
+/// #         *s = gamlr::Sample::new(offset + (i % 5) as f64);
+/// #     }
+/// #     gamlr::estimate_samples_checked(&mut samples, &mut [0.0; 20], &Default::default()).unwrap()
+/// # };
+/// assert_eq!(monitor.update(&estimate(100.0)), None);
+/// assert_eq!(monitor.update(&estimate(100.0)), None);
+/// // The remote clock was stepped by 20 units.
+/// let shift = monitor.update(&estimate(80.0)).unwrap();
+/// assert!(shift.offset < shift.reference);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftMonitor {
+    config: ShiftConfig,
+    /// Offset of the reference and its variance.
+    reference: Option<(f64, f64)>,
+    up: Test,
+    down: Test,
+}
+
+impl ShiftMonitor {
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `threshold` is not
+    /// positive and finite, or an error rate is not strictly between 0 and 1.
+    pub fn new(config: ShiftConfig) -> Result<Self, EstimateError> {
+        let rate = |p: f64| p > 0.0 && p < 1.0;
+        let valid = [
+            (
+                "threshold",
+                config.threshold.is_finite() && config.threshold > 0.0,
+            ),
+            ("false_alarm", rate(config.false_alarm)),
+            ("missed_shift", rate(config.missed_shift)),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(ShiftMonitor {
+            config,
+            reference: None,
+            up: Test::default(),
+            down: Test::default(),
+        })
+    }
+
+    pub fn config(&self) -> &ShiftConfig {
+        &self.config
+    }
+
+    /// Offset the estimates are compared with, `None` before the first one.
+    pub fn reference(&self) -> Option<f64> {
+        self.reference.map(|(offset, _)| offset)
+    }
+
+    /// Forgets the reference and the pending tests, e.g. after stepping the local clock.
+    pub fn reset(&mut self) {
+        self.reference = None;
+        self.up = Test::default();
+        self.down = Test::default();
+    }
+
+    /// Feeds `estimate` to the test, returning the shift it decided, if any. Estimates with a
+    /// non-finite offset, or an uncertainty that is not positive and finite, are ignored.
+    pub fn update(&mut self, estimate: &Estimate) -> Option<OffsetShift> {
+        let (offset, uncertainty) = (estimate.offset, estimate.uncertainty);
+        if !(offset.is_finite() && uncertainty.is_finite() && uncertainty > 0.0) {
+            return None;
+        }
+        let variance = uncertainty * uncertainty;
+        let Some((reference, reference_variance)) = self.reference else {
+            self.reference = Some((offset, variance));
+            return None;
+        };
+        let ShiftConfig {
+            threshold,
+            false_alarm,
+            missed_shift,
+        } = self.config;
+        let accept = float::ln((1.0 - missed_shift) / false_alarm);
+        let reject = float::ln(missed_shift / (1.0 - false_alarm));
+        let difference = offset - reference;
+        let variance = variance + reference_variance;
+        for (test, sign) in [(&mut self.up, 1.0), (&mut self.down, -1.0)] {
+            test.log_ratio += threshold / variance * (sign * difference - 0.5 * threshold);
+            test.estimates += 1;
+            if test.log_ratio <= reject {
+                *test = Test::default();
+            }
+        }
+        let decided = [self.up, self.down]
+            .into_iter()
+            .find(|test| test.log_ratio >= accept)?;
+        self.reference = Some((offset, uncertainty * uncertainty));
+        self.up = Test::default();
+        self.down = Test::default();
+        Some(OffsetShift {
+            reference,
+            offset,
+            estimates: decided.estimates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate(offset: f64, uncertainty: f64) -> Estimate {
+        Estimate::from_offset(offset, uncertainty)
+    }
+
+    #[test]
+    fn test_shift_monitor() {
+        let mut monitor = ShiftMonitor::new(ShiftConfig {
+            threshold: 2.0,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(monitor.update(&estimate(10.0, 0.0)), None);
+        assert_eq!(monitor.reference(), None);
+        // Noise within the uncertainty never decides a shift.
+        for i in 0..200 {
+            let noise = [0.5, -0.3, 0.1, -0.6, 0.2][i % 5];
+            assert_eq!(monitor.update(&estimate(10.0 + noise, 1.0)), None);
+        }
+        assert_eq!(monitor.reference(), Some(10.5));
+
+        // A shift by the threshold takes a few estimates, a large one a single.
+        let steps = (1..)
+            .find(|_| monitor.update(&estimate(12.5, 1.0)).is_some())
+            .unwrap();
+        assert!((2..10).contains(&steps), "{steps}");
+        assert_eq!(monitor.reference(), Some(12.5));
+        let shift = monitor.update(&estimate(-50.0, 1.0)).unwrap();
+        assert_eq!(
+            shift,
+            OffsetShift {
+                reference: 12.5,
+                offset: -50.0,
+                estimates: 1
+            }
+        );
+
+        assert_eq!(
+            ShiftMonitor::new(ShiftConfig {
+                missed_shift: 1.0,
+                ..Default::default()
+            }),
+            Err(EstimateError::InvalidConfig {
+                field: "missed_shift"
+            })
+        );
+    }
+}
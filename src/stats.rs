@@ -0,0 +1,388 @@
+use alloc::vec::Vec;
+
+use crate::calibration::PeerDelayExchange;
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::math;
+use crate::offset_estimator::prepare;
+use crate::sample::{sort_samples, Sample};
+
+/// Summary statistics of the one-way delays, for the basic delay characterization that
+/// accompanies an offset estimate. Quantiles are weighted, interpolated between the weight
+/// midpoints of the sorted delays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayStats {
+    /// Number of observed delays summarized.
+    pub count: usize,
+    /// Number of censored samples, which are left out of the statistics.
+    pub censored: usize,
+    pub min: f64,
+    pub max: f64,
+    /// Weighted mean delay.
+    pub mean: f64,
+    pub median: f64,
+    /// 95th percentile.
+    pub p95: f64,
+    /// 99th percentile.
+    pub p99: f64,
+    /// Interquartile range, the 75th minus the 25th percentile.
+    pub iqr: f64,
+}
+
+impl DelayStats {
+    /// Statistics of the samples the estimator fits under `config`: after the weight, non-finite,
+    /// detrending, subsampling, window and tail stages of
+    /// [`estimate_samples`](crate::estimate_samples), in the unit of the input, without the shift
+    /// of [`NegativePolicy::Shift`](crate::NegativePolicy::Shift).
+    ///
+    /// Fails where the preprocessing of [`estimate_samples`](crate::estimate_samples) does,
+    /// including with [`EstimateError::InsufficientSamples`] below
+    /// [`EstimatorConfig::min_samples`].
+    ///
+    /// ```
+    /// use gamlr::{DelayStats, EstimatorConfig, Sample};
+    ///
+    /// let owds = [0.34, 0.36, 0.35, 0.41, 0.33, 0.38, 0.52, 0.35, 0.37, 0.36];
+    /// let stats =
+    ///     DelayStats::from_samples(owds.map(Sample::new), &EstimatorConfig::default()).unwrap();
+    /// assert_eq!((stats.min, stats.max), (0.33, 0.52));
+    /// assert!((stats.median - 0.36).abs() < 1e-12);
+    /// ```
+    pub fn from_samples<I>(samples: I, config: &EstimatorConfig) -> Result<Self, EstimateError>
+    where
+        I: IntoIterator<Item = Sample>,
+    {
+        let mut samples: Vec<Sample> = samples.into_iter().collect();
+        let shift = prepare(&mut samples, config)?.shift;
+        sort_samples(&mut samples);
+        let censored = samples.iter().filter(|s| s.censored).count();
+        let observed = &samples[..samples.len() - censored];
+        let total = math::sum(observed.iter().map(|s| s.weight), config.precise);
+        let quantile = |q: f64| quantile(observed, total, q) - shift;
+        Ok(DelayStats {
+            count: observed.len(),
+            censored,
+            min: observed[0].value - shift,
+            max: observed[observed.len() - 1].value - shift,
+            mean: math::sum(observed.iter().map(|s| s.weight * s.value), config.precise) / total
+                - shift,
+            median: quantile(0.5),
+            p95: quantile(0.95),
+            p99: quantile(0.99),
+            iqr: quantile(0.75) - quantile(0.25),
+        })
+    }
+}
+
+/// IP packet delay variation of a probe stream after RFC 3393: the differences between the
+/// one-way delays of the packet pairs `lag` apart in the order of the stream, `lag` one for
+/// consecutive packets. Quantiles are interpolated like those of [`DelayStats`].
+///
+/// The clock offset cancels in every difference, so unlike the delays the variation needs no
+/// synchronization; a drift of the clocks adds its rate times the spacing of the pairs.
+///
+/// C. Demichelis, P. Chimento. "IP Packet Delay Variation Metric for IP Performance Metrics
+/// (IPPM)". RFC 3393, 2002.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayVariation {
+    /// Number of pairs with a defined variation.
+    pub pairs: usize,
+    /// Number of pairs whose variation is undefined because a packet was lost, i.e. censored,
+    /// or its delay is not finite.
+    pub undefined: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// 95th percentile.
+    pub p95: f64,
+    /// 99th percentile.
+    pub p99: f64,
+    /// 99th percentile of the magnitude of the variation, whichever its sign.
+    pub p99_magnitude: f64,
+}
+
+impl DelayVariation {
+    /// Variation of the delays of `samples`, in the order of the stream; weights are ignored.
+    ///
+    /// Fails with [`EstimateError::InvalidConfig`] for a zero `lag`, and with
+    /// [`EstimateError::InsufficientSamples`], counting pairs, when no pair is defined.
+    ///
+    /// ```
+    /// use gamlr::{DelayVariation, Sample};
+    ///
+    /// let owds = [10.0, 12.0, 11.0, 11.0, 15.0];
+    /// let ipdv = DelayVariation::from_samples(owds.map(Sample::new), 1, false).unwrap();
+    /// assert_eq!((ipdv.pairs, ipdv.min, ipdv.max), (4, -1.0, 4.0));
+    /// ```
+    pub fn from_samples<I>(samples: I, lag: usize, precise: bool) -> Result<Self, EstimateError>
+    where
+        I: IntoIterator<Item = Sample>,
+    {
+        if lag == 0 {
+            return Err(EstimateError::InvalidConfig { field: "lag" });
+        }
+        let delays: Vec<Option<f64>> = samples
+            .into_iter()
+            .map(|s| (!s.censored && s.value.is_finite()).then_some(s.value))
+            .collect();
+        let pairs = delays.len().saturating_sub(lag);
+        let mut variations: Vec<Sample> = delays
+            .iter()
+            .zip(&delays[lag.min(delays.len())..])
+            .filter_map(|(first, second)| Some(Sample::new((*second)? - (*first)?)))
+            .collect();
+        if variations.is_empty() {
+            return Err(EstimateError::InsufficientSamples { got: 0, need: 1 });
+        }
+        let count = variations.len();
+        let total = count as f64;
+        let mean = math::sum(variations.iter().map(|s| s.value), precise) / total;
+        sort_samples(&mut variations);
+        let (min, max, median, p95, p99) = (
+            variations[0].value,
+            variations[count - 1].value,
+            quantile(&variations, total, 0.5),
+            quantile(&variations, total, 0.95),
+            quantile(&variations, total, 0.99),
+        );
+        for s in variations.iter_mut() {
+            s.value = libm::fabs(s.value);
+        }
+        sort_samples(&mut variations);
+        Ok(DelayVariation {
+            pairs: count,
+            undefined: pairs - count,
+            min,
+            max,
+            mean,
+            median,
+            p95,
+            p99,
+            p99_magnitude: quantile(&variations, total, 0.99),
+        })
+    }
+}
+
+/// Statistics of a stream of one-way delays after RFC 2679, or of round-trip delays after
+/// RFC 2681, the standard metrics of the IETF IP Performance Metrics working group.
+///
+/// Lost packets, i.e. censored samples, as well as non-finite delays have an undefined delay,
+/// which the statistics treat as infinitely large: a percentile, and the median or minimum, is
+/// infinite where it falls on one. Percentiles are empirical, the smallest delay of the stream at
+/// or below which the given percentage of its delays lies, with no interpolation.
+///
+/// G. Almes, S. Kalidindi, M. Zekauskas. "A One-way Delay Metric for IPPM". RFC 2679, 1999.
+/// G. Almes, S. Kalidindi, M. Zekauskas. "A Round-trip Delay Metric for IPPM". RFC 2681, 1999.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelayMetrics {
+    /// Delays of the stream in ascending order, infinite when undefined.
+    delays: Vec<f64>,
+}
+
+impl DelayMetrics {
+    /// Metrics of the delays of `samples`, one-way or round-trip alike; weights and timestamps
+    /// are ignored.
+    ///
+    /// Fails with [`EstimateError::InsufficientSamples`] for an empty stream.
+    ///
+    /// ```
+    /// use gamlr::{DelayMetrics, Sample};
+    ///
+    /// let owds = [Sample::new(12.0), Sample::new(10.0), Sample::timed_out(100.0), Sample::new(11.0)];
+    /// let metrics = DelayMetrics::from_samples(owds).unwrap();
+    /// assert_eq!((metrics.minimum(), metrics.median()), (10.0, 11.5));
+    /// assert_eq!(metrics.percentile(75.0), 12.0);
+    /// assert_eq!(metrics.percentile(90.0), f64::INFINITY);
+    /// ```
+    pub fn from_samples<I>(samples: I) -> Result<Self, EstimateError>
+    where
+        I: IntoIterator<Item = Sample>,
+    {
+        let mut delays: Vec<f64> = samples
+            .into_iter()
+            .map(|s| match !s.censored && s.value.is_finite() {
+                true => s.value,
+                false => f64::INFINITY,
+            })
+            .collect();
+        if delays.is_empty() {
+            return Err(EstimateError::InsufficientSamples { got: 0, need: 1 });
+        }
+        delays.sort_unstable_by(f64::total_cmp);
+        Ok(DelayMetrics { delays })
+    }
+
+    /// Round-trip metrics of RFC 2681 from the exchanges, `t4 - t1` at the local clock. The
+    /// round trip includes the turnaround of the peer, `t3 - t2`, which the RFC requires to be as
+    /// short as possible.
+    pub fn from_exchanges(exchanges: &[PeerDelayExchange]) -> Result<Self, EstimateError> {
+        DelayMetrics::from_samples(exchanges.iter().map(|e| Sample::new(e.t4 - e.t1)))
+    }
+
+    /// Number of packets in the stream.
+    pub fn packets(&self) -> usize {
+        self.delays.len()
+    }
+
+    /// Number of packets whose delay is undefined.
+    pub fn lost(&self) -> usize {
+        self.delays
+            .iter()
+            .rev()
+            .take_while(|d| d.is_infinite())
+            .count()
+    }
+
+    /// Type-P-One-way-Delay-Percentile, or its round-trip counterpart, for `percent` from 0 to
+    /// 100: the smallest delay with at least `percent` of the stream at or below it. NaN for a
+    /// `percent` outside that range.
+    pub fn percentile(&self, percent: f64) -> f64 {
+        if !(0.0..=100.0).contains(&percent) {
+            return f64::NAN;
+        }
+        let n = self.delays.len();
+        let rank = libm::ceil(percent / 100.0 * n as f64) as usize;
+        self.delays[rank.clamp(1, n) - 1]
+    }
+
+    /// Type-P-One-way-Delay-Median: the 50th percentile, or the mean of the two central delays
+    /// of a stream of even length.
+    pub fn median(&self) -> f64 {
+        let n = self.delays.len();
+        match n % 2 {
+            0 => (self.delays[n / 2 - 1] + self.delays[n / 2]) / 2.0,
+            _ => self.delays[n / 2],
+        }
+    }
+
+    /// Type-P-One-way-Delay-Minimum, infinite when every packet was lost.
+    pub fn minimum(&self) -> f64 {
+        self.delays[0]
+    }
+
+    /// Type-P-One-way-Delay-Inverse-Percentile: the percentage of the stream with a delay at or
+    /// below `threshold`.
+    pub fn inverse_percentile(&self, threshold: f64) -> f64 {
+        let below = self.delays.partition_point(|&d| d <= threshold);
+        100.0 * below as f64 / self.delays.len() as f64
+    }
+}
+
+/// Weighted `q` quantile of the non-empty `sorted` samples of total weight `total`, linear
+/// between the midpoints of their weights.
+fn quantile(sorted: &[Sample], total: f64, q: f64) -> f64 {
+    let target = q * total;
+    let mut below = 0.0;
+    let mut previous: Option<(f64, f64)> = None;
+    for s in sorted {
+        let midpoint = below + 0.5 * s.weight;
+        below += s.weight;
+        if midpoint >= target {
+            return match previous {
+                Some((at, value)) if midpoint > at => {
+                    value + (s.value - value) * (target - at) / (midpoint - at)
+                }
+                _ => s.value,
+            };
+        }
+        previous = Some((midpoint, s.value));
+    }
+    sorted[sorted.len() - 1].value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_stats() {
+        // 1..=100 with a timeout and a NaN the estimator drops.
+        let samples = (1..=100)
+            .map(|i| Sample::new(f64::from(i)))
+            .chain([Sample::timed_out(500.0), Sample::new(f64::NAN)]);
+        let config = EstimatorConfig {
+            non_finite: crate::NonFinitePolicy::Drop,
+            ..Default::default()
+        };
+        let stats = DelayStats::from_samples(samples.clone(), &config).unwrap();
+        assert_eq!((stats.count, stats.censored), (100, 1));
+        assert_eq!((stats.min, stats.max, stats.mean), (1.0, 100.0, 50.5));
+        assert!((stats.median - 50.5).abs() < 1e-12);
+        assert!((stats.p95 - 95.5).abs() < 1e-12);
+        assert!((stats.p99 - 99.5).abs() < 1e-12);
+        assert!((stats.iqr - 50.0).abs() < 1e-12);
+
+        // Shifted for the fit, reported as given.
+        let negative = DelayStats::from_samples(
+            samples.map(|s| Sample {
+                value: s.value - 200.0,
+                ..s
+            }),
+            &EstimatorConfig {
+                negative: crate::NegativePolicy::Shift,
+                ..config
+            },
+        )
+        .unwrap();
+        assert_eq!((negative.min, negative.median), (-199.0, -149.5));
+        assert_eq!(
+            DelayStats::from_samples([Sample::new(1.0)], &EstimatorConfig::default()),
+            Err(EstimateError::InsufficientSamples { got: 1, need: 10 })
+        );
+    }
+
+    #[test]
+    fn test_delay_variation() {
+        // A sawtooth of 0, 1, 2, 3 with a lost packet and a NaN.
+        let mut samples: Vec<Sample> = (0..100).map(|i| Sample::new(f64::from(i % 4))).collect();
+        samples[10] = Sample::timed_out(50.0);
+        samples[20].value = f64::NAN;
+        let ipdv = DelayVariation::from_samples(samples.iter().copied(), 1, true).unwrap();
+        assert_eq!((ipdv.pairs, ipdv.undefined), (95, 4));
+        assert_eq!((ipdv.min, ipdv.max, ipdv.median), (-3.0, 1.0, 1.0));
+        assert_eq!(ipdv.p99_magnitude, 3.0);
+        // Pairs a period apart do not vary, and the offset cancels.
+        let shifted = samples.iter().map(|s| Sample {
+            value: s.value + 1e3,
+            ..*s
+        });
+        let ipdv = DelayVariation::from_samples(shifted, 4, false).unwrap();
+        assert_eq!((ipdv.pairs, ipdv.min, ipdv.max), (92, 0.0, 0.0));
+        assert_eq!(
+            DelayVariation::from_samples(samples, 0, false),
+            Err(EstimateError::InvalidConfig { field: "lag" })
+        );
+    }
+
+    #[test]
+    fn test_delay_metrics() {
+        let mut samples: Vec<Sample> = (1..=10).map(|i| Sample::new(f64::from(i))).collect();
+        samples[3] = Sample::timed_out(1e3);
+        let metrics = DelayMetrics::from_samples(samples).unwrap();
+        assert_eq!((metrics.packets(), metrics.lost()), (10, 1));
+        assert_eq!(metrics.minimum(), 1.0);
+        assert_eq!(metrics.median(), 6.5);
+        assert_eq!(metrics.percentile(0.0), 1.0);
+        assert_eq!(metrics.percentile(50.0), 6.0);
+        assert_eq!(metrics.percentile(90.0), 10.0);
+        assert_eq!(metrics.percentile(95.0), f64::INFINITY);
+        assert!(metrics.percentile(101.0).is_nan());
+        assert_eq!(metrics.inverse_percentile(5.0), 40.0);
+
+        let exchanges = [PeerDelayExchange {
+            t1: 0.0,
+            t2: 7.0,
+            t3: 8.0,
+            t4: 10.0,
+        }];
+        assert_eq!(
+            DelayMetrics::from_exchanges(&exchanges).unwrap().median(),
+            10.0
+        );
+        assert_eq!(
+            DelayMetrics::from_samples([]),
+            Err(EstimateError::InsufficientSamples { got: 0, need: 1 })
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::error::EstimateError;
+use crate::offset_estimator::Estimate;
+use crate::online::OnlineEstimator;
+
+/// Tick source that never fires, used when estimates are only produced every k samples.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoTicks;
+
+impl Stream for NoTicks {
+    type Item = ();
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<()>> {
+        Poll::Pending
+    }
+}
+
+/// Stream adapter turning a stream of one-way delay samples into a stream of rolling estimates.
+///
+/// An estimate over the [`OnlineEstimator`] window is produced after every `every` new samples,
+/// whenever the tick stream set with [`EstimateStream::with_ticks`] fires and new samples have
+/// arrived since the last estimate, and once more when the sample stream ends with unreported
+/// samples. Ticks can come from any runtime, e.g. a `tokio` interval wrapped as a stream, so the
+/// adapter does not depend on one.
+///
+/// Both streams must be `Unpin`; wrap other streams with `Box::pin`.
+#[derive(Debug)]
+pub struct EstimateStream<S, T = NoTicks> {
+    samples: S,
+    ticks: Option<T>,
+    estimator: OnlineEstimator,
+    every: usize,
+    pending: usize,
+}
+
+impl<S> EstimateStream<S, NoTicks> {
+    /// Wraps `samples`, producing an estimate after every `every` samples (at least one).
+    pub fn new(samples: S, estimator: OnlineEstimator, every: usize) -> Self {
+        EstimateStream {
+            samples,
+            ticks: Some(NoTicks),
+            estimator,
+            every: every.max(1),
+            pending: 0,
+        }
+    }
+}
+
+impl<S, T> EstimateStream<S, T> {
+    /// Additionally produces an estimate on every item of `ticks`.
+    pub fn with_ticks<U>(self, ticks: U) -> EstimateStream<S, U> {
+        EstimateStream {
+            samples: self.samples,
+            ticks: Some(ticks),
+            estimator: self.estimator,
+            every: self.every,
+            pending: self.pending,
+        }
+    }
+
+    pub fn estimator(&self) -> &OnlineEstimator {
+        &self.estimator
+    }
+
+    fn emit(&mut self) -> Poll<Option<Result<Estimate, EstimateError>>> {
+        self.pending = 0;
+        Poll::Ready(Some(self.estimator.estimate()))
+    }
+}
+
+impl<S, T> Stream for EstimateStream<S, T>
+where
+    S: Stream<Item = f64> + Unpin,
+    T: Stream + Unpin,
+{
+    type Item = Result<Estimate, EstimateError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(ticks) = this.ticks.as_mut() {
+                match Pin::new(ticks).poll_next(cx) {
+                    Poll::Ready(Some(_)) if this.pending > 0 => return this.emit(),
+                    Poll::Ready(None) => this.ticks = None,
+                    _ => {}
+                }
+            }
+            match Pin::new(&mut this.samples).poll_next(cx) {
+                Poll::Ready(Some(value)) => {
+                    this.estimator.push(value);
+                    this.pending += 1;
+                    if this.pending >= this.every {
+                        return this.emit();
+                    }
+                }
+                Poll::Ready(None) if this.pending > 0 => return this.emit(),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EstimatorConfig;
+    use alloc::vec::Vec;
+    use core::task::Waker;
+
+    struct IterStream<I>(I);
+
+    impl<I: Iterator + Unpin> Stream for IterStream<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+
+    fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn test_estimate_stream_every_k_samples() {
+        let samples = IterStream((0..25).map(|i| 100.0 + f64::from(i % 7) * 10.0));
+        let estimator = OnlineEstimator::new(EstimatorConfig::default(), 100);
+        let estimates = collect(EstimateStream::new(samples, estimator, 10));
+
+        let sizes: Vec<usize> = estimates
+            .iter()
+            .map(|e| e.as_ref().map_or(0, |e| e.samples))
+            .collect();
+        assert_eq!(sizes, alloc::vec![10, 20, 25]);
+    }
+
+    #[test]
+    fn test_estimate_stream_ticks() {
+        let samples = IterStream((0..12).map(|i| 100.0 + f64::from(i % 5) * 10.0));
+        let ticks = IterStream(core::iter::repeat(()));
+        let estimator = OnlineEstimator::new(EstimatorConfig::default(), 100);
+        let stream = EstimateStream::new(samples, estimator, 1000).with_ticks(ticks);
+
+        // Every poll after the first sample sees a tick with one new sample pending.
+        let estimates = collect(stream);
+        assert_eq!(estimates.len(), 12);
+        assert!(estimates[11].is_ok());
+    }
+}
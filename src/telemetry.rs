@@ -0,0 +1,173 @@
+//! OpenTelemetry spans and metrics around estimation runs, emitted through the global tracer and
+//! meter providers under the `gamlr` instrumentation scope.
+//!
+//! Each run of the pipeline opens a `gamlr.estimate` span carrying the input size, the model and,
+//! once done, the offset and its uncertainty or the error, and records:
+//!
+//! - `gamlr.estimate.duration`: the duration of the run, in seconds;
+//! - `gamlr.estimate.input_size`: the samples passed in;
+//! - `gamlr.estimate.uncertainty`: the uncertainty of the estimates, in sample units.
+//!
+//! All of them carry the `gamlr.fit`, `gamlr.synthetic` and `gamlr.regression` model attributes
+//! and the `gamlr.outcome`, `ok` or `error`. The instruments are created on the first run, so the
+//! meter provider has to be installed before.
+
+use std::string::ToString;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use opentelemetry::metrics::Histogram;
+use opentelemetry::trace::{Span, Status, Tracer};
+use opentelemetry::{global, KeyValue};
+
+use crate::config::{EstimatorConfig, GammaFit, Regression, SyntheticSample};
+use crate::error::EstimateError;
+use crate::offset_estimator::Estimate;
+
+const SCOPE: &str = "gamlr";
+
+struct Instruments {
+    duration: Histogram<f64>,
+    input_size: Histogram<u64>,
+    uncertainty: Histogram<f64>,
+}
+
+fn instruments() -> &'static Instruments {
+    static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+    INSTRUMENTS.get_or_init(|| {
+        let meter = global::meter(SCOPE);
+        Instruments {
+            duration: meter
+                .f64_histogram("gamlr.estimate.duration")
+                .with_unit("s")
+                .with_description("Duration of the estimation runs.")
+                .build(),
+            input_size: meter
+                .u64_histogram("gamlr.estimate.input_size")
+                .with_unit("{sample}")
+                .with_description("Samples passed to the estimation runs.")
+                .build(),
+            uncertainty: meter
+                .f64_histogram("gamlr.estimate.uncertainty")
+                .with_description("Standard error of the estimated offsets.")
+                .build(),
+        }
+    })
+}
+
+/// Model attributes of `config`.
+fn model(config: &EstimatorConfig) -> [KeyValue; 3] {
+    let fit = match config.fit {
+        GammaFit::Moments => "moments",
+        GammaFit::ProbabilityWeighted => "probability_weighted",
+        GammaFit::LMoments => "l_moments",
+    };
+    let synthetic = match config.synthetic {
+        SyntheticSample::Random => "random",
+        SyntheticSample::Quantiles => "quantiles",
+    };
+    let regression = match config.regression {
+        Regression::LeastSquares => "least_squares",
+        Regression::Deming { .. } => "deming",
+        Regression::PassingBablok => "passing_bablok",
+        Regression::RepeatedMedian => "repeated_median",
+        Regression::TrimmedSquares { .. } => "trimmed_squares",
+    };
+    [
+        KeyValue::new("gamlr.fit", fit),
+        KeyValue::new("gamlr.synthetic", synthetic),
+        KeyValue::new("gamlr.regression", regression),
+    ]
+}
+
+/// A run in progress, from [`start`] to [`finish`].
+pub(crate) struct Run {
+    span: global::BoxedSpan,
+    started: Instant,
+    samples: usize,
+    model: [KeyValue; 3],
+}
+
+/// Opens the span of a run on `samples` samples.
+pub(crate) fn start(samples: usize, config: &EstimatorConfig) -> Run {
+    let model = model(config);
+    let mut span = global::tracer(SCOPE).start("gamlr.estimate");
+    span.set_attribute(KeyValue::new("gamlr.input_size", samples as i64));
+    span.set_attributes(model.clone());
+    Run {
+        span,
+        started: Instant::now(),
+        samples,
+        model,
+    }
+}
+
+/// Closes the span of `run` and records its metrics.
+pub(crate) fn finish(run: Run, result: &Result<Estimate, EstimateError>) {
+    let Run {
+        mut span,
+        started,
+        samples,
+        model,
+    } = run;
+    let elapsed = started.elapsed().as_secs_f64();
+    let outcome = match result {
+        Ok(estimate) => {
+            span.set_attributes([
+                KeyValue::new("gamlr.offset", estimate.offset),
+                KeyValue::new("gamlr.uncertainty", estimate.uncertainty),
+                KeyValue::new("gamlr.samples", estimate.samples as i64),
+            ]);
+            "ok"
+        }
+        Err(error) => {
+            span.set_status(Status::error(error.to_string()));
+            "error"
+        }
+    };
+    let [fit, synthetic, regression] = model;
+    let attributes = [
+        fit,
+        synthetic,
+        regression,
+        KeyValue::new("gamlr.outcome", outcome),
+    ];
+    let instruments = instruments();
+    instruments.duration.record(elapsed, &attributes);
+    instruments.input_size.record(samples as u64, &attributes);
+    if let Ok(estimate) = result {
+        instruments
+            .uncertainty
+            .record(estimate.uncertainty, &attributes);
+    }
+    span.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_attributes() {
+        let config = EstimatorConfig {
+            fit: GammaFit::LMoments,
+            synthetic: SyntheticSample::Quantiles,
+            regression: Regression::RepeatedMedian,
+            ..Default::default()
+        };
+        assert_eq!(
+            model(&config),
+            [
+                KeyValue::new("gamlr.fit", "l_moments"),
+                KeyValue::new("gamlr.synthetic", "quantiles"),
+                KeyValue::new("gamlr.regression", "repeated_median"),
+            ]
+        );
+        // Without providers installed, the no-op ones take the run.
+        let run = start(3, &config);
+        finish(
+            run,
+            &Err(EstimateError::InsufficientSamples { got: 3, need: 10 }),
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Kernel timestamps of `SO_TIMESTAMPING` sockets, taken by the network stack or the NIC at the
+//! moment a packet leaves or arrives, instead of whenever the application gets to read the
+//! clock.
+
+use std::io;
+use std::mem::{size_of, MaybeUninit};
+use std::os::fd::{AsFd, AsRawFd};
+use std::time::Duration;
+
+use crate::sample::{nanos_sample, Sample};
+
+/// Control buffer of `recvmsg`, aligned for `cmsghdr`, large enough for the timestamps and the
+/// extended error of an error queue message.
+type Control = [u64; 64];
+
+/// Timestamps the kernel attached to a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timestamps {
+    /// Taken by the network stack, in `CLOCK_REALTIME`.
+    pub software: Option<Duration>,
+    /// Taken by the NIC, in the time of its hardware clock.
+    pub hardware: Option<Duration>,
+}
+
+impl Timestamps {
+    /// The hardware timestamp if there is one, the software one otherwise.
+    pub fn best(&self) -> Option<Duration> {
+        self.hardware.or(self.software)
+    }
+}
+
+/// Enables the kernel timestamps of `socket`: software timestamps of received and sent packets,
+/// and with `hardware` those of the NIC as well. Hardware timestamps further need the interface
+/// to be configured for them, e.g. with `hwstamp_ctl` or `SIOCSHWTSTAMP`.
+pub fn enable_timestamping(socket: &impl AsFd, hardware: bool) -> io::Result<()> {
+    let mut flags = libc::SOF_TIMESTAMPING_SOFTWARE
+        | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+    if hardware {
+        flags |= libc::SOF_TIMESTAMPING_RAW_HARDWARE
+            | libc::SOF_TIMESTAMPING_RX_HARDWARE
+            | libc::SOF_TIMESTAMPING_TX_HARDWARE;
+    }
+    // SAFETY: `flags` outlives the call and its size is passed along.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_fd().as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPING,
+            (&flags as *const libc::c_uint).cast(),
+            size_of::<libc::c_uint>() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Receives a packet into `buf` like `recv`, together with its receive timestamps.
+///
+/// Returns the length of the packet and its timestamps, empty unless
+/// [`enable_timestamping`] was called on the socket.
+pub fn recv_timestamped(socket: &impl AsFd, buf: &mut [u8]) -> io::Result<(usize, Timestamps)> {
+    recv(socket, buf, 0)
+}
+
+/// Fetches the transmit timestamps of the oldest packet sent on `socket` from its error queue,
+/// without blocking. `None` when no timestamp is queued yet; the kernel queues them shortly after
+/// the packet left.
+pub fn recv_tx_timestamps(socket: &impl AsFd) -> io::Result<Option<Timestamps>> {
+    match recv(socket, &mut [], libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) {
+        Ok((_, timestamps)) => Ok(Some(timestamps)),
+        Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// One-way delay sample of a packet `sent` by the remote side and `received` here, both since
+/// the Unix epoch, e.g. the transmit timestamp the peer wrote into its payload and the
+/// [`best`](Timestamps::best) receive timestamp. The delay is in nanoseconds and the sample is
+/// timestamped with `received`, in nanoseconds, like the samples of `ClockSampler`.
+pub fn owd_sample(sent: Duration, received: Duration) -> Sample {
+    nanos_sample(sent, received)
+}
+
+fn recv(socket: &impl AsFd, buf: &mut [u8], flags: libc::c_int) -> io::Result<(usize, Timestamps)> {
+    let mut control = MaybeUninit::<Control>::zeroed();
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // SAFETY: an all-zero `msghdr` is a valid empty header.
+    let mut message: libc::msghdr = unsafe { MaybeUninit::zeroed().assume_init() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = size_of::<Control>() as _;
+    // SAFETY: `message` points at `iov` and `control`, which outlive the call.
+    let len = unsafe { libc::recvmsg(socket.as_fd().as_raw_fd(), &mut message, flags) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut timestamps = Timestamps::default();
+    // SAFETY: the kernel filled `message.msg_controllen` bytes of `control` with well-formed
+    // control messages, which the `CMSG` macros walk within those bounds.
+    unsafe {
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+        while !header.is_null() {
+            if (*header).cmsg_level == libc::SOL_SOCKET
+                && (*header).cmsg_type == libc::SCM_TIMESTAMPING
+            {
+                // `struct scm_timestamping`: software, deprecated and raw hardware timestamps.
+                let times = libc::CMSG_DATA(header).cast::<[libc::timespec; 3]>();
+                let [software, _, hardware] = times.read_unaligned();
+                timestamps.software = duration(software);
+                timestamps.hardware = duration(hardware);
+            }
+            header = libc::CMSG_NXTHDR(&message, header);
+        }
+    }
+    Ok((len as usize, timestamps))
+}
+
+/// The time of `ts`, `None` for the zero the kernel leaves in timestamps it did not take.
+fn duration(ts: libc::timespec) -> Option<Duration> {
+    let secs = u64::try_from(ts.tv_sec).ok()?;
+    let nanos = u32::try_from(ts.tv_nsec).ok()?;
+    (secs != 0 || nanos != 0).then(|| Duration::new(secs, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_software_timestamps_on_loopback() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        enable_timestamping(&receiver, false).unwrap();
+        enable_timestamping(&sender, false).unwrap();
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        sender
+            .send_to(b"probe", receiver.local_addr().unwrap())
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let (len, rx) = recv_timestamped(&receiver, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"probe");
+        let received = rx.best().unwrap();
+        assert!(received >= before && received - before < Duration::from_secs(5));
+
+        let start = Instant::now();
+        let tx = loop {
+            if let Some(tx) = recv_tx_timestamps(&sender).unwrap() {
+                break tx;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no TX timestamp");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        let sent = tx.software.unwrap();
+        assert!(sent >= before && sent <= received);
+
+        let sample = owd_sample(sent, received);
+        assert!(sample.value >= 0.0 && sample.value < 5e9);
+        assert_eq!(sample.timestamp, Some(received.as_nanos() as f64));
+        assert_eq!(recv_tx_timestamps(&sender).unwrap(), None);
+        assert!(owd_sample(received, sent).value <= 0.0);
+    }
+}
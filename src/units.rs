@@ -0,0 +1,130 @@
+/// Unit of time of a delay or an offset, for APIs that take and return durations in a stated
+/// unit rather than bare `f64`s, which are easily collected in one unit and read in another.
+///
+/// Conversions go through [`to`](Self::to), or `From` between the units of this crate and, with
+/// the `uom` feature, to and from `uom::si::f64::Time` quantities:
+///
+/// ```
+/// use gamlr::{Micros, Millis, Nanos, TimeUnit};
+///
+/// let delay = Micros(350.0);
+/// assert_eq!(delay.to::<Nanos>(), Nanos(350_000.0));
+/// assert_eq!(Millis::from(delay), Millis(0.35));
+/// ```
+pub trait TimeUnit: Copy {
+    /// Number of units in a second.
+    const PER_SECOND: f64;
+
+    fn new(value: f64) -> Self;
+
+    /// The magnitude in this unit.
+    fn get(self) -> f64;
+
+    /// The same duration in the unit `U`.
+    fn to<U: TimeUnit>(self) -> U {
+        U::new(self.get() * U::PER_SECOND / Self::PER_SECOND)
+    }
+
+    /// The duration as a dimensional quantity.
+    #[cfg(feature = "uom")]
+    fn time(self) -> uom::si::f64::Time {
+        self.to::<Seconds>().into()
+    }
+}
+
+macro_rules! time_units {
+    ($($(#[$doc:meta])* $name:ident = $per_second:expr, $uom:ident;)*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+            pub struct $name(pub f64);
+
+            impl TimeUnit for $name {
+                const PER_SECOND: f64 = $per_second;
+
+                fn new(value: f64) -> Self {
+                    $name(value)
+                }
+
+                fn get(self) -> f64 {
+                    self.0
+                }
+            }
+
+            impl From<core::time::Duration> for $name {
+                fn from(duration: core::time::Duration) -> Self {
+                    Seconds(duration.as_secs_f64()).to()
+                }
+            }
+
+            #[cfg(feature = "uom")]
+            impl From<uom::si::f64::Time> for $name {
+                fn from(time: uom::si::f64::Time) -> Self {
+                    $name(time.get::<uom::si::time::$uom>())
+                }
+            }
+
+            #[cfg(feature = "uom")]
+            impl From<$name> for uom::si::f64::Time {
+                fn from(value: $name) -> Self {
+                    uom::si::f64::Time::new::<uom::si::time::$uom>(value.0)
+                }
+            }
+        )*
+        time_units!(@convert $($name)*);
+    };
+    (@convert $first:ident $($rest:ident)*) => {
+        $(
+            impl From<$first> for $rest {
+                fn from(value: $first) -> Self {
+                    value.to()
+                }
+            }
+
+            impl From<$rest> for $first {
+                fn from(value: $rest) -> Self {
+                    value.to()
+                }
+            }
+        )*
+        time_units!(@convert $($rest)*);
+    };
+    (@convert) => {};
+}
+
+time_units! {
+    /// Nanoseconds, the unit of hardware timestamps.
+    Nanos = 1e9, nanosecond;
+    /// Microseconds.
+    Micros = 1e6, microsecond;
+    /// Milliseconds, the unit of most software probes.
+    Millis = 1e3, millisecond;
+    /// Seconds.
+    Seconds = 1.0, second;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_units() {
+        assert_eq!(Seconds(1.5).to::<Millis>(), Millis(1500.0));
+        assert_eq!(Nanos::from(Seconds(2.0)), Nanos(2e9));
+        assert_eq!(Seconds::from(Nanos(2e9)), Seconds(2.0));
+        let duration = core::time::Duration::from_micros(1500);
+        assert_eq!(Micros::from(duration), Micros(1500.0));
+        assert_eq!(Millis::from(duration), Millis(1.5));
+    }
+
+    #[cfg(feature = "uom")]
+    #[test]
+    fn test_uom_time() {
+        use uom::si::f64::Time;
+        use uom::si::time::{microsecond, millisecond};
+
+        let time = Time::new::<millisecond>(1.5);
+        assert_eq!(Micros::from(time), Micros(1500.0));
+        assert_eq!(Time::from(Micros(1500.0)).get::<microsecond>(), 1500.0);
+    }
+}
@@ -0,0 +1,195 @@
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::cancel;
+use crate::config::EstimatorConfig;
+use crate::error::EstimateError;
+use crate::float;
+use crate::math;
+use crate::offset_estimator::{estimate_samples, fit_gamma, prepare, seed, LcgRng};
+use crate::sample::{sort_samples, Sample};
+
+/// Scores how well the Gamma delay model generalizes to unseen samples by k-fold cross-validation.
+///
+/// After the preprocessing configured in `config`, the samples are dealt round-robin into `folds`
+/// folds (at least two). For each fold, the Gamma model is fitted on the other folds exactly as
+/// the estimator fits it and the held-out samples are scored by their log-likelihood under it.
+/// Returns the weighted mean held-out log-likelihood per sample: higher is better, and unlike the
+/// in-sample fit it does not improve by overfitting a small batch. Held-out samples outside the
+/// support of the model, i.e. not positive, score negative infinity. Censored held-out samples
+/// score the log probability of a delay beyond their timeout.
+///
+/// Fails like [`estimate_samples`](crate::estimate_samples), and with
+/// [`EstimateError::InsufficientSamples`] when a training set would fall below
+/// [`EstimatorConfig::min_samples`].
+pub fn cross_validate<I>(
+    samples: I,
+    folds: usize,
+    config: &EstimatorConfig,
+) -> Result<f64, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    cross_validate_with_progress(samples, folds, config, |_, _| ControlFlow::Continue(()))
+}
+
+/// [`cross_validate`], calling `progress` with the folds scored and the total after each fold.
+/// When `progress` breaks, the validation stops with [`EstimateError::Cancelled`].
+pub fn cross_validate_with_progress<I, F>(
+    samples: I,
+    folds: usize,
+    config: &EstimatorConfig,
+    mut progress: F,
+) -> Result<f64, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+    F: FnMut(usize, usize) -> ControlFlow<()>,
+{
+    let mut samples: Vec<Sample> = samples.into_iter().collect();
+    prepare(&mut samples, config)?;
+    // Sorted once, so that every training set comes out sorted for the fit.
+    sort_samples(&mut samples);
+    let n = samples.len();
+    let folds = folds.max(2);
+    let need = config.min_samples.max(2);
+    let smallest_training = n - n.div_ceil(folds);
+    if smallest_training < need {
+        return Err(EstimateError::InsufficientSamples {
+            got: smallest_training,
+            need,
+        });
+    }
+
+    let mut training = Vec::with_capacity(n);
+    let mut log_likelihood = 0.0;
+    let mut weight = 0.0;
+    for fold in 0..folds {
+        training.clear();
+        training.extend(
+            samples
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % folds != fold)
+                .map(|(_, s)| *s),
+        );
+        let (alpha, beta) = fit_gamma(&training, config)?;
+        for sample in samples.iter().skip(fold).step_by(folds) {
+            let score = match sample.censored {
+                true => float::ln(math::gamma_sf(alpha, beta, sample.value)),
+                false => math::gamma_ln_pdf(alpha, beta, sample.value),
+            };
+            log_likelihood += sample.weight * score;
+            weight += sample.weight;
+        }
+        if progress(fold + 1, folds).is_break() {
+            return Err(EstimateError::Cancelled);
+        }
+    }
+    Ok(log_likelihood / weight)
+}
+
+/// Bootstrap p-value of the hypothesis that the offset is zero, e.g. to decide whether the
+/// clocks of hosts already in sync warrant a correction at all.
+///
+/// The offset is estimated from `samples` as [`estimate_samples`] does, then again from each of
+/// `resamples` batches drawn from them with replacement, at least one. The p-value is the share
+/// of bootstrap offsets at least as far from the estimate as the estimate is from zero, with
+/// the estimate itself counted in, so that it is never zero: below 0.05 after a few hundred
+/// resamples, the offset is significant at 5%. The resamples go through the whole pipeline,
+/// preprocessing included, but without the [hook](EstimatorConfig::hook); their draws and
+/// synthetic samples follow [`EstimatorConfig::seed`].
+///
+/// The test refits the model once per resample, so it is meant for small batches. It checks the
+/// [cancellation token](EstimatorConfig::cancel) before each resample and fails like
+/// [`estimate_samples`] on the samples or any resample of them.
+pub fn test_offset_zero<I>(
+    samples: I,
+    resamples: usize,
+    config: &EstimatorConfig,
+) -> Result<f64, EstimateError>
+where
+    I: IntoIterator<Item = Sample>,
+{
+    let samples: Vec<Sample> = samples.into_iter().collect();
+    let offset = estimate_samples(samples.iter().copied(), config)?.offset;
+    let quiet = EstimatorConfig {
+        hook: None,
+        ..config.clone()
+    };
+    let n = samples.len();
+    let resamples = resamples.max(1);
+    let mut rng = LcgRng::new(seed(config));
+    let mut resample = Vec::with_capacity(n);
+    let mut extreme = 0;
+    for _ in 0..resamples {
+        cancel::check(&config.cancel)?;
+        resample.clear();
+        resample.extend((0..n).map(|_| {
+            let index = (rng.gen_range(0.0..1.0) * n as f64) as usize;
+            samples[index.min(n - 1)]
+        }));
+        let bootstrap = estimate_samples(resample.iter().copied(), &quiet)?.offset;
+        if libm::fabs(bootstrap - offset) >= libm::fabs(offset) {
+            extreme += 1;
+        }
+    }
+    Ok((extreme + 1) as f64 / (resamples + 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset_estimator::LcgRng;
+
+    fn exponential(n: usize, scale: f64, seed: u64) -> Vec<Sample> {
+        let mut rng = LcgRng::new(seed);
+        (0..n)
+            .map(|_| Sample::new(-scale * float::ln(1.0 - rng.gen_range(0.0..1.0))))
+            .collect()
+    }
+
+    #[test]
+    fn test_cross_validate_prefers_gamma_data() {
+        let config = EstimatorConfig::default();
+        let gamma = cross_validate(exponential(400, 2.0, 7), 5, &config).unwrap();
+        // Exponential data, i.e. Gamma with shape one: the mean log-likelihood approaches
+        // -(1 + ln 2).
+        assert!((gamma + 1.0 + float::ln(2.0)).abs() < 0.1, "Score {gamma}");
+
+        // A bimodal batch fits the single Gamma model worse.
+        let bimodal = exponential(200, 2.0, 8).into_iter().chain(
+            exponential(200, 2.0, 9)
+                .into_iter()
+                .map(|s| Sample::new(s.value + 40.0)),
+        );
+        let bimodal = cross_validate(bimodal, 5, &config).unwrap();
+        assert!(bimodal < gamma, "Bimodal score {bimodal} not below {gamma}");
+    }
+
+    #[test]
+    fn test_offset_zero_p_value() {
+        let config = EstimatorConfig {
+            synthetic: crate::SyntheticSample::Quantiles,
+            ..Default::default()
+        };
+        let erlang = |offset: f64, seed: u64| {
+            exponential(100, 2.0, seed)
+                .into_iter()
+                .zip(exponential(100, 2.0, seed + 1))
+                .map(move |(a, b)| Sample::new(offset + a.value + b.value))
+        };
+        let p = test_offset_zero(erlang(0.0, 3), 200, &config).unwrap();
+        assert!(p > 0.05, "p-value {p}");
+        let p = test_offset_zero(erlang(20.0, 3), 200, &config).unwrap();
+        assert!(p < 0.01, "p-value {p}");
+    }
+
+    #[test]
+    fn test_cross_validate_insufficient_samples() {
+        let samples = exponential(12, 1.0, 1);
+        assert_eq!(
+            cross_validate(samples, 3, &EstimatorConfig::default()),
+            Err(EstimateError::InsufficientSamples { got: 8, need: 10 })
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use core::time::Duration;
+
+use wasm_bindgen::JsCast;
+
+use crate::phc::ClockSource;
+
+/// The high-resolution clock of a browser or a web worker, `performance.now()` offset by
+/// `performance.timeOrigin`, as a [`ClockSource`] reading the time since the Unix epoch.
+///
+/// Browsers coarsen the clock to between 5 µs and 100 µs against timing attacks, more without
+/// cross-origin isolation; the quantization is part of the delays the estimator sees, see
+/// [`EstimatorConfig::resolution`](crate::EstimatorConfig::resolution).
+///
+/// W3C. "High Resolution Time". W3C Recommendation, 2024.
+#[derive(Debug, Clone)]
+pub struct PerformanceClock {
+    performance: web_sys::Performance,
+}
+
+impl PerformanceClock {
+    /// The clock of the global scope, `None` where it has no `performance` object, e.g. outside
+    /// a browser.
+    pub fn new() -> Option<Self> {
+        let global = js_sys::global();
+        let performance = js_sys::Reflect::get(&global, &"performance".into()).ok()?;
+        Some(PerformanceClock {
+            performance: performance.dyn_into().ok()?,
+        })
+    }
+}
+
+impl ClockSource for PerformanceClock {
+    fn now(&self) -> Duration {
+        let millis = self.performance.time_origin() + self.performance.now();
+        Duration::try_from_secs_f64(millis / 1e3).unwrap_or_default()
+    }
+}
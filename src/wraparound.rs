@@ -0,0 +1,140 @@
+use crate::error::EstimateError;
+use crate::sample::Sample;
+
+/// Arithmetic on the readings of a counter that wraps around at a modulus, e.g. a 32-bit
+/// hardware timer or the 64-bit NTP timestamp, whose seconds roll over at the end of every
+/// 136-year era.
+///
+/// Subtracting two raw readings across a rollover gives a difference off by the modulus, a
+/// delay of years that no tail policy saves the fit from. Differences are taken modulo the
+/// modulus instead, and resolved to the representative of smallest magnitude; the
+/// disambiguation [`window`](Self::window) bounds that magnitude, beyond which the readings are
+/// too far apart to tell how many rollovers they straddle.
+///
+/// ```
+/// use gamlr::Wraparound;
+///
+/// // A probe sent just before a 32-bit microsecond timer wrapped.
+/// let timer = Wraparound::U32;
+/// assert_eq!(timer.difference(250, u32::MAX as u64 - 749), Some(1000));
+/// let sample = timer.delay(u32::MAX as u64 - 749, 250, 1e-6);
+/// assert!((sample.value - 1e-3).abs() < 1e-12);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wraparound {
+    modulus: u128,
+    window: u128,
+}
+
+impl Wraparound {
+    /// 32-bit counters, e.g. the timers of microcontrollers or the seconds of NTP timestamps,
+    /// with differences of up to half the range.
+    pub const U32: Wraparound = Wraparound {
+        modulus: 1 << 32,
+        window: 1 << 31,
+    };
+    /// NTP timestamps in their 64-bit format of 32 bits of seconds and 32 of fraction,
+    /// resolving differences of up to half an era, about 68 years.
+    ///
+    /// D. Mills, J. Martin, J. Burbank, W. Kasch. "Network Time Protocol Version 4: Protocol
+    /// and Algorithms Specification". RFC 5905, 2010, Section 6.
+    pub const NTP: Wraparound = Wraparound {
+        modulus: 1 << 64,
+        window: 1 << 63,
+    };
+
+    /// A counter wrapping around at `modulus`, whose differences are resolved up to `window`
+    /// ticks in magnitude.
+    ///
+    /// Fails with [`EstimateError::InvalidConfig`] naming the field when `modulus` is below 2 or
+    /// above `2^64`, the range of the readings, or `window` is above half the modulus, where the
+    /// difference is ambiguous.
+    pub fn new(modulus: u128, window: u64) -> Result<Self, EstimateError> {
+        let window = u128::from(window);
+        let valid = [
+            ("modulus", (2..=1 << 64).contains(&modulus)),
+            ("window", window <= modulus / 2),
+        ];
+        if let Some(&(field, _)) = valid.iter().find(|(_, valid)| !valid) {
+            return Err(EstimateError::InvalidConfig { field });
+        }
+        Ok(Wraparound { modulus, window })
+    }
+
+    pub fn modulus(&self) -> u128 {
+        self.modulus
+    }
+
+    /// Largest magnitude of a difference, in ticks.
+    pub fn window(&self) -> u128 {
+        self.window
+    }
+
+    /// `later - earlier` in ticks, the difference modulo the modulus of smallest magnitude;
+    /// `None` when that exceeds the window. Readings are taken modulo the modulus.
+    pub fn difference(&self, later: u64, earlier: u64) -> Option<i128> {
+        let m = self.modulus;
+        let forward = (u128::from(later) % m + m - u128::from(earlier) % m) % m;
+        let difference = match forward > m / 2 {
+            true => forward as i128 - m as i128,
+            false => forward as i128,
+        };
+        (difference.unsigned_abs() <= self.window).then_some(difference)
+    }
+
+    /// The full count that `reading` stands for, the one nearest to the full count `near`, e.g.
+    /// the NTP era of a timestamp placed next to the local time. `None` when it is further from
+    /// `near` than the window.
+    pub fn resolve(&self, reading: u64, near: i128) -> Option<i128> {
+        let m = self.modulus as i128;
+        let base = near.rem_euclid(m);
+        let difference = self.difference(reading, base as u64)?;
+        Some(near + difference)
+    }
+
+    /// One-way delay sample of a probe `sent` and `received` at the given readings of `period`
+    /// each, in the unit of the period. A delay beyond the window is NaN, left to the
+    /// [`NonFinitePolicy`](crate::NonFinitePolicy) rather than fitted.
+    pub fn delay(&self, sent: u64, received: u64, period: f64) -> Sample {
+        match self.difference(received, sent) {
+            Some(ticks) => Sample::new(ticks as f64 * period),
+            None => Sample::new(f64::NAN),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wraparound() {
+        let ntp = Wraparound::NTP;
+        // From half a second before the end of era 0 to a second after it.
+        let before = u64::MAX - (1 << 31);
+        let after = 1 << 32;
+        assert_eq!(
+            ntp.difference(after, before),
+            Some((1 << 31) + (1 << 32) + 1)
+        );
+        assert_eq!(
+            ntp.difference(before, after),
+            Some(-((1 << 31) + (1 << 32) + 1))
+        );
+        assert_eq!(
+            ntp.resolve(after, i128::from(before)),
+            Some((1 << 64) + (1 << 32))
+        );
+
+        let timer = Wraparound::new(1000, 100).unwrap();
+        assert_eq!(timer.difference(5, 990), Some(15));
+        assert_eq!(timer.difference(990, 5), Some(-15));
+        assert_eq!(timer.difference(500, 5), None);
+        assert_eq!(timer.resolve(5, 2990), Some(3005));
+        assert!(timer.delay(5, 500, 1.0).value.is_nan());
+        assert_eq!(
+            Wraparound::new(1000, 501),
+            Err(EstimateError::InvalidConfig { field: "window" })
+        );
+    }
+}